@@ -0,0 +1,50 @@
+//! This example shows how a plugin can contribute its own builtin
+//!
+//! The plugin registers a `greet` builtin, which prints a greeting that is configured when the
+//! plugin is created
+
+use shrs::prelude::*;
+
+struct GreetPlugin {
+    greeting: String,
+}
+
+/// State holding the greeting so the builtin can access it
+struct Greeting(String);
+
+fn greet_builtin(
+    greeting: State<Greeting>,
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> ::anyhow::Result<CmdOutput> {
+    let name = args.get(1).map_or("World", |name| name.as_str());
+    out.println(format!("{} {name}", greeting.0))?;
+    Ok(CmdOutput::success())
+}
+
+impl Plugin for GreetPlugin {
+    fn init(&self, config: &mut ShellConfig) -> anyhow::Result<()> {
+        config.states.insert(Greeting(self.greeting.clone()));
+        // builtins registered here can be used as soon as the shell starts
+        config.builtins.insert(
+            "greet",
+            describe(greet_builtin, "Greet someone", "greet [NAME]"),
+        );
+        Ok(())
+    }
+
+    fn meta(&self) -> PluginMeta {
+        PluginMeta::new("Greet", "Adds the greet builtin", None)
+    }
+}
+
+fn main() {
+    let myshell = ShellBuilder::default()
+        .with_plugin(GreetPlugin {
+            greeting: "Hello".into(),
+        })
+        .build()
+        .unwrap();
+
+    myshell.run().unwrap();
+}
@@ -40,6 +40,40 @@
 //! });
 //! alias.set("ls", ls_alias);
 //! ```
+//! [`Alias::set_conditional()`] is a shorthand for this. Here `g` only runs a script while the
+//! working directory is in a certain repository, and is `git` everywhere else:
+//! ```
+//! # use shrs_core::prelude::*;
+//! let mut alias = Alias::new();
+//! alias.set("g", AliasInfo::always("git"));
+//! alias.set_conditional("g", "./scripts/git.sh", |ctx: &AliasRuleCtx| -> bool {
+//!     let rt = ctx.states.get::<Runtime>();
+//!     rt.working_dir.starts_with("/home/user/repo")
+//! });
+//! ```
+//! The first word of an alias is expanded again, so `ll` below becomes `ls --color -l`. An alias
+//! that ends in a blank also expands the word after it, like `sudo` here:
+//! ```
+//! # use shrs_core::prelude::*;
+//! let alias = Alias::from_iter([("ls", "ls --color"), ("ll", "ls -l"), ("sudo", "sudo ")]);
+//! ```
+//! Aliases normally only expand when used as the command name. Global aliases, created with
+//! [`AliasInfo::global`] (or `alias -g`), expand anywhere in the line as long as they are not
+//! quoted:
+//! ```
+//! # use shrs_core::prelude::*;
+//! let mut alias = Alias::new();
+//! // `ls G foo` becomes `ls | grep foo`
+//! alias.set("G", AliasInfo::global("| grep"));
+//! // rules work the same for global aliases
+//! let in_repo = AliasInfo::with_rule("| git blame", |_: &AliasRuleCtx| true);
+//! alias.set("B", in_repo.with_scope(AliasScope::Global));
+//! ```
+//! The command name is expanded first, by any alias of its name whose rule passes, global or
+//! not. The rest of the words, including the ones the command alias expanded to, are then only
+//! expanded by global aliases. If several aliases of the same name apply, the last one set wins.
+
+use std::collections::HashSet;
 
 use multimap::MultiMap;
 
@@ -56,12 +90,24 @@ pub struct AliasRuleCtx<'a> {
 /// Predicate to decide if an alias should be used or not
 pub struct AliasRule(Box<dyn Fn(&AliasRuleCtx) -> bool>);
 
+/// Where in the line an alias is allowed to expand
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AliasScope {
+    /// Only expand when used as the command name
+    #[default]
+    Command,
+    /// Expand in any unquoted word of the line
+    Global,
+}
+
 /// Contains alias value and other metadata
 pub struct AliasInfo {
     /// The actual value to be substituted
     pub subst: String,
     /// Predicate to decide if the alias should be taken or not
     pub rule: AliasRule,
+    /// Where the alias is expanded
+    pub scope: AliasScope,
 }
 
 impl AliasInfo {
@@ -70,6 +116,15 @@ impl AliasInfo {
         Self {
             subst: subst.to_string(),
             rule: AliasRule(Box::new(|_| -> bool { true })),
+            scope: AliasScope::Command,
+        }
+    }
+
+    /// Always use this alias, expanding it anywhere in the line
+    pub fn global<S: ToString>(subst: S) -> Self {
+        Self {
+            scope: AliasScope::Global,
+            ..Self::always(subst)
         }
     }
 
@@ -82,8 +137,14 @@ impl AliasInfo {
         Self {
             subst: subst.to_string(),
             rule: AliasRule(Box::new(rule)),
+            scope: AliasScope::Command,
         }
     }
+
+    /// Change where the alias is expanded, for example to make a conditional alias global
+    pub fn with_scope(self, scope: AliasScope) -> Self {
+        Self { scope, ..self }
+    }
 }
 
 /// Query and set aliases
@@ -116,6 +177,21 @@ impl Alias {
             .collect::<Vec<_>>()
     }
 
+    /// Fetch all possible global aliases
+    pub fn get_global(&self, alias_ctx: &AliasRuleCtx) -> Vec<&String> {
+        let alias_list = match self.aliases.get_vec(alias_ctx.alias_name) {
+            Some(alias_list) => alias_list,
+            None => return vec![],
+        };
+
+        alias_list
+            .iter()
+            .filter(|alias_info| alias_info.scope == AliasScope::Global)
+            .filter(|alias_info| (alias_info.rule.0)(alias_ctx))
+            .map(|alias_info| &alias_info.subst)
+            .collect::<Vec<_>>()
+    }
+
     /// For a given alias, check what it will evaluate to
     pub fn get_subst(&self, alias_name: &String) -> Option<&String> {
         match self.aliases.get(alias_name) {
@@ -124,11 +200,29 @@ impl Alias {
         }
     }
 
+    /// Names of all the aliases
+    pub fn names(&self) -> Vec<&String> {
+        self.aliases.keys().collect()
+    }
+
     /// Update an alias of given name
     pub fn set(&mut self, alias_name: &str, alias_info: AliasInfo) {
         self.aliases.insert(alias_name.into(), alias_info);
     }
 
+    /// Set an alias that is only expanded while the predicate passes
+    ///
+    /// The predicate is checked every time the alias is looked up, so it can depend on the state
+    /// of the shell, such as the working directory. If other aliases of the same name are set
+    /// before it, they are used while the predicate fails.
+    pub fn set_conditional<S, R>(&mut self, alias_name: &str, subst: S, rule: R)
+    where
+        S: ToString,
+        R: Fn(&AliasRuleCtx) -> bool + 'static,
+    {
+        self.set(alias_name, AliasInfo::with_rule(subst, rule));
+    }
+
     /// Clear an alias
     ///
     /// This removes ALL aliases of a given name.
@@ -156,3 +250,284 @@ impl<S: ToString> FromIterator<(S, S)> for Alias {
         }
     }
 }
+
+/// Split a line into the words that aliases are looked up in
+///
+/// Words are separated by the characters in `ifs`, except inside of single or double quotes or
+/// when escaped with a backslash, so quoted arguments are kept whole and unchanged.
+pub(crate) fn split_words(line: &str, ifs: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut single = false;
+    let mut double = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if !single => {
+                word.push(c);
+                if let Some(c) = chars.next() {
+                    word.push(c);
+                }
+                continue;
+            },
+            '\'' if !double => single = !single,
+            '"' if !single => double = !double,
+            c if !single && !double && ifs.contains(c) => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                continue;
+            },
+            _ => {},
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Expand the alias of the command name
+///
+/// `lookup` returns the substitution of an alias. The first word of a substitution is expanded
+/// again, so aliases can build on each other, but an alias is never expanded inside its own
+/// substitution so recursive definitions can't loop. If a substitution ends in a blank, the word
+/// after the alias is expanded too (as in `alias sudo='sudo '`).
+pub(crate) fn expand_command_aliases(
+    words: &[String],
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    expand_command_words(words, lookup, &mut HashSet::new()).0
+}
+
+/// Expand the first of the words, and the following ones for as long as the alias before them
+/// ends in a blank, returns if all of them were aliases that end in a blank
+fn expand_command_words(
+    words: &[String],
+    lookup: &impl Fn(&str) -> Option<String>,
+    expanding: &mut HashSet<String>,
+) -> (Vec<String>, bool) {
+    let mut expanded = vec![];
+
+    for (i, word) in words.iter().enumerate() {
+        let subst = match expanding.contains(word) {
+            true => None,
+            false => lookup(word),
+        };
+        let Some(subst) = subst else {
+            expanded.extend_from_slice(&words[i..]);
+            return (expanded, false);
+        };
+
+        expanding.insert(word.clone());
+        let (mut subst_words, blank) =
+            expand_command_words(&split_words(&subst, " \t\n"), lookup, expanding);
+        expanding.remove(word);
+        expanded.append(&mut subst_words);
+
+        if !blank && !subst.ends_with([' ', '\t']) {
+            expanded.extend_from_slice(&words[i + 1..]);
+            return (expanded, false);
+        }
+    }
+
+    (expanded, !words.is_empty())
+}
+
+/// Expand global aliases in each unquoted word
+///
+/// The words are expected to be split with [split_words], so quoted words never match an alias.
+/// `lookup` returns the substitution of a global alias. Substitutions are expanded again, but an
+/// alias is never expanded inside its own substitution so recursive definitions can't loop.
+pub(crate) fn expand_global_aliases(
+    words: &[String],
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    expand_global_words(words, lookup, &mut HashSet::new())
+}
+
+fn expand_global_words(
+    words: &[String],
+    lookup: &impl Fn(&str) -> Option<String>,
+    expanding: &mut HashSet<String>,
+) -> Vec<String> {
+    let mut expanded = vec![];
+
+    for word in words {
+        let subst = match expanding.contains(word) {
+            true => None,
+            false => lookup(word),
+        };
+
+        match subst {
+            Some(subst) => {
+                let subst_words = split_words(&subst, " \t\n");
+                expanding.insert(word.clone());
+                expanded.append(&mut expand_global_words(&subst_words, lookup, expanding));
+                expanding.remove(word);
+            },
+            None => expanded.push(word.clone()),
+        }
+    }
+
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_command_aliases, expand_global_aliases, split_words, Alias, AliasInfo};
+    use crate::{
+        prelude::*,
+        shell::{Runtime, Shell},
+    };
+
+    fn words(line: &str) -> Vec<String> {
+        split_words(line, " ")
+    }
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "G" => Some("| grep".into()),
+            "L" => Some("| less".into()),
+            "GL" => Some("G L".into()),
+            "loop" => Some("loop again".into()),
+            _ => None,
+        }
+    }
+
+    fn command_lookup(name: &str) -> Option<String> {
+        match name {
+            "ls" => Some("ls --color".into()),
+            "ll" => Some("ls -l".into()),
+            "la" => Some("ll -a".into()),
+            "sudo" => Some("sudo ".into()),
+            "s" => Some("sudo".into()),
+            "ping" => Some("pong".into()),
+            "pong" => Some("ping".into()),
+            "empty" => Some("".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn command_alias_chained() {
+        assert_eq!(
+            expand_command_aliases(&words("ll foo"), &command_lookup),
+            words("ls --color -l foo")
+        );
+        assert_eq!(
+            expand_command_aliases(&words("la ll"), &command_lookup),
+            words("ls --color -l -a ll")
+        );
+        assert_eq!(
+            expand_command_aliases(&words("echo ll"), &command_lookup),
+            words("echo ll")
+        );
+    }
+
+    #[test]
+    fn command_alias_self_referential() {
+        assert_eq!(
+            expand_command_aliases(&words("ls"), &command_lookup),
+            words("ls --color")
+        );
+        assert_eq!(
+            expand_command_aliases(&words("ping x"), &command_lookup),
+            words("ping x")
+        );
+        assert_eq!(
+            expand_command_aliases(&words("pong"), &command_lookup),
+            words("pong")
+        );
+    }
+
+    #[test]
+    fn command_alias_trailing_blank() {
+        assert_eq!(
+            expand_command_aliases(&words("sudo ll ll"), &command_lookup),
+            words("sudo ls --color -l ll")
+        );
+        // also when the alias ending in a blank is reached through another one
+        assert_eq!(
+            expand_command_aliases(&words("s sudo la"), &command_lookup),
+            words("sudo sudo ls --color -l -a")
+        );
+        assert_eq!(
+            expand_command_aliases(&words("empty ll"), &command_lookup),
+            words("ll")
+        );
+    }
+
+    #[test]
+    fn conditional_on_working_dir() {
+        let mut alias = Alias::new();
+        alias.set("g", AliasInfo::always("git"));
+        alias.set_conditional("g", "./repo-git", |ctx: &AliasRuleCtx| -> bool {
+            let rt = ctx.states.get::<Runtime>();
+            rt.working_dir.starts_with("/repo")
+        });
+
+        let sh = Shell::stub();
+        let mut states = States::default();
+        states.insert(Runtime::stub("/"));
+        let lookup = |working_dir: &str| {
+            *states.get_mut::<Runtime>() = Runtime::stub(working_dir);
+            let alias_ctx = AliasRuleCtx {
+                alias_name: "g",
+                sh: &sh,
+                states: &states,
+            };
+            alias.get(&alias_ctx).last().map(|subst| subst.to_string())
+        };
+
+        assert_eq!(lookup("/repo/src").as_deref(), Some("./repo-git"));
+        assert_eq!(lookup("/other").as_deref(), Some("git"));
+        assert_eq!(lookup("/repo").as_deref(), Some("./repo-git"));
+    }
+
+    #[test]
+    fn global_alias_mid_line() {
+        assert_eq!(
+            expand_global_aliases(&words("ls G foo"), &lookup),
+            words("ls | grep foo")
+        );
+        assert_eq!(
+            expand_global_aliases(&words("ls GL"), &lookup),
+            words("ls | grep | less")
+        );
+    }
+
+    #[test]
+    fn global_alias_quoted() {
+        assert_eq!(
+            expand_global_aliases(&words("echo 'G' \"a G\" \\\" G"), &lookup),
+            words("echo 'G' \"a G\" \\\" | grep")
+        );
+    }
+
+    #[test]
+    fn split_quoted() {
+        assert_eq!(
+            split_words("echo \"hello  world\" 'a b'c d\\ e", " \t\n"),
+            ["echo", "\"hello  world\"", "'a b'c", "d\\ e"]
+        );
+        assert_eq!(
+            split_words("a \"it's\" 'say \"hi\"'  b\n", " \t\n"),
+            ["a", "\"it's\"", "'say \"hi\"'", "b"]
+        );
+        // an unterminated quote runs to the end of the line
+        assert_eq!(split_words("a 'b c", " "), ["a", "'b c"]);
+        assert_eq!(split_words("a:'b:c'", ":"), ["a", "'b:c'"]);
+        assert!(split_words("  ", " ").is_empty());
+    }
+
+    #[test]
+    fn global_alias_loop() {
+        assert_eq!(
+            expand_global_aliases(&words("echo loop"), &lookup),
+            words("echo loop again")
+        );
+    }
+}
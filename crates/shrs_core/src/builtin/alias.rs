@@ -8,6 +8,9 @@ use crate::{
 
 #[derive(Parser)]
 struct Cli {
+    /// Expand the alias anywhere in the line instead of only as the command name
+    #[arg(short = 'g')]
+    global: bool,
     alias: String,
 }
 
@@ -22,7 +25,11 @@ pub fn alias_builtin(mut alias: StateMut<Alias>, args: &Vec<String>) -> anyhow::
     match it.next() {
         Some(alias_def) => {
             // if alias body is passed, set the alias
-            alias.set(alias_name, AliasInfo::always(alias_def));
+            let alias_info = match cli.global {
+                true => AliasInfo::global(alias_def),
+                false => AliasInfo::always(alias_def),
+            };
+            alias.set(alias_name, alias_info);
         },
         None => {
             // if alias body is not passed, print the alias definition
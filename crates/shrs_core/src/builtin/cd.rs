@@ -1,11 +1,14 @@
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
+use anyhow::anyhow;
 use clap::Parser;
 
+use super::Builtin;
 use crate::{
-    prelude::{CmdOutput, OutputWriter},
+    completion::user_home_dir,
+    prelude::{CmdOutput, OutputWriter, States},
     shell::{set_working_dir, Runtime, Shell},
-    state::StateMut,
+    state::{Param, StateMut},
 };
 
 #[derive(Parser)]
@@ -13,42 +16,195 @@ struct Cli {
     path: Option<String>,
 }
 
-pub fn cd_builtin(
-    mut rt: StateMut<Runtime>,
-    mut out: StateMut<OutputWriter>,
-    sh: &Shell,
-    args: &Vec<String>,
-) -> anyhow::Result<CmdOutput> {
-    let cli = Cli::try_parse_from(args)?;
-    let path = if let Some(path) = cli.path {
-        // `cd -` moves us back to previous directory
-        if path == "-" {
-            if let Ok(old_pwd) = rt.env.get("OLDPWD") {
-                PathBuf::from(old_pwd)
-            } else {
-                out.eprintln("no OLDPWD")?;
+/// Change the working directory, expanding `~` and `$VAR` in the argument itself
+pub struct CdBuiltin {}
+impl Builtin for CdBuiltin {
+    fn run(&self, sh: &Shell, states: &States, args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+        let cli = Cli::try_parse_from(args)?;
+        let mut rt = StateMut::<Runtime>::retrieve(sh, states)?;
+        let mut out = states.get_mut::<OutputWriter>();
+        let path = match target_dir(&rt, cli.path.as_deref()) {
+            Ok(path) => path,
+            Err(e) => {
+                out.eprintln(e)?;
                 return Ok(CmdOutput::error());
+            },
+        };
+
+        if let Err(e) = set_working_dir(sh, &mut rt, &path, true) {
+            out.eprintln(e)?;
+            return Ok(CmdOutput::error());
+        }
+
+        // like bash, print where `cd -` took us since it isn't visible in the command
+        if cli.path.as_deref() == Some("-") {
+            out.println(rt.working_dir.display())?;
+        }
+
+        // return a dummy command
+        Ok(CmdOutput::success())
+    }
+
+    fn name(&self) -> &str {
+        "cd"
+    }
+
+    fn description(&self) -> &str {
+        "Change the working directory"
+    }
+
+    fn usage(&self) -> &str {
+        "cd [DIR | -]"
+    }
+
+    fn expands_args(&self) -> bool {
+        true
+    }
+}
+
+/// Directory to change to given the argument passed to `cd`
+fn target_dir(rt: &Runtime, path: Option<&str>) -> anyhow::Result<PathBuf> {
+    let Some(path) = path else {
+        return home_dir(rt).ok_or_else(|| anyhow!("cd: no home directory"));
+    };
+
+    // `cd -` moves us back to previous directory
+    if path == "-" {
+        return match rt.env.get("OLDPWD") {
+            Ok(old_pwd) => Ok(PathBuf::from(old_pwd)),
+            Err(_) => Err(anyhow!(
+                "cd: OLDPWD not set, no previous directory to return to"
+            )),
+        };
+    }
+
+    Ok(rt.working_dir.join(expand_path(rt, path)?))
+}
+
+/// Expand a leading `~` and `$VAR` or `${VAR}` in the path
+///
+/// The argument is passed to `cd` as typed, so single quotes prevent any expansion, and unset
+/// variables expand to nothing.
+fn expand_path(rt: &Runtime, path: &str) -> anyhow::Result<String> {
+    if let Some(quoted) = strip_quotes(path, '\'') {
+        return Ok(quoted.to_string());
+    }
+    let path = strip_quotes(path, '"').unwrap_or(path);
+
+    let path = match path.strip_prefix('~') {
+        Some(rest) => {
+            let (user, rest) = match rest.find('/') {
+                Some(i) => rest.split_at(i),
+                None => (rest, ""),
+            };
+            let home = if user.is_empty() {
+                home_dir(rt)
+            } else {
+                user_home_dir(user)
+            };
+            match home {
+                Some(home) => format!("{}{}", home.to_string_lossy(), rest),
+                None => return Err(anyhow!("cd: no home directory for ~{user}")),
             }
-        } else if let Some(remaining) = path.strip_prefix("~") {
-            match dirs::home_dir() {
-                Some(home) => PathBuf::from(format!("{}{}", home.to_string_lossy(), remaining)),
-                None => {
-                    out.eprintln("No Home Directory")?;
-                    return Ok(CmdOutput::error());
-                },
-            }
+        },
+        None => path.to_string(),
+    };
+
+    let mut expanded = String::new();
+    let mut chars = path.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+        let name: String = if chars.next_if_eq(&'{').is_some() {
+            chars.by_ref().take_while(|c| *c != '}').collect()
         } else {
-            rt.working_dir.join(Path::new(&path))
+            std::iter::from_fn(|| chars.next_if(|c| c.is_alphanumeric() || *c == '_')).collect()
+        };
+        if name.is_empty() {
+            expanded.push('$');
+        } else if let Ok(value) = rt.env.get(&name) {
+            expanded.push_str(value);
         }
-    } else {
-        dirs::home_dir().unwrap()
+    }
+    Ok(expanded)
+}
+
+fn strip_quotes(s: &str, quote: char) -> Option<&str> {
+    s.strip_prefix(quote)?.strip_suffix(quote)
+}
+
+/// Home directory from `HOME` in the shell's environment, falling back to the user's home
+fn home_dir(rt: &Runtime) -> Option<PathBuf> {
+    match rt.env.get("HOME") {
+        Ok(home) => Some(PathBuf::from(home)),
+        Err(_) => dirs::home_dir(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{expand_path, target_dir};
+    use crate::{
+        prelude::Env,
+        shell::{change_working_dir, Runtime},
     };
 
-    if let Err(e) = set_working_dir(sh, &mut rt, &path, true) {
-        out.eprintln(e)?;
-        return Ok(CmdOutput::error());
+    #[test]
+    fn cd_back_to_previous_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        fs::create_dir(root.join("a")).unwrap();
+        fs::create_dir(root.join("b")).unwrap();
+
+        let mut rt = Runtime::stub(&root);
+        assert!(target_dir(&rt, Some("-")).is_err());
+
+        let cd = |rt: &mut Runtime, path: &str| {
+            let target = target_dir(rt, Some(path)).unwrap();
+            change_working_dir(rt, &target).unwrap();
+        };
+        cd(&mut rt, "a");
+        cd(&mut rt, "../b");
+        assert_eq!(rt.working_dir, root.join("b"));
+
+        cd(&mut rt, "-");
+        assert_eq!(rt.working_dir, root.join("a"));
+        assert_eq!(
+            rt.env.get("OLDPWD").unwrap(),
+            root.join("b").to_str().unwrap()
+        );
+
+        cd(&mut rt, "-");
+        assert_eq!(rt.working_dir, root.join("b"));
     }
 
-    // return a dummy command
-    Ok(CmdOutput::success())
+    #[test]
+    fn expand_home() {
+        let rt = Runtime {
+            env: Env::from_iter([("HOME", "/home/me"), ("DIR", "src")]),
+            ..Runtime::stub("/tmp")
+        };
+
+        assert_eq!(target_dir(&rt, None).unwrap().to_str(), Some("/home/me"));
+        assert_eq!(
+            target_dir(&rt, Some("~")).unwrap().to_str(),
+            Some("/home/me")
+        );
+        assert_eq!(
+            target_dir(&rt, Some("~/sub")).unwrap().to_str(),
+            Some("/home/me/sub")
+        );
+        assert_eq!(
+            target_dir(&rt, Some("$HOME/src")).unwrap().to_str(),
+            Some("/home/me/src")
+        );
+        assert_eq!(expand_path(&rt, "${HOME}/$DIR").unwrap(), "/home/me/src");
+        assert_eq!(expand_path(&rt, "\"~/$DIR\"").unwrap(), "/home/me/src");
+        assert_eq!(expand_path(&rt, "'~/$DIR'").unwrap(), "~/$DIR");
+        assert_eq!(expand_path(&rt, "$UNSET/a$").unwrap(), "/a$");
+    }
 }
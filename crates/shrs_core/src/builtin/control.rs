@@ -0,0 +1,17 @@
+//! Builtins that are evaluated by the shell language
+//!
+//! `break`, `continue` and `return` change how the language evaluates the commands around them, so
+//! the language handles them before looking up builtins. They are registered so that `help` and
+//! `type` know about them.
+
+use crate::prelude::{CmdOutput, OutputWriter, StateMut};
+
+/// `break`, `continue` and `return`, which fail when run outside of the shell language
+pub fn control_builtin(
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let name = args.first().map(String::as_str).unwrap_or_default();
+    out.eprintln(format!("{name}: only meaningful in the shell language"))?;
+    Ok(CmdOutput::error())
+}
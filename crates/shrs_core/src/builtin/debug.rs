@@ -1,10 +1,12 @@
 //! Builtin command that has access to shell env for debug and prototyping
 
 use clap::{Parser, Subcommand};
+use shrs_job::JobManager;
 
+use super::Builtin;
 use crate::{
-    prelude::{CmdOutput, OutputWriter, State, StateMut},
-    shell::Runtime,
+    prelude::{Alias, CmdOutput, OutputWriter, States},
+    shell::{Runtime, Shell},
 };
 
 #[derive(Parser)]
@@ -15,27 +17,89 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Print all environment variables
     Env,
+    /// Print every alias and what it expands to
+    Alias,
+    /// Print the jobs started by the shell
+    Jobs,
+    /// Print the working directory of the shell
+    Cwd,
+    /// Print the type of every state in the state store
+    State,
 }
 
-pub fn debug_builtin(
-    rt: State<Runtime>,
-    mut out: StateMut<OutputWriter>,
-    args: &Vec<String>,
-) -> anyhow::Result<CmdOutput> {
-    let cli = Cli::try_parse_from(args)?;
-
-    match &cli.command {
-        None => {
-            out.println("debug utility")?;
-        },
-        Some(Commands::Env) => {
-            for (var, val) in rt.env.iter() {
-                let envs = format!("{:?} = {:?}", var, val);
-                out.println(envs)?;
-            }
-        },
+/// Dump internal state of the shell for troubleshooting
+///
+/// State that is missing or already borrowed is reported instead of causing a panic.
+pub struct DebugBuiltin {}
+impl Builtin for DebugBuiltin {
+    fn run(&self, _sh: &Shell, states: &States, args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+        let cli = Cli::try_parse_from(args)?;
+        let mut out = states.get_mut::<OutputWriter>();
+
+        let lines = match &cli.command {
+            None => vec!["debug utility, see `help debug` for subcommands".to_string()],
+            Some(Commands::Env) => match states.try_get::<Runtime>() {
+                Ok(rt) => {
+                    let mut vars = rt.env.iter().collect::<Vec<_>>();
+                    vars.sort();
+                    vars.into_iter()
+                        .map(|(var, val)| format!("{:?} = {:?}", var, val))
+                        .collect()
+                },
+                Err(e) => unavailable("env", e),
+            },
+            Some(Commands::Alias) => match states.try_get::<Alias>() {
+                Ok(alias) => {
+                    let mut names = alias.names();
+                    names.sort();
+                    names.dedup();
+                    names
+                        .into_iter()
+                        .map(|name| {
+                            let subst = alias.get_subst(name).cloned().unwrap_or_default();
+                            format!("{:?} = {:?}", name, subst)
+                        })
+                        .collect()
+                },
+                Err(e) => unavailable("alias", e),
+            },
+            Some(Commands::Jobs) => match states.try_get::<JobManager>() {
+                Ok(job_manager) => job_manager
+                    .get_jobs()
+                    .iter()
+                    .map(|job| job.display())
+                    .collect(),
+                Err(e) => unavailable("jobs", e),
+            },
+            Some(Commands::Cwd) => match states.try_get::<Runtime>() {
+                Ok(rt) => vec![rt.working_dir.to_string_lossy().to_string()],
+                Err(e) => unavailable("cwd", e),
+            },
+            Some(Commands::State) => states.type_names().into_iter().map(String::from).collect(),
+        };
+
+        for line in lines {
+            out.println(line)?;
+        }
+        Ok(CmdOutput::success())
     }
 
-    Ok(CmdOutput::success())
+    fn name(&self) -> &str {
+        "debug"
+    }
+
+    fn description(&self) -> &str {
+        "Print internal state of the shell"
+    }
+
+    fn usage(&self) -> &str {
+        "debug [env | alias | jobs | cwd | state]"
+    }
+}
+
+/// Message printed in place of state that couldn't be accessed
+fn unavailable(name: &str, e: impl std::fmt::Display) -> Vec<String> {
+    vec![format!("debug: {name}: unavailable ({e})")]
 }
@@ -0,0 +1,164 @@
+//! Directory stack builtins `pushd`, `popd` and `dirs`
+
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use super::Builtin;
+use crate::{
+    prelude::{CmdOutput, OutputWriter, States},
+    shell::{set_working_dir, Runtime, Shell},
+    state::{Param, StateMut},
+};
+
+/// Directories saved by `pushd`, most recently pushed last
+#[derive(Default)]
+pub struct DirStack {
+    dirs: Vec<PathBuf>,
+}
+
+impl DirStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, dir: PathBuf) {
+        self.dirs.push(dir);
+    }
+
+    pub fn pop(&mut self) -> Option<PathBuf> {
+        self.dirs.pop()
+    }
+
+    /// Saved directories, most recently pushed first
+    pub fn iter(&self) -> impl Iterator<Item = &PathBuf> {
+        self.dirs.iter().rev()
+    }
+}
+
+#[derive(Parser)]
+struct PushdCli {
+    /// Directory to change to, swaps the current directory with the top of the stack if omitted
+    dir: Option<String>,
+}
+
+/// Save the current directory on the stack and change to another one
+pub struct PushdBuiltin {}
+impl Builtin for PushdBuiltin {
+    fn run(&self, sh: &Shell, states: &States, args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+        let cli = PushdCli::try_parse_from(args)?;
+        let mut rt = StateMut::<Runtime>::retrieve(sh, states)?;
+        let mut stack = states.get_mut::<DirStack>();
+        let mut out = states.get_mut::<OutputWriter>();
+
+        let dir = match cli.dir {
+            Some(dir) => rt.working_dir.join(dir),
+            None => match stack.pop() {
+                Some(dir) => dir,
+                None => {
+                    out.eprintln("pushd: no other directory")?;
+                    return Ok(CmdOutput::error());
+                },
+            },
+        };
+
+        let cur_dir = rt.working_dir.clone();
+        if let Err(e) = set_working_dir(sh, &mut rt, &dir, true) {
+            out.eprintln(format!("pushd: {e}"))?;
+            return Ok(CmdOutput::error());
+        }
+        stack.push(cur_dir);
+
+        Ok(CmdOutput::success())
+    }
+
+    fn name(&self) -> &str {
+        "pushd"
+    }
+
+    fn description(&self) -> &str {
+        "Save the current directory on the stack and change to another one"
+    }
+
+    fn usage(&self) -> &str {
+        "pushd [DIR]"
+    }
+}
+
+/// Change back to the directory on the top of the stack
+pub struct PopdBuiltin {}
+impl Builtin for PopdBuiltin {
+    fn run(&self, sh: &Shell, states: &States, _args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+        let mut rt = StateMut::<Runtime>::retrieve(sh, states)?;
+        let mut stack = states.get_mut::<DirStack>();
+        let mut out = states.get_mut::<OutputWriter>();
+
+        let Some(dir) = stack.pop() else {
+            out.eprintln("popd: directory stack empty")?;
+            return Ok(CmdOutput::error());
+        };
+        if let Err(e) = set_working_dir(sh, &mut rt, &dir, true) {
+            // keep the directory so it isn't lost
+            stack.push(dir);
+            out.eprintln(format!("popd: {e}"))?;
+            return Ok(CmdOutput::error());
+        }
+
+        Ok(CmdOutput::success())
+    }
+
+    fn name(&self) -> &str {
+        "popd"
+    }
+
+    fn description(&self) -> &str {
+        "Change to the directory on top of the stack and remove it"
+    }
+
+    fn usage(&self) -> &str {
+        "popd"
+    }
+}
+
+#[derive(Parser)]
+struct DirsCli {
+    /// Print one directory per line along with its position in the stack
+    #[arg(short)]
+    verbose: bool,
+}
+
+/// Print the current directory followed by the directory stack
+pub struct DirsBuiltin {}
+impl Builtin for DirsBuiltin {
+    fn run(&self, _sh: &Shell, states: &States, args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+        let cli = DirsCli::try_parse_from(args)?;
+        let rt = states.get::<Runtime>();
+        let stack = states.get::<DirStack>();
+        let mut out = states.get_mut::<OutputWriter>();
+
+        let dirs = std::iter::once(&rt.working_dir)
+            .chain(stack.iter())
+            .map(|dir| dir.to_string_lossy().to_string());
+        if cli.verbose {
+            for (i, dir) in dirs.enumerate() {
+                out.println(format!("{i:>2}  {dir}"))?;
+            }
+        } else {
+            out.println(dirs.collect::<Vec<_>>().join(" "))?;
+        }
+
+        Ok(CmdOutput::success())
+    }
+
+    fn name(&self) -> &str {
+        "dirs"
+    }
+
+    fn description(&self) -> &str {
+        "Print the directory stack"
+    }
+
+    fn usage(&self) -> &str {
+        "dirs [-v]"
+    }
+}
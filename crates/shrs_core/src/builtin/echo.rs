@@ -0,0 +1,121 @@
+//! Builtin to print its arguments
+
+use crate::prelude::{CmdOutput, OutputWriter, StateMut};
+
+/// Print the arguments separated by spaces, `-n` leaves out the trailing newline and `-e`
+/// interprets backslash escapes
+pub fn echo_builtin(
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    out.print(echo(args.get(1..).unwrap_or_default()))?;
+    Ok(CmdOutput::success())
+}
+
+/// What echo prints for the arguments, not including the name of the builtin
+fn echo(args: &[String]) -> String {
+    let mut newline = true;
+    let mut escapes = false;
+
+    // flags are only recognized before the first argument, and unknown flags are printed
+    let mut args = args.iter().peekable();
+    while let Some(flags) = args.peek().and_then(|arg| arg.strip_prefix('-')) {
+        if flags.is_empty() || !flags.chars().all(|c| matches!(c, 'n' | 'e' | 'E')) {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false,
+            }
+        }
+        args.next();
+    }
+
+    let mut output = args.map(String::as_str).collect::<Vec<_>>().join(" ");
+    if escapes {
+        let (unescaped, stop) = unescape(&output);
+        if stop {
+            return unescaped;
+        }
+        output = unescaped;
+    }
+    if newline {
+        output.push('\n');
+    }
+    output
+}
+
+/// Interpret backslash escapes, also returning if `\c` was found, which stops all further output
+fn unescape(s: &str) -> (String, bool) {
+    let mut unescaped = String::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('a') => unescaped.push('\x07'),
+            Some('b') => unescaped.push('\x08'),
+            Some('c') => return (unescaped, true),
+            Some('e') => unescaped.push('\x1b'),
+            Some('f') => unescaped.push('\x0c'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some('v') => unescaped.push('\x0b'),
+            Some('\\') => unescaped.push('\\'),
+            // up to three octal digits
+            Some('0') => {
+                let mut value = 0;
+                for _ in 0..3 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => value = value * 8 + digit,
+                        None => break,
+                    }
+                    chars.next();
+                }
+                unescaped.push(char::from_u32(value & 0xff).unwrap_or_default());
+            },
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            },
+            None => unescaped.push('\\'),
+        }
+    }
+
+    (unescaped, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::echo;
+
+    fn echo_line(line: &str) -> String {
+        let args = line.split(' ').map(String::from).collect::<Vec<_>>();
+        echo(&args)
+    }
+
+    #[test]
+    fn flags() {
+        assert_eq!(echo_line("a b"), "a b\n");
+        assert_eq!(echo_line("-n a b"), "a b");
+        assert_eq!(echo_line("-x a"), "-x a\n");
+        assert_eq!(echo_line("a -n"), "a -n\n");
+        assert_eq!(echo(&[]), "\n");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(echo_line("a\\tb\\n"), "a\\tb\\n\n");
+        assert_eq!(echo_line("-e a\\tb\\n"), "a\tb\n\n");
+        assert_eq!(echo_line("-en a\\\\b"), "a\\b");
+        assert_eq!(echo_line("-e \\0101\\x"), "A\\x\n");
+        assert_eq!(echo_line("-e a\\cb"), "a");
+        assert_eq!(echo_line("-eE a\\tb"), "a\\tb\n");
+    }
+}
@@ -1,5 +1,67 @@
-use crate::prelude::CmdOutput;
+use super::Builtin;
+use crate::{
+    prelude::{CmdOutput, ExitCtx, OutputWriter, States},
+    shell::{Runtime, Shell},
+};
 
-pub fn exit_builtin(_args: &Vec<String>) -> anyhow::Result<CmdOutput> {
-    std::process::exit(0)
+/// Exit the shell with the given code, or the status of the last command if there is none
+pub struct ExitBuiltin {}
+impl Builtin for ExitBuiltin {
+    fn run(&self, sh: &Shell, states: &States, args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+        let last_status = states.get::<Runtime>().exit_status;
+        let exit_code = match exit_code(args, last_status) {
+            Ok(exit_code) => exit_code,
+            Err(e) => {
+                states.get_mut::<OutputWriter>().eprintln(e)?;
+                return Ok(CmdOutput::from_status(2));
+            },
+        };
+
+        let _ = sh.hooks.run(sh, states, &ExitCtx { exit_code });
+        std::process::exit(exit_code)
+    }
+
+    fn name(&self) -> &str {
+        "exit"
+    }
+
+    fn description(&self) -> &str {
+        "Exit the shell"
+    }
+
+    fn usage(&self) -> &str {
+        "exit [CODE]"
+    }
+}
+
+/// Code the shell should exit with, `args` includes the name of the builtin
+fn exit_code(args: &[String], last_status: i32) -> Result<i32, String> {
+    match args.get(1..).unwrap_or_default() {
+        [] => Ok(last_status),
+        [code] => code
+            .parse::<i32>()
+            // only the lowest 8 bits of the exit code are seen by the parent process
+            .map(|code| code & 0xff)
+            .map_err(|_| format!("exit: {code}: numeric argument required")),
+        _ => Err("exit: too many arguments".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::exit_code;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn last_status_by_default() {
+        assert_eq!(exit_code(&args(&["exit"]), 0), Ok(0));
+        assert_eq!(exit_code(&args(&["exit"]), 127), Ok(127));
+        assert_eq!(exit_code(&args(&["exit", "3"]), 127), Ok(3));
+        assert_eq!(exit_code(&args(&["exit", "256"]), 1), Ok(0));
+        assert!(exit_code(&args(&["exit", "abc"]), 0).is_err());
+        assert!(exit_code(&args(&["exit", "1", "2"]), 0).is_err());
+    }
 }
@@ -8,8 +8,10 @@ use crate::{
 #[derive(Parser)]
 struct Cli {
     vars: Vec<String>,
+    /// Print the exported variables
     #[arg(short)]
     p: bool,
+    /// Stop passing the variables to child processes, keeping them in the shell
     #[arg(short)]
     n: bool,
 }
@@ -24,19 +26,16 @@ pub fn export_builtin(
 ) -> anyhow::Result<CmdOutput> {
     let cli = Cli::try_parse_from(args)?;
 
-    // remove arg
-    if cli.n {
-        for var in cli.vars {
-            rt.env.remove(&var)?;
-        }
-        return Ok(CmdOutput::success());
-    }
-
-    // print all env vars
-    if cli.p {
-        for (var, val) in rt.env.iter() {
-            let s = format!("export {:?}={:?}", var, val);
-            out.println(s)?;
+    // print all exported vars in a form that can be sourced again
+    if cli.p || (cli.vars.is_empty() && !cli.n) {
+        let mut vars = rt
+            .env
+            .iter()
+            .filter(|(var, _)| rt.env.is_exported(var))
+            .collect::<Vec<_>>();
+        vars.sort();
+        for (var, val) in vars {
+            out.println(format!("export {var}={}", quote(val)))?;
         }
         return Ok(CmdOutput::success());
     }
@@ -44,10 +43,44 @@ pub fn export_builtin(
     for var in cli.vars {
         let mut it = var.splitn(2, '=');
         let var = it.next().unwrap();
-        let val = it.next().unwrap_or_default();
 
-        rt.env.set(var, val)?;
+        match it.next() {
+            Some(val) => rt.env.set(var, val)?,
+            // exporting a variable that isn't set defines it
+            None if rt.env.get(var).is_err() && !cli.n => rt.env.set(var, "")?,
+            None => {},
+        }
+
+        if cli.n {
+            // unexporting a variable that isn't set does nothing
+            let _ = rt.env.unexport(var);
+        } else {
+            rt.env.export(var)?;
+        }
     }
 
     Ok(CmdOutput::success())
 }
+
+/// Quote a value with single quotes if the shell would otherwise split or expand it
+fn quote(val: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c);
+    if !val.is_empty() && val.chars().all(plain) {
+        return val.to_string();
+    }
+    format!("'{}'", val.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::quote;
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("/usr/bin:/bin"), "/usr/bin:/bin");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("$HOME"), "'$HOME'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+    }
+}
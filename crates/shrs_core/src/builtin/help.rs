@@ -3,7 +3,7 @@ use crossterm::style::Stylize;
 use shrs_utils::styled_buf;
 
 use crate::{
-    prelude::{CmdOutput, OutputWriter, Shell, State},
+    prelude::{Builtins, CmdOutput, OutputWriter, Shell, State},
     shell::PluginMetas,
     state::StateMut,
 };
@@ -12,14 +12,19 @@ use crate::{
 #[clap(disable_help_flag = true, disable_help_subcommand = true)]
 struct Cli {
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     Builtin,
     Bindings,
-    Plugin { plugin_name: Vec<String> },
+    Plugin {
+        plugin_name: Vec<String>,
+    },
+    /// Name of a builtin to show the usage of
+    #[command(external_subcommand)]
+    Name(Vec<String>),
 }
 
 pub fn help_builtin(
@@ -31,16 +36,24 @@ pub fn help_builtin(
     let cli = Cli::try_parse_from(args)?;
 
     match &cli.command {
-        Commands::Builtin => {
-            let cmds = sh.builtins.builtins.keys();
-
+        None | Some(Commands::Builtin) => {
             out.println("Builtin Commands")?;
-
-            for cmd in cmds {
-                out.println(cmd)?;
+            for line in builtin_list(&sh.builtins) {
+                out.println(line)?;
+            }
+        },
+        Some(Commands::Name(names)) => {
+            let name = names.join(" ");
+            let Some(builtin) = sh.builtins.builtins.get(&name) else {
+                out.eprintln(format!("help: no builtin named '{name}'"))?;
+                return Ok(CmdOutput::error());
+            };
+            if !builtin.usage().is_empty() {
+                out.println(format!("usage: {}", builtin.usage()))?;
             }
+            out.println(builtin.description())?;
         },
-        Commands::Bindings => {
+        Some(Commands::Bindings) => {
             let info = sh.keybindings.get_info();
 
             out.println("Key Bindings")?;
@@ -49,7 +62,7 @@ pub fn help_builtin(
                 out.println(format!("{}: {}", binding, desc))?;
             }
         },
-        Commands::Plugin { plugin_name } => {
+        Some(Commands::Plugin { plugin_name }) => {
             let plugin_name = plugin_name.join(" ");
             if plugin_name.len() == 0 {
                 out.println(format!("{} Plugins installed", plugin_metas.len()))?;
@@ -93,3 +106,54 @@ pub fn help_builtin(
 
     Ok(CmdOutput::success())
 }
+
+/// Name and description of each builtin sorted by name, with the descriptions aligned
+fn builtin_list(builtins: &Builtins) -> Vec<String> {
+    let mut builtins = builtins.iter().collect::<Vec<_>>();
+    builtins.sort_by_key(|(name, _)| *name);
+    let width = builtins
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+
+    builtins
+        .into_iter()
+        .map(|(name, builtin)| {
+            format!("{name:width$}  {}", builtin.description())
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::builtin_list;
+    use crate::prelude::Builtins;
+
+    #[test]
+    fn default_builtins_described() {
+        let builtins = Builtins::default();
+        assert!(builtins
+            .iter()
+            .all(|(_, builtin)| !builtin.description().is_empty() && !builtin.usage().is_empty()));
+        assert!(builtins
+            .iter()
+            .all(|(name, builtin)| builtin.name().is_empty() || builtin.name() == name));
+
+        let list = builtin_list(&builtins);
+        assert!(list.windows(2).all(|lines| lines[0] < lines[1]));
+        assert!(
+            list.iter()
+                .any(|line| line.starts_with("cd ")
+                    && line.ends_with(" Change the working directory"))
+        );
+        // evaluated by the language, but still listed
+        for name in ["break", "continue", "return", "local"] {
+            assert!(list
+                .iter()
+                .any(|line| line.starts_with(&format!("{name} "))));
+        }
+    }
+}
@@ -86,4 +86,16 @@ impl Builtin for HistoryBuiltin {
 
         Ok(CmdOutput::success())
     }
+
+    fn name(&self) -> &str {
+        "history"
+    }
+
+    fn description(&self) -> &str {
+        "Show, run or search the command history"
+    }
+
+    fn usage(&self) -> &str {
+        "history [clear | run INDEX | search QUERY]"
+    }
 }
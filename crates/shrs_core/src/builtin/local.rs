@@ -0,0 +1,56 @@
+use shrs_lang::ast::Assign;
+
+use super::Builtin;
+use crate::{
+    env::EnvError,
+    prelude::{CmdOutput, OutputWriter, States},
+    shell::{Runtime, Shell},
+};
+
+/// Declare variables that are restored when the function being evaluated returns
+///
+/// A variable without a value keeps the value it had. Using it outside of a function is an
+/// error.
+pub struct LocalBuiltin {}
+impl Builtin for LocalBuiltin {
+    fn run(&self, _sh: &Shell, states: &States, args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+        let mut rt = states.get_mut::<Runtime>();
+        let mut out = states.get_mut::<OutputWriter>();
+
+        let mut status = CmdOutput::success();
+        for arg in args.iter().skip(1) {
+            let (var, val) = match arg.split_once('=') {
+                Some((var, val)) => (var, Some(val)),
+                None => (arg.as_str(), None),
+            };
+            if Assign::parse(&format!("{var}=")).is_none() {
+                out.eprintln(format!("local: `{arg}': not a valid identifier"))?;
+                status = CmdOutput::error();
+                continue;
+            }
+            match rt.env.declare_local(var) {
+                Err(EnvError::NotInFunction(_)) => {
+                    out.eprintln("local: can only be used in a function")?;
+                    return Ok(CmdOutput::error());
+                },
+                res => res?,
+            }
+            if let Some(val) = val {
+                rt.env.set_local(var, val)?;
+            }
+        }
+        Ok(status)
+    }
+
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn description(&self) -> &str {
+        "Declare variables that are restored when the function returns"
+    }
+
+    fn usage(&self) -> &str {
+        "local NAME[=VALUE]..."
+    }
+}
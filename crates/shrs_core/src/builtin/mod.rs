@@ -7,15 +7,29 @@
 
 mod alias;
 mod cd;
+mod control;
 mod debug;
+mod dir_stack;
+mod echo;
 mod exit;
 mod export;
 mod help;
 mod history;
 mod jobs;
+mod local;
+mod pwd;
+mod read;
+mod rehash;
+#[cfg(feature = "serde")]
+mod session;
+mod set;
 mod source;
+mod status;
 mod r#type;
 mod unalias;
+mod unset;
+mod version;
+mod which;
 
 use std::{
     collections::{hash_map::Iter, HashMap},
@@ -23,12 +37,32 @@ use std::{
 };
 
 use anyhow::Result;
+pub use dir_stack::DirStack;
 use unalias::unalias_builtin;
+use unset::unset_builtin;
 
 use self::{
-    alias::alias_builtin, cd::cd_builtin, debug::debug_builtin, exit::exit_builtin,
-    export::export_builtin, help::help_builtin, history::HistoryBuiltin, jobs::jobs_builtin,
-    r#type::type_builtin, source::source_builtin,
+    alias::alias_builtin,
+    cd::CdBuiltin,
+    control::control_builtin,
+    debug::DebugBuiltin,
+    dir_stack::{DirsBuiltin, PopdBuiltin, PushdBuiltin},
+    echo::echo_builtin,
+    exit::ExitBuiltin,
+    export::export_builtin,
+    help::help_builtin,
+    history::HistoryBuiltin,
+    jobs::jobs_builtin,
+    local::LocalBuiltin,
+    pwd::pwd_builtin,
+    r#type::type_builtin,
+    read::read_builtin,
+    rehash::rehash_builtin,
+    set::{set_builtin, shopt_builtin},
+    source::SourceBuiltin,
+    status::{false_builtin, true_builtin},
+    version::version_builtin,
+    which::which_builtin,
 };
 use crate::{
     all_the_tuples,
@@ -83,17 +117,162 @@ impl Builtins {
 impl Default for Builtins {
     fn default() -> Self {
         let mut builtins = Builtins::new();
-        builtins.insert("exit", exit_builtin);
-        builtins.insert("help", help_builtin);
-        builtins.insert("alias", alias_builtin);
-        builtins.insert("cd", cd_builtin);
-        builtins.insert("type", type_builtin);
-        builtins.insert("export", export_builtin);
+        builtins.insert("exit", ExitBuiltin {});
+        builtins.insert(
+            "help",
+            describe(
+                help_builtin,
+                "List builtins or show how to use one",
+                "help [NAME | builtin | bindings | plugin [NAME]]",
+            ),
+        );
+        builtins.insert(
+            "alias",
+            describe(alias_builtin, "Define an alias", "alias [-g] NAME=VALUE"),
+        );
+        builtins.insert("cd", CdBuiltin {});
+        builtins.insert(
+            "pwd",
+            describe(pwd_builtin, "Print the working directory", "pwd [-LP]"),
+        );
+        builtins.insert("pushd", PushdBuiltin {});
+        builtins.insert("popd", PopdBuiltin {});
+        builtins.insert("dirs", DirsBuiltin {});
+        builtins.insert(
+            "type",
+            describe(
+                type_builtin,
+                "Describe how each name would be interpreted as a command",
+                "type [-aptP] NAME...",
+            ),
+        );
+        builtins.insert(
+            "echo",
+            describe(
+                echo_builtin,
+                "Print the arguments separated by spaces",
+                "echo [-neE] [ARG...]",
+            ),
+        );
+        builtins.insert(
+            "export",
+            describe(
+                export_builtin,
+                "Set environment variables",
+                "export [-pn] [NAME[=VALUE]...]",
+            ),
+        );
         builtins.insert("history", HistoryBuiltin {});
-        builtins.insert("jobs", jobs_builtin);
-        builtins.insert("source", source_builtin);
-        builtins.insert("debug", debug_builtin);
-        builtins.insert("unalias", unalias_builtin);
+        builtins.insert(
+            "read",
+            describe(
+                read_builtin,
+                "Read a line from stdin into variables",
+                "read [-rs] [-p PROMPT] [NAME...]",
+            ),
+        );
+        builtins.insert(
+            "unset",
+            describe(
+                unset_builtin,
+                "Remove variables or functions",
+                "unset [-f | -v] NAME...",
+            ),
+        );
+        builtins.insert(
+            "jobs",
+            describe(jobs_builtin, "List background jobs", "jobs"),
+        );
+        builtins.insert(
+            "set",
+            describe(
+                set_builtin,
+                "Enable or disable shell options and set positional parameters",
+                "set [-+Ceux] [-+o [NAME]] [--] [ARG...]",
+            ),
+        );
+        builtins.insert(
+            "shopt",
+            describe(
+                shopt_builtin,
+                "Enable, disable or list shell options by name",
+                "shopt [-su] [-p] [NAME...]",
+            ),
+        );
+        builtins.insert("source", SourceBuiltin {});
+        builtins.insert("debug", DebugBuiltin {});
+        builtins.insert(
+            "unalias",
+            describe(unalias_builtin, "Remove aliases", "unalias [-a] NAME..."),
+        );
+        builtins.insert(
+            "version",
+            describe(version_builtin, "Print the version of shrs", "version [-v]"),
+        );
+        builtins.insert(
+            "rehash",
+            describe(
+                rehash_builtin,
+                "Forget the cached executables in PATH",
+                "rehash",
+            ),
+        );
+        builtins.insert(
+            ":",
+            describe(true_builtin, "Do nothing and succeed", ": [ARG...]"),
+        );
+        builtins.insert(
+            "true",
+            describe(true_builtin, "Do nothing and succeed", "true [ARG...]"),
+        );
+        builtins.insert(
+            "false",
+            describe(false_builtin, "Do nothing and fail", "false [ARG...]"),
+        );
+        builtins.insert(
+            "break",
+            describe(control_builtin, "Exit from enclosing loops", "break [N]"),
+        );
+        builtins.insert(
+            "continue",
+            describe(
+                control_builtin,
+                "Resume the next iteration of enclosing loops",
+                "continue [N]",
+            ),
+        );
+        builtins.insert(
+            "return",
+            describe(control_builtin, "Return from a function", "return [N]"),
+        );
+        builtins.insert("local", LocalBuiltin {});
+        builtins.insert(
+            "which",
+            describe(
+                which_builtin,
+                "Show what each command name resolves to",
+                "which [-a] NAME...",
+            ),
+        );
+        #[cfg(feature = "serde")]
+        {
+            builtins.insert(
+                "record",
+                describe(
+                    session::record_builtin,
+                    "Record the session to replay it later",
+                    "record [start FILE | stop]",
+                ),
+            );
+            builtins.insert(
+                "replay",
+                describe(
+                    session::replay_builtin,
+                    "Replay a recorded session",
+                    "replay [--fast] FILE",
+                ),
+            );
+        }
 
         builtins
     }
@@ -102,6 +281,81 @@ impl Default for Builtins {
 /// Implement this trait to define your own builtin command
 pub trait Builtin {
     fn run(&self, sh: &Shell, states: &States, args: &Vec<String>) -> Result<CmdOutput>;
+
+    /// Name the builtin is meant to be inserted under with [Builtins::insert], empty if it has no
+    /// fixed name
+    fn name(&self) -> &str {
+        ""
+    }
+
+    /// One line summary of what the builtin does, listed by `help`
+    fn description(&self) -> &str {
+        ""
+    }
+
+    /// How to call the builtin, shown by `help <name>`
+    fn usage(&self) -> &str {
+        ""
+    }
+
+    /// If the builtin expands its arguments itself, in which case they are passed to it as typed,
+    /// with quotes, instead of being expanded like the arguments of other commands
+    fn expands_args(&self) -> bool {
+        false
+    }
+}
+
+/// Builtin with a description and usage attached to it, see [describe]
+pub struct DescribedBuiltin<B> {
+    builtin: B,
+    description: String,
+    usage: String,
+}
+
+impl<B: Builtin> Builtin for DescribedBuiltin<B> {
+    fn run(&self, sh: &Shell, states: &States, args: &Vec<String>) -> Result<CmdOutput> {
+        self.builtin.run(sh, states, args)
+    }
+
+    fn name(&self) -> &str {
+        self.builtin.name()
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn usage(&self) -> &str {
+        &self.usage
+    }
+
+    fn expands_args(&self) -> bool {
+        self.builtin.expands_args()
+    }
+}
+
+/// Attach a description and usage to a builtin, which is how builtins defined as functions can
+/// document themselves
+/// ```
+/// # use shrs_core::prelude::*;
+/// fn greet_builtin(args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+///     println!("hello {}", args[1..].join(" "));
+///     Ok(CmdOutput::success())
+/// }
+///
+/// let mut builtins = Builtins::default();
+/// builtins.insert("greet", describe(greet_builtin, "Say hello", "greet NAME..."));
+/// ```
+pub fn describe<I, B: Builtin>(
+    builtin: impl IntoBuiltin<I, Builtin = B>,
+    description: impl ToString,
+    usage: impl ToString,
+) -> DescribedBuiltin<B> {
+    DescribedBuiltin {
+        builtin: builtin.into_builtin(),
+        description: description.to_string(),
+        usage: usage.to_string(),
+    }
 }
 pub trait IntoBuiltin<Input> {
     type Builtin: Builtin;
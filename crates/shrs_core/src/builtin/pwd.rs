@@ -0,0 +1,84 @@
+//! Builtin to print the working directory
+
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+
+use crate::{
+    prelude::{CmdOutput, OutputWriter, State, StateMut},
+    shell::Runtime,
+};
+
+#[derive(Parser)]
+struct Cli {
+    /// Print the path as it was navigated to, which may contain symlinks (default)
+    #[arg(short = 'L', overrides_with = "physical")]
+    logical: bool,
+    /// Print the path with all symlinks resolved
+    #[arg(short = 'P', overrides_with = "logical")]
+    physical: bool,
+}
+
+pub fn pwd_builtin(
+    rt: State<Runtime>,
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let cli = Cli::try_parse_from(args)?;
+
+    let pwd = if cli.physical {
+        physical_pwd(&rt.working_dir)
+    } else {
+        logical_pwd(&rt.working_dir, rt.env.get("PWD").ok().map(String::as_str))
+    };
+    out.println(pwd.display())?;
+
+    Ok(CmdOutput::success())
+}
+
+/// `PWD` if it is an absolute path to the working directory, otherwise the working directory
+fn logical_pwd(working_dir: &Path, pwd: Option<&str>) -> PathBuf {
+    match pwd.map(Path::new) {
+        Some(pwd) if pwd.is_absolute() && same_dir(pwd, working_dir) => pwd.to_path_buf(),
+        _ => working_dir.to_path_buf(),
+    }
+}
+
+fn physical_pwd(working_dir: &Path) -> PathBuf {
+    working_dir
+        .canonicalize()
+        .unwrap_or_else(|_| working_dir.to_path_buf())
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::{logical_pwd, physical_pwd};
+
+    #[test]
+    fn symlinked_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real");
+        let link = dir.path().join("link");
+        std::fs::create_dir_all(&real).unwrap();
+        symlink(&real, &link).unwrap();
+        let real = real.canonicalize().unwrap();
+
+        let pwd = link.to_str().unwrap();
+        assert_eq!(logical_pwd(&link, Some(pwd)), link);
+        assert_eq!(physical_pwd(&link), real);
+
+        // PWD is ignored if it doesn't point to the working directory
+        assert_eq!(logical_pwd(&real, Some("/")), real);
+        assert_eq!(logical_pwd(&real, Some("relative")), real);
+        assert_eq!(logical_pwd(&real, None), real);
+    }
+}
@@ -0,0 +1,194 @@
+//! Builtin to read a line from stdin into variables
+
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use clap::Parser;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    terminal::{disable_raw_mode, enable_raw_mode},
+};
+
+use crate::{
+    prelude::{CmdOutput, StateMut},
+    shell::Runtime,
+};
+
+#[derive(Parser)]
+struct Cli {
+    /// Prompt printed to stderr before reading, if stdin is a terminal
+    #[arg(short)]
+    p: Option<String>,
+    /// Don't treat backslashes as escape characters
+    #[arg(short)]
+    r: bool,
+    /// Don't echo the input back, for passwords
+    #[arg(short)]
+    s: bool,
+    /// Variables to assign the fields to, `REPLY` if none are given
+    names: Vec<String>,
+}
+
+/// Read a line and split it into fields with `IFS`, assigning one field to each variable and the
+/// rest of the line to the last one
+///
+/// Returns an error status if the end of input is reached before a line is read.
+pub fn read_builtin(mut rt: StateMut<Runtime>, args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+    let cli = Cli::try_parse_from(args)?;
+
+    if let Some(prompt) = &cli.p {
+        if io::stdin().is_terminal() {
+            let mut stderr = io::stderr();
+            write!(stderr, "{prompt}")?;
+            stderr.flush()?;
+        }
+    }
+
+    let mut line = String::new();
+    loop {
+        let next = if cli.s { read_silent()? } else { read_line()? };
+        let Some(next) = next else {
+            return Ok(CmdOutput::error());
+        };
+        line.push_str(&next);
+
+        // a backslash at the end of the line continues it on the next line
+        if cli.r || !ends_with_escape(&line) {
+            break;
+        }
+        line.pop();
+    }
+
+    let names = if cli.names.is_empty() {
+        vec!["REPLY".to_string()]
+    } else {
+        cli.names
+    };
+    let ifs = rt.env.ifs().to_string();
+    let fields = split_fields(&line, &ifs, names.len(), cli.r);
+    for (name, field) in names.iter().zip(fields) {
+        rt.env.set(name, &field)?;
+    }
+
+    Ok(CmdOutput::success())
+}
+
+/// Read a line from stdin without the trailing newline, [None] at the end of input
+fn read_line() -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+/// Read a line from the terminal without echoing it, [None] if input is ended with Ctrl-d or
+/// cancelled with Ctrl-c
+fn read_silent() -> io::Result<Option<String>> {
+    enable_raw_mode()?;
+    let mut line = String::new();
+    let res = loop {
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        else {
+            continue;
+        };
+        match (code, modifiers) {
+            (KeyCode::Enter, _) => break Some(line),
+            (KeyCode::Char('c'), KeyModifiers::CONTROL) => break None,
+            (KeyCode::Char('d'), KeyModifiers::CONTROL) if line.is_empty() => break None,
+            (KeyCode::Backspace, _) => {
+                line.pop();
+            },
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => line.push(c),
+            _ => {},
+        }
+    };
+    disable_raw_mode()?;
+    // the newline typed by the user wasn't echoed either
+    eprintln!();
+    Ok(res)
+}
+
+/// If the line ends with a backslash that isn't itself escaped
+fn ends_with_escape(line: &str) -> bool {
+    line.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+/// Split a line into at most `count` fields on the characters in `ifs`
+///
+/// The last field holds the rest of the line, without the separators around it. Unless `raw` is
+/// set, a backslash escapes the next character so it is never treated as a separator, and is
+/// removed. Missing fields are empty.
+fn split_fields(line: &str, ifs: &str, count: usize, raw: bool) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    // length of the last field without trailing separators
+    let mut field_len = 0;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        let escaped = !raw && c == '\\';
+        let c = match escaped {
+            true => match chars.next() {
+                Some(c) => c,
+                None => break,
+            },
+            false => c,
+        };
+
+        if !escaped && ifs.contains(c) {
+            if field.is_empty() {
+                continue;
+            }
+            if fields.len() + 1 < count {
+                fields.push(std::mem::take(&mut field));
+                field_len = 0;
+                continue;
+            }
+        }
+
+        field.push(c);
+        if escaped || !ifs.contains(c) {
+            field_len = field.len();
+        }
+    }
+
+    field.truncate(field_len);
+    fields.push(field);
+    fields.resize(count.max(1), String::new());
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ends_with_escape, split_fields};
+
+    #[test]
+    fn fields() {
+        let ifs = " \t\n";
+        assert_eq!(split_fields("  a b  c d ", ifs, 2, false), ["a", "b  c d"]);
+        assert_eq!(split_fields("a b", ifs, 3, false), ["a", "b", ""]);
+        assert_eq!(split_fields("  a b  ", ifs, 1, false), ["a b"]);
+        assert_eq!(split_fields("a:b:c", ":", 2, false), ["a", "b:c"]);
+        assert_eq!(split_fields("a b", "", 2, false), ["a b", ""]);
+    }
+
+    #[test]
+    fn escapes() {
+        let ifs = " \t\n";
+        assert_eq!(split_fields("a\\ b c", ifs, 2, false), ["a b", "c"]);
+        assert_eq!(split_fields("a\\ b c", ifs, 2, true), ["a\\", "b c"]);
+        assert_eq!(split_fields("a b\\ ", ifs, 2, false), ["a", "b "]);
+
+        assert!(ends_with_escape("a\\"));
+        assert!(!ends_with_escape("a\\\\"));
+        assert!(!ends_with_escape("a"));
+    }
+}
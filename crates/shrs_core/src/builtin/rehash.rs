@@ -0,0 +1,12 @@
+//! Builtin to rescan PATH for executables
+
+use crate::prelude::{CmdOutput, PathExecCache, State};
+
+/// Forget the cached executables in PATH, so newly installed programs are completed
+pub fn rehash_builtin(
+    cache: State<PathExecCache>,
+    _args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    cache.rehash();
+    Ok(CmdOutput::success())
+}
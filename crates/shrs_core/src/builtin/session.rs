@@ -0,0 +1,69 @@
+//! Builtins to record and replay interactive sessions, see [crate::session]
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::{
+    prelude::{CmdOutput, OutputWriter, StateMut},
+    session::{SessionRecorder, SessionReplay},
+};
+
+#[derive(Parser)]
+struct RecordCli {
+    #[command(subcommand)]
+    command: Option<RecordCommands>,
+}
+
+#[derive(Subcommand)]
+enum RecordCommands {
+    /// Start recording the session to a file
+    Start { file: PathBuf },
+    /// Stop recording
+    Stop,
+}
+
+pub fn record_builtin(
+    mut recorder: StateMut<SessionRecorder>,
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let cli = RecordCli::try_parse_from(args)?;
+
+    match &cli.command {
+        None => {
+            let status = match recorder.is_recording() {
+                true => "recording",
+                false => "not recording",
+            };
+            out.println(status)?;
+        },
+        Some(RecordCommands::Start { file }) => {
+            recorder.start(file)?;
+        },
+        Some(RecordCommands::Stop) => {
+            recorder.stop()?;
+        },
+    }
+
+    Ok(CmdOutput::success())
+}
+
+#[derive(Parser)]
+struct ReplayCli {
+    /// Feed the events back without waiting between them
+    #[arg(long)]
+    fast: bool,
+    file: PathBuf,
+}
+
+pub fn replay_builtin(
+    mut replay: StateMut<SessionReplay>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let cli = ReplayCli::try_parse_from(args)?;
+
+    *replay = SessionReplay::load(&cli.file, !cli.fast)?;
+
+    Ok(CmdOutput::success())
+}
@@ -0,0 +1,253 @@
+//! Builtins to toggle shell options and set positional parameters
+
+use clap::Parser;
+
+use crate::{
+    prelude::{CmdOutput, OutputWriter, ShellOptions, StateMut},
+    shell::Runtime,
+};
+
+/// Enable options with `-`, disable them with `+` and list them with `set -o`, or as commands that
+/// restore them with `set +o`
+///
+/// Any other arguments, or those after `--`, replace the positional parameters.
+pub fn set_builtin(
+    mut options: StateMut<ShellOptions>,
+    mut rt: StateMut<Runtime>,
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let set_args = match parse_args(&args[1..]) {
+        Ok(set_args) => set_args,
+        Err(e) => {
+            out.eprintln(format!("set: {e}"))?;
+            return Ok(CmdOutput::error());
+        },
+    };
+
+    match set_args.list {
+        Some(Listing::Table) => {
+            for line in option_table(&options, None) {
+                out.println(line)?;
+            }
+        },
+        Some(Listing::Commands) => {
+            for (name, enabled) in options.iter() {
+                out.println(format!("set {}o {name}", if enabled { '-' } else { '+' }))?;
+            }
+        },
+        None => {},
+    }
+    for (name, enabled) in set_args.options {
+        if !options.set(&name, enabled) {
+            out.eprintln(format!("set: {name}: invalid option name"))?;
+            return Ok(CmdOutput::error());
+        }
+    }
+    if let Some(positional) = set_args.positional {
+        rt.args = positional;
+    }
+    Ok(CmdOutput::success())
+}
+
+/// What `set` was asked to do
+#[derive(Debug, Default, PartialEq)]
+struct SetArgs {
+    /// Options to enable or disable
+    options: Vec<(String, bool)>,
+    /// Replacement for the positional parameters
+    positional: Option<Vec<String>>,
+    /// Print every option
+    list: Option<Listing>,
+}
+
+/// How `set` prints the options
+#[derive(Debug, PartialEq)]
+enum Listing {
+    /// Name of each option and if it is on or off (`set -o`)
+    Table,
+    /// Commands that restore the current options when they are run (`set +o`)
+    Commands,
+}
+
+/// Name of each option and if it is on or off, limited to the given names
+fn option_table(options: &ShellOptions, names: Option<&[String]>) -> Vec<String> {
+    options
+        .iter()
+        .filter(|(name, _)| names.is_none_or(|names| names.iter().any(|n| n == name)))
+        .map(|(name, enabled)| format!("{name:<15} {}", if enabled { "on" } else { "off" }))
+        .collect()
+}
+
+/// Name of the option toggled by a single letter flag
+fn short_option(flag: char) -> Option<&'static str> {
+    match flag {
+        'C' => Some("noclobber"),
+        'e' => Some("errexit"),
+        'u' => Some("nounset"),
+        'x' => Some("xtrace"),
+        _ => None,
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<SetArgs, String> {
+    let mut set_args = SetArgs::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            set_args.positional = Some(args.cloned().collect());
+            break;
+        }
+        let (enabled, flags) = match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => (true, flags),
+            _ => match arg.strip_prefix('+') {
+                Some(flags) if !flags.is_empty() => (false, flags),
+                _ => {
+                    let positional = std::iter::once(arg).chain(args).cloned().collect();
+                    set_args.positional = Some(positional);
+                    break;
+                },
+            },
+        };
+
+        if flags == "o" {
+            match args.next() {
+                Some(name) => set_args.options.push((name.clone(), enabled)),
+                None if enabled => set_args.list = Some(Listing::Table),
+                None => set_args.list = Some(Listing::Commands),
+            }
+            continue;
+        }
+        for flag in flags.chars() {
+            let name =
+                short_option(flag).ok_or_else(|| format!("{}{flag}: invalid option", &arg[..1]))?;
+            set_args.options.push((name.to_string(), enabled));
+        }
+    }
+
+    // `set` on its own lists the options like `set -o`
+    if set_args == SetArgs::default() {
+        set_args.list = Some(Listing::Table);
+    }
+    Ok(set_args)
+}
+
+#[derive(Parser)]
+struct ShoptCli {
+    /// Enable the options
+    #[arg(short, conflicts_with = "u")]
+    s: bool,
+    /// Disable the options
+    #[arg(short)]
+    u: bool,
+    /// Print the options as commands that restore them
+    #[arg(short)]
+    p: bool,
+    names: Vec<String>,
+}
+
+/// Enable options by name with `-s` and disable them with `-u`, otherwise list the named options,
+/// or all of them, with `-p` listing them as commands that restore them
+pub fn shopt_builtin(
+    mut options: StateMut<ShellOptions>,
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let cli = ShoptCli::try_parse_from(args)?;
+
+    if let Some(name) = cli
+        .names
+        .iter()
+        .find(|name| !options.iter().any(|(option, _)| option == name.as_str()))
+    {
+        out.eprintln(format!("shopt: {name}: invalid shell option name"))?;
+        return Ok(CmdOutput::error());
+    }
+
+    if cli.s || cli.u {
+        for name in cli.names {
+            options.set(&name, cli.s);
+        }
+        return Ok(CmdOutput::success());
+    }
+
+    let names = (!cli.names.is_empty()).then_some(cli.names.as_slice());
+    if cli.p {
+        for (name, enabled) in options.iter() {
+            if names.is_none_or(|names| names.iter().any(|n| n == name)) {
+                out.println(format!("shopt -{} {name}", if enabled { 's' } else { 'u' }))?;
+            }
+        }
+    } else {
+        for line in option_table(&options, names) {
+            out.println(line)?;
+        }
+    }
+    Ok(CmdOutput::success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{option_table, parse_args, Listing, SetArgs};
+    use crate::prelude::ShellOptions;
+
+    fn parse(line: &str) -> Result<SetArgs, String> {
+        let args: Vec<String> = line.split_whitespace().map(|s| s.to_string()).collect();
+        parse_args(&args)
+    }
+
+    fn options(options: &[(&str, bool)]) -> Vec<(String, bool)> {
+        options
+            .iter()
+            .map(|(name, enabled)| (name.to_string(), *enabled))
+            .collect()
+    }
+
+    #[test]
+    fn options_flags() {
+        assert_eq!(
+            parse("-C").unwrap().options,
+            options(&[("noclobber", true)])
+        );
+        assert_eq!(
+            parse("-eu +x").unwrap().options,
+            options(&[("errexit", true), ("nounset", true), ("xtrace", false)])
+        );
+        assert_eq!(
+            parse("-o pipefail +o noclobber").unwrap().options,
+            options(&[("pipefail", true), ("noclobber", false)])
+        );
+        assert_eq!(parse("-o").unwrap().list, Some(Listing::Table));
+        assert_eq!(parse("+o").unwrap().list, Some(Listing::Commands));
+        assert_eq!(parse("").unwrap().list, Some(Listing::Table));
+        assert!(parse("-Z").is_err());
+    }
+
+    #[test]
+    fn positional() {
+        let set_args = parse("-e a -b").unwrap();
+        assert_eq!(set_args.options, options(&[("errexit", true)]));
+        assert_eq!(set_args.positional, Some(vec!["a".into(), "-b".into()]));
+
+        assert_eq!(parse("-- -x").unwrap().positional, Some(vec!["-x".into()]));
+        assert_eq!(parse("--").unwrap().positional, Some(vec![]));
+        assert_eq!(parse("-x").unwrap().positional, None);
+    }
+
+    #[test]
+    fn table() {
+        let options = ShellOptions {
+            errexit: true,
+            ..Default::default()
+        };
+        let table = option_table(&options, None);
+        assert_eq!(table.len(), options.iter().count());
+        assert_eq!(table[0], "errexit         on");
+
+        let names = ["xtrace".to_string()];
+        assert_eq!(
+            option_table(&options, Some(&names)),
+            ["xtrace          off"]
+        );
+    }
+}
@@ -1,10 +1,15 @@
-use std::{fs::read_to_string, path::PathBuf, process::Command};
+use std::{fs::read_to_string, iter::Enumerate, path::PathBuf, process::Command, str::Lines};
 
 use clap::Parser;
 use lazy_static::lazy_static;
 use regex::Regex;
 
-use crate::prelude::{CmdOutput, OutputWriter, StateMut};
+use super::Builtin;
+use crate::{
+    lang::SyntaxCheck,
+    prelude::{CmdOutput, OutputWriter, States},
+    shell::{builtin_args, Shell},
+};
 
 lazy_static! {
     static ref SHEBANG_REGEX: Regex = Regex::new(r"#!(?P<interp>.+)").unwrap();
@@ -15,41 +20,208 @@ struct Cli {
     source_file: String,
 }
 
-pub fn source_builtin(
-    mut out: StateMut<OutputWriter>,
-    args: &Vec<String>,
-) -> anyhow::Result<CmdOutput> {
-    let cli = Cli::try_parse_from(args)?;
-
-    let file_path = PathBuf::from(&cli.source_file);
-    let file_contents = read_to_string(file_path)?;
-
-    // read shebang from first line
-    let mut it = file_contents.lines();
-
-    let interp = it
-        .next()
-        .and_then(|first_line| SHEBANG_REGEX.captures(first_line))
-        .and_then(|capture| capture.name("interp"));
-
-    match interp {
-        Some(interp) => {
-            let s = format!("using interp {} at {}", interp.as_str(), &cli.source_file);
-            out.println(s)?;
-            let mut _child = Command::new(interp.as_str())
-                .args(vec![cli.source_file])
-                .spawn()?;
-
-            // need command output here
-            // TODO temp disable this
-            // command_output(sh, ctx, rt, &mut child)?;
-
-            Ok(CmdOutput::success())
-        },
-        None => {
+/// Run the commands in a file with the interpreter from its shebang, or with the shell itself
+pub struct SourceBuiltin {}
+impl Builtin for SourceBuiltin {
+    fn run(&self, sh: &Shell, states: &States, args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+        let cli = Cli::try_parse_from(args)?;
+
+        let file_path = PathBuf::from(&cli.source_file);
+        let file_contents = read_to_string(file_path)?;
+
+        // read shebang from first line
+        let mut it = file_contents.lines();
+
+        let interp = it
+            .next()
+            .and_then(|first_line| SHEBANG_REGEX.captures(first_line))
+            .and_then(|capture| capture.name("interp"));
+
+        match interp {
+            Some(interp) => {
+                let s = format!("using interp {} at {}", interp.as_str(), &cli.source_file);
+                states.get_mut::<OutputWriter>().println(s)?;
+                let mut _child = Command::new(interp.as_str())
+                    .args(vec![cli.source_file])
+                    .spawn()?;
+
+                // need command output here
+                // TODO temp disable this
+                // command_output(sh, ctx, rt, &mut child)?;
+
+                Ok(CmdOutput::success())
+            },
             // otherwise evaluate with self
+            None => source_script(sh, states, &cli.source_file, &file_contents),
+        }
+    }
+
+    fn name(&self) -> &str {
+        "source"
+    }
+
+    fn description(&self) -> &str {
+        "Run the commands in a file"
+    }
+
+    fn usage(&self) -> &str {
+        "source FILE"
+    }
+}
+
+/// Evaluate a script one command at a time, reporting errors with the line they happened on
+///
+/// Returns the output of the last command, sourcing stops at the first syntax error.
+fn source_script(
+    sh: &Shell,
+    states: &States,
+    file: &str,
+    contents: &str,
+) -> anyhow::Result<CmdOutput> {
+    let mut commands = ScriptCommands::new(contents);
+    let mut output = CmdOutput::success();
+
+    loop {
+        let command = commands.next_command(|cmd| sh.lang.syntax_check(sh, states, cmd));
+        let (line, cmd) = match command {
+            Some(Ok(command)) => command,
+            Some(Err((line, e))) => {
+                states
+                    .get_mut::<OutputWriter>()
+                    .eprintln(format!("{file}: line {line}: syntax error: {e}"))?;
+                return Ok(CmdOutput::from_status(2));
+            },
+            None => return Ok(output),
+        };
+
+        let name = cmd.split_whitespace().next().unwrap_or_default();
+        let builtin_cmd = sh
+            .builtins
+            .iter()
+            .find(|(builtin_name, _)| *builtin_name == name)
+            .map(|(_, builtin_cmd)| builtin_cmd);
+
+        let res = match builtin_cmd {
+            Some(builtin_cmd) => builtin_args(states, builtin_cmd.as_ref(), &cmd)
+                .and_then(|args| builtin_cmd.run(sh, states, &args)),
+            None => sh.lang.eval(sh, states, cmd),
+        };
+        output = match res {
+            Ok(output) => output,
+            Err(e) => {
+                states
+                    .get_mut::<OutputWriter>()
+                    .eprintln(format!("{file}: line {line}: {e}"))?;
+                CmdOutput::error()
+            },
+        };
+    }
+}
+
+/// Splits a script into commands, which may span several lines
+struct ScriptCommands<'a> {
+    lines: Enumerate<Lines<'a>>,
+}
+
+impl<'a> ScriptCommands<'a> {
+    fn new(contents: &'a str) -> Self {
+        Self {
+            lines: contents.lines().enumerate(),
+        }
+    }
+
+    /// Next command and the line it starts on, using `check` to find where the command ends
+    ///
+    /// A syntax error is returned with the line it was found on.
+    fn next_command(
+        &mut self,
+        check: impl Fn(&str) -> SyntaxCheck,
+    ) -> Option<Result<(usize, String), (usize, String)>> {
+        let mut cmd = String::new();
+        let mut start = 0;
+        let mut last = 0;
+
+        for (i, line) in self.lines.by_ref() {
+            if cmd.is_empty() && line.trim().is_empty() {
+                continue;
+            }
+            if cmd.is_empty() {
+                start = i + 1;
+            }
+            last = i + 1;
+
+            // a backslash at the end of the line continues the command on the next line
+            if let Some(line) = line.strip_suffix('\\') {
+                cmd.push_str(line);
+                continue;
+            }
+            cmd.push_str(line);
+            cmd.push('\n');
+
+            match check(&cmd) {
+                SyntaxCheck::Complete => return Some(Ok((start, cmd))),
+                SyntaxCheck::Incomplete => {},
+                SyntaxCheck::Error(e) => return Some(Err((last, e))),
+            }
+        }
+
+        if cmd.is_empty() {
+            return None;
+        }
+        match check(&cmd) {
+            SyntaxCheck::Complete => Some(Ok((start, cmd))),
+            SyntaxCheck::Incomplete => Some(Err((last, "unexpected end of file".to_string()))),
+            SyntaxCheck::Error(e) => Some(Err((last, e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shrs_lang::{Lexer, Parser, ParserError};
+
+    use super::ScriptCommands;
+    use crate::lang::SyntaxCheck;
+
+    /// Stand-in for the posix language
+    fn check(cmd: &str) -> SyntaxCheck {
+        match Parser::default().parse(Lexer::new(cmd)) {
+            Ok(_) => SyntaxCheck::Complete,
+            Err(ParserError::UnexpectedEof) => SyntaxCheck::Incomplete,
+            Err(ParserError::Syntax(e)) => SyntaxCheck::Error(e.to_string()),
+        }
+    }
+
+    fn commands(script: &str) -> Vec<Result<(usize, String), (usize, String)>> {
+        let mut commands = ScriptCommands::new(script);
+        std::iter::from_fn(|| commands.next_command(check)).collect()
+    }
+
+    #[test]
+    fn line_numbers() {
+        let script = "echo a\n\nif true; then\n  echo b\nfi\necho c \\\n  d\n";
+        assert_eq!(
+            commands(script),
+            [
+                Ok((1, "echo a\n".to_string())),
+                Ok((3, "if true; then\n  echo b\nfi\n".to_string())),
+                Ok((6, "echo c   d\n".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let errors = commands("echo a\necho b\necho c; fi\necho d\n");
+        assert_eq!(
+            errors[2],
+            Err((
+                3,
+                "unexpected `fi` at line 1, column 9\necho c; fi\n        ^^".to_string()
+            ))
+        );
 
-            todo!()
-        },
+        let errors = commands("echo a\nif true; then\n  echo b\n");
+        assert_eq!(errors[1], Err((3, "unexpected end of file".to_string())));
     }
 }
@@ -0,0 +1,13 @@
+//! Builtins that do nothing but exit with a status
+
+use crate::prelude::CmdOutput;
+
+/// `:` and `true`, which ignore their arguments and succeed
+pub fn true_builtin(_args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+    Ok(CmdOutput::success())
+}
+
+/// `false`, which ignores its arguments and fails
+pub fn false_builtin(_args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+    Ok(CmdOutput::error())
+}
@@ -0,0 +1,136 @@
+use clap::Parser;
+use shrs_lang::unset_function;
+
+use crate::{
+    prelude::{CmdOutput, OutputWriter, StateMut},
+    shell::Runtime,
+};
+
+#[derive(Parser)]
+struct Cli {
+    names: Vec<String>,
+    /// Only unset shell functions
+    #[arg(short, conflicts_with = "v")]
+    f: bool,
+    /// Only unset variables
+    #[arg(short)]
+    v: bool,
+}
+
+pub fn unset_builtin(
+    mut rt: StateMut<Runtime>,
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let cli = Cli::try_parse_from(args)?;
+
+    let mut missing = false;
+    for name in cli.names.iter() {
+        if !cli.f && rt.env.get(name).is_ok() {
+            rt.env.remove(name)?;
+            continue;
+        }
+
+        if cli.v {
+            out.eprintln(format!("unset: {name}: not a variable"))?;
+            missing = true;
+            continue;
+        }
+        // without -f, a function is only removed if there is no variable of the same name
+        if !unset_function(name) && cli.f {
+            out.eprintln(format!("unset: {name}: not a function"))?;
+            missing = true;
+        }
+    }
+
+    if missing {
+        return Ok(CmdOutput::error());
+    }
+    Ok(CmdOutput::success())
+}
+
+#[cfg(test)]
+mod tests {
+    use shrs_job::JobManager;
+    use shrs_lang::{function_names, Lexer, NoBuiltins, Parser, ShellOptions};
+
+    use crate::{
+        prelude::*,
+        shell::{Runtime, Shell},
+    };
+
+    fn states() -> States {
+        let mut states = States::default();
+        states.insert(Runtime::stub("/"));
+        states.insert(OutputWriter::default());
+        states
+    }
+
+    /// Run `unset` with the arguments, returning if it succeeded
+    fn unset(states: &States, args: &[&str]) -> bool {
+        let sh = Shell::stub();
+        let args = ["unset"]
+            .iter()
+            .chain(args)
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        sh.builtins
+            .get("unset")
+            .unwrap()
+            .run(&sh, states, &args)
+            .unwrap()
+            .status
+            .success()
+    }
+
+    fn define_function(name: &str) {
+        let line = format!("{name}() {{ true; }}");
+        shrs_lang::eval(
+            &mut JobManager::default(),
+            &ShellOptions::default(),
+            &mut NoBuiltins,
+            Parser::default(),
+            Lexer::new(&line),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn unset_var() {
+        let states = states();
+        states
+            .get_mut::<Runtime>()
+            .env
+            .set("SHRS_UNSET_VAR", "val")
+            .unwrap();
+
+        assert!(unset(&states, &["SHRS_UNSET_VAR"]));
+        assert!(states.get::<Runtime>().env.get("SHRS_UNSET_VAR").is_err());
+    }
+
+    #[test]
+    fn unset_function() {
+        let states = states();
+        define_function("shrs_unset_fn");
+
+        // a variable of the same name is removed first
+        states
+            .get_mut::<Runtime>()
+            .env
+            .set("shrs_unset_fn", "val")
+            .unwrap();
+        assert!(unset(&states, &["shrs_unset_fn"]));
+        assert!(function_names().contains(&"shrs_unset_fn".to_string()));
+
+        assert!(unset(&states, &["-f", "shrs_unset_fn"]));
+        assert!(!function_names().contains(&"shrs_unset_fn".to_string()));
+    }
+
+    #[test]
+    fn unset_unknown() {
+        let states = states();
+        assert!(unset(&states, &["shrs_unset_unknown"]));
+        assert!(!unset(&states, &["-f", "shrs_unset_unknown"]));
+        assert!(!unset(&states, &["-v", "shrs_unset_unknown"]));
+    }
+}
@@ -0,0 +1,32 @@
+//! Builtin to report the version of shrs the shell was built with
+
+use clap::Parser;
+
+use crate::{
+    prelude::{CmdOutput, OutputWriter, State, StateMut},
+    shell::BuildInfo,
+};
+
+#[derive(Parser)]
+struct Cli {
+    /// Also display build information
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+pub fn version_builtin(
+    build_info: State<BuildInfo>,
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let cli = Cli::try_parse_from(args)?;
+
+    out.println(format!("shrs {}", build_info.version))?;
+    if cli.verbose {
+        let profile = if build_info.debug { "debug" } else { "release" };
+        out.println(format!("target: {}-{}", build_info.arch, build_info.os))?;
+        out.println(format!("profile: {profile}"))?;
+    }
+
+    Ok(CmdOutput::success())
+}
@@ -0,0 +1,152 @@
+//! Builtin to find out what a command name resolves to
+
+use std::{os::unix::fs::PermissionsExt, path::PathBuf};
+
+use clap::Parser;
+use shrs_lang::function_names;
+
+use crate::{
+    prelude::{Alias, CmdOutput, OutputWriter, Runtime, State, StateMut},
+    shell::Shell,
+};
+
+#[derive(Parser)]
+struct Cli {
+    /// Print every match instead of only the one that is used
+    #[arg(short, long)]
+    all: bool,
+    names: Vec<String>,
+}
+
+/// What a command name resolves to, in the order the shell looks them up
+#[derive(Debug, PartialEq, Eq)]
+enum Resolution {
+    Alias(String),
+    Function,
+    Builtin,
+    Executable(PathBuf),
+}
+
+fn resolve(name: &str, sh: &Shell, alias: &Alias, path: &str) -> Vec<Resolution> {
+    let mut resolutions = vec![];
+
+    if let Some(subst) = alias.get_subst(&name.to_string()) {
+        resolutions.push(Resolution::Alias(subst.clone()));
+    }
+    if function_names().iter().any(|function| function == name) {
+        resolutions.push(Resolution::Function);
+    }
+    if sh.builtins.builtins.contains_key(name) {
+        resolutions.push(Resolution::Builtin);
+    }
+    resolutions.extend(
+        find_executables(name, path)
+            .into_iter()
+            .map(Resolution::Executable),
+    );
+
+    resolutions
+}
+
+/// Every executable file with the given name in the directories of PATH
+fn find_executables(name: &str, path: &str) -> Vec<PathBuf> {
+    path.split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join(name))
+        .filter(|file| {
+            file.metadata()
+                .is_ok_and(|md| md.is_file() && md.permissions().mode() & 0o111 != 0)
+        })
+        .collect()
+}
+
+pub fn which_builtin(
+    alias: State<Alias>,
+    rt: State<Runtime>,
+    mut out: StateMut<OutputWriter>,
+    sh: &Shell,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let cli = Cli::try_parse_from(args)?;
+    let path = rt.env.get("PATH").cloned().unwrap_or_default();
+
+    let mut all_found = true;
+    for name in cli.names.iter() {
+        let mut resolutions = resolve(name, sh, &alias, &path);
+        if resolutions.is_empty() {
+            out.eprintln(format!("which: no {name} in ({path})"))?;
+            all_found = false;
+            continue;
+        }
+        if !cli.all {
+            resolutions.truncate(1);
+        }
+
+        for resolution in resolutions {
+            match resolution {
+                Resolution::Alias(subst) => out.println(format!("{name}: aliased to {subst}"))?,
+                Resolution::Function => out.println(format!("{name}: shell function"))?,
+                Resolution::Builtin => out.println(format!("{name}: shell builtin"))?,
+                Resolution::Executable(file) => out.println(file.display())?,
+            }
+        }
+    }
+
+    if all_found {
+        Ok(CmdOutput::success())
+    } else {
+        Ok(CmdOutput::error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use shrs_job::JobManager;
+    use shrs_lang::{Lexer, NoBuiltins, Parser, ShellOptions};
+
+    use super::{find_executables, resolve, Resolution};
+    use crate::{prelude::Alias, shell::Shell};
+
+    #[test]
+    fn function() {
+        shrs_lang::eval(
+            &mut JobManager::default(),
+            &ShellOptions::default(),
+            &mut NoBuiltins,
+            Parser::default(),
+            Lexer::new("shrs_which_fn() { true; }"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            resolve("shrs_which_fn", &Shell::stub(), &Alias::new(), ""),
+            vec![Resolution::Function]
+        );
+    }
+
+    #[test]
+    fn executables_in_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second) = (dir.path().join("first"), dir.path().join("second"));
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        for (file, mode) in [
+            (first.join("tool"), 0o755),
+            (second.join("tool"), 0o755),
+            (first.join("data"), 0o644),
+        ] {
+            fs::write(&file, "").unwrap();
+            fs::set_permissions(&file, fs::Permissions::from_mode(mode)).unwrap();
+        }
+        let path = format!("{}::{}", first.display(), second.display());
+
+        assert_eq!(
+            find_executables("tool", &path),
+            vec![first.join("tool"), second.join("tool")]
+        );
+        assert!(find_executables("data", &path).is_empty());
+        assert!(find_executables("missing", &path).is_empty());
+    }
+}
@@ -1,15 +1,31 @@
 //! Describes the output of a command
 
-use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+use std::{
+    os::unix::process::ExitStatusExt,
+    process::{ExitStatus, Output},
+};
 /// Describes the output of a command
 ///
 /// Command output is used by shell builtins as well as shell languages to pass return state of
 /// commands or programs. This captures stdout and stderr output, as well as exit code
+///
+/// The captured output is limited in size, see [`crate::prelude::OutputWriter`], so it may be
+/// truncated for commands that print a lot.
+///
+/// Output is not required to be valid UTF-8. The `stdout` and `stderr` strings have invalid
+/// sequences replaced with `U+FFFD`, while `stdout_bytes` and `stderr_bytes` hold the exact bytes
+/// for consumers that need them, such as when writing the output to a file.
 #[derive(Clone, Debug)]
 pub struct CmdOutput {
     pub stdout: String,
     pub stderr: String,
+    /// Exact bytes of stdout
+    pub stdout_bytes: Vec<u8>,
+    /// Exact bytes of stderr
+    pub stderr_bytes: Vec<u8>,
     pub status: ExitStatus,
+    /// If the captured stdout or stderr was cut off because it went over the capture limit
+    pub truncated: bool,
 }
 
 impl CmdOutput {
@@ -18,7 +34,10 @@ impl CmdOutput {
         CmdOutput {
             stdout: String::new(),
             stderr: String::new(),
+            stdout_bytes: vec![],
+            stderr_bytes: vec![],
             status: ExitStatus::from_raw(status << 8),
+            truncated: false,
         }
     }
 
@@ -35,12 +54,61 @@ impl CmdOutput {
     // Set the stdout
     pub fn stdout<S: ToString>(&mut self, stdout: S) -> &mut Self {
         self.stdout = stdout.to_string();
+        self.stdout_bytes = self.stdout.as_bytes().to_vec();
         self
     }
 
     // Set the stderr
     pub fn stderr<S: ToString>(&mut self, stderr: S) -> &mut Self {
         self.stderr = stderr.to_string();
+        self.stderr_bytes = self.stderr.as_bytes().to_vec();
+        self
+    }
+
+    /// Set the stdout from bytes that may not be valid UTF-8
+    pub fn stdout_bytes(&mut self, stdout: Vec<u8>) -> &mut Self {
+        self.stdout = String::from_utf8_lossy(&stdout).into_owned();
+        self.stdout_bytes = stdout;
+        self
+    }
+
+    /// Set the stderr from bytes that may not be valid UTF-8
+    pub fn stderr_bytes(&mut self, stderr: Vec<u8>) -> &mut Self {
+        self.stderr = String::from_utf8_lossy(&stderr).into_owned();
+        self.stderr_bytes = stderr;
         self
     }
 }
+
+impl From<Output> for CmdOutput {
+    fn from(output: Output) -> Self {
+        let mut cmd_output = CmdOutput {
+            status: output.status,
+            ..CmdOutput::success()
+        };
+        cmd_output
+            .stdout_bytes(output.stdout)
+            .stderr_bytes(output.stderr);
+        cmd_output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+    use super::CmdOutput;
+
+    #[test]
+    fn invalid_utf8() {
+        let output = CmdOutput::from(std::process::Output {
+            status: ExitStatus::from_raw(0),
+            stdout: b"caf\xe9\n".to_vec(),
+            stderr: b"ok".to_vec(),
+        });
+        assert_eq!(output.stdout, "caf\u{FFFD}\n");
+        assert_eq!(output.stdout_bytes, b"caf\xe9\n");
+        assert_eq!(output.stderr, "ok");
+        assert_eq!(output.stderr_bytes, b"ok");
+    }
+}
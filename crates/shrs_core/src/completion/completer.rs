@@ -1,16 +1,24 @@
 //! Implementation of default rule based completer
 
-use std::path::{Path, PathBuf};
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+use shrs_lang::{function_description, function_names};
 
 use super::{
-    data::*, drop_path_end, filepaths, find_executables_in_path, Completer, Completion,
-    CompletionCtx, ReplaceMethod,
+    data::*, drop_path_end, filepaths, fuzzy_match, user_home_dir, usernames, AcceptedCompletions,
+    Completer, Completion, CompletionCtx, PathExecCache, ReplaceMethod,
 };
 use crate::prelude::Builtins;
 
-// TODO make this FnMut?
 /// Actions return a list of possible completions
-pub type Action = Box<dyn Fn(&CompletionCtx) -> Vec<Completion>>;
+///
+/// Actions may hold mutable state, such as a cache of expensive lookups.
+pub type Action = Box<dyn FnMut(&CompletionCtx) -> Vec<Completion>>;
 
 /// Predicates are functions that take in some context and return a boolean
 pub struct Pred {
@@ -47,75 +55,171 @@ pub struct Rule {
     pub pred: Pred,
     /// Action to execute if predicate is satisfied
     pub completions: Action,
+    /// Only use this rule if no other (non fallback) rule matched
+    pub fallback: bool,
     // pub filter: Filter,
     // pub format: Format,
 }
 
 impl Rule {
-    pub fn new(pred: Pred, action: impl Fn(&CompletionCtx) -> Vec<Completion> + 'static) -> Self {
+    pub fn new(
+        pred: Pred,
+        action: impl FnMut(&CompletionCtx) -> Vec<Completion> + 'static,
+    ) -> Self {
         Self {
             pred,
             completions: Box::new(action),
+            fallback: false,
             // filter:
             // format: Box::new(default_format),
         }
     }
 
+    /// Construct a low priority rule that is only used when no other rule matches
+    pub fn fallback(
+        pred: Pred,
+        action: impl FnMut(&CompletionCtx) -> Vec<Completion> + 'static,
+    ) -> Self {
+        Self {
+            fallback: true,
+            ..Self::new(pred, action)
+        }
+    }
+
     // TODO this could maybe be rewritten as a builder pattern
 }
 
+/// How completions are matched against the word currently being typed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    /// Completion must start with the current word
+    #[default]
+    Prefix,
+    /// Characters of the current word must appear in order in the completion, see
+    /// [fuzzy_match]. Completions are sorted from best to worst match
+    Fuzzy,
+}
+
 /// Default rule-based completion system
 pub struct DefaultCompleter {
     rules: Vec<Rule>,
+    /// Ignore case when matching completions against the current word
+    case_insensitive: bool,
+    match_mode: MatchMode,
+    /// Completions accepted before are ranked higher
+    accepted: AcceptedCompletions,
 }
 
 impl DefaultCompleter {
     pub fn new() -> Self {
-        Self { rules: vec![] }
+        Self {
+            rules: vec![],
+            case_insensitive: false,
+            match_mode: MatchMode::Prefix,
+            accepted: AcceptedCompletions::default(),
+        }
     }
 
-    fn complete_helper(&self, ctx: &CompletionCtx) -> Vec<Completion> {
-        let rules: Vec<&Rule> = self.rules.iter().filter(|p| (p.pred).test(ctx)).collect();
+    /// Use a custom store of previously accepted completions, for example one that is persisted
+    /// with [AcceptedCompletions::persisted]
+    pub fn accepted_completions(mut self, accepted: AcceptedCompletions) -> Self {
+        self.accepted = accepted;
+        self
+    }
 
-        let mut output = vec![];
-        //if no rules were matched, default to files in the current folder
+    /// Change how completions are matched against the current word
+    pub fn match_mode(mut self, match_mode: MatchMode) -> Self {
+        self.match_mode = match_mode;
+        self
+    }
+
+    /// Match completions against the current word regardless of case, so `READ` completes
+    /// `README.md`
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Score how well a completion matches the word currently being typed, [None] if it doesn't
+    /// match at all
+    fn match_score(&self, completion: &Completion, ctx: &CompletionCtx) -> Option<i64> {
+        let cur_word = ctx.cur_word().map(String::as_str).unwrap_or_default();
+        let accept = completion.accept();
+        match self.match_mode {
+            MatchMode::Prefix => {
+                let matches = match self.case_insensitive {
+                    true => accept.to_lowercase().starts_with(&cur_word.to_lowercase()),
+                    false => accept.starts_with(cur_word),
+                };
+                matches.then_some(0)
+            },
+            MatchMode::Fuzzy => {
+                fuzzy_match(cur_word, &accept, self.case_insensitive).map(|m| m.score)
+            },
+        }
+    }
+
+    /// Only keep completions that match the current word
+    ///
+    /// Completions that were accepted the most in this context come first, followed by the best
+    /// matches, and the rest are sorted alphabetically.
+    fn filter_matches(&self, completions: Vec<Completion>, ctx: &CompletionCtx) -> Vec<Completion> {
+        let mut scored = completions
+            .into_iter()
+            .filter_map(|c| {
+                let score = self.match_score(&c, ctx)?;
+                let accepted = self.accepted.count(ctx, &c.completion);
+                Some((
+                    (
+                        Reverse(accepted),
+                        Reverse(score),
+                        c.display().to_lowercase(),
+                    ),
+                    c,
+                ))
+            })
+            .collect::<Vec<_>>();
+        scored.sort_by(|a, b| a.0.cmp(&b.0));
+        scored.into_iter().map(|(_, c)| c).collect()
+    }
+
+    fn complete_helper(&mut self, ctx: &CompletionCtx) -> Vec<Completion> {
+        let (fallback_rules, mut rules): (Vec<&mut Rule>, Vec<&mut Rule>) = self
+            .rules
+            .iter_mut()
+            .filter(|p| (p.pred).test(ctx))
+            .partition(|p| p.fallback);
         if rules.is_empty() {
-            return filename_action(ctx)
-                .into_iter()
-                .filter(|s| {
-                    s.accept()
-                        .starts_with(ctx.cur_word().unwrap_or(&String::new()))
-                })
-                // .map(|s| (rule.format)(s))
-                .collect::<Vec<_>>();
+            rules = fallback_rules;
         }
 
-        for rule in rules {
-            // if rule was matched, run the corresponding action
-            // also do prefix search (could make if prefix search is used a config option)
-            let mut comps = (rule.completions)(ctx)
-                .into_iter()
-                .filter(|s| {
-                    s.accept()
-                        .starts_with(ctx.cur_word().unwrap_or(&String::new()))
-                })
-                // .map(|s| (rule.format)(s))
-                .collect::<Vec<_>>();
-
-            output.append(&mut comps);
+        //if no rules were matched, default to files in the current folder
+        if rules.is_empty() {
+            return self.filter_matches(filename_action(ctx), ctx);
         }
-        output
+
+        // if rule was matched, run the corresponding action
+        let output = rules
+            .into_iter()
+            .flat_map(|rule| (rule.completions)(ctx))
+            .collect::<Vec<_>>();
+        self.filter_matches(output, ctx)
     }
 }
 
 impl Completer for DefaultCompleter {
-    fn complete(&self, ctx: &CompletionCtx) -> Vec<Completion> {
+    fn complete(&mut self, ctx: &CompletionCtx) -> Vec<Completion> {
         self.complete_helper(ctx)
     }
     /// Register a new rule to use
     fn register(&mut self, rule: Rule) {
         self.rules.push(rule);
     }
+    fn accepted(&mut self, ctx: &CompletionCtx, completion: &Completion) {
+        if let Err(e) = self.accepted.record(ctx, &completion.completion) {
+            warn!("Failed to save accepted completions {e}");
+        }
+    }
 }
 
 impl Default for DefaultCompleter {
@@ -137,24 +241,86 @@ impl Default for DefaultCompleter {
 }
 
 /// Return all the executables in PATH
-pub fn cmdname_action(path_str: String) -> impl Fn(&CompletionCtx) -> Vec<Completion> {
-    move |_ctx: &CompletionCtx| -> Vec<Completion> {
-        default_format(find_executables_in_path(&path_str))
-    }
+///
+/// The executables are read from the cache, which is refreshed by the `rehash` builtin.
+pub fn cmdname_action(cache: PathExecCache) -> impl Fn(&CompletionCtx) -> Vec<Completion> {
+    move |_ctx: &CompletionCtx| -> Vec<Completion> { default_format(cache.executables()) }
 }
 
-/// Return all the builtin command names
+/// Return all the builtin command names, described with their usage
 pub fn builtin_cmdname_action(builtin: &Builtins) -> impl Fn(&CompletionCtx) -> Vec<Completion> {
-    let builtin_names = builtin
+    let builtins = builtin
         .iter()
-        .map(|(name, _)| name.to_owned().to_string())
+        .map(|(name, builtin)| {
+            let hint = [builtin.usage(), builtin.description()]
+                .into_iter()
+                .find(|hint| !hint.is_empty())
+                .map(str::to_string);
+            (name.to_string(), hint)
+        })
         .collect::<Vec<_>>();
-    move |_ctx: &CompletionCtx| -> Vec<Completion> { default_format(builtin_names.clone()) }
+    move |_ctx: &CompletionCtx| -> Vec<Completion> {
+        builtins
+            .iter()
+            .map(|(name, hint)| Completion {
+                description: hint.clone(),
+                ..default_format(vec![name.clone()]).remove(0)
+            })
+            .collect()
+    }
+}
+
+/// Complete the names of shell functions starting with the current word, described with the
+/// comment documenting the function
+pub fn function_completions(cur_word: &str) -> Vec<Completion> {
+    function_names()
+        .into_iter()
+        .filter(|name| name.starts_with(cur_word))
+        .map(|name| Completion {
+            description: function_description(&name),
+            ..default_format(vec![name]).remove(0)
+        })
+        .collect()
+}
+
+/// Complete arguments previously passed to the command being completed, most recent first
+///
+/// Only history entries that ran the same command are used, so after `ssh myhost` completing the
+/// arguments of `ssh` offers `myhost`. The completions are commented with `history`.
+pub fn history_arg_completions(history: &[String], ctx: &CompletionCtx) -> Vec<Completion> {
+    let (Some(cmd_name), Some(cur_word)) = (ctx.cmd_name(), ctx.cur_word()) else {
+        return vec![];
+    };
+    if ctx.arg_num() == 0 {
+        return vec![];
+    }
+
+    let mut seen = HashSet::new();
+    history
+        .iter()
+        .filter_map(|entry| {
+            let mut words = entry.split_whitespace();
+            (words.next() == Some(cmd_name.as_str())).then_some(words)
+        })
+        .flatten()
+        .filter(|arg| arg.starts_with(cur_word.as_str()) && arg != cur_word)
+        .filter(|arg| seen.insert(*arg))
+        .map(|arg| Completion {
+            comment: Some("history".to_string()),
+            ..default_format(vec![arg.to_string()]).remove(0)
+        })
+        .collect()
 }
 
 /// Look in current directory for potential filenames to complete
+///
+/// A word starting with `~` that has no slash yet is completed to a user's home directory.
 pub fn filename_action(ctx: &CompletionCtx) -> Vec<Completion> {
     let cur_word = ctx.cur_word().unwrap();
+    if cur_word.starts_with('~') && !cur_word.contains('/') {
+        return tilde_user_action(ctx);
+    }
+
     let drop_end = drop_path_end(cur_word);
     let cur_path = to_absolute(&drop_end, &dirs::home_dir().unwrap());
 
@@ -179,6 +345,25 @@ pub fn filename_action(ctx: &CompletionCtx) -> Vec<Completion> {
                 completion: drop_end.to_owned() + &filename,
                 replace_method: ReplaceMethod::Replace,
                 comment: None,
+                description: None,
+            }
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Complete `~user/` for every user in the passwd database
+pub fn tilde_user_action(_ctx: &CompletionCtx) -> Vec<Completion> {
+    usernames()
+        .into_iter()
+        .map(|name| {
+            let completion = format!("~{name}/");
+            Completion {
+                add_space: false,
+                display: Some(completion.clone()),
+                completion,
+                replace_method: ReplaceMethod::Replace,
+                comment: None,
+                description: None,
             }
         })
         .collect::<Vec<_>>()
@@ -192,10 +377,12 @@ fn to_absolute(path_str: &str, home_dir: &Path) -> PathBuf {
         path_buf
     } else {
         // handle home directory tilde
-        // TODO ~username/ not yet handled
         if let Ok(stripped) = path_buf.strip_prefix("~/") {
             home_dir.join(stripped)
+        } else if let Some(user_home) = expand_tilde_user(path_str) {
+            user_home
         } else {
+            // unknown users are treated as a literal path
             std::env::current_dir().unwrap().join(path_buf)
         }
     };
@@ -203,6 +390,16 @@ fn to_absolute(path_str: &str, home_dir: &Path) -> PathBuf {
     absolute
 }
 
+/// Expand `~username/rest` using the home directory of the user, [None] if the user doesn't exist
+fn expand_tilde_user(path_str: &str) -> Option<PathBuf> {
+    let rest = path_str.strip_prefix('~')?;
+    let (user, rest) = rest.split_once('/').unwrap_or((rest, ""));
+    if user.is_empty() {
+        return None;
+    }
+    Some(user_home_dir(user)?.join(rest))
+}
+
 /// Check if we are completing the command name
 pub fn cmdname_pred(ctx: &CompletionCtx) -> bool {
     ctx.arg_num() == 0
@@ -254,6 +451,7 @@ pub fn default_format(s: Vec<String>) -> Vec<Completion> {
             completion: x.to_owned(),
             replace_method: ReplaceMethod::Replace,
             comment: None,
+            description: None,
         })
         .collect::<Vec<_>>()
 }
@@ -266,6 +464,17 @@ pub fn default_format_with_comment(s: Vec<(String, String)>) -> Vec<Completion>
             completion: x.0.to_string(),
             replace_method: ReplaceMethod::Replace,
             comment: Some(x.1.to_string()),
+            description: None,
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Construct a [Completion] from pairs of completion and description
+pub fn default_format_with_description(s: Vec<(String, String)>) -> Vec<Completion> {
+    s.into_iter()
+        .map(|(completion, description)| Completion {
+            description: Some(description),
+            ..default_format(vec![completion]).remove(0)
         })
         .collect::<Vec<_>>()
 }
@@ -284,8 +493,16 @@ fn sanitize_file_name(filename: String) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::{flag_pred, DefaultCompleter};
-    use crate::completion::CompletionCtx;
+    use std::path::Path;
+
+    use shrs_job::JobManager;
+    use shrs_lang::{Lexer, NoBuiltins, Parser, ShellOptions};
+
+    use super::{
+        default_format, flag_pred, function_completions, history_arg_completions, to_absolute,
+        DefaultCompleter, MatchMode, Pred, Rule,
+    };
+    use crate::completion::{user_home_dir, Completer, CompletionCtx};
 
     #[test]
     fn simple() {
@@ -293,6 +510,49 @@ mod tests {
         // comp.register(Rule::new());
     }
 
+    #[test]
+    fn function_description() {
+        let line = "# long listing\nshrs_completer_ll() { ls -l; }\nshrs_completer_la() { ls -a; }";
+        shrs_lang::eval(
+            &mut JobManager::default(),
+            &ShellOptions::default(),
+            &mut NoBuiltins,
+            Parser::default(),
+            Lexer::new(line),
+        )
+        .unwrap();
+
+        let completions = function_completions("shrs_completer_l");
+        assert_eq!(completions.len(), 2);
+        assert_eq!(completions[0].completion, "shrs_completer_la");
+        assert_eq!(completions[0].description, None);
+        assert_eq!(completions[1].completion, "shrs_completer_ll");
+        assert_eq!(completions[1].description.as_deref(), Some("long listing"));
+    }
+
+    #[test]
+    fn history_args() {
+        let history = [
+            "ssh otherhost",
+            "git push",
+            "ssh myhost -v",
+            "scp myfile host:",
+        ]
+        .map(String::from);
+        let completions = |line: &str| {
+            let ctx = CompletionCtx::new(line.split(' ').map(String::from).collect());
+            history_arg_completions(&history, &ctx)
+                .into_iter()
+                .map(|completion| completion.completion)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(completions("ssh "), ["otherhost", "myhost", "-v"]);
+        assert_eq!(completions("ssh m"), ["myhost"]);
+        assert_eq!(completions("ssh myhost"), Vec::<String>::new());
+        assert_eq!(completions("ss"), Vec::<String>::new());
+    }
+
     #[test]
     fn test_is_flag() {
         let ctx = CompletionCtx::new(vec!["git".into(), "-".into()]);
@@ -300,4 +560,122 @@ mod tests {
         let ctx = CompletionCtx::new(vec![]);
         assert!(!flag_pred(&ctx));
     }
+
+    #[test]
+    fn fallback_rule() {
+        let mut comp = DefaultCompleter::new();
+        comp.register(Rule::fallback(Pred::new(|_| true), |_| {
+            default_format(vec!["fallback".into()])
+        }));
+        comp.register(Rule::new(
+            Pred::new(|ctx| ctx.cmd_name().map(String::as_str) == Some("ls")),
+            |_| default_format(vec!["explicit".into()]),
+        ));
+
+        let mut complete = |line: Vec<String>| -> Vec<String> {
+            comp.complete(&CompletionCtx::new(line))
+                .iter()
+                .map(|c| c.completion.clone())
+                .collect()
+        };
+        assert_eq!(complete(vec!["ls".into(), "".into()]), vec!["explicit"]);
+        assert_eq!(complete(vec!["cat".into(), "".into()]), vec!["fallback"]);
+    }
+
+    #[test]
+    fn tilde_username() {
+        let home = Path::new("/home/me");
+        let root_home = user_home_dir("root").unwrap();
+        assert_eq!(to_absolute("~root/", home), root_home);
+        assert_eq!(to_absolute("~root/src", home), root_home.join("src"));
+        assert_eq!(to_absolute("~/src", home), home.join("src"));
+
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            to_absolute("~nosuchuser/src", home),
+            cwd.join("~nosuchuser/src")
+        );
+    }
+
+    #[test]
+    fn case_insensitive_prefix() {
+        let register = |comp: &mut DefaultCompleter| {
+            comp.register(Rule::new(Pred::new(|_| true), |_| {
+                default_format(vec!["README.md".into(), "src".into()])
+            }));
+        };
+        let ctx = CompletionCtx::new(vec!["cat".into(), "read".into()]);
+
+        let mut comp = DefaultCompleter::new();
+        register(&mut comp);
+        assert!(comp.complete(&ctx).is_empty());
+
+        let mut comp = DefaultCompleter::new().case_insensitive(true);
+        register(&mut comp);
+        let completions = comp.complete(&ctx);
+        assert_eq!(completions.len(), 1);
+        assert_eq!(completions[0].completion, "README.md");
+    }
+
+    #[test]
+    fn fuzzy_ordering() {
+        let complete = |candidates: &[&str], word: &str| -> Vec<String> {
+            let candidates = candidates.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+            let mut comp = DefaultCompleter::new().match_mode(MatchMode::Fuzzy);
+            comp.register(Rule::new(Pred::new(|_| true), move |_| {
+                default_format(candidates.clone())
+            }));
+            comp.complete(&CompletionCtx::new(vec!["cmd".into(), word.into()]))
+                .iter()
+                .map(|c| c.completion.clone())
+                .collect()
+        };
+
+        assert_eq!(
+            complete(&["config", "git-checkout", "gco"], "gco"),
+            vec!["gco", "git-checkout"]
+        );
+        assert_eq!(
+            complete(&["main.rs", "Makefile", "README.md", "mod.rs"], "mr"),
+            vec!["mod.rs", "main.rs"]
+        );
+        assert_eq!(
+            complete(&["xbuild", "build", "b-uild"], "bu"),
+            vec!["build", "b-uild", "xbuild"]
+        );
+    }
+
+    #[test]
+    fn accepted_ranked_first() {
+        let mut comp = DefaultCompleter::new();
+        comp.register(Rule::new(Pred::new(|_| true), |_| {
+            default_format(vec!["stash".into(), "status".into()])
+        }));
+        let ctx = CompletionCtx::new(vec!["git".into(), "st".into()]);
+        let complete = |comp: &mut DefaultCompleter| -> Vec<String> {
+            comp.complete(&ctx)
+                .iter()
+                .map(|c| c.completion.clone())
+                .collect()
+        };
+
+        assert_eq!(complete(&mut comp), vec!["stash", "status"]);
+        let status = comp.complete(&ctx).remove(1);
+        comp.accepted(&ctx, &status);
+        assert_eq!(complete(&mut comp), vec!["status", "stash"]);
+    }
+
+    #[test]
+    fn stateful_action() {
+        let mut comp = DefaultCompleter::new();
+        let mut calls = 0;
+        comp.register(Rule::new(Pred::new(|_| true), move |_| {
+            calls += 1;
+            default_format(vec![format!("call{calls}")])
+        }));
+        let ctx = CompletionCtx::new(vec!["cmd".into(), "".into()]);
+
+        assert_eq!(comp.complete(&ctx)[0].completion, "call1");
+        assert_eq!(comp.complete(&ctx)[0].completion, "call2");
+    }
 }
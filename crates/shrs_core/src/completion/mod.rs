@@ -10,8 +10,14 @@ pub use utils::*;
 
 mod data;
 
+mod ranking;
+pub use ranking::*;
+
+mod path_cache;
+pub use path_cache::*;
+
 /// How should the completion be substituted
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ReplaceMethod {
     /// Append the returned value after the cursor
     Append,
@@ -31,6 +37,9 @@ pub struct Completion {
     pub replace_method: ReplaceMethod,
     /// Additional helpful information about the completion
     pub comment: Option<String>,
+    /// One line explanation of what the completion does, such as the help text of a flag. Shown
+    /// dimmed after the display text
+    pub description: Option<String>,
 }
 
 impl Completion {
@@ -51,8 +60,10 @@ impl Completion {
 /// Implement this trait to define your own tab completion system
 pub trait Completer {
     /// Given context on the current state of the input, output list of possible completions
-    fn complete(&self, ctx: &CompletionCtx) -> Vec<Completion>;
+    fn complete(&mut self, ctx: &CompletionCtx) -> Vec<Completion>;
     fn register(&mut self, rule: Rule);
+    /// Called when the user accepts one of the completions returned for the given context
+    fn accepted(&mut self, _ctx: &CompletionCtx, _completion: &Completion) {}
 }
 #[derive(Clone)]
 pub struct CompletionCtx {
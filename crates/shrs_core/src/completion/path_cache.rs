@@ -0,0 +1,137 @@
+//! Cache of the executables found in PATH
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use super::find_executables_in_path;
+
+#[derive(Default)]
+struct Scan {
+    /// Value of PATH the executables were found in
+    path: String,
+    executables: Vec<String>,
+    /// When the scan happened, [None] if PATH still needs to be scanned
+    time: Option<Instant>,
+}
+
+thread_local! {
+    /// Scan shared by every [PathExecCache]
+    static SCAN: Rc<RefCell<Scan>> = Rc::default();
+}
+
+/// Executables found in PATH, shared between the completer and the shell state
+///
+/// Scanning every directory in PATH can be slow, especially on network filesystems, so the scan is
+/// only redone when PATH changes, the optional time to live elapses, or [PathExecCache::rehash] is
+/// called (for example with the `rehash` builtin after installing a new program).
+///
+/// Every cache shares the same scan, so rehashing the one in the shell's states also refreshes
+/// the one given to [crate::prelude::cmdname_action].
+#[derive(Clone)]
+pub struct PathExecCache {
+    scan: Rc<RefCell<Scan>>,
+    ttl: Option<Duration>,
+}
+
+impl Default for PathExecCache {
+    fn default() -> Self {
+        Self {
+            scan: SCAN.with(Rc::clone),
+            ttl: None,
+        }
+    }
+}
+
+impl PathExecCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rescan PATH once the cached executables are older than the given duration
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Names of the executables in PATH, scanning it if the cache is out of date
+    pub fn executables(&self) -> Vec<String> {
+        self.executables_in(&std::env::var("PATH").unwrap_or_default())
+    }
+
+    fn executables_in(&self, path: &str) -> Vec<String> {
+        let mut scan = self.scan.borrow_mut();
+
+        let expired = match (scan.time, self.ttl) {
+            (None, _) => true,
+            (Some(time), Some(ttl)) => time.elapsed() >= ttl,
+            (Some(_), None) => false,
+        };
+        if expired || scan.path != path {
+            *scan = Scan {
+                executables: find_executables_in_path(path),
+                path: path.to_string(),
+                time: Some(Instant::now()),
+            };
+        }
+
+        scan.executables.clone()
+    }
+
+    /// Forget the cached executables so PATH is scanned again on the next completion
+    pub fn rehash(&self) {
+        *self.scan.borrow_mut() = Scan::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use super::PathExecCache;
+
+    #[test]
+    fn rehash() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let new_exec = |name: &str| {
+            let file = dir.join(name);
+            fs::write(&file, "").unwrap();
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        new_exec("first");
+        let path = dir.to_str().unwrap();
+
+        let cache = PathExecCache::new();
+        assert_eq!(cache.executables_in(path), vec!["first"]);
+
+        // new executables are only found after rehashing
+        new_exec("second");
+        assert_eq!(cache.executables_in(path), vec!["first"]);
+        cache.rehash();
+        let mut execs = cache.executables_in(path);
+        execs.sort();
+        assert_eq!(execs, vec!["first", "second"]);
+
+        // changing PATH rescans
+        assert!(cache.executables_in("").is_empty());
+    }
+
+    #[test]
+    fn shared_scan() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().to_str().unwrap();
+
+        let completer_cache = PathExecCache::new();
+        let state_cache = PathExecCache::new();
+        assert!(completer_cache.executables_in(path).is_empty());
+
+        let file = tmp.path().join("new");
+        fs::write(&file, "").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+        state_cache.rehash();
+        assert_eq!(completer_cache.executables_in(path), vec!["new"]);
+    }
+}
@@ -0,0 +1,161 @@
+//! Rank completions that were accepted before higher
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::CompletionCtx;
+
+/// Remembers how many times each completion was accepted in each context, so completions that are
+/// picked often can be ranked higher
+///
+/// The context is the line before the word being completed, so accepting `status` after `git`
+/// only ranks `status` higher when completing the first argument of `git`. Only the most recently
+/// used contexts are kept.
+pub struct AcceptedCompletions {
+    /// Times each completion was accepted by context, least recently used context first
+    contexts: Vec<(String, HashMap<String, usize>)>,
+    max_contexts: usize,
+    /// File the counts are persisted to
+    file: Option<PathBuf>,
+}
+
+impl AcceptedCompletions {
+    pub fn new(max_contexts: usize) -> Self {
+        Self {
+            contexts: vec![],
+            max_contexts,
+            file: None,
+        }
+    }
+
+    /// Load the counts from a file and write them back to it whenever a completion is accepted
+    ///
+    /// The file is created when the first completion is accepted if it doesn't exist yet.
+    pub fn persisted(file: &Path, max_contexts: usize) -> Self {
+        let mut accepted = Self::new(max_contexts);
+        accepted.file = Some(file.to_path_buf());
+
+        let contents = fs::read_to_string(file).unwrap_or_default();
+        for line in contents.lines() {
+            let mut fields = line.splitn(3, '\t');
+            let (Some(count), Some(context), Some(completion)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let Ok(count) = count.parse::<usize>() else {
+                continue;
+            };
+            *accepted
+                .context_mut(context)
+                .entry(completion.to_string())
+                .or_default() += count;
+        }
+        accepted.evict();
+        accepted
+    }
+
+    /// Number of times a completion was accepted in the given context
+    pub fn count(&self, ctx: &CompletionCtx, completion: &str) -> usize {
+        let key = context_key(ctx);
+        self.contexts
+            .iter()
+            .find(|(context, _)| *context == key)
+            .and_then(|(_, counts)| counts.get(completion))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Remember that a completion was accepted, saving the counts if they are persisted
+    pub fn record(&mut self, ctx: &CompletionCtx, completion: &str) -> std::io::Result<()> {
+        let key = context_key(ctx);
+        *self
+            .context_mut(&key)
+            .entry(completion.to_string())
+            .or_default() += 1;
+        self.evict();
+        self.save()
+    }
+
+    /// Write the counts to the persisted file, no-op if they are not persisted
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let mut contents = String::new();
+        for (context, counts) in self.contexts.iter() {
+            for (completion, count) in counts.iter() {
+                contents += &format!("{count}\t{context}\t{completion}\n");
+            }
+        }
+        fs::write(file, contents)
+    }
+
+    /// Get the counts of a context, marking it as the most recently used
+    fn context_mut(&mut self, key: &str) -> &mut HashMap<String, usize> {
+        let entry = match self.contexts.iter().position(|(context, _)| context == key) {
+            Some(i) => self.contexts.remove(i),
+            None => (key.to_string(), HashMap::new()),
+        };
+        self.contexts.push(entry);
+        &mut self.contexts.last_mut().unwrap().1
+    }
+
+    /// Forget the least recently used contexts once there are too many
+    fn evict(&mut self) {
+        let excess = self.contexts.len().saturating_sub(self.max_contexts);
+        self.contexts.drain(..excess);
+    }
+}
+
+impl Default for AcceptedCompletions {
+    fn default() -> Self {
+        Self::new(500)
+    }
+}
+
+/// Words before the one currently being completed
+fn context_key(ctx: &CompletionCtx) -> String {
+    ctx.line[..ctx.arg_num()].join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AcceptedCompletions;
+    use crate::prelude::CompletionCtx;
+
+    fn ctx(line: &[&str]) -> CompletionCtx {
+        CompletionCtx::new(line.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn bounded_contexts() {
+        let mut accepted = AcceptedCompletions::new(2);
+        accepted.record(&ctx(&["git", "st"]), "status").unwrap();
+        accepted.record(&ctx(&["git", "sta"]), "status").unwrap();
+        accepted.record(&ctx(&["cargo", "b"]), "build").unwrap();
+        assert_eq!(accepted.count(&ctx(&["git", ""]), "status"), 2);
+        assert_eq!(accepted.count(&ctx(&["git", "add", ""]), "status"), 0);
+
+        // git was used less recently than cargo
+        accepted.record(&ctx(&["ls", "-"]), "-a").unwrap();
+        assert_eq!(accepted.count(&ctx(&["git", ""]), "status"), 0);
+        assert_eq!(accepted.count(&ctx(&["cargo", ""]), "build"), 1);
+    }
+
+    #[test]
+    fn persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("accepted");
+
+        let mut accepted = AcceptedCompletions::persisted(&file, 10);
+        accepted.record(&ctx(&["git", "st"]), "status").unwrap();
+        accepted.record(&ctx(&["git", "st"]), "status").unwrap();
+
+        let reloaded = AcceptedCompletions::persisted(&file, 10);
+        assert_eq!(reloaded.count(&ctx(&["git", ""]), "status"), 2);
+    }
+}
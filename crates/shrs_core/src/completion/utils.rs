@@ -53,6 +53,87 @@ pub(crate) fn find_executables_in_path(path_str: &str) -> Vec<String> {
     execs
 }
 
+/// Names of all users in the passwd database
+pub(crate) fn usernames() -> Vec<String> {
+    let passwd = std::fs::read_to_string("/etc/passwd").unwrap_or_default();
+    parse_passwd_usernames(&passwd)
+}
+
+fn parse_passwd_usernames(passwd: &str) -> Vec<String> {
+    passwd
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split(':').next())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Home directory of a user
+pub(crate) fn user_home_dir(name: &str) -> Option<PathBuf> {
+    nix::unistd::User::from_name(name)
+        .ok()
+        .flatten()
+        .map(|user| user.dir)
+}
+
+/// Result of fuzzy matching a pattern against a candidate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// Higher scores are better matches
+    pub score: i64,
+    /// Character indices of the candidate that matched the pattern
+    pub indices: Vec<usize>,
+}
+
+/// Check if every character of the pattern appears in the candidate in order, so `gco` matches
+/// `git-checkout`
+///
+/// Matches at the start of the candidate or of a word, and consecutive matches score higher,
+/// while characters skipped between matches lower the score.
+pub fn fuzzy_match(pattern: &str, candidate: &str, case_insensitive: bool) -> Option<FuzzyMatch> {
+    const MATCH_SCORE: i64 = 16;
+    const CONSECUTIVE_BONUS: i64 = 16;
+    const BOUNDARY_BONUS: i64 = 16;
+
+    let fold = |c: char| match case_insensitive {
+        true => c.to_lowercase().next().unwrap_or(c),
+        false => c,
+    };
+    let mut pattern = pattern.chars().map(fold).peekable();
+    let mut score = 0;
+    let mut indices: Vec<usize> = vec![];
+    let mut prev_char = None;
+
+    for (i, c) in candidate.chars().enumerate() {
+        let Some(&p) = pattern.peek() else {
+            break;
+        };
+        if fold(c) == p {
+            pattern.next();
+            score += MATCH_SCORE;
+
+            let gap = match indices.last() {
+                Some(prev) => i - prev - 1,
+                None => i,
+            };
+            if gap == 0 && !indices.is_empty() {
+                score += CONSECUTIVE_BONUS;
+            }
+            if prev_char.is_none_or(|prev| matches!(prev, '-' | '_' | '.' | '/' | ' ')) {
+                score += BOUNDARY_BONUS;
+            }
+            score -= gap as i64;
+            indices.push(i);
+        }
+        prev_char = Some(c);
+    }
+
+    match pattern.peek() {
+        Some(_) => None,
+        None => Some(FuzzyMatch { score, indices }),
+    }
+}
+
 /// Drop everything after the last / character
 pub(crate) fn drop_path_end(path: &str) -> String {
     let drop_end = path
@@ -74,7 +155,7 @@ pub(crate) fn drop_path_end(path: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::drop_path_end;
+    use super::{drop_path_end, fuzzy_match, parse_passwd_usernames};
 
     #[test]
     fn test_drop_path_end() {
@@ -82,4 +163,22 @@ mod tests {
         assert_eq!(drop_path_end("Downloads/"), "Downloads/".to_owned());
         assert_eq!(drop_path_end("Downloads"), "".to_owned());
     }
+
+    #[test]
+    fn test_fuzzy_match() {
+        let m = fuzzy_match("gco", "git-checkout", false).unwrap();
+        assert_eq!(m.indices, vec![0, 4, 9]);
+        assert!(fuzzy_match("gco", "git-commit", false).is_some());
+        assert!(fuzzy_match("gco", "cog", false).is_none());
+        assert!(fuzzy_match("RM", "readme", false).is_none());
+        assert!(fuzzy_match("RM", "readme", true).is_some());
+        assert!(fuzzy_match("", "anything", false).is_some());
+    }
+
+    #[test]
+    fn test_parse_passwd_usernames() {
+        let passwd =
+            "# comment\nroot:x:0:0:root:/root:/bin/sh\n\nalice:x:1000:1000::/home/alice:/bin/zsh\n";
+        assert_eq!(parse_passwd_usernames(passwd), vec!["root", "alice"]);
+    }
 }
@@ -18,8 +18,13 @@
 //! env.set("SHELL", "my_shrs");
 //! ```
 
-use std::{collections::HashMap, env};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+};
 
+use shrs_lang::DEFAULT_IFS;
+use shrs_utils::{colors_enabled_from_env, set_colors_enabled};
 use thiserror::Error;
 
 /// Hook for when environment variable gets modified
@@ -45,6 +50,8 @@ pub enum EnvError {
     InvalidValue(String),
     #[error("Key not found: {0}")]
     NotFound(String),
+    #[error("Not in a function: {0}")]
+    NotInFunction(String),
 }
 
 /// Set and query environment variables
@@ -52,27 +59,69 @@ pub enum EnvError {
 #[derive(Debug, Clone, Default)]
 pub struct Env {
     var_table: HashMap<String, String>,
+    /// Variables that are not passed to child processes
+    #[cfg_attr(feature = "serde", serde(default))]
+    unexported: HashSet<String>,
+    /// Scope of each function call being evaluated, see [Env::push_scope]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    scopes: Vec<Scope>,
 }
 
+/// Variables declared local in a function call, with their value and if they were exported
+/// before the declaration
+type Scope = Vec<(String, Option<String>, bool)>;
+
 impl Env {
     /// Create a new environment variable holder
     pub fn new() -> Self {
         Env {
             var_table: HashMap::new(),
+            unexported: HashSet::new(),
+            scopes: vec![],
         }
     }
 
     /// Load environment variables into shrs
     ///
-    /// Useful if calling shrs from another shell and some environment variables are already set
+    /// Useful if calling shrs from another shell and some environment variables are already set.
     // could inherit all from calling shell for now
     pub fn load(&mut self) -> Result<(), EnvError> {
         for (var, val) in std::env::vars() {
-            self.set(&var, &val)?;
+            if self.var_table.get(&var) != Some(&val) {
+                self.set(&var, &val)?;
+            }
         }
         Ok(())
     }
 
+    /// Pick up the variables the shell language assigned or unset, which it stores in the
+    /// environment of the shell process
+    ///
+    /// Variables that didn't exist before are not exported.
+    pub(crate) fn load_assigned(&mut self) {
+        for (var, val) in std::env::vars() {
+            if self.var_table.get(&var) == Some(&val) {
+                continue;
+            }
+            if !self.var_table.contains_key(&var) {
+                self.unexported.insert(var.clone());
+            }
+            update_colors_enabled(&var);
+            self.var_table.insert(var, val);
+        }
+        let unset = self
+            .var_table
+            .keys()
+            .filter(|var| env::var_os(var).is_none())
+            .cloned()
+            .collect::<Vec<_>>();
+        for var in unset {
+            self.var_table.remove(&var);
+            self.unexported.remove(&var);
+            update_colors_enabled(&var);
+        }
+    }
+
     /// Query environment variable
     pub fn get(&self, var: &str) -> Result<&String, EnvError> {
         // env::var(var).map_err(|_| EnvError::NotFound(var.into()))
@@ -83,8 +132,8 @@ impl Env {
 
     /// Set an environment variable
     ///
-    /// If the variable was already set it is overridden. Environment variables are case
-    /// insensitive
+    /// If the variable was already set it is overridden, and stays unexported if it was unexported
+    /// with [Env::unexport]. Environment variables are case insensitive
     pub fn set(&mut self, var: &str, val: &str) -> Result<(), EnvError> {
         // Careful: env::set_var will panic in the following cases (from the docs)
         //
@@ -101,6 +150,20 @@ impl Env {
 
         env::set_var(var, val);
         self.var_table.insert(var.into(), val.into());
+        update_colors_enabled(var);
+        Ok(())
+    }
+
+    /// Set a shell variable that is not passed to child processes, like `FOO=bar`
+    ///
+    /// A variable that was already exported stays exported, use [Env::unexport] to stop passing
+    /// it to child processes.
+    pub fn set_local(&mut self, var: &str, val: &str) -> Result<(), EnvError> {
+        let exists = self.var_table.contains_key(var);
+        self.set(var, val)?;
+        if !exists {
+            self.unexported.insert(var.into());
+        }
         Ok(())
     }
 
@@ -119,15 +182,110 @@ impl Env {
         }
         env::remove_var(var);
         self.var_table.remove(var);
+        self.unexported.remove(var);
+        update_colors_enabled(var);
+        Ok(())
+    }
+
+    /// Pass a variable to child processes again after it was unexported
+    pub fn export(&mut self, var: &str) -> Result<(), EnvError> {
+        if !self.var_table.contains_key(var) {
+            return Err(EnvError::NotFound(var.into()));
+        }
+        self.unexported.remove(var);
+        Ok(())
+    }
+
+    /// Stop passing a variable to child processes, while keeping it set in the shell
+    pub fn unexport(&mut self, var: &str) -> Result<(), EnvError> {
+        if !self.var_table.contains_key(var) {
+            return Err(EnvError::NotFound(var.into()));
+        }
+        self.unexported.insert(var.into());
+        Ok(())
+    }
+
+    /// Check if a variable is passed to child processes
+    pub fn is_exported(&self, var: &str) -> bool {
+        self.var_table.contains_key(var) && !self.unexported.contains(var)
+    }
+
+    /// Variables that are passed to child processes
+    pub fn exported(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.var_table
+            .iter()
+            .filter(|(var, _)| !self.unexported.contains(*var))
+    }
+
+    /// Enter the scope of a function call, the variables declared local in it with
+    /// [Env::declare_local] are restored by [Env::pop_scope]
+    pub fn push_scope(&mut self) {
+        self.scopes.push(vec![]);
+    }
+
+    /// Leave the scope of a function call, restoring the variables declared local in it to the
+    /// value they had before, or unsetting them if they weren't set
+    pub fn pop_scope(&mut self) -> Result<(), EnvError> {
+        let Some(scope) = self.scopes.pop() else {
+            return Ok(());
+        };
+        for (var, val, exported) in scope.into_iter().rev() {
+            match val {
+                Some(val) => {
+                    self.set(&var, &val)?;
+                    if exported {
+                        self.unexported.remove(&var);
+                    } else {
+                        self.unexported.insert(var);
+                    }
+                },
+                None => self.remove(&var)?,
+            }
+        }
         Ok(())
     }
 
+    /// Make a variable local to the innermost function call, so its current value is restored
+    /// when the call returns
+    ///
+    /// Declaring the same variable again in the same call keeps the value from before the first
+    /// declaration.
+    pub fn declare_local(&mut self, var: &str) -> Result<(), EnvError> {
+        let val = self.var_table.get(var).cloned();
+        let exported = self.is_exported(var);
+        let Some(scope) = self.scopes.last_mut() else {
+            return Err(EnvError::NotInFunction(var.into()));
+        };
+        if !scope.iter().any(|(local, _, _)| local == var) {
+            scope.push((var.to_string(), val, exported));
+        }
+        Ok(())
+    }
+
+    /// Set `COLUMNS` and `LINES` to the size of the terminal so child processes can see it
+    pub fn set_term_size(&mut self, cols: u16, rows: u16) -> Result<(), EnvError> {
+        self.set("COLUMNS", &cols.to_string())?;
+        self.set("LINES", &rows.to_string())
+    }
+
+    /// Characters that words are split on, which is `IFS` or [DEFAULT_IFS] if it is unset
+    pub fn ifs(&self) -> &str {
+        self.get("IFS").map_or(DEFAULT_IFS, |ifs| ifs.as_str())
+    }
+
     /// Writes all of the currently defined environment variables to the process
     pub fn sync(&self) -> Result<(), EnvError> {
         unimplemented!()
     }
 }
 
+/// Re-evaluate if styling is enabled when a variable that controls it is modified
+fn update_colors_enabled(var: &str) {
+    if var == "NO_COLOR" || var == "TERM" {
+        set_colors_enabled(colors_enabled_from_env());
+    }
+}
+
 /// Checks if a environment variable name is valid
 fn key_sanitation(var: &str) -> bool {
     var.is_empty() || var.contains('=') || var.contains('\0')
@@ -145,6 +303,92 @@ impl<S: ToString> FromIterator<(S, S)> for Env {
                 iter.into_iter()
                     .map(|(k, v)| (k.to_string(), v.to_string())),
             ),
+            unexported: HashSet::new(),
+            scopes: vec![],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Env;
+
+    #[test]
+    fn term_size() {
+        let mut env = Env::new();
+        env.set_term_size(80, 24).unwrap();
+        assert_eq!(env.get("COLUMNS").unwrap(), "80");
+        assert_eq!(env.get("LINES").unwrap(), "24");
+        assert_eq!(std::env::var("COLUMNS").unwrap(), "80");
+    }
+
+    #[test]
+    fn unexport() {
+        let mut env = Env::new();
+        env.set("SHRS_ENV_UNEXPORT", "val").unwrap();
+        assert!(env.is_exported("SHRS_ENV_UNEXPORT"));
+
+        env.unexport("SHRS_ENV_UNEXPORT").unwrap();
+        assert!(!env.is_exported("SHRS_ENV_UNEXPORT"));
+        assert!(env.exported().all(|(var, _)| var != "SHRS_ENV_UNEXPORT"));
+
+        // the value is kept and stays unexported when it changes
+        env.set("SHRS_ENV_UNEXPORT", "new").unwrap();
+        assert_eq!(env.get("SHRS_ENV_UNEXPORT").unwrap(), "new");
+        assert!(!env.is_exported("SHRS_ENV_UNEXPORT"));
+
+        env.export("SHRS_ENV_UNEXPORT").unwrap();
+        assert!(env.is_exported("SHRS_ENV_UNEXPORT"));
+        assert!(env.unexport("SHRS_ENV_UNSET").is_err());
+    }
+
+    #[test]
+    fn local() {
+        let mut env = Env::new();
+        env.set_local("SHRS_ENV_LOCAL", "val").unwrap();
+        assert_eq!(std::env::var("SHRS_ENV_LOCAL").unwrap(), "val");
+        assert!(!env.is_exported("SHRS_ENV_LOCAL"));
+
+        env.export("SHRS_ENV_LOCAL").unwrap();
+        env.set_local("SHRS_ENV_LOCAL", "new").unwrap();
+        assert!(env.is_exported("SHRS_ENV_LOCAL"));
+
+        // assigned by the shell language
+        std::env::set_var("SHRS_ENV_ASSIGNED", "val");
+        env.load_assigned();
+        assert_eq!(env.get("SHRS_ENV_ASSIGNED").unwrap(), "val");
+        assert!(!env.is_exported("SHRS_ENV_ASSIGNED"));
+
+        // and unset by it
+        std::env::remove_var("SHRS_ENV_LOCAL");
+        env.load_assigned();
+        assert!(env.get("SHRS_ENV_LOCAL").is_err());
+    }
+
+    #[test]
+    fn scopes() {
+        let mut env = Env::new();
+        env.set("SHRS_ENV_SHADOWED", "outer").unwrap();
+        assert!(env.declare_local("SHRS_ENV_SHADOWED").is_err());
+
+        env.push_scope();
+        env.declare_local("SHRS_ENV_SHADOWED").unwrap();
+        env.set_local("SHRS_ENV_SHADOWED", "middle").unwrap();
+        env.declare_local("SHRS_ENV_NEW").unwrap();
+        env.set_local("SHRS_ENV_NEW", "new").unwrap();
+
+        env.push_scope();
+        env.declare_local("SHRS_ENV_SHADOWED").unwrap();
+        env.set_local("SHRS_ENV_SHADOWED", "inner").unwrap();
+        // declaring it again keeps the value from before the first declaration
+        env.declare_local("SHRS_ENV_SHADOWED").unwrap();
+        env.pop_scope().unwrap();
+        assert_eq!(env.get("SHRS_ENV_SHADOWED").unwrap(), "middle");
+
+        env.pop_scope().unwrap();
+        assert_eq!(env.get("SHRS_ENV_SHADOWED").unwrap(), "outer");
+        assert!(env.is_exported("SHRS_ENV_SHADOWED"));
+        assert!(env.get("SHRS_ENV_NEW").is_err());
+        assert!(std::env::var("SHRS_ENV_NEW").is_err());
+    }
+}
@@ -26,6 +26,9 @@ pub struct AfterCommandCtx {
     /// The command that was ran
     pub command: String,
     /// Command output
+    ///
+    /// Only output written through [`crate::prelude::OutputWriter`] is captured, output from
+    /// external programs goes straight to the terminal
     pub cmd_output: CmdOutput,
 }
 
@@ -40,6 +43,23 @@ pub struct ChangeDirCtx {
     pub new_dir: PathBuf,
 }
 
+/// Runs when the terminal is resized while reading a line
+///
+/// Resizes are reported once the size has stopped changing for a moment, so dragging the window
+/// only runs the hook once.
+#[derive(HookEvent)]
+pub struct ResizeCtx {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+/// Runs right before the shell exits
+#[derive(HookEvent)]
+pub struct ExitCtx {
+    /// Exit code the shell is exiting with
+    pub exit_code: i32,
+}
+
 /// Runs when a job is completed
 ///
 /// Multiple jobs may have completed at the same time so a vector of exit statuses is returned
@@ -22,16 +22,25 @@
 //! let myshell = ShellBuilder::default().with_keybindings(bindings);
 //! ```
 
-use std::{collections::HashMap, marker::PhantomData};
+use std::{
+    collections::HashMap,
+    io::{Cursor, Write},
+    marker::PhantomData,
+    process::{Command, Stdio},
+};
 
 use anyhow::Result;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use log::warn;
+use shrs_utils::Location;
+use skim::prelude::{Skim, SkimItemReader, SkimOptionsBuilder};
 use thiserror::Error;
 
 use super::state::Param;
 use crate::{
     all_the_tuples,
-    prelude::{Shell, States},
+    prelude::{LineContents, PathExecCache, Shell, States},
+    readline::terminal::suspend_terminal,
 };
 
 /// Shell state containing registered keybindings
@@ -69,16 +78,30 @@ impl Keybindings {
 
     /// Attempt to evaluate any registered keybindings
     ///
-    /// Return true indicates that some event was handled.
-    pub fn handle_key_event(&self, sh: &Shell, states: &States, key_event: KeyEvent) -> bool {
-        let mut handled = false;
+    /// Returns if the key event was handled, and if so whether the line should be submitted.
+    pub fn handle_key_event(
+        &self,
+        sh: &Shell,
+        states: &States,
+        key_event: KeyEvent,
+    ) -> KeybindingOutcome {
+        let mut outcome = KeybindingOutcome::Unhandled;
         for (k, v) in self.bindings.iter() {
             if key_event == *k {
-                v.run(sh, states).unwrap();
-                handled = true;
+                if let Err(e) = v.run(sh, states) {
+                    warn!("keybinding for {k:?} failed: {e}");
+                    return KeybindingOutcome::KeepLine;
+                }
+                if v.keeps_line() {
+                    if outcome == KeybindingOutcome::Unhandled {
+                        outcome = KeybindingOutcome::KeepLine;
+                    }
+                } else {
+                    outcome = KeybindingOutcome::Submit;
+                }
             }
         }
-        return handled;
+        outcome
     }
 
     /// Get all the keybindings and their respective descriptions
@@ -88,6 +111,17 @@ impl Keybindings {
     }
 }
 
+/// What the line editor should do after a key event was passed to the keybindings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeybindingOutcome {
+    /// No keybinding matched the key event
+    Unhandled,
+    /// A keybinding ran and the line is submitted
+    Submit,
+    /// Only keybindings that edit the line ran, so the line is redrawn and editing continues
+    KeepLine,
+}
+
 /// Errors from parsing keybinding from string
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum BindingFromStrError {
@@ -244,6 +278,214 @@ mod tests {
 /// Keybinding handler function
 pub trait Keybinding {
     fn run(&self, sh: &Shell, states: &States) -> Result<()>;
+
+    /// Continue editing the line after running the keybinding instead of submitting it
+    fn keeps_line(&self) -> bool {
+        false
+    }
+}
+
+/// Where the output of a [CommandKeybinding] is put
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandOutputMode {
+    /// Insert the output at the cursor
+    Insert,
+    /// Replace the whole line with the output
+    Replace,
+}
+
+/// Keybinding that runs a shell command and puts what it prints into the line
+///
+/// The command is run with `sh -c` and keeps access to the terminal, so interactive programs like
+/// fzf can be used to pick what to insert. Nothing is changed if the command fails or prints
+/// nothing, which is how most pickers report being cancelled.
+/// ```
+/// # use shrs_core::prelude::*;
+/// let mut bindings = Keybindings::new();
+/// bindings
+///     .insert(
+///         "C-t",
+///         "Pick a file with fzf",
+///         CommandKeybinding::insert("fzf --height 40%"),
+///     )
+///     .unwrap();
+/// ```
+pub struct CommandKeybinding {
+    command: String,
+    mode: CommandOutputMode,
+}
+
+impl CommandKeybinding {
+    pub fn new(command: impl ToString, mode: CommandOutputMode) -> Self {
+        Self {
+            command: command.to_string(),
+            mode,
+        }
+    }
+
+    /// Insert the output of the command at the cursor
+    pub fn insert(command: impl ToString) -> Self {
+        Self::new(command, CommandOutputMode::Insert)
+    }
+
+    /// Replace the line with the output of the command
+    pub fn replace(command: impl ToString) -> Self {
+        Self::new(command, CommandOutputMode::Replace)
+    }
+}
+
+impl Keybinding for CommandKeybinding {
+    fn run(&self, _sh: &Shell, states: &States) -> Result<()> {
+        if let Some(output) = run_with_terminal(&self.command, None)? {
+            put_in_line(states, &output, self.mode)?;
+        }
+        Ok(())
+    }
+
+    fn keeps_line(&self) -> bool {
+        true
+    }
+}
+
+impl IntoKeybinding<()> for CommandKeybinding {
+    type Keybinding = Self;
+
+    fn into_keybinding(self) -> Self::Keybinding {
+        self
+    }
+}
+
+/// Keybinding that picks a command from the history with fzf and replaces the line with it
+///
+/// The history is passed to the picker most recent first. If the picker isn't found in PATH, the
+/// builtin fuzzy finder is used instead.
+/// ```
+/// # use shrs_core::prelude::*;
+/// let mut bindings = Keybindings::new();
+/// bindings
+///     .insert("C-r", "Search history", HistoryPickerKeybinding::default())
+///     .unwrap();
+/// ```
+pub struct HistoryPickerKeybinding {
+    command: String,
+}
+
+impl HistoryPickerKeybinding {
+    /// Use a different picker command, which reads the history from stdin and prints the selection
+    pub fn new(command: impl ToString) -> Self {
+        Self {
+            command: command.to_string(),
+        }
+    }
+}
+
+impl Default for HistoryPickerKeybinding {
+    fn default() -> Self {
+        Self::new("fzf --height 40% --no-sort")
+    }
+}
+
+impl Keybinding for HistoryPickerKeybinding {
+    fn run(&self, sh: &Shell, states: &States) -> Result<()> {
+        let history = sh.history.items(sh, states);
+        if history.is_empty() {
+            return Ok(());
+        }
+
+        let program = self.command.split_whitespace().next().unwrap_or_default();
+        let installed = states
+            .get::<PathExecCache>()
+            .executables()
+            .iter()
+            .any(|executable| executable == program);
+        let selected = if installed {
+            run_with_terminal(&self.command, Some(history.join("\n")))?
+        } else {
+            suspend_terminal(|| skim_select(history))?
+        };
+
+        if let Some(selected) = selected {
+            put_in_line(states, &selected, CommandOutputMode::Replace)?;
+        }
+        Ok(())
+    }
+
+    fn keeps_line(&self) -> bool {
+        true
+    }
+}
+
+impl IntoKeybinding<()> for HistoryPickerKeybinding {
+    type Keybinding = Self;
+
+    fn into_keybinding(self) -> Self::Keybinding {
+        self
+    }
+}
+
+/// Pick one of the items with the builtin fuzzy finder
+fn skim_select(items: Vec<String>) -> Option<String> {
+    let options = SkimOptionsBuilder::default()
+        .height(Some("40%"))
+        .nosort(true)
+        .build()
+        .ok()?;
+    let items = SkimItemReader::default().of_bufread(Cursor::new(items.join("\n")));
+
+    let output = Skim::run_with(&options, Some(items))?;
+    if output.is_abort {
+        return None;
+    }
+    output
+        .selected_items
+        .first()
+        .map(|item| item.output().to_string())
+}
+
+/// Put the output of a command into the line, ignoring the trailing newline
+fn put_in_line(states: &States, output: &str, mode: CommandOutputMode) -> Result<()> {
+    let output = output.trim_end_matches('\n');
+    if output.is_empty() {
+        return Ok(());
+    }
+
+    let cb = &mut states.get_mut::<LineContents>().cb;
+    if mode == CommandOutputMode::Replace {
+        cb.clear();
+    }
+    cb.insert(Location::Cursor(), output)?;
+    Ok(())
+}
+
+/// Run a command that can draw on the terminal and capture its stdout, [None] if it failed
+///
+/// The input is written to the stdin of the command if given, otherwise stdin is inherited.
+fn run_with_terminal(command: &str, input: Option<String>) -> Result<Option<String>> {
+    let output = suspend_terminal(|| -> std::io::Result<std::process::Output> {
+        let mut child = Command::new("sh")
+            .args(["-c", command])
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::inherit()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+
+        // write from another thread so a child that doesn't read all of stdin can't block us
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            std::thread::spawn(move || {
+                let _ = stdin.write_all(input.as_bytes());
+            });
+        }
+        child.wait_with_output()
+    })??;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
 }
 
 pub trait IntoKeybinding<Input> {
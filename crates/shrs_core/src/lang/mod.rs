@@ -14,4 +14,28 @@ pub trait Lang {
     /// Called when enter is pressed in line to check if the command is complete or needs another
     /// line. Use `state.line.get_full_command()`
     fn needs_line_check(&self, sh: &Shell, ctx: &States) -> bool;
+    /// Check if a command is syntactically valid, used to indicate errors while typing
+    ///
+    /// Defaults to treating every command as valid.
+    fn parse_check(&self, _sh: &Shell, _ctx: &States, _cmd: &str) -> bool {
+        true
+    }
+    /// Check if the lines read from a script so far form a complete command, used by `source` to
+    /// find where each command ends
+    ///
+    /// Defaults to treating every line as a complete command.
+    fn syntax_check(&self, _sh: &Shell, _ctx: &States, _cmd: &str) -> SyntaxCheck {
+        SyntaxCheck::Complete
+    }
+}
+
+/// Result of [Lang::syntax_check]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxCheck {
+    /// The command can be evaluated
+    Complete,
+    /// The command continues on the next line
+    Incomplete,
+    /// The command can't be evaluated, with a message describing why
+    Error(String),
 }
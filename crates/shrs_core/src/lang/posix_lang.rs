@@ -1,13 +1,100 @@
+use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
 use shrs_job::{initialize_job_control, JobManager};
-use shrs_lang::{Lexer, Parser, ParserError, Token, PosixError};
+use shrs_lang::{
+    positional_args, set_positional_args, Lexer, LexerError, Parser, ParserError, PosixError,
+    ShellBuiltins, ShellOptions, Token,
+};
 use thiserror::Error;
 
-use super::Lang;
+use super::{Lang, SyntaxCheck};
 use crate::{
-    prelude::{CmdOutput, LineContents, States},
-    shell::Shell,
+    prelude::{CmdOutput, ExitCtx, LineContents, OutputWriter, States},
+    shell::{Runtime, Shell},
 };
 
+/// Builtins of the shell, which can be run anywhere in a line such as in a pipeline
+///
+/// Builtins see the arguments of the function they are called from in [`Runtime::args`], and
+/// changes they make to them (such as with `set`) are passed back.
+struct LangBuiltins<'a> {
+    sh: &'a Shell,
+    states: &'a States,
+}
+
+impl ShellBuiltins for LangBuiltins<'_> {
+    fn run(
+        &mut self,
+        job_manager: &mut JobManager,
+        args: &[String],
+        capture: bool,
+    ) -> Option<(ExitStatus, Vec<u8>)> {
+        let (_, builtin) = self
+            .sh
+            .builtins
+            .iter()
+            .find(|(name, _)| **name == args[0])?;
+
+        // the jobs are only in the states while the builtin runs, so it sees all of them and the
+        // ones it starts (such as with `source`) are kept
+        std::mem::swap(&mut *self.states.get_mut::<JobManager>(), job_manager);
+        let mut rt = self.states.get_mut::<Runtime>();
+        rt.args = positional_args();
+        // the builtin sees the variables assigned so far, such as with `FOO=bar; export FOO`
+        rt.env.load_assigned();
+        drop(rt);
+        if capture {
+            self.states.get_mut::<OutputWriter>().begin_capture();
+        }
+        let output = builtin.run(self.sh, self.states, &args.to_vec());
+        let captured = if capture {
+            self.states.get_mut::<OutputWriter>().end_capture()
+        } else {
+            vec![]
+        };
+        set_positional_args(self.states.get::<Runtime>().args.clone());
+        std::mem::swap(&mut *self.states.get_mut::<JobManager>(), job_manager);
+
+        let status = match output {
+            Ok(output) => output.status,
+            Err(e) => {
+                eprintln!("error: {e:?}");
+                ExitStatus::from_raw(1 << 8)
+            },
+        };
+        Some((status, captured))
+    }
+
+    fn exported_vars(&self) -> Vec<(String, String)> {
+        let rt = self.states.get::<Runtime>();
+        // the language stores the current values in the environment of the process, the shell
+        // keeps track of which ones are exported
+        std::env::vars()
+            .filter(|(var, _)| rt.env.is_exported(var))
+            .collect()
+    }
+
+    fn push_scope(&mut self) {
+        self.states.get_mut::<Runtime>().env.push_scope();
+    }
+
+    fn pop_scope(&mut self) {
+        let env = &mut self.states.get_mut::<Runtime>().env;
+        // what the function assigned is overwritten by the values from before the call
+        env.load_assigned();
+        if let Err(e) = env.pop_scope() {
+            eprintln!("error: {e:?}");
+        }
+    }
+
+    fn expands_args(&self, name: &str) -> bool {
+        self.sh
+            .builtins
+            .iter()
+            .any(|(builtin_name, builtin)| builtin_name == name && builtin.expands_args())
+    }
+}
+
 /// Posix implementation of shell command language
 pub struct PosixLang {}
 
@@ -19,16 +106,41 @@ impl Default for PosixLang {
 }
 
 impl Lang for PosixLang {
-
-    fn eval(&self, _sh: &Shell, states: &States, line: String) -> anyhow::Result<CmdOutput> {
+    fn eval(&self, sh: &Shell, states: &States, line: String) -> anyhow::Result<CmdOutput> {
         // TODO rewrite the error handling here better
         // TODO why are we creating a new lexer and parser each eval? is this necessary?
         let lexer = Lexer::new(&line);
         let parser = Parser::default();
-        let job_manger = &mut states.get_mut::<JobManager>();
+        // builtins run during the evaluation can access the states, so none of them are borrowed
+        // while evaluating, the jobs are handed back to the states for each builtin
+        let mut job_manager = std::mem::take(&mut *states.get_mut::<JobManager>());
+        let options = states.get::<ShellOptions>().clone();
+        let mut builtins = LangBuiltins { sh, states };
+        set_positional_args(states.get::<Runtime>().args.clone());
+
+        let res = shrs_lang::eval(&mut job_manager, &options, &mut builtins, parser, lexer);
+        *states.get_mut::<JobManager>() = job_manager;
+        // pick up the variables assigned by the command
+        states.get_mut::<Runtime>().env.load_assigned();
+        states.get_mut::<Runtime>().args = positional_args();
 
-        match shrs_lang::eval(job_manger, parser, lexer) {
-            Ok(_) => Ok(CmdOutput::success()),
+        match res {
+            Ok(status) => {
+                let mut output = CmdOutput::success();
+                output.status = status;
+                Ok(output)
+            },
+            // exit once the command is done, like the shell does for builtins
+            Err(PosixError::Errexit(status)) => {
+                let exit_code = status.code().unwrap_or(1);
+                sh.run_cmd(move |sh: &mut Shell, states: &mut States| {
+                    sh.run_hooks_in_core(states, ExitCtx { exit_code });
+                    std::process::exit(exit_code);
+                });
+                let mut output = CmdOutput::success();
+                output.status = status;
+                Ok(output)
+            },
             Err(_e) => Ok(CmdOutput::error()),
         }
     }
@@ -37,6 +149,18 @@ impl Lang for PosixLang {
         "posix".to_string()
     }
 
+    fn parse_check(&self, _sh: &Shell, _ctx: &States, cmd: &str) -> bool {
+        Parser::default().parse(Lexer::new(cmd)).is_ok()
+    }
+
+    fn syntax_check(&self, _sh: &Shell, _ctx: &States, cmd: &str) -> SyntaxCheck {
+        match Parser::default().parse(Lexer::new(cmd)) {
+            Ok(_) => SyntaxCheck::Complete,
+            Err(ParserError::UnexpectedEof) => SyntaxCheck::Incomplete,
+            Err(ParserError::Syntax(e)) => SyntaxCheck::Error(e.to_string()),
+        }
+    }
+
     fn needs_line_check(&self, _sh: &Shell, ctx: &States) -> bool {
         //TODO check if open quotes or brackets
         let command = ctx.get::<LineContents>().get_full_command();
@@ -47,6 +171,13 @@ impl Lang for PosixLang {
             }
         };
 
+        // keep reading lines until every here document is terminated
+        if Lexer::new(command.as_str())
+            .any(|token| matches!(token, Err(LexerError::UnterminatedHeredoc(..))))
+        {
+            return true;
+        }
+
         let mut brackets: Vec<Token> = vec![];
 
         let lexer = Lexer::new(command.as_str());
@@ -106,3 +237,139 @@ impl Lang for PosixLang {
         !brackets.is_empty()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use shrs_job::{BuiltinProcess, JobManager, ProcessGroup};
+    use shrs_lang::{Lexer, Parser, ShellBuiltins, ShellOptions};
+
+    use super::LangBuiltins;
+    use crate::{
+        prelude::*,
+        shell::{Runtime, Shell},
+    };
+
+    #[test]
+    fn builtins_see_jobs() {
+        let sh = Shell::stub();
+        let mut states = States::default();
+        states.insert(Runtime::stub("/"));
+        states.insert(OutputWriter::default());
+        states.insert(JobManager::default());
+
+        // the jobs are taken out of the states while a line is evaluated
+        let mut job_manager = JobManager::default();
+        let proc = BuiltinProcess::new("sleep", &["1"], Default::default(), None);
+        let group = ProcessGroup {
+            id: None,
+            processes: vec![Box::new(proc)],
+            foreground: false,
+        };
+        job_manager.create_job("sleep 1", group);
+
+        let mut builtins = LangBuiltins {
+            sh: &sh,
+            states: &states,
+        };
+        let args = ["debug".to_string(), "jobs".to_string()];
+        let (status, output) = builtins.run(&mut job_manager, &args, true).unwrap();
+        assert!(status.success());
+        assert!(String::from_utf8(output).unwrap().contains("sleep 1"));
+        // and handed back once the builtin is done
+        assert_eq!(job_manager.get_jobs().len(), 1);
+        assert!(!states.get::<JobManager>().has_jobs());
+    }
+
+    /// Evaluate a line with the builtins of the shell, returning its exit code
+    fn eval_line(sh: &Shell, states: &States, line: &str) -> i32 {
+        shrs_lang::eval(
+            &mut JobManager::default(),
+            &ShellOptions::default(),
+            &mut LangBuiltins { sh, states },
+            Parser::default(),
+            Lexer::new(line),
+        )
+        .unwrap()
+        .code()
+        .unwrap()
+    }
+
+    #[test]
+    fn local_vars() {
+        let sh = Shell::stub();
+        let mut states = States::default();
+        let mut env = Env::new();
+        env.set("SHRS_LANG_SCOPED", "outer").unwrap();
+        std::env::remove_var("SHRS_LANG_NEW");
+        states.insert(Runtime {
+            env,
+            ..Runtime::stub("/")
+        });
+        states.insert(OutputWriter::default());
+        states.insert(JobManager::default());
+
+        let line = "shrs_lang_scope() { local SHRS_LANG_SCOPED=inner SHRS_LANG_NEW=new; \
+                    SHRS_LANG_SEEN=$SHRS_LANG_SCOPED; }; shrs_lang_scope";
+        assert_eq!(eval_line(&sh, &states, line), 0);
+        assert_eq!(std::env::var("SHRS_LANG_SEEN").unwrap(), "inner");
+        assert_eq!(std::env::var("SHRS_LANG_SCOPED").unwrap(), "outer");
+        assert!(std::env::var("SHRS_LANG_NEW").is_err());
+        assert!(states.get::<Runtime>().env.is_exported("SHRS_LANG_SCOPED"));
+
+        // nested calls restore their own variables and keep the value without an assignment
+        let line = "shrs_lang_inner() { local SHRS_LANG_SCOPED; SHRS_LANG_SCOPED=inner; }; \
+                    shrs_lang_outer() { local SHRS_LANG_SCOPED=middle; shrs_lang_inner; \
+                    SHRS_LANG_SEEN=$SHRS_LANG_SCOPED; return 2; }; shrs_lang_outer";
+        assert_eq!(eval_line(&sh, &states, line), 2);
+        assert_eq!(std::env::var("SHRS_LANG_SEEN").unwrap(), "middle");
+        assert_eq!(std::env::var("SHRS_LANG_SCOPED").unwrap(), "outer");
+
+        assert_eq!(eval_line(&sh, &states, "local SHRS_LANG_SCOPED=top"), 1);
+        assert_eq!(std::env::var("SHRS_LANG_SCOPED").unwrap(), "outer");
+    }
+
+    #[test]
+    fn substitution_in_copy() {
+        let sh = Shell::stub();
+        let mut states = States::default();
+        let working_dir = std::env::current_dir().unwrap();
+        states.insert(Runtime::stub(working_dir.clone()));
+        states.insert(OutputWriter::default());
+        states.insert(JobManager::default());
+
+        // builtins run in a copy of the shell that can't change its directory or make it exit
+        assert_eq!(eval_line(&sh, &states, "SHRS_LANG_SUB=$(cd /; pwd)"), 0);
+        assert_eq!(std::env::var("SHRS_LANG_SUB").unwrap(), "/");
+        assert_eq!(states.get::<Runtime>().working_dir, working_dir);
+        assert_eq!(std::env::current_dir().unwrap(), working_dir);
+
+        let line = "SHRS_LANG_SUB=$(exit 3; echo unreachable); SHRS_LANG_SUB=$SHRS_LANG_SUB.after";
+        assert_eq!(eval_line(&sh, &states, line), 0);
+        assert_eq!(std::env::var("SHRS_LANG_SUB").unwrap(), ".after");
+    }
+
+    #[test]
+    fn exported_vars() {
+        let sh = Shell::stub();
+        let mut states = States::default();
+        let mut env = Env::new();
+        env.set("SHRS_LANG_EXPORTED", "val").unwrap();
+        env.set_local("SHRS_LANG_LOCAL", "val").unwrap();
+        states.insert(Runtime {
+            env,
+            ..Runtime::stub("/")
+        });
+        // assigned by the language without being exported
+        std::env::set_var("SHRS_LANG_ASSIGNED", "val");
+
+        let builtins = LangBuiltins {
+            sh: &sh,
+            states: &states,
+        };
+        let exported = builtins.exported_vars();
+        let exported = |var: &str| exported.iter().any(|(exported, _)| exported == var);
+        assert!(exported("SHRS_LANG_EXPORTED"));
+        assert!(!exported("SHRS_LANG_LOCAL"));
+        assert!(!exported("SHRS_LANG_ASSIGNED"));
+    }
+}
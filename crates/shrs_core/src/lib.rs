@@ -13,6 +13,7 @@ pub mod hooks;
 pub mod jobs;
 pub mod keybinding;
 pub mod lang;
+pub mod motd;
 #[macro_use]
 mod macros;
 mod cmd_output;
@@ -20,20 +21,23 @@ mod output_writer;
 pub mod plugin;
 pub mod prompt_content_queue;
 pub mod readline;
+#[cfg(feature = "serde")]
+pub mod session;
 pub mod shell;
 pub mod state;
 pub mod theme;
 
 pub use cmd_output::CmdOutput;
-pub use output_writer::OutputWriter;
+pub use output_writer::{OutputWriter, DEFAULT_CAPTURE_LIMIT};
 
 pub mod prelude {
     //! Conveniently import commonly used types
 
     pub use shrs_core_macros::*;
+    pub use shrs_lang::ShellOptions;
 
     pub use crate::{
-        alias::{Alias, AliasInfo, AliasRule, AliasRuleCtx},
+        alias::{Alias, AliasInfo, AliasRule, AliasRuleCtx, AliasScope},
         builtin::*,
         cmd_output::CmdOutput,
         commands::Commands,
@@ -43,22 +47,24 @@ pub mod prelude {
         hooks::{events::*, Hook, HookEventMarker, Hooks, IntoHook},
         jobs::{JobId, JobInfo, Jobs},
         keybinding::*,
-        lang::{Lang, PosixLang},
-        output_writer::OutputWriter,
+        lang::{Lang, PosixLang, SyntaxCheck},
+        motd::Motd,
+        output_writer::{OutputWriter, DEFAULT_CAPTURE_LIMIT},
         plugin::*,
         prompt_content_queue::{PromptContent, PromptContentQueue},
         readline::{
             buffer_history::{BufferHistory, DefaultBufferHistory},
             highlight::{DefaultHighlighter, Highlighter, SyntaxHighlighter, SyntaxTheme},
-            line::{Line, LineContents, LineMode, Readline},
+            line::{CompletionMode, Line, LineContents, LineMode, Readline},
             line_events::*,
-            menu::{DefaultMenu, DefaultMenuState, Menu},
+            menu::{DefaultMenu, DefaultMenuState, Menu, MenuAction, MenuKeys},
             prompt::*,
             snippet::*,
-            suggester::{DefaultSuggester, Suggester},
+            suggester::{CompletionSuggester, DefaultSuggester, Suggester, SuggesterState},
+            terminal::suspend_terminal,
             vi::*,
         },
-        shell::{set_working_dir, Runtime, Shell, ShellBuilder, ShellConfig},
+        shell::{set_working_dir, BuildInfo, Runtime, Shell, ShellBuilder, ShellConfig},
         state::*,
         theme::Theme,
     };
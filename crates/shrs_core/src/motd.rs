@@ -0,0 +1,129 @@
+//! Message shown when the shell starts up
+//!
+//! By default the contents of `motd.txt` in the config directory are printed on startup, if the
+//! file exists. The message can be changed with [`ShellBuilder::with_motd`] to a fixed text, a
+//! different file or the output of a command, or turned off with [`Motd::Disabled`].
+//! ```
+//! # use shrs_core::prelude::*;
+//! let myshell = ShellBuilder::default().with_motd(Motd::Text("welcome to shrs".into()));
+//! ```
+//!
+//! [`ShellBuilder::with_motd`]: crate::shell::ShellBuilder::with_motd
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    hooks::events::StartupCtx,
+    output_writer::OutputWriter,
+    shell::Runtime,
+    state::{State, StateMut},
+};
+
+/// Source of the message shown when the shell starts up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Motd {
+    /// Don't show a message
+    Disabled,
+    /// Show the given text
+    Text(String),
+    /// Show the contents of a file, relative paths are relative to the config directory
+    ///
+    /// Nothing is shown if the file doesn't exist.
+    File(PathBuf),
+    /// Run a command with `sh -c` and show what it prints
+    Command(String),
+}
+
+impl Default for Motd {
+    fn default() -> Self {
+        Motd::File("motd.txt".into())
+    }
+}
+
+impl Motd {
+    /// Text of the message, [None] if there is nothing to show
+    pub fn message(&self, config_dir: &Path) -> Result<Option<String>> {
+        let message = match self {
+            Motd::Disabled => return Ok(None),
+            Motd::Text(text) => text.clone(),
+            Motd::File(path) => match std::fs::read_to_string(config_dir.join(path)) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(anyhow!("{}: {e}", path.display())),
+            },
+            Motd::Command(command) => {
+                let output = Command::new("sh")
+                    .args(["-c", command])
+                    .stdin(Stdio::null())
+                    .stderr(Stdio::inherit())
+                    .output()?;
+                if !output.status.success() {
+                    return Err(anyhow!("{command}: exited with {}", output.status));
+                }
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            },
+        };
+
+        let message = message.trim_end_matches('\n');
+        Ok((!message.is_empty()).then(|| message.to_string()))
+    }
+}
+
+/// Startup hook that prints the configured [`Motd`]
+///
+/// Failing to get the message is reported instead of failing the hook, so the other startup hooks
+/// still run.
+pub(crate) fn show_motd(
+    mut out: StateMut<OutputWriter>,
+    motd: State<Motd>,
+    rt: State<Runtime>,
+    _ctx: &StartupCtx,
+) -> Result<()> {
+    match motd.message(&rt.config_dir) {
+        Ok(Some(message)) => out.println(message),
+        Ok(None) => Ok(()),
+        Err(e) => out.eprintln(format!("shrs: motd: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Motd;
+
+    #[test]
+    fn message() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+
+        assert_eq!(Motd::Disabled.message(dir).unwrap(), None);
+        assert_eq!(
+            Motd::Text("hello".into()).message(dir).unwrap().as_deref(),
+            Some("hello")
+        );
+
+        // the default file is optional
+        assert_eq!(Motd::default().message(dir).unwrap(), None);
+        fs::write(dir.join("motd.txt"), "from file\n\n").unwrap();
+        assert_eq!(
+            Motd::default().message(dir).unwrap().as_deref(),
+            Some("from file")
+        );
+
+        assert_eq!(
+            Motd::Command("echo from command".into())
+                .message(dir)
+                .unwrap()
+                .as_deref(),
+            Some("from command")
+        );
+        assert!(Motd::Command("exit 1".into()).message(dir).is_err());
+    }
+}
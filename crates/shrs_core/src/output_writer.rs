@@ -9,12 +9,32 @@ use crossterm::{
     style::{ContentStyle, PrintStyledContent, Stylize},
     QueueableCommand,
 };
-use shrs_utils::StyledBuf;
+use shrs_utils::{effective_style, StyledBuf};
+
+/// Number of bytes of stdout and stderr that are collected per command by default (1 MiB)
+pub const DEFAULT_CAPTURE_LIMIT: usize = 1024 * 1024;
+
 /// Writer for printing to stdout and stderr
 ///
 /// Printing in handlers should be done through `OutputWriter`,
 /// which automatically uses the configured out and err colors.
 /// It also records output in commands so that it can be collected into `CmdOutput`
+///
+/// Collection never holds back output: everything is written and flushed to the terminal as soon
+/// as it is printed, and a copy is kept for the [`crate::prelude::AfterCommandCtx`] hook. External
+/// programs inherit the terminal directly, so their output is always displayed in real time and
+/// is not part of the collected output.
+///
+/// To keep commands that print a lot from using up all the memory, at most
+/// [`DEFAULT_CAPTURE_LIMIT`] bytes of each stream are kept by default. The limit can be changed
+/// with [`crate::shell::ShellBuilder::with_capture_limit`]. Output past the limit is still printed,
+/// but the collected output is cut off and [`crate::prelude::CmdOutput::truncated`] is set.
+///
+/// Output is collected as bytes, so output that isn't valid UTF-8 can be passed along unchanged
+/// with [`OutputWriter::print_bytes`].
+///
+/// When a builtin is piped into another command or its output is redirected, stdout is captured
+/// instead of printed. Captured output isn't styled and lines end with `\n` instead of `\r\n`.
 /// ```
 /// # use shrs_core::prelude::*;
 /// fn hello(mut out: StateMut<OutputWriter>) -> anyhow::Result<()> {
@@ -27,8 +47,14 @@ pub struct OutputWriter {
     stdout: BufWriter<std::io::Stdout>,
     stderr: BufWriter<std::io::Stderr>,
     collecting: bool,
-    out: String,
-    err: String,
+    out: Vec<u8>,
+    err: Vec<u8>,
+    /// Maximum number of bytes collected of each stream
+    capture_limit: usize,
+    /// If output was left out of the collected output because of the limit
+    truncated: bool,
+    /// Stdout of the builtins being captured, innermost last
+    captured: Vec<Vec<u8>>,
     out_style: ContentStyle,
     err_style: ContentStyle,
 }
@@ -40,11 +66,20 @@ impl OutputWriter {
             stdout: BufWriter::new(stdout()),
             stderr: BufWriter::new(stderr()),
             collecting: false,
-            out: String::new(),
-            err: String::new(),
+            out: vec![],
+            err: vec![],
+            capture_limit: DEFAULT_CAPTURE_LIMIT,
+            truncated: false,
+            captured: vec![],
         }
     }
 
+    /// Set the maximum number of bytes of stdout and stderr that is collected per command
+    pub fn set_capture_limit(&mut self, limit: usize) {
+        self.capture_limit = limit;
+    }
+
+    /// Start recording output in addition to printing it
     pub(crate) fn begin_collecting(&mut self) {
         self.collecting = true;
     }
@@ -52,11 +87,12 @@ impl OutputWriter {
     /// Prints to stderr and appends a newline character
     pub fn eprint<T: Display>(&mut self, s: T) -> anyhow::Result<()> {
         if self.collecting {
-            self.err.push_str(s.to_string().as_str());
+            self.truncated |= !collect(&mut self.err, s.to_string().as_bytes(), self.capture_limit);
         }
 
-        self.stderr
-            .queue(PrintStyledContent(self.err_style.apply(s.to_string())))?;
+        self.stderr.queue(PrintStyledContent(
+            effective_style(self.err_style).apply(s.to_string()),
+        ))?;
         self.stderr.flush()?;
         Ok(())
     }
@@ -70,24 +106,81 @@ impl OutputWriter {
 
     /// Prints to stdout using out_style for styling.
     pub fn print<T: Display>(&mut self, s: T) -> anyhow::Result<()> {
+        if let Some(captured) = self.captured.last_mut() {
+            captured.extend_from_slice(s.to_string().as_bytes());
+            return Ok(());
+        }
         if self.collecting {
-            self.out.push_str(s.to_string().as_str());
+            self.truncated |= !collect(&mut self.out, s.to_string().as_bytes(), self.capture_limit);
         }
-        self.stdout
-            .queue(PrintStyledContent(self.out_style.apply(s.to_string())))?;
+        self.stdout.queue(PrintStyledContent(
+            effective_style(self.out_style).apply(s.to_string()),
+        ))?;
         self.stdout.flush()?;
         Ok(())
     }
     ///Calls print, then prints a newline.
     pub fn println<T: Display>(&mut self, s: T) -> anyhow::Result<()> {
         self.print(s)?;
-        self.print("\r\n")?;
+        self.print(self.newline())?;
+        Ok(())
+    }
+
+    /// Prints raw bytes to stdout as they are, without styling them
+    ///
+    /// Use this to pass along the output of other programs, which isn't necessarily valid UTF-8.
+    pub fn print_bytes(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        if let Some(captured) = self.captured.last_mut() {
+            captured.extend_from_slice(bytes);
+            return Ok(());
+        }
+        if self.collecting {
+            self.truncated |= !collect(&mut self.out, bytes, self.capture_limit);
+        }
+        self.stdout.write_all(bytes)?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    /// Prints raw bytes to stderr as they are, without styling them
+    pub fn eprint_bytes(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        if self.collecting {
+            self.truncated |= !collect(&mut self.err, bytes, self.capture_limit);
+        }
+        self.stderr.write_all(bytes)?;
+        self.stderr.flush()?;
         Ok(())
     }
 
-    pub(crate) fn end_collecting(&mut self) -> (String, String) {
+    /// Capture stdout instead of printing it until [`Self::end_capture`]
+    pub(crate) fn begin_capture(&mut self) {
+        self.captured.push(vec![]);
+    }
+
+    /// Stop capturing stdout and return what was printed since [`Self::begin_capture`]
+    pub(crate) fn end_capture(&mut self) -> Vec<u8> {
+        self.captured.pop().unwrap_or_default()
+    }
+
+    /// Line ending for stdout, the terminal needs a carriage return but other programs don't
+    fn newline(&self) -> &'static str {
+        if self.captured.is_empty() {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
+    /// Stop recording output and return the stdout and stderr recorded since
+    /// [`Self::begin_collecting`], along with whether any of it was cut off by the capture limit
+    pub(crate) fn end_collecting(&mut self) -> (Vec<u8>, Vec<u8>, bool) {
         self.collecting = false;
-        (self.out.drain(..).collect(), self.err.drain(..).collect())
+        let truncated = std::mem::take(&mut self.truncated);
+        (
+            std::mem::take(&mut self.out),
+            std::mem::take(&mut self.err),
+            truncated,
+        )
     }
     /// Prints a `StyledBuf` to stdout.
     /// If there are multiple lines, if will print \r\n between them.
@@ -96,9 +189,17 @@ impl OutputWriter {
 
         for (i, line) in lines.iter().enumerate() {
             if i > 0 {
-                self.print("\r\n")?;
+                self.print(self.newline())?;
             }
             for span in line {
+                if let Some(captured) = self.captured.last_mut() {
+                    captured.extend_from_slice(span.content().as_bytes());
+                    continue;
+                }
+                if self.collecting {
+                    self.truncated |=
+                        !collect(&mut self.out, span.content().as_bytes(), self.capture_limit);
+                }
                 self.stdout.queue(PrintStyledContent(span.clone()))?;
             }
         }
@@ -107,8 +208,69 @@ impl OutputWriter {
         Ok(())
     }
 }
+/// Append as much of `bytes` to `buf` as fits in `limit` bytes, returns false if some of it was
+/// left out
+fn collect(buf: &mut Vec<u8>, bytes: &[u8], limit: usize) -> bool {
+    let remaining = limit.saturating_sub(buf.len());
+    let end = bytes.len().min(remaining);
+    buf.extend_from_slice(&bytes[..end]);
+    end == bytes.len()
+}
+
 impl Default for OutputWriter {
     fn default() -> Self {
         Self::new(ContentStyle::new().white(), ContentStyle::new().red())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::OutputWriter;
+
+    #[test]
+    fn capture_limit() {
+        let mut out = OutputWriter::default();
+        out.set_capture_limit(6);
+
+        out.begin_collecting();
+        out.print("abc").unwrap();
+        out.eprint("é").unwrap();
+        assert_eq!(out.end_collecting(), ("abc".into(), "é".into(), false));
+
+        out.begin_collecting();
+        out.print("abcd").unwrap();
+        out.print("ééx").unwrap();
+        out.eprint("éééé").unwrap();
+        assert_eq!(out.end_collecting(), ("abcdé".into(), "ééé".into(), true));
+    }
+
+    #[test]
+    fn raw_bytes() {
+        let mut out = OutputWriter::default();
+        out.begin_collecting();
+        out.print("a").unwrap();
+        out.print_bytes(b"\xff\xfe").unwrap();
+        out.eprint_bytes(b"\x80").unwrap();
+        assert_eq!(
+            out.end_collecting(),
+            (b"a\xff\xfe".to_vec(), b"\x80".to_vec(), false)
+        );
+    }
+
+    #[test]
+    fn capture() {
+        let mut out = OutputWriter::default();
+        out.begin_collecting();
+        out.begin_capture();
+        out.println("a").unwrap();
+        out.begin_capture();
+        out.print_bytes(b"inner").unwrap();
+        assert_eq!(out.end_capture(), b"inner");
+        out.print("b").unwrap();
+        assert_eq!(out.end_capture(), b"a\nb");
+
+        // captured output goes to the next command instead of the terminal, so it isn't collected
+        out.print("c").unwrap();
+        assert_eq!(out.end_collecting(), (b"c".to_vec(), vec![], false));
+    }
+}
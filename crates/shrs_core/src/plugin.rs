@@ -23,6 +23,9 @@
 //! # use shrs_core::prelude::*;
 //! # #[derive(HookEvent)]
 //! # struct MyHookEvent {}
+//! # fn my_builtin(args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+//! #     Ok(CmdOutput::success())
+//! # }
 //! // Define a struct for your plugin with any configuration as it's fields
 //! pub struct MyPlugin {
 //!     number: u32,
@@ -48,6 +51,7 @@
 //!         // Insert any state here
 //!         config.hooks.insert(my_hook);
 //!         config.states.insert(my_state);
+//!         config.builtins.insert("mybuiltin", my_builtin);
 //!         Ok(())
 //!     }
 //!
@@ -112,6 +116,10 @@ pub trait Plugin {
     ///
     /// Hook onto the initialization of the shell and add any hooks, functions, state variables
     /// that you would like
+    ///
+    /// Called when the shell starts running, before the config is turned into the [Shell]. Anything
+    /// added to the config here, such as builtins inserted with `config.builtins.insert`, is
+    /// available once the shell starts reading commands.
     fn init(&self, config: &mut ShellConfig) -> anyhow::Result<()>;
 
     /// Plugin post initialization
@@ -90,6 +90,7 @@ pub struct ShrsTheme {
     cmd_style: ContentStyle,
     string_style: ContentStyle,
     reserved_style: ContentStyle,
+    comment_style: ContentStyle,
 }
 impl Default for ShrsTheme {
     fn default() -> Self {
@@ -106,6 +107,10 @@ impl Default for ShrsTheme {
                 foreground_color: Some(Color::Yellow),
                 ..Default::default()
             },
+            ContentStyle {
+                foreground_color: Some(Color::DarkGrey),
+                ..Default::default()
+            },
         )
     }
 }
@@ -114,11 +119,13 @@ impl ShrsTheme {
         cmd_style: ContentStyle,
         string_style: ContentStyle,
         reserved_style: ContentStyle,
+        comment_style: ContentStyle,
     ) -> Self {
         ShrsTheme {
             cmd_style,
             string_style,
             reserved_style,
+            comment_style,
         }
     }
 }
@@ -170,10 +177,14 @@ impl SyntaxTheme for ShrsTheme {
                 },
                 _ => (),
             }
-            if let Token::WORD(w) = token.1 {
-                if w.starts_with('\'') || w.starts_with('\"') {
+            match token.1 {
+                Token::WORD(w) if w.starts_with('\'') || w.starts_with('\"') => {
                     buf.apply_style_in_range(token.0..token.2, self.string_style);
-                }
+                },
+                Token::COMMENT(_) => {
+                    buf.apply_style_in_range(token.0..token.2, self.comment_style);
+                },
+                _ => (),
             }
         }
     }
@@ -286,3 +297,31 @@ macro_rules! impl_into_highlighter {
     }
 }
 all_the_tuples!(impl_highlighter, impl_into_highlighter);
+
+#[cfg(test)]
+mod tests {
+    use crossterm::style::{Color, ContentStyle};
+    use shrs_utils::StyledBuf;
+
+    use super::{ShrsTheme, SyntaxTheme};
+
+    #[test]
+    fn dim_comments() {
+        let comment_style = ContentStyle {
+            foreground_color: Some(Color::DarkGrey),
+            ..Default::default()
+        };
+        let line = "echo a#b # note";
+        let mut buf = StyledBuf::new(line);
+        ShrsTheme::default().apply(&mut buf);
+
+        let comment_start = line.find(" # ").unwrap() + 1;
+        for (i, span) in buf.spans().iter().enumerate() {
+            assert_eq!(
+                *span.style() == comment_style,
+                i >= comment_start,
+                "unexpected style at {i}"
+            );
+        }
+    }
+}
@@ -1,26 +1,35 @@
 //! Core readline configuration
 
-use std::io::{Read, Seek, Write};
+use std::{
+    io::{Read, Seek, Write},
+    time::{Duration, Instant},
+};
 
 use ::crossterm::{
     event::{
-        read, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyModifiers,
+        poll, read, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent,
+        KeyModifiers,
     },
     execute,
-    style::{Color, ContentStyle},
-    terminal::{disable_raw_mode, enable_raw_mode},
+    terminal::{self, disable_raw_mode, enable_raw_mode},
 };
+use log::warn;
 use pino_deref::{Deref, DerefMut};
-use shrs_utils::{CursorBuffer, Location};
+use shrs_utils::{longest_common_prefix, CursorBuffer, Location, StyledBuf};
 use shrs_vi::{Action, Command, Motion, Parser};
 
-use super::painter::Painter;
+use super::{painter::Painter, prompt::SPINNER_INTERVAL};
+#[cfg(feature = "serde")]
+use crate::session::{SessionRecorder, SessionReplay};
 use crate::{
     prelude::{
-        BufferHistory, Completer, Completion, CompletionCtx, DefaultMenuState, InsertPosition,
-        LineModeSwitchEvent, ReplaceMethod, Shell, Snippets, Theme, ViCursorBuffer,
+        function_completions, history_arg_completions, BufferHistory, Completer, Completion,
+        CompletionCtx, DefaultMenuState, ExitCtx, InsertPosition, KeybindingOutcome,
+        LineModeSwitchEvent, MenuAction, MenuKeys, PromptEpoch, PromptStatus, ReplaceMethod,
+        ResizeCtx, Shell, Snippets, SuggesterState, Theme, ViCursorBuffer,
     },
     prompt_content_queue::PromptContentQueue,
+    shell::Runtime,
     state::States,
 };
 
@@ -46,6 +55,20 @@ pub enum LineMode {
     Normal,
 }
 
+/// How tab completion behaves when there are multiple candidates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompletionMode {
+    /// Open the completion menu right away
+    #[default]
+    Menu,
+    /// Insert the longest common prefix of the candidates, and open the menu when tab is pressed
+    /// again without anything more to insert (readline's "complete then list")
+    List,
+    /// Insert the longest common prefix of the candidates, and ring the bell when there is
+    /// nothing more to insert or nothing matches
+    Bell,
+}
+
 /// State or where the prompt is in history browse mode
 #[derive(Debug, PartialEq, Eq)]
 pub enum HistoryInd {
@@ -128,6 +151,25 @@ pub struct Line {
 
     /// Currently pressed keys in normal mode
     normal_keys: String,
+
+    /// If the previous key handled was tab, used to detect consecutive tab presses
+    prev_key_was_tab: bool,
+
+    /// Context the current completions were generated for
+    completion_ctx: Option<CompletionCtx>,
+
+    /// History index to take the next last argument from, and the length of the last argument
+    /// that was inserted, while Alt-. is pressed repeatedly
+    last_arg_cycle: Option<(usize, usize)>,
+
+    /// Simpler rendering for screen readers, see [Line::with_accessibility]
+    accessible: bool,
+
+    /// Resizes that haven't been reported to [ResizeCtx] hooks yet
+    resize: ResizeDebounce,
+
+    /// Modes that take key events before they edit the line
+    layers: KeymapLayers,
 }
 
 impl Default for Line {
@@ -135,8 +177,167 @@ impl Default for Line {
         Self {
             painter: Painter::default(),
             normal_keys: String::new(),
+            prev_key_was_tab: false,
+            completion_ctx: None,
+            last_arg_cycle: None,
+            accessible: false,
+            resize: ResizeDebounce::default(),
+            layers: KeymapLayers::default(),
+        }
+    }
+}
+
+impl Line {
+    /// Render the line in a way that works better with screen readers and other assistive
+    /// technology
+    ///
+    /// Syntax highlighting, inline suggestions and the right prompt are turned off, completions
+    /// are printed as a plain list below the line instead of opening the completion menu, and
+    /// typing at the end of the line only prints the new text instead of redrawing the whole
+    /// line. Editing works the same as usual.
+    /// ```
+    /// # use shrs_core::prelude::*;
+    /// let readline = Line::default().with_accessibility(true);
+    /// ```
+    pub fn with_accessibility(mut self, accessible: bool) -> Self {
+        self.accessible = accessible;
+        self.painter.set_accessible(accessible);
+        self
+    }
+}
+
+/// Check if the cursor is after the last character of the line
+fn cursor_at_end(states: &States) -> bool {
+    let contents = states.get::<LineContents>();
+    contents.cb.cursor() == contents.cb.len()
+}
+
+/// Get the inline suggestion that is displayed after the cursor
+fn visible_suggestion(sh: &Shell, states: &States) -> Option<String> {
+    if !states.get::<SuggesterState>().enabled
+        || !cursor_at_end(states)
+        || states.get::<DefaultMenuState>().is_active()
+    {
+        return None;
+    }
+    sh.suggester.suggest(sh, states)
+}
+
+/// Mode that takes key events before they reach the line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeymapLayer {
+    /// The completion menu is open, its keys are configured with [MenuKeys]
+    Menu,
+}
+
+/// Stack of keymap layers, where the top layer receives each key event first
+///
+/// A layer that doesn't handle a key is popped and passes the key down, until the key is handled
+/// by the current [LineMode].
+#[derive(Debug, Default)]
+struct KeymapLayers {
+    layers: Vec<KeymapLayer>,
+}
+
+impl KeymapLayers {
+    /// Put a layer on top, unless it is already active
+    fn push(&mut self, layer: KeymapLayer) {
+        if !self.layers.contains(&layer) {
+            self.layers.push(layer);
         }
     }
+
+    fn pop(&mut self) -> Option<KeymapLayer> {
+        self.layers.pop()
+    }
+
+    /// Remove a layer wherever it is in the stack
+    fn remove(&mut self, layer: KeymapLayer) {
+        self.layers.retain(|l| *l != layer);
+    }
+
+    fn top(&self) -> Option<KeymapLayer> {
+        self.layers.last().copied()
+    }
+}
+
+/// How long the terminal size has to stay the same before a resize is reported
+const RESIZE_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Collapses the stream of resize events sent while the window is being dragged into one
+#[derive(Default)]
+struct ResizeDebounce {
+    /// Size last reported to the hooks
+    reported: Option<(u16, u16)>,
+    /// Latest size received and when it was received
+    pending: Option<((u16, u16), Instant)>,
+}
+
+impl ResizeDebounce {
+    /// Remember the size the terminal started with, so it isn't reported as a resize
+    fn init(&mut self, size: (u16, u16)) {
+        self.reported.get_or_insert(size);
+    }
+
+    fn resized(&mut self, size: (u16, u16), now: Instant) {
+        self.pending = Some((size, now));
+    }
+
+    /// How long to wait for more events before the pending resize can be reported
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.pending
+            .map(|(_, at)| RESIZE_DEBOUNCE.saturating_sub(now.duration_since(at)))
+    }
+
+    /// Size to report once it has stopped changing, if it differs from the last reported size
+    fn settled(&mut self, now: Instant) -> Option<(u16, u16)> {
+        if self.remaining(now)? > Duration::ZERO {
+            return None;
+        }
+        let (size, _) = self.pending.take()?;
+        if self.reported == Some(size) {
+            return None;
+        }
+        self.reported = Some(size);
+        Some(size)
+    }
+}
+
+/// Check if an event can be read, waiting at most a spinner frame while the prompt has a pending
+/// status so that the spinner can be repainted, or until a pending resize can be reported
+fn event_ready(states: &States, resize: &ResizeDebounce) -> anyhow::Result<bool> {
+    let spinner = states
+        .get::<PromptStatus>()
+        .is_pending()
+        .then_some(SPINNER_INTERVAL);
+    let timeout = match (spinner, resize.remaining(Instant::now())) {
+        (Some(a), Some(b)) => a.min(b),
+        (Some(timeout), None) | (None, Some(timeout)) => timeout,
+        (None, None) => return Ok(true),
+    };
+    #[cfg(feature = "serde")]
+    if !states.get::<SessionReplay>().is_finished() {
+        return Ok(true);
+    }
+    Ok(poll(timeout)?)
+}
+
+/// Read the next terminal event, taking it from a replayed session if there is one
+fn next_event(states: &States) -> anyhow::Result<Event> {
+    #[cfg(feature = "serde")]
+    let event = {
+        let replayed = states.get_mut::<SessionReplay>().next_event();
+        let event = match replayed {
+            Some(event) => event,
+            None => read()?,
+        };
+        states.get_mut::<SessionRecorder>().record_event(&event)?;
+        event
+    };
+    #[cfg(not(feature = "serde"))]
+    let event = read()?;
+
+    Ok(event)
 }
 
 impl Readline for Line {
@@ -177,51 +378,61 @@ impl Line {
                 .insert(Location::Cursor(), c.content.as_str())?;
         }
 
+        // there may not be a terminal, in which case the first resize is always reported
+        if let Ok(size) = terminal::size() {
+            self.resize.init(size);
+        }
+
         loop {
+            if let Some((cols, rows)) = self.resize.settled(Instant::now()) {
+                sh.run_hooks_in_core(states, ResizeCtx { rows, cols });
+            }
+
             let res = states.get::<LineContents>().get_full_command();
 
-            // syntax highlight
-            let mut styled_buf = sh
-                .highlighter
-                .highlight(sh, states, &res)?
-                .slice_from(states.get::<LineContents>().lines.len());
+            let mut styled_buf = self.highlighted_line(sh, states)?;
 
-            // add currently selected completion to buf
-            if states.get::<DefaultMenuState>().is_active() {
+            // add currently selected completion to buf, which is left out in accessible mode since
+            // text that isn't part of the line would be read out as if it was
+            if !self.accessible && states.get::<DefaultMenuState>().is_active() {
                 if let Some(selection) = states.get::<DefaultMenuState>().current_selection() {
                     let trimmed_selection =
                         &selection.accept()[states.get::<CurrentWord>().len()..];
-                    styled_buf.push(
-                        trimmed_selection,
-                        ContentStyle {
-                            foreground_color: Some(Color::Red),
-                            ..Default::default()
-                        },
-                    );
-                }
-            } else {
-                if let Some(suggestion) = sh.suggester.suggest(sh, states) {
-                    let trimmed_selection = suggestion[res.len()..].to_string();
-                    styled_buf.push(
-                        trimmed_selection.as_str(),
-                        states.get::<Theme>().suggestion_style,
-                    );
+                    styled_buf.push(trimmed_selection, states.get::<Theme>().completion_style);
                 }
+            } else if let Some(suggestion) =
+                visible_suggestion(sh, states).filter(|_| !self.accessible)
+            {
+                let trimmed_selection = suggestion[res.len()..].to_string();
+                styled_buf.push(
+                    trimmed_selection.as_str(),
+                    states.get::<Theme>().suggestion_style,
+                );
             }
 
+            states.get::<PromptStatus>().tick();
             self.painter
                 .paint(states, sh, &states.get::<DefaultMenuState>(), &styled_buf)?;
             if auto_run {
                 states.get_mut::<Box<dyn BufferHistory>>().clear();
-                self.painter.newline()?;
+                self.finish_line(sh, states)?;
                 break;
             }
 
-            let event = read()?;
+            if !event_ready(states, &self.resize)? {
+                continue;
+            }
+            let event = next_event(states)?;
 
             if let Event::Key(key_event) = event {
-                if sh.keybindings.handle_key_event(sh, states, key_event) {
-                    break;
+                match sh.keybindings.handle_key_event(sh, states, key_event) {
+                    KeybindingOutcome::Unhandled => {},
+                    KeybindingOutcome::Submit => break,
+                    KeybindingOutcome::KeepLine => {
+                        // the keybinding may have drawn on the terminal
+                        self.painter.init()?;
+                        continue;
+                    },
                 }
             }
 
@@ -230,17 +441,34 @@ impl Line {
                 break;
             }
 
-            // handle menu events
+            let is_tab = matches!(
+                event,
+                Event::Key(KeyEvent {
+                    code: KeyCode::Tab,
+                    modifiers: KeyModifiers::NONE,
+                    ..
+                })
+            );
+            let is_last_arg = matches!(
+                event,
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('.'),
+                    modifiers: KeyModifiers::ALT,
+                    ..
+                })
+            );
+            if !is_last_arg {
+                self.last_arg_cycle = None;
+            }
+
+            // the menu may have been opened or closed by the previous key
             if states.get::<DefaultMenuState>().is_active() {
-                self.handle_menu_keys(sh, states, event.clone())?;
+                self.layers.push(KeymapLayer::Menu);
             } else {
-                let mode = *states.get::<LineMode>();
-                match mode {
-                    LineMode::Insert => self.handle_insert_keys(sh, states, event)?,
-
-                    LineMode::Normal => self.handle_normal_keys(sh, states, event)?,
-                }
+                self.layers.remove(KeymapLayer::Menu);
             }
+            self.handle_layer_keys(sh, states, event)?;
+            self.prev_key_was_tab = is_tab;
         }
 
         let res = states.get::<LineContents>().get_full_command();
@@ -250,64 +478,58 @@ impl Line {
         Ok(res)
     }
 
+    /// Pass a key event to the top keymap layer, or to the current mode if there is none
+    fn handle_layer_keys(
+        &mut self,
+        sh: &mut Shell,
+        states: &mut States,
+        event: Event,
+    ) -> anyhow::Result<()> {
+        match self.layers.top() {
+            Some(KeymapLayer::Menu) => self.handle_menu_keys(sh, states, event),
+            None => {
+                let mode = *states.get::<LineMode>();
+                match mode {
+                    LineMode::Insert => self.handle_insert_keys(sh, states, event),
+                    LineMode::Normal => self.handle_normal_keys(sh, states, event),
+                }
+            },
+        }
+    }
+
     fn handle_menu_keys(
         &mut self,
         sh: &mut Shell,
         states: &mut States,
         event: Event,
     ) -> anyhow::Result<()> {
-        match event {
-            Event::Key(KeyEvent {
-                code: KeyCode::Enter,
-                modifiers: KeyModifiers::NONE,
-                ..
-            }) => {
+        let action = match &event {
+            Event::Key(key_event) => states.get::<MenuKeys>().get(key_event),
+            _ => None,
+        };
+        match action {
+            Some(MenuAction::Accept) => {
+                self.layers.pop();
                 if let Some(accepted) = states.get_mut::<DefaultMenuState>().accept().cloned() {
+                    self.record_accepted(states, &accepted);
                     self.accept_completion(states, accepted)?;
                 }
             },
-            Event::Key(KeyEvent {
-                code: KeyCode::Esc,
-                modifiers: KeyModifiers::NONE,
-                ..
-            }) => {
+            Some(MenuAction::Cancel) => {
+                self.layers.pop();
                 states.get_mut::<DefaultMenuState>().disactivate();
             },
-            Event::Key(KeyEvent {
-                code: KeyCode::Tab,
-                modifiers: KeyModifiers::SHIFT,
-                ..
-            })
-            | Event::Key(KeyEvent {
-                code: KeyCode::Up,
-                modifiers: KeyModifiers::NONE,
-                ..
-            }) => {
+            Some(MenuAction::Previous) => {
                 states.get_mut::<DefaultMenuState>().previous();
             },
-            Event::Key(KeyEvent {
-                code: KeyCode::Tab,
-                modifiers: KeyModifiers::NONE,
-                ..
-            })
-            | Event::Key(KeyEvent {
-                code: KeyCode::Down,
-                modifiers: KeyModifiers::NONE,
-                ..
-            }) => {
+            Some(MenuAction::Next) => {
                 states.get_mut::<DefaultMenuState>().next();
             },
-            _ => {
+            None => {
+                // any other key closes the menu and is handled as if it wasn't open
+                self.layers.pop();
                 states.get_mut::<DefaultMenuState>().disactivate();
-                let mode = *states.get::<LineMode>();
-                match mode {
-                    LineMode::Insert => {
-                        self.handle_insert_keys(sh, states, event)?;
-                    },
-                    LineMode::Normal => {
-                        self.handle_normal_keys(sh, states, event)?;
-                    },
-                };
+                self.handle_layer_keys(sh, states, event)?;
             },
         };
         Ok(())
@@ -320,9 +542,21 @@ impl Line {
         states: &mut States,
         event: Event,
     ) -> anyhow::Result<bool> {
+        let (accept_key, accept_and_run_key) = {
+            let suggester_state = states.get::<SuggesterState>();
+            (
+                suggester_state.accept_key,
+                suggester_state.accept_and_run_key,
+            )
+        };
+
         match event {
             Event::Resize(a, b) => {
                 self.painter.set_term_size(a, b);
+                self.resize.resized((a, b), Instant::now());
+                if let Err(e) = states.get_mut::<Runtime>().env.set_term_size(a, b) {
+                    warn!("Failed to set terminal size variables with {}", e);
+                }
             },
             Event::Paste(p) => {
                 states
@@ -335,26 +569,22 @@ impl Line {
                 modifiers: KeyModifiers::CONTROL,
                 ..
             }) => {
+                self.finish_line(sh, states)?;
                 states.get_mut::<LineContents>().cb.clear();
                 states.get_mut::<Box<dyn BufferHistory>>().clear();
                 states.get_mut::<LineContents>().lines = String::new();
-                self.painter.newline()?;
 
                 return Ok(true);
             },
-            // Insert suggestion when right arrow
-            Event::Key(KeyEvent {
-                code: KeyCode::Right,
-                modifiers: KeyModifiers::NONE,
-                ..
-            }) => {
-                if let Some(suggestion) = sh.suggester.suggest(sh, states) {
-                    states.get_mut::<LineContents>().cb.clear();
-                    states
-                        .get_mut::<LineContents>()
-                        .cb
-                        .insert(Location::Cursor(), suggestion.as_str())?;
-                }
+            Event::Key(key_event) if key_event == accept_key => {
+                self.accept_suggestion(sh, states)?;
+            },
+            Event::Key(key_event)
+                if Some(key_event) == accept_and_run_key
+                    && visible_suggestion(sh, states).is_some() =>
+            {
+                self.accept_suggestion(sh, states)?;
+                return self.submit(sh, states);
             },
 
             Event::Key(KeyEvent {
@@ -367,23 +597,7 @@ impl Line {
                 modifiers: KeyModifiers::CONTROL,
                 ..
             }) => {
-                if states.get::<DefaultMenuState>().is_active() {
-                    return Ok(false);
-                }
-                states.get_mut::<Box<dyn BufferHistory>>().clear();
-                self.painter.newline()?;
-
-                if sh.lang.needs_line_check(sh, states) {
-                    let mut lc = states.get_mut::<LineContents>();
-                    let cb_str = lc.cb.as_str().to_string();
-                    lc.lines += cb_str.as_str();
-                    lc.lines += "\n";
-                    lc.cb.clear();
-
-                    return Ok(false);
-                }
-
-                return Ok(true);
+                return self.submit(sh, states);
             },
             Event::Key(KeyEvent {
                 code: KeyCode::Char('d'),
@@ -395,10 +609,11 @@ impl Line {
                     // TODO maybe unify exiting the shell
                     let _ = disable_raw_mode(); // TODO this is temp fix, should be more graceful way of
                                                 // handling cleanup code
+                    let _ = sh.hooks.run(sh, states, &ExitCtx { exit_code: 0 });
                     std::process::exit(0);
                 } else {
                     states.get_mut::<Box<dyn BufferHistory>>().clear();
-                    self.painter.newline()?;
+                    self.finish_line(sh, states)?;
                     return Ok(true);
                 }
             },
@@ -474,7 +689,7 @@ impl Line {
                 modifiers: KeyModifiers::NONE,
                 ..
             }) => {
-                self.populate_completions(states)?;
+                self.populate_completions(sh, states)?;
                 let mut menu = states.get_mut::<DefaultMenuState>();
                 menu.activate();
 
@@ -483,53 +698,65 @@ impl Line {
                 // no-op if no completions
                 if completion_len == 0 {
                     menu.disactivate();
+                    drop(menu);
+                    if *states.get::<CompletionMode>() != CompletionMode::Menu {
+                        self.painter.bell()?;
+                    }
                     return Ok(());
                 }
                 // if completions only has one entry, automatically select it
                 if completion_len == 1 {
                     // TODO stupid ownership stuff
                     let item = menu.items().get(0).map(|x| (*x).clone()).unwrap();
+                    self.record_accepted(states, &item.1);
                     self.accept_completion(states, item.1)?;
                     menu.disactivate();
                     return Ok(());
                 }
 
-                // TODO make this feature toggable
-                // TODO this is broken
-                // Automatically accept the common prefix
-                /*
-                let completions: Vec<&str> = self
-                    .menu
-                    .items()
-                    .iter()
-                    .map(|(preview, _)| preview.as_str())
-                    .collect();
-                let prefix = longest_common_prefix(completions);
-                self.accept_completion(
-                    states,
-                    Completion {
-                        add_space: false,
-                        display: None,
-                        completion: prefix.clone(),
-                        replace_method: ReplaceMethod::Append,
-                    },
-                )?;
-
-                // recompute completions with prefix stripped
-                // TODO this code is horrifying
-                let items = self.menu.items();
-                let new_items = items
-                    .iter()
-                    .map(|(preview, complete)| {
-                        let mut complete = complete.clone();
-                        complete.completion = complete.completion[prefix.len()..].to_string();
-                        (preview.clone(), complete)
-                    })
-                    .collect();
-                self.menu.set_items(new_items);
-                */
+                drop(menu);
 
-                menu.activate();
+                if self.accessible {
+                    states.get_mut::<DefaultMenuState>().disactivate();
+                    return self.list_completions(states);
+                }
+
+                let mode = *states.get::<CompletionMode>();
+                if mode == CompletionMode::Menu {
+                    return Ok(());
+                }
+                states.get_mut::<DefaultMenuState>().disactivate();
+
+                // insert the common prefix of all the completions if it adds anything
+                if let Some(prefix) = self.completion_prefix(states) {
+                    self.accept_completion(
+                        states,
+                        Completion {
+                            add_space: false,
+                            display: None,
+                            completion: prefix,
+                            replace_method: ReplaceMethod::Replace,
+                            comment: None,
+                            description: None,
+                        },
+                    )?;
+                    return Ok(());
+                }
+
+                // nothing more can be inserted, list the completions if tab was pressed twice
+                if mode == CompletionMode::List && self.prev_key_was_tab {
+                    states.get_mut::<DefaultMenuState>().activate();
+                } else {
+                    self.painter.bell()?;
+                }
+                return Ok(());
+            },
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('.'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            }) => {
+                self.insert_last_arg(sh, states)?;
             },
             Event::Key(KeyEvent {
                 code: KeyCode::Left,
@@ -760,8 +987,74 @@ impl Line {
         Ok(())
     }
 
+    /// Replace the line with the suggestion currently displayed, if there is one
+    fn accept_suggestion(&mut self, sh: &Shell, states: &States) -> anyhow::Result<()> {
+        if let Some(suggestion) = visible_suggestion(sh, states) {
+            states.get_mut::<LineContents>().cb.clear();
+            states
+                .get_mut::<LineContents>()
+                .cb
+                .insert(Location::Cursor(), suggestion.as_str())?;
+        }
+        Ok(())
+    }
+
+    /// Current line of the command with syntax highlighting, without the lines before it
+    fn highlighted_line(&self, sh: &Shell, states: &States) -> anyhow::Result<StyledBuf> {
+        let res = states.get::<LineContents>().get_full_command();
+        let styled_buf = match self.accessible {
+            true => StyledBuf::new(&res),
+            false => sh.highlighter.highlight(sh, states, &res)?,
+        };
+        Ok(styled_buf.slice_from(states.get::<LineContents>().lines.len()))
+    }
+
+    /// Move below the line once it is done, erasing the prompt or only the right prompt if they
+    /// are transient
+    fn finish_line(&mut self, sh: &Shell, states: &States) -> anyhow::Result<()> {
+        // lines after the first in a multiline command only have the continuation prompt, and
+        // redrawing the line is avoided in accessible mode
+        if sh.prompt.transient && !self.accessible && states.get::<LineContents>().lines.is_empty()
+        {
+            let line = self.highlighted_line(sh, states)?;
+            self.painter.paint_transient(&line)?;
+        } else if sh.prompt.transient_right {
+            self.painter.clear_right_prompt()?;
+        }
+        self.painter.newline()?;
+        Ok(())
+    }
+
+    /// Finish reading the line, unless the language needs more lines to complete the command
+    ///
+    /// Returns true if the line should be ran
+    fn submit(&mut self, sh: &Shell, states: &States) -> anyhow::Result<bool> {
+        if states.get::<DefaultMenuState>().is_active() {
+            return Ok(false);
+        }
+        states.get_mut::<Box<dyn BufferHistory>>().clear();
+        self.finish_line(sh, states)?;
+
+        if sh.lang.needs_line_check(sh, states) {
+            let mut lc = states.get_mut::<LineContents>();
+            let cb_str = lc.cb.as_str().to_string();
+            lc.lines += cb_str.as_str();
+            lc.lines += "\n";
+            lc.cb.clear();
+
+            // keep the previous lines on screen and start painting on the new line
+            self.painter.init()?;
+            return Ok(false);
+        }
+
+        if sh.prompt.shell_integration {
+            self.painter.mark_command_start()?;
+        }
+        Ok(true)
+    }
+
     // recalculate the current completions
-    fn populate_completions(&mut self, states: &mut States) -> anyhow::Result<()> {
+    fn populate_completions(&mut self, sh: &Shell, states: &mut States) -> anyhow::Result<()> {
         // TODO IFS
         let line_contents = states.get::<LineContents>();
         let cursor = line_contents.cb.cursor();
@@ -779,7 +1072,26 @@ impl Line {
 
         let comp_states = CompletionCtx::new(args);
 
-        let completions = states.get::<Box<dyn Completer>>().complete(&comp_states);
+        let mut completions = states
+            .get_mut::<Box<dyn Completer>>()
+            .complete(&comp_states);
+        // functions can be defined while the shell is running, so they are completed from the
+        // current functions instead of a completion rule
+        if comp_states.arg_num() == 0 {
+            let cur_word = states.get::<CurrentWord>();
+            completions.extend(function_completions(&cur_word));
+        }
+        // arguments the command was called with before are listed after the other completions
+        let history = sh.history.items(sh, states);
+        for completion in history_arg_completions(&history, &comp_states) {
+            if !completions
+                .iter()
+                .any(|c| c.completion == completion.completion)
+            {
+                completions.push(completion);
+            }
+        }
+        self.completion_ctx = Some(comp_states);
         let completions = completions.iter().collect::<Vec<_>>();
 
         let menuitems = completions
@@ -791,6 +1103,98 @@ impl Line {
         Ok(())
     }
 
+    /// Insert the common prefix of the current completions, or print them one per line below the
+    /// line if there is nothing to insert, which is used in place of the menu in accessible mode
+    fn list_completions(&mut self, states: &mut States) -> anyhow::Result<()> {
+        if let Some(prefix) = self.completion_prefix(states) {
+            return self.accept_completion(
+                states,
+                Completion {
+                    add_space: false,
+                    display: None,
+                    completion: prefix,
+                    replace_method: ReplaceMethod::Replace,
+                    comment: None,
+                    description: None,
+                },
+            );
+        }
+
+        let listing = states
+            .get::<DefaultMenuState>()
+            .items()
+            .iter()
+            .map(|(display, completion)| match &completion.description {
+                Some(description) => format!("{display} - {description}"),
+                None => display.clone(),
+            })
+            .collect::<Vec<_>>();
+        self.painter.print_lines(&listing)?;
+        // the line is drawn again below the list
+        self.painter.init()?;
+        Ok(())
+    }
+
+    /// Longest common prefix of the current completions, if it is longer than the current word
+    fn completion_prefix(&self, states: &States) -> Option<String> {
+        let menu = states.get::<DefaultMenuState>();
+        let items = menu.items();
+        // a common prefix only makes sense if every completion replaces the current word
+        if items
+            .iter()
+            .any(|(_, c)| c.replace_method != ReplaceMethod::Replace)
+        {
+            return None;
+        }
+        let prefix =
+            longest_common_prefix(items.iter().map(|(_, c)| c.completion.as_str()).collect());
+
+        let cur_word = states.get::<CurrentWord>();
+        (prefix.len() > cur_word.len() && prefix.starts_with(cur_word.as_str())).then_some(prefix)
+    }
+
+    /// Insert the last argument of the previous command at the cursor
+    ///
+    /// Pressing it again replaces the inserted argument with the last argument of the command
+    /// before that.
+    fn insert_last_arg(&mut self, sh: &Shell, states: &States) -> anyhow::Result<()> {
+        let (start, inserted_len) = self.last_arg_cycle.unwrap_or((0, 0));
+
+        let history_len = sh.history.len(sh, states);
+        let found = (start..history_len).find_map(|i| {
+            let cmd = sh.history.get(sh, states, i)?;
+            let last_arg = cmd.split_whitespace().last()?.to_string();
+            Some((i, last_arg))
+        });
+        let Some((i, last_arg)) = found else {
+            self.painter.bell()?;
+            return Ok(());
+        };
+
+        let mut contents = states.get_mut::<LineContents>();
+        if inserted_len > 0 {
+            contents
+                .cb
+                .move_cursor(Location::Rel(-(inserted_len as isize)))?;
+            contents
+                .cb
+                .delete(Location::Cursor(), Location::Rel(inserted_len as isize))?;
+        }
+        contents.cb.insert(Location::Cursor(), &last_arg)?;
+
+        self.last_arg_cycle = Some((i + 1, last_arg.chars().count()));
+        Ok(())
+    }
+
+    /// Let the completer know which completion the user picked
+    fn record_accepted(&self, states: &States, completion: &Completion) {
+        if let Some(ctx) = &self.completion_ctx {
+            states
+                .get_mut::<Box<dyn Completer>>()
+                .accepted(ctx, completion);
+        }
+    }
+
     // replace word at cursor with accepted word (used in automcompletion)
     fn accept_completion(&mut self, states: &States, completion: Completion) -> anyhow::Result<()> {
         // first remove current word
@@ -873,6 +1277,7 @@ impl Line {
 
     fn to_normal_mode(&self, sh: &mut Shell, states: &mut States) -> anyhow::Result<()> {
         *states.get_mut::<LineMode>() = LineMode::Normal;
+        states.get_mut::<PromptEpoch>().invalidate();
 
         let hook_states = LineModeSwitchEvent {
             line_mode: LineMode::Normal,
@@ -883,6 +1288,7 @@ impl Line {
 
     fn to_insert_mode(&self, sh: &mut Shell, states: &mut States) -> anyhow::Result<()> {
         *states.get_mut::<LineMode>() = LineMode::Insert;
+        states.get_mut::<PromptEpoch>().invalidate();
 
         let hook_states = LineModeSwitchEvent {
             line_mode: LineMode::Insert,
@@ -891,3 +1297,46 @@ impl Line {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{KeymapLayer, KeymapLayers, ResizeDebounce, RESIZE_DEBOUNCE};
+
+    #[test]
+    fn resize_debounce() {
+        let start = Instant::now();
+        let mut resize = ResizeDebounce::default();
+        resize.init((80, 24));
+        assert_eq!(resize.settled(start), None);
+
+        // dragging the window only reports the final size
+        resize.resized((90, 24), start);
+        resize.resized((100, 30), start + Duration::from_millis(50));
+        assert_eq!(resize.settled(start + Duration::from_millis(100)), None);
+        let settled = start + Duration::from_millis(50) + RESIZE_DEBOUNCE;
+        assert_eq!(resize.settled(settled), Some((100, 30)));
+        assert_eq!(resize.settled(settled), None);
+
+        // resizing back to the same size isn't reported
+        resize.resized((100, 30), settled);
+        assert_eq!(resize.settled(settled + RESIZE_DEBOUNCE), None);
+    }
+
+    #[test]
+    fn keymap_layers() {
+        let mut layers = KeymapLayers::default();
+        assert_eq!(layers.top(), None);
+
+        layers.push(KeymapLayer::Menu);
+        layers.push(KeymapLayer::Menu);
+        assert_eq!(layers.top(), Some(KeymapLayer::Menu));
+        assert_eq!(layers.pop(), Some(KeymapLayer::Menu));
+        assert_eq!(layers.top(), None);
+
+        layers.push(KeymapLayer::Menu);
+        layers.remove(KeymapLayer::Menu);
+        assert_eq!(layers.pop(), None);
+    }
+}
@@ -3,19 +3,27 @@
 //! The menu is used to select between multiple choices, most commonly used to select a tab
 //! completion.
 
-use std::{cmp::Ordering, fmt::Display};
+use std::{cmp::Ordering, collections::HashMap, fmt::Display};
 
 use crossterm::{
     cursor::{MoveDown, MoveToColumn, MoveUp},
+    event::KeyEvent,
     execute,
-    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    style::{
+        Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+    },
     QueueableCommand,
 };
+use shrs_utils::colors_enabled;
 
 use super::painter::Painter;
-use crate::prelude::Completion;
+use crate::{
+    keybinding::{parse_keybinding, BindingFromStrError},
+    prelude::Completion,
+};
 
-pub type Out = std::io::BufWriter<std::io::Stdout>;
+/// Output the painter and menus draw to, which is stdout outside of tests
+pub type Out = std::io::BufWriter<Box<dyn std::io::Write>>;
 
 /// Implement this trait to define your own menu
 pub trait Menu {
@@ -46,6 +54,80 @@ pub trait Menu {
     fn required_lines(&self, painter: &Painter) -> usize;
 }
 
+/// What a key does while the menu is active
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    /// Select the next item
+    Next,
+    /// Select the previous item
+    Previous,
+    /// Complete the selected item and close the menu
+    Accept,
+    /// Close the menu without completing anything
+    Cancel,
+}
+
+/// Keys used to navigate the menu while it is active
+///
+/// Any other key closes the menu and is handled as usual. The defaults are tab and down for
+/// [MenuAction::Next], shift-tab and up for [MenuAction::Previous], enter for
+/// [MenuAction::Accept] and escape for [MenuAction::Cancel]. Keys are given as keybinding
+/// strings, see [parse_keybinding].
+/// ```
+/// # use shrs_core::prelude::*;
+/// let mut menu_keys = MenuKeys::default();
+/// menu_keys.insert("<right>", MenuAction::Accept).unwrap();
+/// menu_keys.insert("C-<space>", MenuAction::Accept).unwrap();
+///
+/// let myshell = ShellBuilder::default().with_state(menu_keys);
+/// ```
+pub struct MenuKeys {
+    bindings: HashMap<KeyEvent, MenuAction>,
+}
+
+impl MenuKeys {
+    /// Menu keys without any bindings
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Bind a key to an action, replacing what the key did before
+    pub fn insert(&mut self, key: &str, action: MenuAction) -> Result<(), BindingFromStrError> {
+        self.bindings.insert(parse_keybinding(key)?, action);
+        Ok(())
+    }
+
+    /// Unbind a key so it closes the menu
+    pub fn remove(&mut self, key: &str) -> Result<(), BindingFromStrError> {
+        self.bindings.remove(&parse_keybinding(key)?);
+        Ok(())
+    }
+
+    /// Action bound to a key
+    pub fn get(&self, key_event: &KeyEvent) -> Option<MenuAction> {
+        self.bindings.get(key_event).copied()
+    }
+}
+
+impl Default for MenuKeys {
+    fn default() -> Self {
+        let mut menu_keys = Self::new();
+        for (key, action) in [
+            ("<tab>", MenuAction::Next),
+            ("<down>", MenuAction::Next),
+            ("S-<tab>", MenuAction::Previous),
+            ("<up>", MenuAction::Previous),
+            ("<enter>", MenuAction::Accept),
+            ("<esc>", MenuAction::Cancel),
+        ] {
+            menu_keys.insert(key, action).unwrap();
+        }
+        menu_keys
+    }
+}
+
 pub type SortFn = fn(&(String, Completion), &(String, Completion)) -> Ordering;
 pub type DefaultMenuState = Box<dyn Menu<MenuItem = Completion, PreviewItem = String>>;
 /// Simple menu that prompts user for a selection
@@ -57,6 +139,8 @@ pub struct DefaultMenu {
     column_padding: usize,
     /// Max length in characters that the comment message is allowed to take up
     comment_max_length: usize,
+    /// Max length in characters that the description is allowed to take up
+    description_max_length: usize,
     /// Max number of entries to show when rendering the menu
     _limit: usize,
     /// Function to use to sort the entries
@@ -72,10 +156,11 @@ impl Default for DefaultMenu {
             cursor: 0,
             active: false,
             comment_max_length: 30,
+            description_max_length: 40,
             column_padding: 2,
             _limit: 20,
-            // by default sort alphabetical by display name
-            sort: |a, b| -> Ordering { a.0.to_lowercase().cmp(&b.0.to_lowercase()) },
+            // by default keep the order the completer ranked the entries in
+            sort: |_, _| -> Ordering { Ordering::Equal },
         }
     }
 }
@@ -89,6 +174,9 @@ impl DefaultMenu {
 
     // TODO make these configurable?
     fn selected_style(&self, out: &mut Out) -> crossterm::Result<()> {
+        if !colors_enabled() {
+            return Ok(());
+        }
         execute!(
             out,
             SetBackgroundColor(Color::White),
@@ -98,15 +186,38 @@ impl DefaultMenu {
     }
 
     fn unselected_style(&self, out: &mut Out) -> crossterm::Result<()> {
+        if !colors_enabled() {
+            return Ok(());
+        }
         execute!(out, ResetColor)?;
         Ok(())
     }
 
     fn comment_style(&self, out: &mut Out) -> crossterm::Result<()> {
+        if !colors_enabled() {
+            return Ok(());
+        }
         execute!(out, SetForegroundColor(Color::Yellow),)?;
         Ok(())
     }
 
+    fn description_style(&self, out: &mut Out) -> crossterm::Result<()> {
+        if !colors_enabled() {
+            return Ok(());
+        }
+        execute!(out, SetAttribute(Attribute::Dim))?;
+        Ok(())
+    }
+
+    /// Length of the description of a completion as rendered, including the separating spaces
+    fn description_len(&self, completion: &Completion) -> usize {
+        completion
+            .description
+            .as_ref()
+            .map(|description| description.len().min(self.description_max_length) + 2)
+            .unwrap_or(0)
+    }
+
     fn max_width(&self) -> usize {
         // first determine how many columns are needed to list all completions
         let mut max_width = 0;
@@ -118,7 +229,8 @@ impl DefaultMenu {
                 .as_ref()
                 .map(|comment| comment.len().min(self.comment_max_length) + 4)
                 .unwrap_or(0);
-            let menu_item_len = menu_item.0.len() + comment_len;
+            let menu_item_len =
+                menu_item.0.len() + self.description_len(&menu_item.1) + comment_len;
 
             max_width = max_width.max(menu_item_len);
         }
@@ -206,6 +318,14 @@ impl Menu for DefaultMenu {
                 out.queue(Print(&menu_item.0))?;
                 self.unselected_style(out)?;
 
+                if let Some(description) = &menu_item.1.description {
+                    let description_len = description.len().min(self.description_max_length);
+                    out.queue(Print("  "))?;
+                    self.description_style(out)?;
+                    out.queue(Print(truncate(description, description_len)))?;
+                    out.queue(SetAttribute(Attribute::NormalIntensity))?;
+                }
+
                 if let Some(comment) = &menu_item.1.comment {
                     let comment_len = comment.len().min(self.comment_max_length);
                     out.queue(MoveToColumn(
@@ -259,3 +379,29 @@ fn truncate(s: &str, max_chars: usize) -> String {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::{MenuAction, MenuKeys};
+
+    #[test]
+    fn menu_keys() {
+        let mut menu_keys = MenuKeys::default();
+        let tab = KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE);
+        let right = KeyEvent::new(KeyCode::Right, KeyModifiers::NONE);
+        assert_eq!(menu_keys.get(&tab), Some(MenuAction::Next));
+        assert_eq!(
+            menu_keys.get(&KeyEvent::new(KeyCode::Tab, KeyModifiers::SHIFT)),
+            Some(MenuAction::Previous)
+        );
+        assert_eq!(menu_keys.get(&right), None);
+
+        menu_keys.insert("<right>", MenuAction::Accept).unwrap();
+        menu_keys.remove("<tab>").unwrap();
+        assert_eq!(menu_keys.get(&right), Some(MenuAction::Accept));
+        assert_eq!(menu_keys.get(&tab), None);
+        assert!(menu_keys.insert("<nope>", MenuAction::Next).is_err());
+    }
+}
@@ -17,4 +17,5 @@ pub mod painter;
 pub mod prompt;
 pub mod snippet;
 pub mod suggester;
+pub mod terminal;
 pub mod vi;
@@ -7,7 +7,7 @@ use std::{
 
 use crossterm::{
     cursor::{self, MoveToColumn, MoveToNextLine, MoveToPreviousLine},
-    style::{Print, PrintStyledContent},
+    style::{ContentStyle, Print, PrintStyledContent, StyledContent},
     terminal::{self, Clear, ScrollUp},
     QueueableCommand,
 };
@@ -16,26 +16,80 @@ use unicode_width::UnicodeWidthStr;
 
 use super::{
     line::{LineContents, LineMode},
-    menu::Menu,
+    menu::{Menu, Out},
 };
-use crate::prelude::{Completion, Shell, States, Theme};
+use crate::prelude::{Completion, PromptEpoch, PromptStatus, Shell, States, Theme};
 pub struct Painter {
     /// The output buffer
-    out: RefCell<BufWriter<std::io::Stdout>>,
+    out: RefCell<Out>,
     /// Dimensions of current terminal window
     term_size: (u16, u16),
     /// Current line the prompt is on
     prompt_line: u16,
     num_newlines: usize,
+    /// Avoid redrawing the line and moving the cursor around where possible, see
+    /// [`crate::readline::Line::with_accessibility`]
+    accessible: bool,
+    /// Prompt, line contents and cursor position drawn last, used in accessible mode
+    last_paint: Option<(String, String, usize)>,
+    /// Left and right prompt rendered for the current [`PromptEpoch`]
+    prompt_cache: PromptCache,
+    /// Row below the top of the prompt and column of each right prompt line drawn last
+    right_prompt: Vec<(u16, u16)>,
+}
+
+/// Parts of the prompt rendered for a line
+#[derive(Clone)]
+struct RenderedPrompt {
+    left: StyledBuf,
+    /// Empty if the prompt has no [`Prompt::with_input_marker`]
+    ///
+    /// [`Prompt::with_input_marker`]: crate::prelude::Prompt::with_input_marker
+    input_marker: StyledBuf,
+    right: StyledBuf,
+}
+
+/// Rendered prompt, reused between repaints of the same line
+#[derive(Default)]
+struct PromptCache {
+    prompt: Option<RenderedPrompt>,
+    epoch: PromptEpoch,
+    pending: bool,
+}
+
+impl PromptCache {
+    /// Return the cached prompt, rendering it again if the epoch changed or a status is pending
+    fn get_or_render(
+        &mut self,
+        epoch: PromptEpoch,
+        pending: bool,
+        render: impl FnOnce() -> RenderedPrompt,
+    ) -> RenderedPrompt {
+        // a status that was pending during the last render needs one more to show its result
+        if self.epoch != epoch || pending || self.pending {
+            self.prompt = None;
+        }
+        self.epoch = epoch;
+        self.pending = pending;
+        self.prompt.get_or_insert_with(render).clone()
+    }
+
+    fn invalidate(&mut self) {
+        self.prompt = None;
+    }
 }
 
 impl Default for Painter {
     fn default() -> Self {
         Self {
-            out: RefCell::new(BufWriter::new(stdout())),
+            out: RefCell::new(BufWriter::new(Box::new(stdout()))),
             term_size: (0, 0),
             prompt_line: 0,
             num_newlines: 0,
+            accessible: false,
+            last_paint: None,
+            prompt_cache: PromptCache::default(),
+            right_prompt: Vec::new(),
         }
     }
 }
@@ -45,6 +99,9 @@ impl Painter {
     pub fn init(&mut self) -> crossterm::Result<()> {
         self.prompt_line = 0;
         self.num_newlines = 0;
+        self.last_paint = None;
+        self.prompt_cache.invalidate();
+        self.right_prompt.clear();
         self.term_size = terminal::size()?;
 
         // advance to next row if cursor in middle of line
@@ -58,12 +115,17 @@ impl Painter {
 
     pub fn set_term_size(&mut self, w: u16, h: u16) {
         self.term_size = (w, h);
+        self.prompt_cache.invalidate();
     }
 
     pub fn get_term_size(&self) -> (u16, u16) {
         self.term_size
     }
 
+    pub fn set_accessible(&mut self, accessible: bool) {
+        self.accessible = accessible;
+    }
+
     // Clippy thinks we can just use &dyn but we cannot
     #[allow(clippy::borrowed_box)]
     pub fn paint(
@@ -74,6 +136,53 @@ impl Painter {
         styled_buf: &StyledBuf,
     ) -> anyhow::Result<()> {
         let cursor_ind: usize = states.get::<LineContents>().cb.cursor();
+
+        // lines after the first in a multiline command only get the continuation prompt, the
+        // right prompt is left out in accessible mode since drawing it moves the cursor
+        let (prompt_left, prompt_right) = if !states.get::<LineContents>().lines.is_empty() {
+            (
+                sh.prompt.continuation.prompt(sh, states),
+                StyledBuf::empty(),
+            )
+        } else {
+            let accessible = self.accessible;
+            let RenderedPrompt {
+                mut left,
+                input_marker,
+                right,
+            } = self.prompt_cache.get_or_render(
+                *states.get::<PromptEpoch>(),
+                states.get::<PromptStatus>().is_pending(),
+                || RenderedPrompt {
+                    left: sh.prompt.prompt_left.prompt(sh, states),
+                    input_marker: match &sh.prompt.input_marker {
+                        Some(input_marker) => input_marker.prompt(sh, states),
+                        None => StyledBuf::empty(),
+                    },
+                    right: match accessible {
+                        true => StyledBuf::empty(),
+                        false => sh.prompt.prompt_right.prompt(sh, states),
+                    },
+                },
+            );
+            left.push_buf(input_marker);
+            (left, right)
+        };
+        let shell_integration = sh.prompt.shell_integration;
+
+        if self.accessible {
+            let painted = (
+                prompt_left.content.clone(),
+                styled_buf.content.clone(),
+                cursor_ind,
+            );
+            let appended = self.paint_appended(&painted)?;
+            self.last_paint = Some(painted);
+            if appended {
+                return Ok(());
+            }
+        }
+
         self.out.borrow_mut().queue(cursor::Hide)?;
 
         // scroll up if we need more lines
@@ -86,9 +195,7 @@ impl Painter {
                 self.prompt_line = self.prompt_line.saturating_sub(extra_lines);
             }
         }
-        //newlines to account for when clearing and printing prompt
-        let prompt_left = sh.prompt.prompt_left.prompt(sh, states);
-        let prompt_right = sh.prompt.prompt_right.prompt(sh, states);
+        let gutter = error_line_gutter(states, sh);
         let prompt_left_lines = prompt_left.lines();
         let prompt_right_lines = prompt_right.lines();
         let styled_buf_lines = styled_buf.lines();
@@ -116,9 +223,13 @@ impl Painter {
                 self.prompt_line.saturating_sub(self.num_newlines as u16),
             ))?
             .queue(Clear(terminal::ClearType::FromCursorDown))?;
+        if shell_integration {
+            self.out.borrow_mut().queue(Print(PROMPT_START))?;
+        }
 
         // cursor position from left side of terminal
         let mut left_space = 0;
+        self.right_prompt.clear();
 
         let mut ri = 0;
         let mut li = 0;
@@ -128,32 +239,48 @@ impl Painter {
         //buffer when prompt_left is out of lines
 
         loop {
+            // width of what is drawn left of the right prompt on this row
+            let mut used = 0;
             if li < prompt_left_lines.len() {
+                if let Some(gutter) = &gutter {
+                    used += UnicodeWidthStr::width(gutter.content().as_str()) as u16;
+                    self.out
+                        .borrow_mut()
+                        .queue(PrintStyledContent(gutter.clone()))?;
+                }
                 for span in prompt_left_lines[li].iter() {
                     self.out
                         .borrow_mut()
                         .queue(PrintStyledContent(span.clone()))?;
                 }
+                used += line_content_len(prompt_left_lines[li].clone());
 
                 li += 1;
             }
             if bi < styled_buf_lines.len() && li >= prompt_left_lines.len() {
+                if bi == 0 && shell_integration {
+                    self.out.borrow_mut().queue(Print(INPUT_START))?;
+                }
                 for span in styled_buf_lines[bi].iter() {
                     self.out
                         .borrow_mut()
                         .queue(PrintStyledContent(span.clone()))?;
                 }
+                used += line_content_len(styled_buf_lines[bi].clone());
                 bi += 1;
             }
 
             if ri < prompt_right_lines.len() {
-                let mut right_space = self.term_size.0;
-                right_space -= line_content_len(prompt_right_lines[ri].clone());
-                self.out.borrow_mut().queue(MoveToColumn(right_space))?;
-                for span in prompt_right_lines[ri].iter() {
-                    self.out
-                        .borrow_mut()
-                        .queue(PrintStyledContent(span.clone()))?;
+                let right_len = line_content_len(prompt_right_lines[ri].clone());
+                // the right prompt is left out on rows where it would overlap the line
+                if let Some(column) = right_prompt_column(self.term_size.0, used, right_len) {
+                    self.out.borrow_mut().queue(MoveToColumn(column))?;
+                    for span in prompt_right_lines[ri].iter() {
+                        self.out
+                            .borrow_mut()
+                            .queue(PrintStyledContent(span.clone()))?;
+                    }
+                    self.right_prompt.push((ri as u16, column));
                 }
 
                 ri += 1;
@@ -178,6 +305,9 @@ impl Painter {
         //calculate left space
         //prompt space is 0 if there is going to be a newline in the styled_buf
         if styled_buf_lines.len().saturating_sub(1) == 0 {
+            if let Some(gutter) = &gutter {
+                left_space += UnicodeWidthStr::width(gutter.content().as_str());
+            }
             //space is width of last line of prompt_left
             left_space += UnicodeWidthStr::width(
                 prompt_left
@@ -225,9 +355,321 @@ impl Painter {
         Ok(())
     }
 
+    /// Print only the text typed since the last paint if that is all that changed, returns false
+    /// if the line has to be redrawn
+    fn paint_appended(&mut self, painted: &(String, String, usize)) -> anyhow::Result<bool> {
+        let Some((last_prompt, last_content, last_cursor)) = &self.last_paint else {
+            return Ok(false);
+        };
+        let (prompt, content, cursor) = painted;
+        if last_prompt != prompt
+            || *last_cursor != last_content.chars().count()
+            || *cursor != content.chars().count()
+        {
+            return Ok(false);
+        }
+        let Some(appended) = content.strip_prefix(last_content.as_str()) else {
+            return Ok(false);
+        };
+        if appended.contains('\n') {
+            return Ok(false);
+        }
+
+        if !appended.is_empty() {
+            self.out.borrow_mut().queue(Print(appended))?;
+            self.out.borrow_mut().flush()?;
+        }
+        Ok(true)
+    }
+
+    /// Erase the right prompt drawn by the last paint, so it doesn't stay in the scrollback once
+    /// the line is submitted
+    pub fn clear_right_prompt(&mut self) -> crossterm::Result<()> {
+        if self.right_prompt.is_empty() {
+            return Ok(());
+        }
+        let top = self.prompt_line.saturating_sub(self.num_newlines as u16);
+        let mut out = self.out.borrow_mut();
+        out.queue(cursor::SavePosition)?;
+        for (row, column) in self.right_prompt.drain(..) {
+            out.queue(cursor::MoveTo(column, top + row))?
+                .queue(Clear(terminal::ClearType::UntilNewLine))?;
+        }
+        out.queue(cursor::RestorePosition)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Redraw the line with only the input marker of the prompt in front of it, erasing everything
+    /// else that was drawn, see [`Prompt::with_transient`]
+    ///
+    /// [`Prompt::with_transient`]: crate::prelude::Prompt::with_transient
+    pub fn paint_transient(&mut self, line: &StyledBuf) -> crossterm::Result<()> {
+        let input_marker = self
+            .prompt_cache
+            .prompt
+            .as_ref()
+            .map_or_else(StyledBuf::empty, |prompt| prompt.input_marker.clone());
+        self.right_prompt.clear();
+
+        let mut out = self.out.borrow_mut();
+        out.queue(cursor::MoveTo(
+            0,
+            self.prompt_line.saturating_sub(self.num_newlines as u16),
+        ))?
+        .queue(Clear(terminal::ClearType::FromCursorDown))?;
+        for span in input_marker.spans() {
+            out.queue(PrintStyledContent(span))?;
+        }
+        for (i, line) in line.lines().into_iter().enumerate() {
+            if i > 0 {
+                out.queue(MoveToNextLine(1))?;
+            }
+            for span in line {
+                out.queue(PrintStyledContent(span))?;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Mark the start of the output of the submitted command, see
+    /// [`Prompt::with_shell_integration`]
+    ///
+    /// [`Prompt::with_shell_integration`]: crate::prelude::Prompt::with_shell_integration
+    pub fn mark_command_start(&mut self) -> crossterm::Result<()> {
+        self.out.borrow_mut().queue(Print(COMMAND_START))?;
+        self.out.borrow_mut().flush()?;
+        Ok(())
+    }
+
+    /// Ring the terminal bell
+    pub fn bell(&mut self) -> crossterm::Result<()> {
+        self.out.borrow_mut().queue(Print("\x07"))?;
+        self.out.borrow_mut().flush()?;
+        Ok(())
+    }
+
     pub fn newline(&mut self) -> crossterm::Result<()> {
         self.out.borrow_mut().queue(Print("\r\n"))?;
         self.out.borrow_mut().flush()?;
         Ok(())
     }
+
+    /// Print each line below the current one, leaving the cursor on a new line after them
+    pub fn print_lines(&mut self, lines: &[String]) -> crossterm::Result<()> {
+        self.newline()?;
+        for line in lines {
+            self.out.borrow_mut().queue(Print(line))?;
+            self.out.borrow_mut().queue(Print("\r\n"))?;
+        }
+        self.out.borrow_mut().flush()?;
+        Ok(())
+    }
+}
+
+/// OSC 133 mark printed where the prompt starts
+const PROMPT_START: &str = "\x1b]133;A\x1b\\";
+/// OSC 133 mark printed where the input starts, after the prompt
+const INPUT_START: &str = "\x1b]133;B\x1b\\";
+/// OSC 133 mark printed where the output of a command starts
+const COMMAND_START: &str = "\x1b]133;C\x1b\\";
+
+/// Column to draw a right prompt line at, or `None` if it doesn't fit after `used` columns
+///
+/// The prompt is aligned to the right edge of the terminal and at least one column is kept free
+/// between it and the line.
+fn right_prompt_column(term_width: u16, used: u16, right_len: u16) -> Option<u16> {
+    if right_len == 0 {
+        return None;
+    }
+    let column = term_width.checked_sub(right_len)?;
+    (column > used).then_some(column)
+}
+
+/// Marker drawn before each prompt line when [Theme::error_line_indicator] is enabled
+///
+/// A blank marker of the same width is used while the input is valid so the prompt doesn't shift.
+fn error_line_gutter(states: &States, sh: &Shell) -> Option<StyledContent<String>> {
+    let theme = states.get::<Theme>();
+    if !theme.error_line_indicator {
+        return None;
+    }
+
+    let command = states.get::<LineContents>().get_full_command();
+    if command.trim().is_empty() || sh.lang.parse_check(sh, states, &command) {
+        Some(StyledContent::new(ContentStyle::new(), String::from(" ")))
+    } else {
+        Some(StyledContent::new(
+            theme.error_line_style,
+            String::from("▎"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        io::Write,
+        rc::Rc,
+    };
+
+    use shrs_utils::StyledBuf;
+
+    use super::{
+        right_prompt_column, Painter, PromptCache, RenderedPrompt, COMMAND_START, INPUT_START,
+        PROMPT_START,
+    };
+    use crate::{
+        prelude::{
+            DefaultMenu, DefaultMenuState, LineContents, LineMode, Prompt, PromptEpoch,
+            PromptStatus, Shell, States, Theme,
+        },
+        readline::menu::Out,
+    };
+
+    /// Output shared with the test, so what was painted can be inspected
+    #[derive(Clone, Default)]
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Captured {
+        /// Everything written since the last call
+        fn take(&self) -> String {
+            String::from_utf8(std::mem::take(&mut *self.0.borrow_mut())).unwrap()
+        }
+    }
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Paint `input` after the prompt, returning the painter and its output
+    fn paint(prompt: Prompt, input: &str) -> (Painter, Captured) {
+        let mut sh = Shell::stub();
+        sh.prompt = prompt;
+        let mut states = States::default();
+        states.insert(LineContents::new());
+        states.insert(LineMode::Insert);
+        states.insert(PromptEpoch::default());
+        states.insert(PromptStatus::default());
+        states.insert(Theme::default());
+        let menu: DefaultMenuState = Box::new(DefaultMenu::default());
+
+        let captured = Captured::default();
+        let mut painter = Painter::default();
+        painter.out = RefCell::new(Out::new(Box::new(captured.clone())));
+        painter.set_term_size(80, 24);
+        painter
+            .paint(&states, &sh, &menu, &StyledBuf::new(input))
+            .unwrap();
+        (painter, captured)
+    }
+
+    /// Byte offset of each string in the output, panicking if one is missing
+    fn positions(output: &str, needles: &[&str]) -> Vec<usize> {
+        needles
+            .iter()
+            .map(|needle| {
+                output
+                    .find(needle)
+                    .unwrap_or_else(|| panic!("{needle:?} not in {output:?}"))
+            })
+            .collect()
+    }
+
+    fn marked_prompt() -> Prompt {
+        Prompt::from_left(|| StyledBuf::new("left")).with_input_marker(|| StyledBuf::new("> "))
+    }
+
+    #[test]
+    fn prompt_cache() {
+        let renders = Cell::new(0);
+        let render = || {
+            renders.set(renders.get() + 1);
+            RenderedPrompt {
+                left: StyledBuf::new("left"),
+                input_marker: StyledBuf::empty(),
+                right: StyledBuf::empty(),
+            }
+        };
+        let mut cache = PromptCache::default();
+        let mut epoch = PromptEpoch::default();
+
+        assert_eq!(
+            cache.get_or_render(epoch, false, render).left.content,
+            "left"
+        );
+        cache.get_or_render(epoch, false, render);
+        assert_eq!(renders.get(), 1);
+
+        epoch.invalidate();
+        cache.get_or_render(epoch, false, render);
+        assert_eq!(renders.get(), 2);
+
+        // rendered while pending and once more after the status resolves
+        cache.get_or_render(epoch, true, render);
+        cache.get_or_render(epoch, true, render);
+        cache.get_or_render(epoch, false, render);
+        cache.get_or_render(epoch, false, render);
+        assert_eq!(renders.get(), 5);
+
+        cache.invalidate();
+        cache.get_or_render(epoch, false, render);
+        assert_eq!(renders.get(), 6);
+    }
+
+    #[test]
+    fn input_marker_before_input() {
+        let (_, captured) = paint(marked_prompt(), "ls");
+        let output = captured.take();
+        let pos = positions(&output, &["left", "> ", "ls"]);
+        assert!(pos.windows(2).all(|pos| pos[0] < pos[1]));
+    }
+
+    #[test]
+    fn transient_keeps_input_marker() {
+        let (mut painter, captured) = paint(marked_prompt().with_transient(true), "ls");
+        captured.take();
+
+        painter.paint_transient(&StyledBuf::new("ls")).unwrap();
+        let output = captured.take();
+        assert!(!output.contains("left"));
+        let pos = positions(&output, &["> ", "ls"]);
+        assert!(pos[0] < pos[1]);
+    }
+
+    #[test]
+    fn shell_integration_marks() {
+        let (mut painter, captured) = paint(marked_prompt().with_shell_integration(true), "ls");
+        let output = captured.take();
+        // the input starts after the input marker, which is part of the prompt
+        let pos = positions(&output, &[PROMPT_START, "left", "> ", INPUT_START, "ls"]);
+        assert!(pos.windows(2).all(|pos| pos[0] < pos[1]));
+        assert!(!output.contains(COMMAND_START));
+
+        painter.mark_command_start().unwrap();
+        assert_eq!(captured.take(), COMMAND_START);
+
+        // nothing is marked without shell integration
+        let (_, captured) = paint(marked_prompt(), "ls");
+        let output = captured.take();
+        assert!(!output.contains(PROMPT_START) && !output.contains(INPUT_START));
+    }
+
+    #[test]
+    fn right_prompt_fits() {
+        assert_eq!(right_prompt_column(80, 10, 5), Some(75));
+        assert_eq!(right_prompt_column(20, 11, 8), Some(12));
+        // touching or overlapping the line
+        assert_eq!(right_prompt_column(20, 12, 8), None);
+        assert_eq!(right_prompt_column(20, 0, 30), None);
+        assert_eq!(right_prompt_column(80, 0, 0), None);
+    }
 }
@@ -44,11 +44,13 @@
 //! ```
 //!
 
+mod status;
 mod utils;
 use std::marker::PhantomData;
 
-use crossterm::style::Stylize;
+use crossterm::style::{StyledContent, Stylize};
 use shrs_utils::{styled_buf, StyledBuf};
+pub use status::*;
 pub use utils::*;
 
 use super::super::state::*;
@@ -60,9 +62,21 @@ pub trait PromptFn {
 }
 
 /// [`Prompt`] is split into right and left where each is a [`PromptFn`]
+///
+/// When a command spans multiple lines, the continuation prompt is displayed in place of the left
+/// prompt for every line after the first one (similar to `PS2` in other shells).
 pub struct Prompt {
     pub prompt_left: Box<dyn PromptFn>,
     pub prompt_right: Box<dyn PromptFn>,
+    pub continuation: Box<dyn PromptFn>,
+    /// Erase the right prompt when the line is submitted, see [`Prompt::with_transient_right`]
+    pub transient_right: bool,
+    /// Start of the input, drawn after the left prompt, see [`Prompt::with_input_marker`]
+    pub input_marker: Option<Box<dyn PromptFn>>,
+    /// Erase all but the input marker when the line is submitted, see [`Prompt::with_transient`]
+    pub transient: bool,
+    /// Mark where the prompt, input and output start, see [`Prompt::with_shell_integration`]
+    pub shell_integration: bool,
 }
 
 impl Prompt {
@@ -74,6 +88,11 @@ impl Prompt {
         Self {
             prompt_left: Box::new(prompt_left.into_prompt()),
             prompt_right: Box::new(prompt_right.into_prompt()),
+            continuation: Box::new(default_continuation_prompt.into_prompt()),
+            transient_right: true,
+            input_marker: None,
+            transient: false,
+            shell_integration: false,
         }
     }
     pub fn from_left<I, L: PromptFn + 'static>(
@@ -82,6 +101,11 @@ impl Prompt {
         Self {
             prompt_left: Box::new(prompt_left.into_prompt()),
             prompt_right: Box::new((|| StyledBuf::empty()).into_prompt()),
+            continuation: Box::new(default_continuation_prompt.into_prompt()),
+            transient_right: true,
+            input_marker: None,
+            transient: false,
+            shell_integration: false,
         }
     }
     pub fn from_right<I, R: PromptFn + 'static>(
@@ -90,8 +114,82 @@ impl Prompt {
         Self {
             prompt_left: Box::new((|| StyledBuf::empty()).into_prompt()),
             prompt_right: Box::new(prompt_right.into_prompt()),
+            continuation: Box::new(default_continuation_prompt.into_prompt()),
+            transient_right: true,
+            input_marker: None,
+            transient: false,
+            shell_integration: false,
         }
     }
+
+    /// Default prompt split into an info line with the user and full working directory, followed
+    /// by a separate line for input
+    pub fn default_two_line() -> Self {
+        Prompt::from_left(default_prompt_two_line_left)
+    }
+
+    /// Replace the prompt displayed for every line after the first in a multiline command
+    pub fn with_continuation<I, C: PromptFn + 'static>(
+        mut self,
+        continuation: impl IntoPromptFn<I, PromptFn = C>,
+    ) -> Self {
+        self.continuation = Box::new(continuation.into_prompt());
+        self
+    }
+
+    /// Choose whether the right prompt is erased when the line is submitted
+    ///
+    /// It is erased by default so the scrollback only shows the commands that were run. When it is
+    /// kept, it stays on screen as it was last drawn.
+    pub fn with_transient_right(mut self, transient_right: bool) -> Self {
+        self.transient_right = transient_right;
+        self
+    }
+
+    /// Draw the part of the prompt that the input follows, such as `> `, separately from the left
+    /// prompt
+    ///
+    /// The left prompt is then only decoration before the marker. Without a marker the whole left
+    /// prompt is decoration, which is erased along with the rest by a transient prompt.
+    pub fn with_input_marker<I, M: PromptFn + 'static>(
+        mut self,
+        input_marker: impl IntoPromptFn<I, PromptFn = M>,
+    ) -> Self {
+        self.input_marker = Some(Box::new(input_marker.into_prompt()));
+        self
+    }
+
+    /// Choose whether the prompt is erased when the line is submitted, so the scrollback only
+    /// shows the input marker and the command
+    pub fn with_transient(mut self, transient: bool) -> Self {
+        self.transient = transient;
+        self
+    }
+
+    /// Choose whether to print the OSC 133 escape sequences that mark where the prompt, the input
+    /// and the output of each command start
+    ///
+    /// Terminals that support them can jump between prompts or select the output of a command.
+    pub fn with_shell_integration(mut self, shell_integration: bool) -> Self {
+        self.shell_integration = shell_integration;
+        self
+    }
+}
+
+/// Counter of prompt epochs, the rendered prompt is reused until the epoch changes
+///
+/// The prompt is rendered once whenever the shell starts reading a new line, such as after a
+/// command completes or the directory changes, and when the terminal is resized, the line mode
+/// changes or a [`PromptStatus`] is pending. Anything else that changes what the prompt displays
+/// while the line is being edited should call [`PromptEpoch::invalidate`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PromptEpoch(u64);
+
+impl PromptEpoch {
+    /// Render the prompt again on the next repaint
+    pub fn invalidate(&mut self) {
+        self.0 = self.0.wrapping_add(1);
+    }
 }
 
 impl Default for Prompt {
@@ -102,7 +200,40 @@ impl Default for Prompt {
 
 /// Left side of bash style default prompt
 fn default_prompt_left() -> StyledBuf {
-    styled_buf!(" ", top_pwd().white().bold(), " > ")
+    styled_buf!(
+        " ",
+        top_pwd().white().bold(),
+        " ",
+        default_prompt_symbol(),
+        " "
+    )
+}
+
+/// Left side of the default prompt with the info line above the input line
+fn default_prompt_two_line_left() -> StyledBuf {
+    styled_buf!(
+        " ",
+        username().map(|u| u.blue()),
+        " ",
+        full_pwd().white().bold(),
+        "\n ",
+        default_prompt_symbol(),
+        " "
+    )
+}
+
+/// `>` for normal users and a red `#` when running as root
+fn default_prompt_symbol() -> StyledContent<&'static str> {
+    if is_root() {
+        "#".red().bold()
+    } else {
+        ">".stylize()
+    }
+}
+
+/// Default continuation prompt
+fn default_continuation_prompt() -> StyledBuf {
+    styled_buf!(" ", "…".dark_grey(), " ")
 }
 
 pub trait IntoPromptFn<Input> {
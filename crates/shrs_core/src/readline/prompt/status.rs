@@ -0,0 +1,134 @@
+//! Transient status indicators for parts of the prompt that are computed in the background
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crossterm::style::Stylize;
+use shrs_utils::StyledBuf;
+
+/// Frames of the spinner displayed while a status is pending
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// How often the prompt is repainted while a status is pending
+pub(crate) const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+
+enum Status {
+    Pending,
+    Resolved(StyledBuf),
+}
+
+#[derive(Default)]
+struct Statuses {
+    statuses: HashMap<String, Status>,
+    /// Spinner frame to display, advanced on every repaint
+    frame: usize,
+}
+
+/// Parts of the prompt that are computed asynchronously
+///
+/// A plugin marks its part of the prompt as pending with [PromptStatus::set_pending] and starts
+/// computing it on another thread, which calls [PromptStatus::resolve] once the result is ready.
+/// The prompt renders a spinner for pending statuses with [PromptStatus::get], and is repainted
+/// periodically until every status is resolved.
+///
+/// [PromptStatus] is a cheap handle that can be cloned and sent to other threads.
+/// ```
+/// # use shrs_core::prelude::*;
+/// # use shrs_utils::*;
+/// fn prompt_left(status: State<PromptStatus>) -> StyledBuf {
+///     styled_buf!(status.get("git").unwrap_or_else(StyledBuf::empty), " > ")
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct PromptStatus {
+    inner: Arc<Mutex<Statuses>>,
+}
+
+impl PromptStatus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Display a spinner for the status until it is resolved
+    pub fn set_pending(&self, key: &str) {
+        self.inner
+            .lock()
+            .unwrap()
+            .statuses
+            .insert(key.to_string(), Status::Pending);
+    }
+
+    /// Replace the spinner with the computed contents
+    pub fn resolve(&self, key: &str, contents: StyledBuf) {
+        self.inner
+            .lock()
+            .unwrap()
+            .statuses
+            .insert(key.to_string(), Status::Resolved(contents));
+    }
+
+    /// Stop displaying the status
+    pub fn remove(&self, key: &str) {
+        self.inner.lock().unwrap().statuses.remove(key);
+    }
+
+    /// Contents to render for the status, which is a spinner frame if it is still pending
+    pub fn get(&self, key: &str) -> Option<StyledBuf> {
+        let inner = self.inner.lock().unwrap();
+        match inner.statuses.get(key)? {
+            Status::Pending => {
+                let frame = SPINNER_FRAMES[inner.frame % SPINNER_FRAMES.len()];
+                Some(frame.dark_grey().into())
+            },
+            Status::Resolved(contents) => Some(contents.clone()),
+        }
+    }
+
+    /// Check if any status is still waiting on its result
+    pub fn is_pending(&self) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .statuses
+            .values()
+            .any(|status| matches!(status, Status::Pending))
+    }
+
+    /// Advance the spinner to the next frame
+    pub(crate) fn tick(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.frame = inner.frame.wrapping_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shrs_utils::StyledBuf;
+
+    use super::{PromptStatus, SPINNER_FRAMES};
+
+    #[test]
+    fn pending_then_resolved() {
+        let status = PromptStatus::new();
+        assert!(status.get("git").is_none());
+
+        status.set_pending("git");
+        assert!(status.is_pending());
+        assert_eq!(status.get("git").unwrap().content, SPINNER_FRAMES[0]);
+        status.tick();
+        assert_eq!(status.get("git").unwrap().content, SPINNER_FRAMES[1]);
+
+        // resolved from another thread
+        let handle = status.clone();
+        std::thread::spawn(move || {
+            handle.resolve("git", StyledBuf::new("main"));
+        })
+        .join()
+        .unwrap();
+        assert!(!status.is_pending());
+        assert_eq!(status.get("git").unwrap().content, "main");
+    }
+}
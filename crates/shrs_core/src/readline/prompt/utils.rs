@@ -53,6 +53,17 @@ pub fn hostname() -> anyhow::Result<String> {
     Ok(encoded)
 }
 
+/// Check if the shell is running as root, useful to warn that commands have elevated privileges
+///
+/// Uses the effective user id, so this is also true inside of `sudo`. Always false on platforms
+/// without user ids.
+pub fn is_root() -> bool {
+    #[cfg(unix)]
+    return nix::unistd::geteuid().is_root();
+    #[cfg(not(unix))]
+    return false;
+}
+
 // /// Get the current time
 // pub fn current_time() {
 //     todo!()
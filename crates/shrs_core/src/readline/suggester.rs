@@ -1,11 +1,40 @@
 //! Inline suggestions
 //!
-//! Suggester is a handler that provides inline suggestions.
+//! Suggester is a handler that provides inline suggestions. [`DefaultSuggester`] suggests from
+//! history, while [`CompletionSuggester`] additionally falls back to the completer.
 //!
-//! The suggestion is styled using the `suggestion_style` field in `Theme`.
+//! The suggestion is styled using the `suggestion_style` field in `Theme`, and is only displayed
+//! while the cursor is at the end of the line. Suggestions can be turned off at runtime through
+//! [`SuggesterState`], which also holds the keys used to accept them.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use shrs_utils::longest_common_prefix;
 
 use super::line::LineContents;
-use crate::prelude::{Shell, States};
+use crate::prelude::{Completer, CompletionCtx, ReplaceMethod, Shell, States};
+
+/// Runtime configuration for inline suggestions
+pub struct SuggesterState {
+    /// Whether suggestions are displayed
+    pub enabled: bool,
+    /// Key that inserts the suggestion into the line without running it
+    pub accept_key: KeyEvent,
+    /// Key that inserts the suggestion and runs the line right away
+    ///
+    /// This is only used while a suggestion is displayed, otherwise the key behaves as usual. Set
+    /// to [None] to never run suggestions without confirming them first.
+    pub accept_and_run_key: Option<KeyEvent>,
+}
+
+impl Default for SuggesterState {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            accept_key: KeyEvent::new(KeyCode::Right, KeyModifiers::NONE),
+            accept_and_run_key: Some(KeyEvent::new(KeyCode::Char('j'), KeyModifiers::CONTROL)),
+        }
+    }
+}
 
 pub trait Suggester {
     fn suggest(&self, sh: &Shell, states: &States) -> Option<String>;
@@ -29,3 +58,46 @@ impl Suggester for DefaultSuggester {
         None
     }
 }
+
+/// Inline `Suggester` that falls back to completions when there is no matching history item
+///
+/// The completer is queried for the word currently being typed, and the longest common prefix of
+/// all the candidates is suggested. This gives useful suggestions for commands that have never
+/// been ran before.
+pub struct CompletionSuggester;
+impl Suggester for CompletionSuggester {
+    fn suggest(&self, sh: &Shell, states: &States) -> Option<String> {
+        if let Some(suggestion) = DefaultSuggester.suggest(sh, states) {
+            return Some(suggestion);
+        }
+
+        let res = states.get::<LineContents>().get_full_command();
+        // only suggest while in the middle of typing a word
+        if res.is_empty() || res.ends_with(char::is_whitespace) {
+            return None;
+        }
+
+        let args = res.split(' ').map(|s| s.to_owned()).collect::<Vec<_>>();
+        let cur_word = args.last().cloned().unwrap_or_default();
+        let completions = states
+            .get_mut::<Box<dyn Completer>>()
+            .complete(&CompletionCtx::new(args));
+
+        // all candidates need to be replaced the same way for a common prefix to make sense
+        let replace_method = completions.first()?.replace_method;
+        if completions
+            .iter()
+            .any(|c| c.replace_method != replace_method)
+        {
+            return None;
+        }
+        let best =
+            longest_common_prefix(completions.iter().map(|c| c.completion.as_str()).collect());
+
+        let suggestion = match replace_method {
+            ReplaceMethod::Replace => format!("{}{best}", &res[..res.len() - cur_word.len()]),
+            ReplaceMethod::Append => format!("{res}{best}"),
+        };
+        (suggestion.len() > res.len() && suggestion.starts_with(&res)).then_some(suggestion)
+    }
+}
@@ -0,0 +1,47 @@
+//! Handing the terminal over to other programs while a line is being read
+
+use std::io::stdout;
+
+use crossterm::{
+    event::{DisableBracketedPaste, EnableBracketedPaste},
+    execute,
+    style::Print,
+    terminal::{disable_raw_mode, enable_raw_mode},
+};
+
+/// Give a program full control of the terminal while running the function
+///
+/// Raw mode is disabled so programs like pickers or `$EDITOR` behave like they were started from
+/// the shell. They start on a new line, and the terminal is put back into raw mode afterwards,
+/// even if the function panics. The line is repainted below once the key handler returns.
+/// ```no_run
+/// # use shrs_core::prelude::*;
+/// let status = suspend_terminal(|| std::process::Command::new("vim").status()).unwrap();
+/// ```
+pub fn suspend_terminal<T>(f: impl FnOnce() -> T) -> anyhow::Result<T> {
+    execute!(stdout(), Print("\r\n"))?;
+    without_raw_mode(f)
+}
+
+/// Run the function with raw mode and bracketed paste disabled, restoring them afterwards
+pub(crate) fn without_raw_mode<T>(f: impl FnOnce() -> T) -> anyhow::Result<T> {
+    // restores the terminal if the function panics
+    struct Restore;
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let _ = enable_raw_mode();
+            let _ = execute!(stdout(), EnableBracketedPaste);
+        }
+    }
+
+    execute!(stdout(), DisableBracketedPaste)?;
+    disable_raw_mode()?;
+
+    let restore = Restore;
+    let res = f();
+    std::mem::forget(restore);
+
+    enable_raw_mode()?;
+    execute!(stdout(), EnableBracketedPaste)?;
+    Ok(res)
+}
@@ -0,0 +1,202 @@
+//! Record and replay interactive sessions
+//!
+//! While recording, every terminal event read by the line editor is logged along with the output
+//! of each command to a file in JSON lines format. Replaying feeds the recorded events back into
+//! the line editor as if they were typed, which is useful for reproducing bugs and for demos.
+//!
+//! Sessions are recorded with the `record` builtin and replayed with the `replay` builtin.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crossterm::event::Event;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::CmdOutput;
+
+/// A single line of a recorded session
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SessionEntry {
+    /// Terminal event read by the line editor
+    Event {
+        /// Milliseconds since recording started
+        time_ms: u64,
+        event: Event,
+    },
+    /// Output of a command once it finished running
+    Output {
+        /// Milliseconds since recording started
+        time_ms: u64,
+        command: String,
+        status: i32,
+        stdout: String,
+        stderr: String,
+    },
+}
+
+/// Writes the events of the current session to a file
+#[derive(Default)]
+pub struct SessionRecorder {
+    recording: Option<(BufWriter<File>, Instant)>,
+}
+
+impl SessionRecorder {
+    /// Start recording to a file, overwriting it if it exists
+    pub fn start(&mut self, path: &Path) -> std::io::Result<()> {
+        let file = File::create(path)?;
+        self.recording = Some((BufWriter::new(file), Instant::now()));
+        Ok(())
+    }
+
+    /// Stop recording and flush the recorded entries
+    pub fn stop(&mut self) -> std::io::Result<()> {
+        match self.recording.take() {
+            Some((mut file, _)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Log an event read by the line editor
+    pub fn record_event(&mut self, event: &Event) -> anyhow::Result<()> {
+        self.record(|time_ms| SessionEntry::Event {
+            time_ms,
+            event: event.clone(),
+        })
+    }
+
+    /// Log the output of a command
+    pub fn record_output(&mut self, command: &str, output: &CmdOutput) -> anyhow::Result<()> {
+        self.record(|time_ms| SessionEntry::Output {
+            time_ms,
+            command: command.to_string(),
+            status: output.status.code().unwrap_or(-1),
+            stdout: output.stdout.clone(),
+            stderr: output.stderr.clone(),
+        })
+    }
+
+    fn record(&mut self, entry: impl FnOnce(u64) -> SessionEntry) -> anyhow::Result<()> {
+        let Some((file, start)) = &mut self.recording else {
+            return Ok(());
+        };
+
+        let entry = entry(start.elapsed().as_millis() as u64);
+        serde_json::to_writer(&mut *file, &entry)?;
+        writeln!(file)?;
+        // flush so the recording is usable even if the shell crashes
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// Recorded events that are waiting to be fed back into the line editor
+#[derive(Default)]
+pub struct SessionReplay {
+    events: VecDeque<(u64, Event)>,
+    /// Wait between events to match the timing of the recording
+    realtime: bool,
+    last_ms: u64,
+}
+
+impl SessionReplay {
+    /// Replay the events of recorded entries, command outputs are skipped
+    pub fn new(entries: Vec<SessionEntry>, realtime: bool) -> Self {
+        let events = entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                SessionEntry::Event { time_ms, event } => Some((time_ms, event)),
+                SessionEntry::Output { .. } => None,
+            })
+            .collect();
+
+        Self {
+            events,
+            realtime,
+            last_ms: 0,
+        }
+    }
+
+    /// Read a session recorded by [SessionRecorder]
+    pub fn load(path: &Path, realtime: bool) -> anyhow::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        let mut entries = vec![];
+        for line in file.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line)?);
+        }
+        Ok(Self::new(entries, realtime))
+    }
+
+    /// Take the next recorded event, returns [None] once the replay is finished
+    pub fn next_event(&mut self) -> Option<Event> {
+        let (time_ms, event) = self.events.pop_front()?;
+        if self.realtime {
+            std::thread::sleep(Duration::from_millis(time_ms.saturating_sub(self.last_ms)));
+        }
+        self.last_ms = time_ms;
+        Some(event)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+    use super::{SessionEntry, SessionReplay};
+
+    #[test]
+    fn replay_entries() {
+        let key = Event::Key(KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE));
+        let entries = vec![
+            SessionEntry::Event {
+                time_ms: 5,
+                event: key.clone(),
+            },
+            SessionEntry::Output {
+                time_ms: 10,
+                command: "l".into(),
+                status: 0,
+                stdout: "out".into(),
+                stderr: String::new(),
+            },
+            SessionEntry::Event {
+                time_ms: 20,
+                event: Event::Paste("s".into()),
+            },
+        ];
+
+        // entries survive being written as json lines
+        let lines = entries
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap())
+            .collect::<Vec<_>>();
+        let parsed = lines
+            .iter()
+            .map(|line| serde_json::from_str::<SessionEntry>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(parsed, entries);
+
+        let mut replay = SessionReplay::new(parsed, false);
+        assert_eq!(replay.next_event(), Some(key));
+        assert_eq!(replay.next_event(), Some(Event::Paste("s".into())));
+        assert!(replay.is_finished());
+        assert_eq!(replay.next_event(), None);
+    }
+}
@@ -5,6 +5,7 @@
 
 use std::{
     env,
+    os::unix::process::ExitStatusExt,
     path::{Path, PathBuf},
     process::ExitStatus,
     time::Instant,
@@ -15,10 +16,14 @@ use dirs::home_dir;
 use log::{info, warn};
 use pino_deref::Deref;
 use shrs_job::JobManager;
+use shrs_lang::{expand_words, Lexer, NoBuiltins, Token};
+use shrs_utils::{colors_enabled_from_env, set_colors_enabled};
 
 use crate::{
+    alias::{expand_command_aliases, expand_global_aliases, split_words},
     commands::{Command, Commands},
     history::History,
+    motd::show_motd,
     prelude::*,
     state::States,
 };
@@ -31,6 +36,33 @@ pub struct StartupTime(Instant);
 #[derive(Deref)]
 pub struct PluginMetas(Vec<PluginMeta>);
 
+/// Version and build information of shrs
+///
+/// Useful for plugins and scripts that need to check compatibility. The version is also exposed
+/// to the shell with the `SHRS_VERSION` environment variable.
+#[derive(Debug, Clone)]
+pub struct BuildInfo {
+    /// Version of shrs the shell was built with
+    pub version: &'static str,
+    /// Target architecture
+    pub arch: &'static str,
+    /// Target operating system
+    pub os: &'static str,
+    /// If shrs was built with debug assertions enabled
+    pub debug: bool,
+}
+
+impl Default for BuildInfo {
+    fn default() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            arch: std::env::consts::ARCH,
+            os: std::env::consts::OS,
+            debug: cfg!(debug_assertions),
+        }
+    }
+}
+
 /// Container for shell components
 ///
 /// This struct can be queried for in any handler (insert link), allowing for easy access to shell
@@ -99,6 +131,42 @@ impl Shell {
             let _ = sh.lang.eval(sh, states, cmd_str.clone());
         });
     }
+
+    /// Shell made of the default parts, except for a language that can't evaluate anything, for
+    /// tests of code that takes a shell
+    #[cfg(test)]
+    pub(crate) fn stub() -> Shell {
+        Shell {
+            builtins: Builtins::default(),
+            lang: Box::new(StubLang),
+            keybindings: Keybindings::new(),
+            hooks: Hooks::default(),
+            prompt: Prompt::default(),
+            highlighter: Box::new(SyntaxHighlighter::default()),
+            suggester: Box::new(DefaultSuggester),
+            history: Box::new(DefaultHistory::default()),
+            cmd: Commands::new(),
+        }
+    }
+}
+
+/// Language of [Shell::stub]
+#[cfg(test)]
+struct StubLang;
+
+#[cfg(test)]
+impl Lang for StubLang {
+    fn eval(&self, _sh: &Shell, _ctx: &States, _cmd: String) -> anyhow::Result<CmdOutput> {
+        anyhow::bail!("commands can't be evaluated in a stub shell")
+    }
+
+    fn name(&self) -> String {
+        "stub".into()
+    }
+
+    fn needs_line_check(&self, _sh: &Shell, _ctx: &States) -> bool {
+        false
+    }
 }
 
 /// Runtime context for the shell
@@ -114,7 +182,8 @@ pub struct Runtime {
     pub env: Env,
     /// Name of the shell or shell script
     pub name: String,
-    /// Arguments this shell was called with
+    /// Positional parameters (`$1`, `$2`, ...), which are the arguments of the function being
+    /// called while a builtin runs in one
     pub args: Vec<String>,
     /// Exit status of most recent pipeline
     pub exit_status: i32,
@@ -124,6 +193,21 @@ pub struct Runtime {
     // pub functions: HashMap<String, Box<ast::Command>>,
 }
 
+impl Runtime {
+    /// Runtime with an empty environment in the given directory, for tests that need one
+    #[cfg(test)]
+    pub(crate) fn stub(working_dir: impl Into<PathBuf>) -> Runtime {
+        Runtime {
+            working_dir: working_dir.into(),
+            env: Env::default(),
+            name: "shrs".into(),
+            args: vec![],
+            exit_status: 0,
+            config_dir: PathBuf::new(),
+        }
+    }
+}
+
 /// Unified shell config struct
 #[derive(Builder)]
 #[builder(name = "ShellBuilder", pattern = "owned")]
@@ -187,6 +271,10 @@ pub struct ShellConfig {
     #[builder(default = "home_dir().unwrap().join(\".config/shrs\")")]
     pub config_dir: PathBuf,
 
+    /// Message shown on startup, see [`crate::motd`]
+    #[builder(default = "Motd::default()")]
+    pub motd: Motd,
+
     /// Keybindings, see [`crate::keybinding`]
     #[builder(default = "Keybindings::new()")]
     #[builder(setter(custom))]
@@ -220,6 +308,11 @@ pub struct ShellConfig {
     /// Buffer snippets, see [`crate::readline::snippet`]
     #[builder(default = "Snippets::default()")]
     snippets: Snippets,
+
+    /// Maximum number of bytes of stdout and stderr collected into [`CmdOutput`] for each
+    /// command, see [`OutputWriter`]
+    #[builder(default = "DEFAULT_CAPTURE_LIMIT")]
+    pub capture_limit: usize,
 }
 
 impl ShellBuilder {
@@ -279,7 +372,8 @@ impl ShellConfig {
         // calls in ShellBuilder, so we are sort of defining the full default here. Maybe end
         // up implementing Default for Context and Runtime
 
-        // run plugins first
+        // run plugins first, so the builtins, hooks and states they add are moved into the shell
+        // below
         // TODO ownership issue here since other plugins can technically add plugins during init
         // process
         let plugins = self.plugins.drain(..).collect::<Vec<_>>();
@@ -301,28 +395,44 @@ impl ShellConfig {
                 }
             }
         }
+        // respect NO_COLOR and TERM=dumb, this is re-evaluated whenever those variables change
+        set_colors_enabled(colors_enabled_from_env());
+
+        // the variables the shell was started with are passed on to child processes
+        if let Err(e) = self.env.load() {
+            warn!("Failed to load environment variables with {}", e);
+        }
+
+        let build_info = BuildInfo::default();
+        if let Err(e) = self.env.set("SHRS_VERSION", build_info.version) {
+            warn!("Failed to set SHRS_VERSION with {}", e);
+        }
+
+        update_term_size(&mut self.env);
+
         let rt = Runtime {
             env: self.env,
             working_dir: std::env::current_dir().unwrap(),
             // TODO currently hardcoded
             name: "shrs".into(),
-            // TODO currently unused (since we have not implemented functions etc)
             args: vec![],
             exit_status: 0,
             config_dir: self.config_dir,
             // functions: self.functions,
         };
         self.states.insert(rt);
+        self.states.insert(self.motd);
+        self.hooks.insert(show_motd);
         self.states.insert(self.alias);
-        self.states.insert(OutputWriter::new(
-            self.theme.out_style,
-            self.theme.err_style,
-        ));
+        let mut out = OutputWriter::new(self.theme.out_style, self.theme.err_style);
+        out.set_capture_limit(self.capture_limit);
+        self.states.insert(out);
         self.states.insert(self.theme);
         self.states.insert(Jobs::default());
         self.states.insert(PromptContentQueue::new());
         self.states.insert(self.completer);
         self.states.insert(StartupTime(Instant::now()));
+        self.states.insert(build_info);
         self.states.insert(PluginMetas(
             plugins
                 .iter()
@@ -330,11 +440,42 @@ impl ShellConfig {
                 .collect::<Vec<PluginMeta>>(),
         ));
         self.states.insert(JobManager::default());
+        #[cfg(feature = "serde")]
+        {
+            self.states
+                .insert(crate::session::SessionRecorder::default());
+            self.states.insert(crate::session::SessionReplay::default());
+        }
 
         //Line states
         self.states.insert(self.buffer_history);
         self.states.insert(self.menu);
         self.states.insert(self.snippets);
+        // these may have been configured with `with_state`
+        if self.states.try_get::<SuggesterState>().is_err() {
+            self.states.insert(SuggesterState::default());
+        }
+        if self.states.try_get::<MenuKeys>().is_err() {
+            self.states.insert(MenuKeys::default());
+        }
+        if self.states.try_get::<CompletionMode>().is_err() {
+            self.states.insert(CompletionMode::default());
+        }
+        if self.states.try_get::<PathExecCache>().is_err() {
+            self.states.insert(PathExecCache::default());
+        }
+        if self.states.try_get::<PromptStatus>().is_err() {
+            self.states.insert(PromptStatus::default());
+        }
+        if self.states.try_get::<PromptEpoch>().is_err() {
+            self.states.insert(PromptEpoch::default());
+        }
+        if self.states.try_get::<ShellOptions>().is_err() {
+            self.states.insert(ShellOptions::default());
+        }
+        if self.states.try_get::<DirStack>().is_err() {
+            self.states.insert(DirStack::default());
+        }
 
         let mut sh = Shell {
             builtins: self.builtins,
@@ -387,26 +528,47 @@ fn run_shell(
     loop {
         let line = readline.read_line(sh, states);
 
-        // attempt to expand alias
-        // TODO IFS
-        let mut words = line
-            .split(' ')
-            .map(|s| s.trim_start_matches("\\\n").trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect::<Vec<_>>();
-        if let Some(first) = words.get_mut(0) {
+        // attempt to expand alias, the line is split into words like the language does, IFS only
+        // splits the results of expansions
+        let unexpanded = split_words(&line.replace("\\\n", ""), " \t\n");
+        let lookup = |word: &str| {
             let alias_ctx = AliasRuleCtx {
-                alias_name: first,
+                alias_name: word,
                 sh,
                 states,
             };
-
             // Currently only use the last alias, can also render a menu
-            if let Some(expanded) = states.get::<Alias>().get(&alias_ctx).last() {
-                *first = expanded.to_string();
-            }
+            states
+                .get::<Alias>()
+                .get(&alias_ctx)
+                .last()
+                .map(|expanded| expanded.to_string())
+        };
+        let mut words = expand_command_aliases(&unexpanded, &lookup);
+        if words.len() > 1 {
+            let lookup = |word: &str| {
+                let alias_ctx = AliasRuleCtx {
+                    alias_name: word,
+                    sh,
+                    states,
+                };
+                states
+                    .get::<Alias>()
+                    .get_global(&alias_ctx)
+                    .last()
+                    .map(|expanded| expanded.to_string())
+            };
+            let mut args = expand_global_aliases(&words[1..], &lookup);
+            words.truncate(1);
+            words.append(&mut args);
         }
-        let line = words.join(" ");
+        // only rebuild the line from the words if an alias was expanded, since spacing and
+        // newlines are lost when splitting it (here documents depend on them)
+        let line = if words == unexpanded {
+            line.replace("\\\n", "")
+        } else {
+            words.join(" ")
+        };
 
         // TODO not sure if hook should run here (since not all vars are expanded yet)
         let hook_ctx = BeforeCommandCtx {
@@ -415,28 +577,39 @@ fn run_shell(
         };
         sh.run_hooks_in_core(states, hook_ctx);
 
-        // Retrieve command name or return immediately (empty command)
+        // the terminal may have been resized since the line was read
+        update_term_size(&mut states.get_mut::<Runtime>().env);
+
+        // Retrieve command name or return immediately (empty command or only a comment)
         let cmd_name = match words.first() {
-            Some(cmd_name) => cmd_name,
-            None => continue,
+            Some(cmd_name) if !cmd_name.starts_with('#') => cmd_name,
+            _ => continue,
         };
 
+        // lines with more than one command are evaluated by the language, which runs the
+        // builtins in them itself
         let builtin_cmd = sh
             .builtins
             .iter()
             .find(|(builtin_name, _)| *builtin_name == cmd_name)
-            .map(|(_, builtin_cmd)| builtin_cmd);
+            .map(|(_, builtin_cmd)| builtin_cmd)
+            .filter(|_| !needs_lang(&line));
 
+        let is_builtin = builtin_cmd.is_some();
         let mut cmd_output: CmdOutput = CmdOutput::error();
         states.get_mut::<OutputWriter>().begin_collecting();
         if let Some(builtin_cmd) = builtin_cmd {
-            let output = builtin_cmd.run(sh, states, &words);
+            let output = builtin_args(states, builtin_cmd.as_ref(), &line).and_then(|args| {
+                // the language prints the commands it runs for `set -x` itself
+                if states.get::<ShellOptions>().xtrace {
+                    eprintln!("+ {}", args.join(" "));
+                }
+                builtin_cmd.run(sh, states, &args)
+            });
             match output {
                 Ok(o) => cmd_output = o,
                 Err(e) => eprintln!("error: {e:?}"),
             }
-
-            sh.apply_queue(states);
         } else {
             let output = sh.lang.eval(sh, states, line.clone());
             match output {
@@ -444,9 +617,26 @@ fn run_shell(
                 Err(e) => eprintln!("error: {e:?}"),
             }
         }
-        let (out, err) = states.get_mut::<OutputWriter>().end_collecting();
-        cmd_output.stdout(out);
-        cmd_output.stderr(err);
+        // builtins run by the language can queue commands too
+        sh.apply_queue(states);
+        let (out, err, truncated) = states.get_mut::<OutputWriter>().end_collecting();
+        cmd_output.stdout_bytes(out).stderr_bytes(err);
+        cmd_output.truncated |= truncated;
+
+        // commands killed by a signal report 128 + the signal number like other shells
+        states.get_mut::<Runtime>().exit_status = cmd_output
+            .status
+            .code()
+            .or_else(|| cmd_output.status.signal().map(|signal| 128 + signal))
+            .unwrap_or(1);
+
+        #[cfg(feature = "serde")]
+        if let Err(e) = states
+            .get_mut::<crate::session::SessionRecorder>()
+            .record_output(&line, &cmd_output)
+        {
+            warn!("Failed to record command output {e}");
+        }
 
         sh.run_hooks_in_core(
             states,
@@ -456,6 +646,14 @@ fn run_shell(
             },
         );
 
+        // `set -e` exits the shell once a builtin fails, the language decides this for the
+        // commands it evaluates since failures in conditions don't count
+        let exit_code = states.get::<Runtime>().exit_status;
+        if is_builtin && exit_code != 0 && states.get::<ShellOptions>().errexit {
+            sh.run_hooks_in_core(states, ExitCtx { exit_code });
+            std::process::exit(exit_code);
+        }
+
         // check up on running jobs
         let mut exit_statuses = vec![];
         states.get_mut::<Jobs>().retain(|status: ExitStatus| {
@@ -466,6 +664,74 @@ fn run_shell(
     }
 }
 
+/// If a line joins several commands with `;`, `&`, `|`, `&&` or `||`, or has redirections or
+/// process substitutions, which the language handles even if the line starts with a builtin
+fn needs_lang(line: &str) -> bool {
+    Lexer::new(line).flatten().any(|(_, token, _)| {
+        matches!(
+            token,
+            Token::SEMI
+                | Token::AMP
+                | Token::PIPE
+                | Token::AND_IF
+                | Token::OR_IF
+                | Token::LESS
+                | Token::GREAT
+                | Token::DGREAT
+                | Token::LESSAND
+                | Token::GREATAND
+                | Token::DLESS
+                | Token::DLESSDASH
+                | Token::CLOBBER
+                | Token::LESSGREAT
+                | Token::ANDGREAT
+                | Token::ANDDGREAT
+                | Token::IO_NUMBER(_)
+        ) || matches!(token, Token::WORD(word) if word.starts_with("<(") || word.starts_with(">("))
+    })
+}
+
+/// Words of a line expanded like the arguments of a command, which builtins are called with
+///
+/// Builtins that expand their arguments themselves get the words after the name as typed.
+pub(crate) fn builtin_args(
+    states: &States,
+    builtin: &dyn Builtin,
+    line: &str,
+) -> anyhow::Result<Vec<String>> {
+    let job_manager = &mut states.get_mut::<JobManager>();
+    let options = states.get::<ShellOptions>();
+    if !builtin.expands_args() {
+        return Ok(expand_words(job_manager, &options, &mut NoBuiltins, line)?);
+    }
+
+    let mut words = Lexer::new(line)
+        .flatten()
+        .filter_map(|(_, token, _)| match token {
+            Token::WORD(word) | Token::ASSIGNMENT_WORD(word) | Token::NAME(word) => {
+                Some(word.to_string())
+            },
+            _ => None,
+        });
+    let Some(name) = words.next() else {
+        return Ok(vec![]);
+    };
+    let mut args = expand_words(job_manager, &options, &mut NoBuiltins, &name)?;
+    args.extend(words);
+    Ok(args)
+}
+
+/// Set `COLUMNS` and `LINES` to the current size of the terminal
+pub(crate) fn update_term_size(env: &mut Env) {
+    // there may not be a terminal, in which case the variables are left alone
+    let Ok((cols, rows)) = crossterm::terminal::size() else {
+        return;
+    };
+    if let Err(e) = env.set_term_size(cols, rows) {
+        warn!("Failed to set terminal size variables with {}", e);
+    }
+}
+
 /// Set the current working directory programatically
 ///
 /// The `run_hook` parameter determines if a change directory event should be emitted.
@@ -475,6 +741,26 @@ pub fn set_working_dir(
     wd: &Path,
     run_hook: bool,
 ) -> anyhow::Result<()> {
+    let old_path = change_working_dir(rt, wd)?;
+
+    // Set process working directory too
+    env::set_current_dir(&rt.working_dir).expect("failed setting process current dir");
+
+    // Run change directory hook
+    if run_hook {
+        let hook_ctx = ChangeDirCtx {
+            old_dir: old_path,
+            new_dir: rt.working_dir.clone(),
+        };
+        sh.run_hooks(hook_ctx);
+    }
+
+    Ok(())
+}
+
+/// Update the working directory of the runtime along with `PWD` and `OLDPWD`, returning the old
+/// working directory
+pub(crate) fn change_working_dir(rt: &mut Runtime, wd: &Path) -> anyhow::Result<PathBuf> {
     // Check working directory validity
     let path = if let Ok(path) = PathBuf::from(wd).canonicalize() {
         if !path.is_dir() {
@@ -486,7 +772,7 @@ pub fn set_working_dir(
     };
 
     // Save old working directory
-    let old_path = get_working_dir(&rt).to_path_buf();
+    let old_path = get_working_dir(rt).to_path_buf();
     let old_path_str = old_path.to_str().expect("failed converting to str");
     rt.env
         .set("OLDPWD", old_path_str)
@@ -494,24 +780,56 @@ pub fn set_working_dir(
 
     let pwd = path.to_str().expect("failed converting to str");
     rt.env.set("PWD", pwd).expect("failed setting env var");
-    rt.working_dir = path.clone();
+    rt.working_dir = path;
 
-    // Set process working directory too
-    env::set_current_dir(path.clone()).expect("failed setting process current dir");
-
-    // Run change directory hook
-    if run_hook {
-        let hook_ctx = ChangeDirCtx {
-            old_dir: old_path.clone(),
-            new_dir: path.clone(),
-        };
-        sh.run_hooks(hook_ctx);
-    }
-
-    Ok(())
+    Ok(old_path)
 }
 
 /// Fetch the current working directory the shell is in
 pub fn get_working_dir(rt: &Runtime) -> &Path {
     &rt.working_dir
 }
+
+#[cfg(test)]
+mod tests {
+    use shrs_job::JobManager;
+
+    use super::{builtin_args, needs_lang};
+    use crate::prelude::{Builtins, ShellOptions, States};
+
+    #[test]
+    fn args_as_typed() {
+        let mut states = States::default();
+        states.insert(JobManager::default());
+        states.insert(ShellOptions::default());
+        let builtins = Builtins::default();
+
+        // `cd` expands its argument itself, so it isn't expanded twice
+        let cd = builtins.get("cd").unwrap();
+        assert_eq!(
+            builtin_args(&states, cd.as_ref(), "cd '$HOME' ~/src # note").unwrap(),
+            ["cd", "'$HOME'", "~/src"]
+        );
+
+        let echo = builtins.get("echo").unwrap();
+        assert_eq!(
+            builtin_args(&states, echo.as_ref(), "echo '$HOME' # note").unwrap(),
+            ["echo", "$HOME"]
+        );
+    }
+
+    #[test]
+    fn lines_for_lang() {
+        assert!(!needs_lang("cd src"));
+        assert!(!needs_lang("echo 'a > b' \"|\" # > c"));
+        assert!(needs_lang("cd src && ls"));
+        assert!(needs_lang("history | grep ls"));
+        assert!(needs_lang("echo hi > f"));
+        assert!(needs_lang("echo hi >> f"));
+        assert!(needs_lang("cd x 2>/dev/null"));
+        assert!(needs_lang("read line < f"));
+        assert!(needs_lang("cat <<EOF\nhi\nEOF"));
+        assert!(needs_lang("source <(echo true)"));
+        assert!(!needs_lang("echo '<(x)'"));
+    }
+}
@@ -176,6 +176,8 @@ pub enum StateError {
 #[derive(Default)]
 pub struct States {
     states: HashMap<TypeId, RefCell<Box<dyn Any>>>,
+    /// Type name of each state, for debugging
+    names: HashMap<TypeId, &'static str>,
 }
 
 impl States {
@@ -185,6 +187,8 @@ impl States {
     pub fn insert<S: 'static>(&mut self, res: S) {
         self.states
             .insert(TypeId::of::<S>(), RefCell::new(Box::new(res)));
+        self.names
+            .insert(TypeId::of::<S>(), std::any::type_name::<S>());
     }
 
     // TODO this is potentially dangerous to allow arbitrary code to remove state
@@ -192,9 +196,17 @@ impl States {
         let Some(s) = self.states.remove(&TypeId::of::<S>()) else {
             return Err(StateError::Missing);
         };
+        self.names.remove(&TypeId::of::<S>());
         Ok(())
     }
 
+    /// Type names of all the states in the store, sorted
+    pub fn type_names(&self) -> Vec<&'static str> {
+        let mut names = self.names.values().copied().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
     /// Get an immutable borrow of a state of a given type S from global state store. Will panic
     /// if a borrow exists or the type specified does not exist in the state store
     pub fn get<S: 'static>(&self) -> Ref<S> {
@@ -241,3 +253,19 @@ impl States {
         Ok(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::States;
+
+    #[test]
+    fn type_names() {
+        let mut states = States::default();
+        states.insert(String::new());
+        states.insert(0u32);
+        assert_eq!(states.type_names(), vec!["alloc::string::String", "u32"]);
+
+        states.remove::<String>().unwrap();
+        assert_eq!(states.type_names(), vec!["u32"]);
+    }
+}
@@ -13,6 +13,10 @@ pub struct Theme {
     pub suggestion_style: ContentStyle,
     pub normal_cursor_style: SetCursorStyle,
     pub insert_cursor_style: SetCursorStyle,
+    /// Show a marker to the left of the prompt when the current input fails to parse
+    pub error_line_indicator: bool,
+    /// Style of the marker shown by `error_line_indicator`
+    pub error_line_style: ContentStyle,
 }
 
 impl Default for Theme {
@@ -25,6 +29,8 @@ impl Default for Theme {
             suggestion_style: ContentStyle::new().dark_grey(),
             normal_cursor_style: SetCursorStyle::BlinkingBlock,
             insert_cursor_style: SetCursorStyle::BlinkingBar,
+            error_line_indicator: false,
+            error_line_style: ContentStyle::new().red(),
         }
     }
 }
@@ -123,7 +123,9 @@ impl JobManager {
             self.jobs[job_index].set_last_running_in_foreground(true);
             let job_pgid = self.jobs[job_index].pgid();
             let job_tmodes = self.jobs[job_index].tmodes().clone();
-            let _terminal_state = job_pgid.map(|pgid| TerminalState::new(Pid::from_raw(pgid)));
+            let _terminal_state = job_pgid
+                .filter(|_| util::job_control_enabled())
+                .map(|pgid| TerminalState::new(Pid::from_raw(pgid)));
 
             // Send the job a continue signal if necessary
             if cont {
@@ -1,12 +1,30 @@
 use std::{
     ffi::OsStr,
-    fmt, iter,
-    os::fd::AsRawFd,
+    fmt,
+    fs::File,
+    io::{self, Write},
+    iter,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::process::ExitStatusExt,
+    },
+    path::{Path, PathBuf},
     process::{Child, Command, ExitStatus},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use log::*;
-use nix::libc::{STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+use nix::{
+    errno::Errno,
+    fcntl::{self, OFlag},
+    libc::{STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO},
+    sys::{
+        signal::{self, SigHandler, Signal},
+        stat::Mode,
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::{self, ForkResult, Pid},
+};
 
 use super::{io::Stdin, pid_t, util, Output};
 use crate::warn_if_err;
@@ -36,6 +54,11 @@ pub trait Process {
     fn kill(&mut self) -> anyhow::Result<()>;
     fn wait(&mut self) -> anyhow::Result<ExitStatus>;
     fn try_wait(&mut self) -> anyhow::Result<Option<ExitStatus>>;
+    /// Whether the process is a process substitution, whose status doesn't count towards the
+    /// status of the pipeline it is part of
+    fn is_substitution(&self) -> bool {
+        false
+    }
 }
 
 impl fmt::Debug for dyn Process {
@@ -57,33 +80,34 @@ pub struct ProcessGroup {
     pub foreground: bool,
 }
 
-struct BuiltinProcess {
+/// Process that has already completed, used for commands run by the shell itself
+pub struct BuiltinProcess {
     argv: Vec<String>,
     status_code: ExitStatus,
     stdout: Option<Stdin>,
 }
 
-// impl BuiltinProcess {
-//     pub fn new<S1, S2>(
-//         program: S1,
-//         args: &[S2],
-//         status_code: ExitStatus,
-//         stdout: Option<Stdin>,
-//     ) -> Self
-//     where
-//         S1: AsRef<str>,
-//         S2: AsRef<str>,
-//     {
-//         Self {
-//             argv: iter::once(program)
-//                 .map(|p| p.as_ref().to_string())
-//                 .chain(args.iter().map(|arg| arg.as_ref().to_string()))
-//                 .collect(),
-//             status_code,
-//             stdout,
-//         }
-//     }
-// }
+impl BuiltinProcess {
+    pub fn new<S1, S2>(
+        program: S1,
+        args: &[S2],
+        status_code: ExitStatus,
+        stdout: Option<Stdin>,
+    ) -> Self
+    where
+        S1: AsRef<str>,
+        S2: AsRef<str>,
+    {
+        Self {
+            argv: iter::once(program)
+                .map(|p| p.as_ref().to_string())
+                .chain(args.iter().map(|arg| arg.as_ref().to_string()))
+                .collect(),
+            status_code,
+            stdout,
+        }
+    }
+}
 
 impl Process for BuiltinProcess {
     fn id(&self) -> Option<ProcessId> {
@@ -188,9 +212,380 @@ impl Process for ExternalProcess {
     }
 }
 
+/// Copy of the shell forked to run a command that isn't a single program, such as a loop that is
+/// part of a pipeline
+pub struct ForkedProcess {
+    argv: String,
+    pid: Pid,
+    stdout: Option<Stdin>,
+    status_code: Option<ExitStatus>,
+}
+
+impl ForkedProcess {
+    /// Fork the shell and call `run` in the copy, which exits with the status code it returns
+    ///
+    /// The stdin and stdout of the copy are connected to the ones given before `run` is called.
+    /// The copy joins the process group `pgid`, or leads a new one if it isn't given, and the
+    /// commands it runs stay in that group.
+    pub fn spawn<F>(
+        argv: &str,
+        stdin: Stdin,
+        stdout: Output,
+        pgid: Option<u32>,
+        run: F,
+    ) -> anyhow::Result<(Self, Option<u32>)>
+    where
+        F: FnOnce() -> i32,
+    {
+        let pipe = match stdout {
+            Output::CreatePipe => Some(unistd::pipe()?),
+            _ => None,
+        };
+        let job_control = util::job_control_enabled();
+
+        // anything still buffered would otherwise be written by both processes
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+
+        // SAFETY: the child only runs the command and exits without returning to the caller
+        match unsafe { unistd::fork() }? {
+            ForkResult::Child => {
+                if job_control {
+                    let pid = unistd::getpid();
+                    let pgid = pgid.map_or(pid, |pgid| Pid::from_raw(pgid as pid_t));
+                    let _ = unistd::setpgid(pid, pgid);
+                    // the shell ignores these, but the commands of the copy can be interrupted
+                    // and stopped like any other
+                    for signal in [
+                        Signal::SIGINT,
+                        Signal::SIGQUIT,
+                        Signal::SIGTSTP,
+                        Signal::SIGTTIN,
+                        Signal::SIGTTOU,
+                    ] {
+                        // SAFETY: resetting to the default handler
+                        let _ = unsafe { signal::signal(signal, SigHandler::SigDfl) };
+                    }
+                }
+                util::disable_job_control();
+
+                let stdout_fd = match (&stdout, pipe) {
+                    (_, Some((read, write))) => {
+                        let _ = unistd::close(read);
+                        Some(write)
+                    },
+                    (Output::File(file), None) => Some(file.as_raw_fd()),
+                    (Output::FileDescriptor(fd), None) => Some(*fd),
+                    _ => None,
+                };
+                let stdin_fd = match &stdin {
+                    Stdin::Inherit => None,
+                    stdin => Some(stdin.as_raw_fd()),
+                };
+                for (fd, target) in [(stdin_fd, STDIN_FILENO), (stdout_fd, STDOUT_FILENO)] {
+                    let Some(fd) = fd.filter(|fd| *fd != target) else {
+                        continue;
+                    };
+                    if let Err(e) = unistd::dup2(fd, target) {
+                        eprintln!("shrs: {argv}: {e}");
+                        std::process::exit(1);
+                    }
+                }
+                drop(stdin);
+                drop(stdout);
+
+                let code = run();
+                let _ = io::stdout().flush();
+                std::process::exit(code);
+            },
+            ForkResult::Parent { child } => {
+                let pgid = pgid.unwrap_or(child.as_raw() as u32);
+                if job_control {
+                    let temp_result = unistd::setpgid(child, Pid::from_raw(pgid as pid_t));
+                    warn_if_err!(
+                        temp_result,
+                        "failed to set pgid ({}) for pid ({})",
+                        pgid,
+                        child
+                    );
+                }
+                let stdout = pipe.map(|(read, write)| {
+                    let _ = unistd::close(write);
+                    // SAFETY: the read end of the pipe was just created and isn't used anywhere
+                    // else
+                    Stdin::File(unsafe { File::from_raw_fd(read) })
+                });
+                let proc = Self {
+                    argv: argv.to_string(),
+                    pid: child,
+                    stdout,
+                    status_code: None,
+                };
+                Ok((proc, Some(pgid)))
+            },
+        }
+    }
+}
+
+impl Process for ForkedProcess {
+    fn id(&self) -> Option<ProcessId> {
+        Some((self.pid.as_raw() as u32).into())
+    }
+
+    fn argv(&self) -> String {
+        self.argv.clone()
+    }
+
+    fn status(&self) -> ProcessStatus {
+        match self.status_code {
+            Some(_) => ProcessStatus::Completed,
+            None => ProcessStatus::Running,
+        }
+    }
+
+    fn status_code(&self) -> Option<ExitStatus> {
+        self.status_code
+    }
+
+    fn stdout(&mut self) -> Option<Stdin> {
+        self.stdout.take()
+    }
+
+    fn kill(&mut self) -> anyhow::Result<()> {
+        if self.status_code.is_none() {
+            signal::kill(self.pid, Signal::SIGKILL)?;
+        }
+        Ok(())
+    }
+
+    fn wait(&mut self) -> anyhow::Result<ExitStatus> {
+        loop {
+            if let Some(status_code) = self.status_code {
+                return Ok(status_code);
+            }
+            match waitpid(self.pid, None) {
+                Ok(status) => self.status_code = exit_status(status),
+                Err(Errno::EINTR) => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn try_wait(&mut self) -> anyhow::Result<Option<ExitStatus>> {
+        if self.status_code.is_none() {
+            self.status_code = exit_status(waitpid(self.pid, Some(WaitPidFlag::WNOHANG))?);
+        }
+        Ok(self.status_code)
+    }
+}
+
+/// Exit status of a process that has exited or was killed, [None] if it is still running
+fn exit_status(status: WaitStatus) -> Option<ExitStatus> {
+    match status {
+        WaitStatus::Exited(_, code) => Some(ExitStatus::from_raw(code << 8)),
+        WaitStatus::Signaled(_, signal, _) => Some(ExitStatus::from_raw(signal as i32)),
+        _ => None,
+    }
+}
+
+/// Direction of a process substitution
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Substitution {
+    /// `<(cmd)`, the output of the command is read from the path
+    Input,
+    /// `>(cmd)`, what is written to the path is the input of the command
+    Output,
+}
+
+/// Copy of the shell forked to run the command of a process substitution
+///
+/// The command is connected to a pipe, the other end of which is passed to the command that the
+/// substitution is an argument of as `/dev/fd/N`. A named pipe is used instead on platforms
+/// without `/dev/fd`.
+pub struct SubstitutionProcess {
+    argv: String,
+    pid: Pid,
+    /// End of the pipe that the shell keeps open until the command has started
+    fd: Option<OwnedFd>,
+    path: PathBuf,
+    /// If `path` is a named pipe, which is removed once the process exits
+    fifo: bool,
+    status_code: Option<ExitStatus>,
+}
+
+impl SubstitutionProcess {
+    /// Fork the shell and call `run` in the copy, which exits with the status code it returns
+    ///
+    /// The stdout of the copy is connected to [`Self::path`] for [`Substitution::Input`], and its
+    /// stdin for [`Substitution::Output`]. Job control is disabled in the copy, so the commands it
+    /// runs don't take the terminal from the command that reads or writes the path.
+    pub fn spawn<F>(argv: &str, direction: Substitution, run: F) -> anyhow::Result<Self>
+    where
+        F: FnOnce() -> i32,
+    {
+        static FIFOS: AtomicUsize = AtomicUsize::new(0);
+
+        let fifo = if Path::new("/dev/fd").is_dir() {
+            None
+        } else {
+            let path = std::env::temp_dir().join(format!(
+                "shrs_fifo_{}_{}",
+                std::process::id(),
+                FIFOS.fetch_add(1, Ordering::Relaxed)
+            ));
+            unistd::mkfifo(&path, Mode::S_IRUSR | Mode::S_IWUSR)?;
+            Some(path)
+        };
+        // the end used by the copy and the end kept by the shell
+        let pipe = match (fifo.is_none(), direction) {
+            (false, _) => None,
+            (true, Substitution::Input) => {
+                unistd::pipe().map(|(read, write)| Some((write, read)))?
+            },
+            (true, Substitution::Output) => unistd::pipe().map(Some)?,
+        };
+
+        // anything still buffered would otherwise be written by both processes
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+
+        // SAFETY: the child only runs the command and exits without returning to the caller
+        match unsafe { unistd::fork() }? {
+            ForkResult::Child => {
+                util::disable_job_control();
+                let (target, flags) = match direction {
+                    Substitution::Input => (STDOUT_FILENO, OFlag::O_WRONLY),
+                    Substitution::Output => (STDIN_FILENO, OFlag::O_RDONLY),
+                };
+                let fd = match (&fifo, pipe) {
+                    (_, Some((child, shell))) => {
+                        let _ = unistd::close(shell);
+                        Ok(child)
+                    },
+                    // opening blocks until the other end is opened by the command
+                    (Some(path), None) => fcntl::open(path, flags, Mode::empty()),
+                    (None, None) => unreachable!(),
+                };
+                if let Err(e) = fd.and_then(|fd| unistd::dup2(fd, target).and(unistd::close(fd))) {
+                    eprintln!("shrs: {argv}: {e}");
+                    std::process::exit(1);
+                }
+
+                let code = run();
+                let _ = io::stdout().flush();
+                std::process::exit(code);
+            },
+            ForkResult::Parent { child } => {
+                let (fd, path) = match (fifo, pipe) {
+                    (_, Some((child, shell))) => {
+                        let _ = unistd::close(child);
+                        // SAFETY: the shell's end of the pipe was just created and isn't used
+                        // anywhere else
+                        let fd = unsafe { OwnedFd::from_raw_fd(shell) };
+                        (Some(fd), PathBuf::from(format!("/dev/fd/{shell}")))
+                    },
+                    (Some(path), None) => (None, path),
+                    (None, None) => unreachable!(),
+                };
+                Ok(Self {
+                    argv: argv.to_string(),
+                    pid: child,
+                    fifo: fd.is_none(),
+                    fd,
+                    path,
+                    status_code: None,
+                })
+            },
+        }
+    }
+
+    /// Path that the command reads the output of the substitution from, or writes its input to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Close the shell's end of the pipe, once the command that uses it has started
+    ///
+    /// A `>(cmd)` only sees the end of its input once this is done.
+    pub fn close(&mut self) {
+        self.fd = None;
+    }
+
+    fn exited(&mut self, status: WaitStatus) -> Option<ExitStatus> {
+        let status_code = exit_status(status)?;
+        if self.fifo {
+            let _ = std::fs::remove_file(&self.path);
+        }
+        self.status_code = Some(status_code);
+        self.status_code
+    }
+}
+
+impl Process for SubstitutionProcess {
+    fn id(&self) -> Option<ProcessId> {
+        Some((self.pid.as_raw() as u32).into())
+    }
+
+    fn is_substitution(&self) -> bool {
+        true
+    }
+
+    fn argv(&self) -> String {
+        self.argv.clone()
+    }
+
+    fn status(&self) -> ProcessStatus {
+        match self.status_code {
+            Some(_) => ProcessStatus::Completed,
+            None => ProcessStatus::Running,
+        }
+    }
+
+    fn status_code(&self) -> Option<ExitStatus> {
+        self.status_code
+    }
+
+    fn stdout(&mut self) -> Option<Stdin> {
+        None
+    }
+
+    fn kill(&mut self) -> anyhow::Result<()> {
+        if self.status_code.is_none() {
+            signal::kill(self.pid, Signal::SIGKILL)?;
+        }
+        Ok(())
+    }
+
+    fn wait(&mut self) -> anyhow::Result<ExitStatus> {
+        loop {
+            if let Some(status_code) = self.status_code {
+                return Ok(status_code);
+            }
+            match waitpid(self.pid, None) {
+                Ok(status) => {
+                    self.exited(status);
+                },
+                Err(Errno::EINTR) => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn try_wait(&mut self) -> anyhow::Result<Option<ExitStatus>> {
+        if self.status_code.is_some() {
+            return Ok(self.status_code);
+        }
+        let status = waitpid(self.pid, Some(WaitPidFlag::WNOHANG))?;
+        Ok(self.exited(status))
+    }
+}
+
+/// Spawn a program with only the variables in `env`, which are the exported variables of the
+/// shell followed by the ones assigned for this command (`FOO=bar cmd`)
 pub fn run_external_command<S1, S2>(
     program: S1,
     args: &[S2],
+    env: &[(String, String)],
     stdin: Stdin,
     stdout: Output,
     stderr: Output,
@@ -209,6 +604,8 @@ where
 
     let mut command = Command::new(OsStr::new(program.as_ref()));
     command.args(args.iter().map(AsRef::as_ref).map(OsStr::new));
+    command.env_clear();
+    command.envs(env.iter().map(|(var, val)| (var, val)));
 
     // Configure stdout and stderr (e.g. pipe, redirect). Do not configure
     // stdin, as we need to do that manually in before_exec *after* we have
@@ -230,9 +627,7 @@ where
         None
     };
 
-    // TODO add abiliity to disable job control
-    // let job_control_is_enabled = shell.is_job_control_enabled();
-    let job_control_is_enabled = true;
+    let job_control_is_enabled = util::job_control_enabled();
     let shell_terminal = util::get_terminal();
     unsafe {
         command.pre_exec(move || {
@@ -284,17 +679,23 @@ where
                 unistd::close(stdin).expect("failed to close stdin");
             }
 
+            // stdout and stderr can be duplicated from each other (such as with `2>&1`), in which
+            // case the original has to stay open
             if let Some(fd) = stdout_fd {
                 if fd != STDOUT_FILENO {
                     unistd::dup2(fd, STDOUT_FILENO).expect("failed to dup stdout");
-                    unistd::close(fd).expect("failed to close stdout");
+                    if fd > STDERR_FILENO {
+                        unistd::close(fd).expect("failed to close stdout");
+                    }
                 }
             }
 
             if let Some(fd) = stderr_fd {
                 if fd != STDERR_FILENO {
                     unistd::dup2(fd, STDERR_FILENO).expect("failed to dup stderr");
-                    unistd::close(fd).expect("failed to close stderr");
+                    if fd > STDERR_FILENO {
+                        unistd::close(fd).expect("failed to close stderr");
+                    }
                 }
             }
 
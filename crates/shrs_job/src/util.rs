@@ -1,4 +1,7 @@
-use std::os::fd::{AsRawFd, RawFd};
+use std::{
+    os::fd::{AsRawFd, RawFd},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use nix::{
     sys::signal::{self, SigHandler, Signal},
@@ -45,6 +48,21 @@ macro_rules! warn_if_err {
     }};
 }
 
+/// Set once [initialize_job_control] has run
+static JOB_CONTROL: AtomicBool = AtomicBool::new(false);
+
+/// If the shell controls the terminal, in which case each job gets its own process group and is
+/// given the terminal while it runs in the foreground
+pub fn job_control_enabled() -> bool {
+    JOB_CONTROL.load(Ordering::Relaxed)
+}
+
+/// Stop giving jobs their own process group and the terminal, used in copies of the shell that
+/// run in the background
+pub(crate) fn disable_job_control() {
+    JOB_CONTROL.store(false, Ordering::Relaxed);
+}
+
 pub fn get_terminal() -> RawFd {
     std::io::stdin().as_raw_fd()
 }
@@ -85,5 +103,6 @@ pub fn initialize_job_control() -> anyhow::Result<()> {
     let temp_result = unistd::tcsetpgrp(shell_terminal, shell_pgid);
     log_if_err!(temp_result, "failed to grab control of terminal");
 
+    JOB_CONTROL.store(true, Ordering::Relaxed);
     Ok(())
 }
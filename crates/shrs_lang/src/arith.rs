@@ -0,0 +1,376 @@
+//! Evaluation of arithmetic expressions used by `$(( ))` and `for (( ))`
+
+use std::{iter::Peekable, str::Chars};
+
+use crate::eval::set_local;
+
+/// Evaluate an integer arithmetic expression
+///
+/// Supports `+ - * / % **`, comparisons, `&& || !`, unary `+` and `-`, parentheses and variable
+/// names, which are read from the environment and count as `0` if they are unset or empty.
+/// Variables can be assigned with `= += -= *= /= %=` and incremented or decremented with `++` and
+/// `--`. Like in C, the right side of `&&` and `||` is only evaluated if it is needed.
+pub(crate) fn eval_arith(expr: &str) -> Result<i64, String> {
+    let mut parser = ArithParser {
+        chars: expr.chars().peekable(),
+        skip: 0,
+    };
+    let value = parser.expr()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(c) => Err(format!("syntax error: unexpected '{c}'")),
+    }
+}
+
+struct ArithParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    /// Number of operands being skipped by `&&` or `||`, which are parsed without assigning to
+    /// variables
+    skip: usize,
+}
+
+impl ArithParser<'_> {
+    /// Next non whitespace character without consuming it
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    /// If the next non whitespace characters are `op`
+    fn next_is(&mut self, op: &str) -> bool {
+        self.peek();
+        let mut ahead = self.chars.clone();
+        op.chars().all(|c| ahead.next() == Some(c))
+    }
+
+    /// Consume `op` if it is next
+    fn eat(&mut self, op: &str) -> bool {
+        let next = self.next_is(op);
+        if next {
+            op.chars().for_each(|_| {
+                self.chars.next();
+            });
+        }
+        next
+    }
+
+    /// Variable name, if one is next
+    fn name(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(c) if c.is_alphabetic() || c == '_' => Some(
+                std::iter::from_fn(|| self.chars.next_if(|c| c.is_alphanumeric() || *c == '_'))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Parse an operand that may be skipped, in which case it doesn't assign to variables
+    fn operand(
+        &mut self,
+        skip: bool,
+        parse: fn(&mut Self) -> Result<i64, String>,
+    ) -> Result<i64, String> {
+        self.skip += usize::from(skip);
+        let value = parse(self);
+        self.skip -= usize::from(skip);
+        value
+    }
+
+    /// expr = assignment
+    fn expr(&mut self) -> Result<i64, String> {
+        self.assignment()
+    }
+
+    /// assignment = name ('=' | '+=' | '-=' | '*=' | '/=' | '%=') assignment | or
+    fn assignment(&mut self) -> Result<i64, String> {
+        let start = self.chars.clone();
+        if let Some(name) = self.name() {
+            let op = ["+=", "-=", "*=", "/=", "%="]
+                .into_iter()
+                .find(|op| self.next_is(op))
+                .or_else(|| (self.next_is("=") && !self.next_is("==")).then_some("="));
+            if let Some(op) = op {
+                self.eat(op);
+                let rhs = self.assignment()?;
+                let value = match op.strip_suffix('=').and_then(|op| op.chars().next()) {
+                    Some(op) => self.binary(op, var(&name)?, rhs)?,
+                    None => rhs,
+                };
+                return Ok(self.assign(&name, value));
+            }
+        }
+        self.chars = start;
+        self.or()
+    }
+
+    /// or = and ('||' and)*
+    fn or(&mut self) -> Result<i64, String> {
+        let mut value = self.and()?;
+        while self.eat("||") {
+            let rhs = self.operand(value != 0, Self::and)?;
+            value = i64::from(value != 0 || rhs != 0);
+        }
+        Ok(value)
+    }
+
+    /// and = equality ('&&' equality)*
+    fn and(&mut self) -> Result<i64, String> {
+        let mut value = self.equality()?;
+        while self.eat("&&") {
+            let rhs = self.operand(value == 0, Self::equality)?;
+            value = i64::from(value != 0 && rhs != 0);
+        }
+        Ok(value)
+    }
+
+    /// equality = relational (('==' | '!=') relational)*
+    fn equality(&mut self) -> Result<i64, String> {
+        let mut value = self.relational()?;
+        loop {
+            if self.eat("==") {
+                value = i64::from(value == self.relational()?);
+            } else if self.eat("!=") {
+                value = i64::from(value != self.relational()?);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// relational = additive (('<' | '<=' | '>' | '>=') additive)*
+    fn relational(&mut self) -> Result<i64, String> {
+        let mut value = self.additive()?;
+        loop {
+            let op = ["<=", ">=", "<", ">"]
+                .into_iter()
+                .find(|op| self.next_is(op));
+            let Some(op) = op else {
+                return Ok(value);
+            };
+            self.eat(op);
+            let rhs = self.additive()?;
+            value = i64::from(match op {
+                "<=" => value <= rhs,
+                ">=" => value >= rhs,
+                "<" => value < rhs,
+                _ => value > rhs,
+            });
+        }
+    }
+
+    /// additive = term (('+' | '-') term)*
+    fn additive(&mut self) -> Result<i64, String> {
+        let mut value = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Some(op @ ('+' | '-')) => op,
+                _ => return Ok(value),
+            };
+            self.chars.next();
+            let rhs = self.term()?;
+            value = self.binary(op, value, rhs)?;
+        }
+    }
+
+    /// term = power (('*' | '/' | '%') power)*
+    fn term(&mut self) -> Result<i64, String> {
+        let mut value = self.power()?;
+        loop {
+            let op = match self.peek() {
+                Some(op @ ('*' | '/' | '%')) if !self.next_is("**") => op,
+                _ => return Ok(value),
+            };
+            self.chars.next();
+            let rhs = self.power()?;
+            value = self.binary(op, value, rhs)?;
+        }
+    }
+
+    /// power = unary ('**' power)?
+    fn power(&mut self) -> Result<i64, String> {
+        let base = self.unary()?;
+        if self.eat("**") {
+            let exp = self.power()?;
+            return pow(base, exp);
+        }
+        Ok(base)
+    }
+
+    /// unary = ('++' | '--') name | ('+' | '-' | '!') unary | primary
+    fn unary(&mut self) -> Result<i64, String> {
+        for (op, step) in [("++", 1), ("--", -1)] {
+            let start = self.chars.clone();
+            if self.eat(op) {
+                if let Some(name) = self.name() {
+                    let value = self.binary('+', var(&name)?, step)?;
+                    return Ok(self.assign(&name, value));
+                }
+                self.chars = start;
+            }
+        }
+
+        match self.peek() {
+            Some('-') => {
+                self.chars.next();
+                self.unary()?.checked_neg().ok_or("integer overflow".into())
+            },
+            Some('+') => {
+                self.chars.next();
+                self.unary()
+            },
+            Some('!') => {
+                self.chars.next();
+                Ok(i64::from(self.unary()? == 0))
+            },
+            _ => self.primary(),
+        }
+    }
+
+    /// primary = number | name ('++' | '--')? | '(' expr ')'
+    fn primary(&mut self) -> Result<i64, String> {
+        match self.peek() {
+            Some('(') => {
+                self.chars.next();
+                let value = self.expr()?;
+                match self.peek() {
+                    Some(')') => {
+                        self.chars.next();
+                        Ok(value)
+                    },
+                    _ => Err("syntax error: missing ')'".into()),
+                }
+            },
+            Some(c) if c.is_ascii_digit() => {
+                let digits: String =
+                    std::iter::from_fn(|| self.chars.next_if(|c| c.is_alphanumeric())).collect();
+                parse_number(&digits)
+            },
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name = self.name().unwrap_or_default();
+                let value = var(&name)?;
+                // the value from before incrementing or decrementing is used
+                for (op, step) in [("++", 1), ("--", -1)] {
+                    if self.eat(op) {
+                        let new = self.binary('+', value, step)?;
+                        self.assign(&name, new);
+                    }
+                }
+                Ok(value)
+            },
+            Some(c) => Err(format!("syntax error: unexpected '{c}'")),
+            None => Err("syntax error: operand expected".into()),
+        }
+    }
+
+    /// Apply one of `+ - * / %`
+    fn binary(&self, op: char, lhs: i64, rhs: i64) -> Result<i64, String> {
+        if matches!(op, '/' | '%') && rhs == 0 {
+            // a skipped operand is never used, so it doesn't matter that it can't be computed
+            if self.skip > 0 {
+                return Ok(0);
+            }
+            return Err("division by zero".into());
+        }
+        match op {
+            '+' => lhs.checked_add(rhs),
+            '-' => lhs.checked_sub(rhs),
+            '*' => lhs.checked_mul(rhs),
+            '/' => lhs.checked_div(rhs),
+            _ => lhs.checked_rem(rhs),
+        }
+        .ok_or("integer overflow".into())
+    }
+
+    /// Set a variable to a value, unless the operand it is in is skipped
+    fn assign(&self, name: &str, value: i64) -> i64 {
+        if self.skip == 0 {
+            set_local(name, &value.to_string());
+        }
+        value
+    }
+}
+
+/// Value of a variable, which is `0` if it is unset or empty
+fn var(name: &str) -> Result<i64, String> {
+    let value = std::env::var(name).unwrap_or_default();
+    if value.trim().is_empty() {
+        return Ok(0);
+    }
+    parse_number(value.trim()).map_err(|_| format!("{name}: not a number: '{value}'"))
+}
+
+fn parse_number(s: &str) -> Result<i64, String> {
+    s.parse::<i64>()
+        .map_err(|_| format!("invalid number '{s}'"))
+}
+
+fn pow(base: i64, exp: i64) -> Result<i64, String> {
+    let exp = u32::try_from(exp).map_err(|_| "exponent less than 0".to_string())?;
+    base.checked_pow(exp).ok_or("integer overflow".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::eval_arith;
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval_arith("1 + 2 * 3").unwrap(), 7);
+        assert_eq!(eval_arith("10 - 4 - 3").unwrap(), 3);
+        assert_eq!(eval_arith("2 * 3 ** 2").unwrap(), 18);
+        assert_eq!(eval_arith("2 ** 3 ** 2").unwrap(), 512);
+        assert_eq!(eval_arith("7 / 2 + 7 % 2").unwrap(), 4);
+        assert_eq!(eval_arith("-2 + 5").unwrap(), 3);
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(eval_arith("(1 + 2) * 3").unwrap(), 9);
+        assert_eq!(eval_arith("((2))*(3-(1+1))").unwrap(), 2);
+        assert_eq!(eval_arith("-(4 - 6)").unwrap(), 2);
+    }
+
+    #[test]
+    fn variables() {
+        std::env::set_var("SHRS_ARITH_TEST", "4");
+        std::env::remove_var("SHRS_ARITH_UNSET");
+        assert_eq!(eval_arith("SHRS_ARITH_TEST * 2").unwrap(), 8);
+        assert_eq!(eval_arith("SHRS_ARITH_UNSET + 1").unwrap(), 1);
+    }
+
+    #[test]
+    fn logic() {
+        assert_eq!(eval_arith("1 < 2 && 2 <= 2").unwrap(), 1);
+        assert_eq!(eval_arith("3 > 4 || 4 >= 5").unwrap(), 0);
+        assert_eq!(eval_arith("1 + 1 == 2 != 0").unwrap(), 1);
+        assert_eq!(eval_arith("!0 + !5").unwrap(), 1);
+        // the right side is not evaluated when it doesn't matter
+        assert_eq!(eval_arith("0 && 1 / 0").unwrap(), 0);
+        assert_eq!(eval_arith("1 || 1 / 0").unwrap(), 1);
+    }
+
+    #[test]
+    fn assignment() {
+        std::env::remove_var("SHRS_ARITH_SET");
+        assert_eq!(eval_arith("SHRS_ARITH_SET = 2 * 3").unwrap(), 6);
+        assert_eq!(eval_arith("SHRS_ARITH_SET += 4").unwrap(), 10);
+        assert_eq!(eval_arith("SHRS_ARITH_SET++").unwrap(), 10);
+        assert_eq!(eval_arith("++SHRS_ARITH_SET").unwrap(), 12);
+        assert_eq!(eval_arith("SHRS_ARITH_SET--").unwrap(), 12);
+        assert_eq!(eval_arith("SHRS_ARITH_SET %= 4").unwrap(), 3);
+        assert_eq!(std::env::var("SHRS_ARITH_SET").unwrap(), "3");
+
+        assert_eq!(eval_arith("1 || SHRS_ARITH_SET++").unwrap(), 1);
+        assert_eq!(std::env::var("SHRS_ARITH_SET").unwrap(), "3");
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(eval_arith("1 +").is_err());
+        assert!(eval_arith("(1 + 2").is_err());
+        assert!(eval_arith("1 / 0").is_err());
+        assert!(eval_arith("2 ** -1").is_err());
+        assert!(eval_arith("1 2").is_err());
+    }
+}
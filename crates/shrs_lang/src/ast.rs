@@ -13,11 +13,27 @@ pub struct Redirect {
 pub enum RedirectMode {
     Read,
     Write,
+    /// Write to the file even if `noclobber` is set (`>|`)
+    Clobber,
     ReadAppend,
     WriteAppend,
+    /// Duplicate another file descriptor as input (`<&`), the file descriptor is stored in place
+    /// of the file name
     ReadDup,
+    /// Duplicate another file descriptor as output (`>&`), such as `2>&1` to send stderr to
+    /// wherever stdout goes, the file descriptor is stored in place of the file name
     WriteDup,
     ReadWrite,
+    /// Redirect both stdout and stderr to the file (`&>`)
+    WriteBoth,
+    /// Append both stdout and stderr to the file (`&>>`)
+    WriteBothAppend,
+    /// Here document (`<<` or `<<-`) used as stdin, the body is stored in place of the file name
+    ///
+    /// The body is expanded like a double quoted word unless the delimiter was quoted
+    Heredoc {
+        expand: bool,
+    },
 }
 
 /// Assignment
@@ -27,6 +43,23 @@ pub struct Assign {
     pub val: String,
 }
 
+impl Assign {
+    /// Split a word of the form `NAME=value` into an assignment, returns `None` if the word
+    /// doesn't start with a valid variable name followed by `=`
+    pub fn parse(word: &str) -> Option<Self> {
+        let (var, val) = word.split_once('=')?;
+        let mut chars = var.chars();
+        let valid = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        valid.then(|| Assign {
+            var: var.to_string(),
+            val: val.to_string(),
+        })
+    }
+}
+
 /// Separator character between commands
 #[derive(Debug, Clone)]
 pub enum SeparatorOp {
@@ -111,16 +144,97 @@ pub enum Command {
         body: Box<Command>,
     },
 
+    /// Arithmetic for loops
+    /// ```sh
+    /// for ((i = 0; i < 5; i++)); do echo $i; done
+    /// ```
+    /// An empty condition is always true
+    ArithFor {
+        init: String,
+        cond: String,
+        step: String,
+        body: Box<Command>,
+    },
+
     /// Case statements
     Case { word: String, arms: Vec<CaseArm> },
 
     /// Function definition
-    Fn { fname: String, body: Box<Command> },
+    ///
+    /// The description is taken from a comment documenting the function, see [doc_comment]
+    Fn {
+        fname: String,
+        body: Box<Command>,
+        description: Option<String>,
+    },
+
+    /// Compound command with redirections that apply to every command in it
+    /// ```sh
+    /// if true; then echo hi; fi > file
+    /// ```
+    Redirected {
+        cmd: Box<Command>,
+        redirects: Vec<Redirect>,
+    },
 
     /// No op
     None,
 }
 
+impl Command {
+    /// Arithmetic for loop from the `init; cond; step` expressions between its parentheses
+    pub fn arith_for(exprs: &str, body: Command) -> Self {
+        let mut exprs = exprs.splitn(3, ';').map(|expr| expr.trim().to_string());
+        Command::ArithFor {
+            init: exprs.next().unwrap_or_default(),
+            cond: exprs.next().unwrap_or_default(),
+            step: exprs.next().unwrap_or_default(),
+            body: Box::new(body),
+        }
+    }
+}
+
+/// Text of the comment documenting a function defined at `start` in `text`, whose body starts at
+/// `body_start`
+///
+/// This is either the comment lines right above the definition or a comment on the first line of
+/// the body:
+/// ```sh
+/// # greet someone by name
+/// greet() {
+///     echo hello $1
+/// }
+///
+/// ll() { # list files with details
+///     ls -l "$@"
+/// }
+/// ```
+pub fn doc_comment(text: &str, start: usize, body_start: usize) -> Option<String> {
+    let comment = |line: &str| line.trim().strip_prefix('#').map(|c| c.trim().to_string());
+
+    let (above, indent) = text[..start]
+        .rsplit_once('\n')
+        .unwrap_or(("", &text[..start]));
+    if indent.trim().is_empty() {
+        let mut lines = above
+            .split('\n')
+            .rev()
+            .map_while(comment)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+        if !lines.is_empty() {
+            lines.reverse();
+            return Some(lines.join(" "));
+        }
+    }
+
+    let first_line = text[body_start..].lines().next().unwrap_or_default();
+    first_line
+        .strip_prefix(['{', '('])
+        .and_then(comment)
+        .filter(|c| !c.is_empty())
+}
+
 /// Represents each match arm in case statement
 #[derive(Debug, Clone)]
 pub struct CaseArm {
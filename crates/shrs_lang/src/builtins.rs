@@ -0,0 +1,61 @@
+//! Hook for commands that the shell runs itself
+
+use std::process::ExitStatus;
+
+use shrs_job::JobManager;
+
+/// Commands that the shell runs itself instead of starting a process, such as `cd`
+///
+/// They are looked up after functions and before external commands, so they can be used anywhere
+/// in a line, like in a pipeline or the body of a loop.
+pub trait ShellBuiltins {
+    /// Run the builtin named `args[0]` with the rest of `args`, returns [None] if there is no
+    /// builtin with that name
+    ///
+    /// With `capture`, what the builtin prints to stdout is returned along with its exit status
+    /// instead of being printed, so it can be passed on to a pipe or a redirected file. The jobs of
+    /// the shell are passed along for builtins that manage them, like `jobs` and `source`.
+    fn run(
+        &mut self,
+        job_manager: &mut JobManager,
+        args: &[String],
+        capture: bool,
+    ) -> Option<(ExitStatus, Vec<u8>)>;
+
+    /// If the builtin named `name` expands its arguments itself, in which case they are passed to
+    /// it as typed, with quotes, instead of being expanded first
+    fn expands_args(&self, _name: &str) -> bool {
+        false
+    }
+
+    /// Variables passed to external commands, the ones assigned for a command (`FOO=bar cmd`) are
+    /// added to them
+    ///
+    /// Without a shell to keep track of what is exported, every variable is.
+    fn exported_vars(&self) -> Vec<(String, String)> {
+        std::env::vars().collect()
+    }
+
+    /// Called when a function is called, before its body is evaluated
+    ///
+    /// The shell keeps the variables declared `local` in the function, which it restores in
+    /// [ShellBuiltins::pop_scope].
+    fn push_scope(&mut self) {}
+
+    /// Called when a function returns
+    fn pop_scope(&mut self) {}
+}
+
+/// No builtins, every command is run as a function or external command
+pub struct NoBuiltins;
+
+impl ShellBuiltins for NoBuiltins {
+    fn run(
+        &mut self,
+        _job_manager: &mut JobManager,
+        _args: &[String],
+        _capture: bool,
+    ) -> Option<(ExitStatus, Vec<u8>)> {
+        None
+    }
+}
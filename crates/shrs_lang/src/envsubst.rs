@@ -0,0 +1,609 @@
+//! Substitution of variables and command output into words
+
+use glob::Pattern;
+
+use crate::{
+    arith::eval_arith, eval::set_local, fields::ifs, positional_args, PosixError, ShellOptions,
+};
+
+/// Replace `$VAR`, `${VAR}`, `$(cmd)`, `` `cmd` `` and `$((expr))` in a word
+///
+/// Variables are read from the environment of the shell process, which holds both the exported
+/// and the shell-local variables, and expand to nothing if they are unset. Commands are
+/// evaluated with `run`, which returns what the command printed, and have their trailing newlines
+/// removed. Substitutions nested in a command are expanded when that command is evaluated.
+/// With the `nounset` option, expanding a variable that isn't set is an error.
+///
+/// `$1` to `$9` (and `${10}` onwards) are the positional parameters, `$#` is how many there are,
+/// and `$@` and `$*` are all of them joined with spaces or the first character of IFS.
+///
+/// `${...}` can also use a default or alternative value, see [parameter_expansion].
+///
+/// Arithmetic expressions are expanded first and then evaluated, an invalid expression is
+/// reported on stderr and expands to `0`.
+pub(crate) fn envsubst(
+    word: &str,
+    options: &ShellOptions,
+    run: &mut dyn FnMut(&str) -> Result<String, PosixError>,
+) -> Result<String, PosixError> {
+    let mut expanded = String::new();
+    let mut chars = word.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => {
+                let cmd: String = chars.by_ref().take_while(|c| *c != '`').collect();
+                expanded.push_str(run(&cmd)?.trim_end_matches('\n'));
+            },
+            '$' if chars.next_if_eq(&'(').is_some() => {
+                let arithmetic = chars.next_if_eq(&'(').is_some();
+                let mut inner = String::new();
+                let mut depth = 0;
+                for c in chars.by_ref() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' if depth == 0 => break,
+                        ')' => depth -= 1,
+                        _ => {},
+                    }
+                    inner.push(c);
+                }
+                if arithmetic {
+                    chars.next_if_eq(&')');
+                    let value = eval_arith(&envsubst(&inner, options, run)?).unwrap_or_else(|e| {
+                        eprintln!("shrs: $(({inner})): {e}");
+                        0
+                    });
+                    expanded.push_str(&value.to_string());
+                } else {
+                    expanded.push_str(run(&inner)?.trim_end_matches('\n'));
+                }
+            },
+            '$' if chars.next_if_eq(&'{').is_some() => {
+                // the word of an operator can contain expansions with braces of their own
+                let mut body = String::new();
+                let mut depth = 0;
+                for c in chars.by_ref() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' if depth == 0 => break,
+                        '}' => depth -= 1,
+                        _ => {},
+                    }
+                    body.push(c);
+                }
+                expanded.push_str(&parameter_expansion(&body, options, run)?);
+            },
+            '$' => {
+                // positional and special parameters are a single character, `$10` is `${1}0`
+                let name: String = match chars.next_if(|c| c.is_ascii_digit() || "@*#".contains(*c))
+                {
+                    Some(c) => c.to_string(),
+                    None => {
+                        std::iter::from_fn(|| chars.next_if(|c| c.is_alphanumeric() || *c == '_'))
+                            .collect()
+                    },
+                };
+                if name.is_empty() {
+                    expanded.push('$');
+                } else {
+                    expanded.push_str(&var(&name, options)?);
+                }
+            },
+            c => expanded.push(c),
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// How a `${name<op>word}` expansion uses its word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    /// `-`, the word if the parameter is unset
+    Default,
+    /// `=`, the word if the variable is unset, which is also assigned to it
+    Assign,
+    /// `+`, the word if the parameter is set and nothing otherwise
+    Alternative,
+    /// `?`, an error with the word as the message if the parameter is unset
+    Error,
+    /// `#` or `##`, the value without the shortest or longest prefix matching the word
+    RemovePrefix { longest: bool },
+    /// `%` or `%%`, the value without the shortest or longest suffix matching the word
+    RemoveSuffix { longest: bool },
+}
+
+/// Operator of a `${...}` expansion and the word after it
+#[derive(Debug, PartialEq, Eq)]
+struct Modifier<'a> {
+    op: Operator,
+    /// With a colon (`:-`) an empty parameter is treated like an unset one
+    colon: bool,
+    word: &'a str,
+}
+
+/// Body of a `${...}` expansion
+#[derive(Debug, PartialEq, Eq)]
+struct Expansion<'a> {
+    name: &'a str,
+    /// `${#name}`, the length of the value
+    length: bool,
+    modifier: Option<Modifier<'a>>,
+}
+
+/// Split the body of a `${...}` expansion into the parameter and its operator, returns [None] if
+/// it isn't valid
+fn parse_expansion(body: &str) -> Option<Expansion<'_>> {
+    // `${#}` and `${#-word}` are the number of positional parameters instead of a length
+    if let Some((name, "")) = body.strip_prefix('#').and_then(split_name) {
+        return Some(Expansion {
+            name,
+            length: true,
+            modifier: None,
+        });
+    }
+
+    let (name, rest) = split_name(body)?;
+    if rest.is_empty() {
+        return Some(Expansion {
+            name,
+            length: false,
+            modifier: None,
+        });
+    }
+
+    let (colon, rest) = match rest.strip_prefix(':') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let (op, word) = match rest.chars().next()? {
+        '-' => (Operator::Default, &rest[1..]),
+        '=' => (Operator::Assign, &rest[1..]),
+        '+' => (Operator::Alternative, &rest[1..]),
+        '?' => (Operator::Error, &rest[1..]),
+        '#' if !colon => match rest.strip_prefix("##") {
+            Some(word) => (Operator::RemovePrefix { longest: true }, word),
+            None => (Operator::RemovePrefix { longest: false }, &rest[1..]),
+        },
+        '%' if !colon => match rest.strip_prefix("%%") {
+            Some(word) => (Operator::RemoveSuffix { longest: true }, word),
+            None => (Operator::RemoveSuffix { longest: false }, &rest[1..]),
+        },
+        _ => return None,
+    };
+    Some(Expansion {
+        name,
+        length: false,
+        modifier: Some(Modifier { op, colon, word }),
+    })
+}
+
+/// Split the name of a parameter from the start of the body of a `${...}` expansion
+fn split_name(body: &str) -> Option<(&str, &str)> {
+    let name_len = match body.chars().next()? {
+        '@' | '*' | '#' => 1,
+        c if c.is_ascii_digit() => body
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(body.len()),
+        c if c.is_alphabetic() || c == '_' => body
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(body.len()),
+        _ => return None,
+    };
+    Some(body.split_at(name_len))
+}
+
+/// Expand the body of a `${...}` expansion
+///
+/// - `${VAR:-word}` is the word if `VAR` is unset or empty
+/// - `${VAR:=word}` is the same, and also assigns the word to `VAR`
+/// - `${VAR:+word}` is the word if `VAR` is set and not empty, and empty otherwise
+/// - `${VAR:?message}` is an error with the message if `VAR` is unset or empty
+///
+/// Without the colon, only an unset parameter counts as unset. The word is only expanded if it is
+/// used.
+///
+/// - `${#VAR}` is the number of characters in `VAR`
+/// - `${VAR#pattern}` and `${VAR##pattern}` remove the shortest or longest prefix matching the glob
+/// - `${VAR%pattern}` and `${VAR%%pattern}` remove the shortest or longest matching suffix
+fn parameter_expansion(
+    body: &str,
+    options: &ShellOptions,
+    run: &mut dyn FnMut(&str) -> Result<String, PosixError>,
+) -> Result<String, PosixError> {
+    let Some(Expansion {
+        name,
+        length,
+        modifier,
+    }) = parse_expansion(body)
+    else {
+        return Err(PosixError::BadSubstitution(format!("${{{body}}}")));
+    };
+    if length {
+        let length = match name {
+            "@" | "*" => positional_args().len(),
+            _ => var(name, options)?.chars().count(),
+        };
+        return Ok(length.to_string());
+    }
+    let Some(Modifier { op, colon, word }) = modifier else {
+        return var(name, options);
+    };
+
+    let value = lookup(name);
+    let set = value
+        .as_ref()
+        .is_some_and(|value| !colon || !value.is_empty());
+    match op {
+        Operator::Default if !set => envsubst(word, options, run),
+        Operator::Assign if !set => {
+            // only variables can be assigned, not positional or special parameters
+            if parameter(name).is_some() {
+                return Err(PosixError::BadSubstitution(format!("${{{body}}}")));
+            }
+            let word = envsubst(word, options, run)?;
+            set_local(name, &word);
+            Ok(word)
+        },
+        Operator::Alternative if set => envsubst(word, options, run),
+        Operator::Alternative => Ok(String::new()),
+        Operator::Error if !set => {
+            let message = match envsubst(word, options, run)? {
+                message if message.is_empty() => "parameter null or not set".to_string(),
+                message => message,
+            };
+            Err(PosixError::ParameterUnset(name.to_string(), message))
+        },
+        Operator::RemovePrefix { longest } => {
+            let value = var(name, options)?;
+            let pattern = trim_pattern(&envsubst(word, options, run)?);
+            Ok(remove_prefix(&value, &pattern, longest).to_string())
+        },
+        Operator::RemoveSuffix { longest } => {
+            let value = var(name, options)?;
+            let pattern = trim_pattern(&envsubst(word, options, run)?);
+            Ok(remove_suffix(&value, &pattern, longest).to_string())
+        },
+        _ => Ok(value.unwrap_or_default()),
+    }
+}
+
+/// Glob to trim a value with, a pattern that isn't a valid glob only matches itself
+fn trim_pattern(pattern: &str) -> Pattern {
+    Pattern::new(pattern).unwrap_or_else(|_| Pattern::new(&Pattern::escape(pattern)).unwrap())
+}
+
+/// Remove the shortest or longest prefix of the value that matches the pattern
+fn remove_prefix<'a>(value: &'a str, pattern: &Pattern, longest: bool) -> &'a str {
+    let mut ends = value
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(value.len()));
+    let end = if longest {
+        ends.rev().find(|end| pattern.matches(&value[..*end]))
+    } else {
+        ends.find(|end| pattern.matches(&value[..*end]))
+    };
+    end.map_or(value, |end| &value[end..])
+}
+
+/// Remove the shortest or longest suffix of the value that matches the pattern
+fn remove_suffix<'a>(value: &'a str, pattern: &Pattern, longest: bool) -> &'a str {
+    let mut starts = value
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(value.len()));
+    let start = if longest {
+        starts.find(|start| pattern.matches(&value[*start..]))
+    } else {
+        starts.rev().find(|start| pattern.matches(&value[*start..]))
+    };
+    start.map_or(value, |start| &value[..start])
+}
+
+/// Value of a parameter or environment variable, which is empty if it isn't set
+fn var(name: &str, options: &ShellOptions) -> Result<String, PosixError> {
+    match lookup(name) {
+        Some(value) => Ok(value),
+        None if options.nounset => Err(PosixError::UnsetVariable(name.to_string())),
+        None => Ok(String::new()),
+    }
+}
+
+/// Value of a parameter or environment variable, [None] if it isn't set
+fn lookup(name: &str) -> Option<String> {
+    match parameter(name) {
+        Some(value) => value,
+        None => std::env::var(name).ok(),
+    }
+}
+
+/// Value of a positional or special parameter, [None] if `name` isn't one
+///
+/// The inner option is [None] if there is no positional parameter with that number.
+fn parameter(name: &str) -> Option<Option<String>> {
+    let args = positional_args();
+    match name {
+        "@" => Some(Some(args.join(" "))),
+        "*" => {
+            let separator = ifs().chars().next().map(String::from).unwrap_or_default();
+            Some(Some(args.join(&separator)))
+        },
+        "#" => Some(Some(args.len().to_string())),
+        // `$0` is the name of the shell, which is an environment variable
+        "0" => None,
+        _ if name.chars().all(|c| c.is_ascii_digit()) => {
+            let n = name.parse::<usize>().ok()?;
+            Some(n.checked_sub(1).and_then(|i| args.get(i).cloned()))
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::envsubst;
+    use crate::{set_positional_args, PosixError, ShellOptions};
+
+    /// Stand-in for the interpreter that only knows `echo`
+    fn echo(cmd: &str) -> Result<String, PosixError> {
+        let args = cmd.strip_prefix("echo ").unwrap_or_default();
+        Ok(format!(
+            "{}\n",
+            envsubst(args, &ShellOptions::default(), &mut echo)?
+        ))
+    }
+
+    #[test]
+    fn command_substitution() {
+        assert_eq!(
+            envsubst("$(echo hi)", &ShellOptions::default(), &mut echo).unwrap(),
+            "hi"
+        );
+        assert_eq!(
+            envsubst("a`echo b`c", &ShellOptions::default(), &mut echo).unwrap(),
+            "abc"
+        );
+        assert_eq!(
+            envsubst(
+                "$(echo a $(echo b))-$(echo c)",
+                &ShellOptions::default(),
+                &mut echo
+            )
+            .unwrap(),
+            "a b-c"
+        );
+    }
+
+    #[test]
+    fn variables() {
+        std::env::set_var("SHRS_ENVSUBST_TEST", "val");
+        std::env::remove_var("SHRS_ENVSUBST_UNSET");
+        assert_eq!(
+            envsubst(
+                "$SHRS_ENVSUBST_TEST/${SHRS_ENVSUBST_TEST}x",
+                &ShellOptions::default(),
+                &mut echo
+            )
+            .unwrap(),
+            "val/valx"
+        );
+        assert_eq!(
+            envsubst(
+                "a$SHRS_ENVSUBST_UNSET$",
+                &ShellOptions::default(),
+                &mut echo
+            )
+            .unwrap(),
+            "a$"
+        );
+
+        let nounset = ShellOptions {
+            nounset: true,
+            ..Default::default()
+        };
+        assert!(envsubst("$SHRS_ENVSUBST_TEST", &nounset, &mut echo).is_ok());
+        assert!(matches!(
+            envsubst("a${SHRS_ENVSUBST_UNSET}", &nounset, &mut echo),
+            Err(PosixError::UnsetVariable(name)) if name == "SHRS_ENVSUBST_UNSET"
+        ));
+    }
+
+    #[test]
+    fn positional() {
+        set_positional_args(["a", "b c"].map(String::from).to_vec());
+        let options = ShellOptions::default();
+        assert_eq!(
+            envsubst("$1-${2}-$3-$#", &options, &mut echo).unwrap(),
+            "a-b c--2"
+        );
+        assert_eq!(envsubst("$10 $@", &options, &mut echo).unwrap(), "a0 a b c");
+
+        let nounset = ShellOptions {
+            nounset: true,
+            ..Default::default()
+        };
+        assert!(envsubst("$2", &nounset, &mut echo).is_ok());
+        assert!(matches!(
+            envsubst("${10}", &nounset, &mut echo),
+            Err(PosixError::UnsetVariable(name)) if name == "10"
+        ));
+        set_positional_args(vec![]);
+    }
+
+    #[test]
+    fn parameter_expansion() {
+        std::env::set_var("SHRS_ENVSUBST_OP_SET", "val");
+        std::env::set_var("SHRS_ENVSUBST_OP_EMPTY", "");
+        std::env::remove_var("SHRS_ENVSUBST_OP_UNSET");
+        let options = ShellOptions::default();
+
+        // (operator, expansion of a set, empty and unset variable)
+        let cases = [
+            (":-", "val", "word", "word"),
+            ("-", "val", "", "word"),
+            (":+", "word", "", ""),
+            ("+", "word", "word", ""),
+        ];
+        for (op, set, empty, unset) in cases {
+            for (var, expected) in [("SET", set), ("EMPTY", empty), ("UNSET", unset)] {
+                let line = format!("${{SHRS_ENVSUBST_OP_{var}{op}word}}");
+                assert_eq!(
+                    envsubst(&line, &options, &mut echo).unwrap(),
+                    expected,
+                    "{line}"
+                );
+            }
+        }
+
+        // the word is expanded, and only if it's used
+        assert_eq!(
+            envsubst(
+                "${SHRS_ENVSUBST_OP_UNSET:-${SHRS_ENVSUBST_OP_SET}$(echo x)}",
+                &options,
+                &mut echo
+            )
+            .unwrap(),
+            "valx"
+        );
+        let mut fail = |_: &str| -> Result<String, PosixError> { panic!("word was expanded") };
+        assert_eq!(
+            envsubst("${SHRS_ENVSUBST_OP_SET:-$(false)}", &options, &mut fail).unwrap(),
+            "val"
+        );
+
+        // an operator means an unset variable is not an error with nounset
+        let nounset = ShellOptions {
+            nounset: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            envsubst("${SHRS_ENVSUBST_OP_UNSET-word}", &nounset, &mut echo).unwrap(),
+            "word"
+        );
+    }
+
+    #[test]
+    fn parameter_assign() {
+        std::env::set_var("SHRS_ENVSUBST_ASSIGN_EMPTY", "");
+        std::env::remove_var("SHRS_ENVSUBST_ASSIGN_UNSET");
+        let options = ShellOptions::default();
+
+        assert_eq!(
+            envsubst("${SHRS_ENVSUBST_ASSIGN_EMPTY=word}", &options, &mut echo).unwrap(),
+            ""
+        );
+        assert_eq!(
+            envsubst("${SHRS_ENVSUBST_ASSIGN_EMPTY:=word}", &options, &mut echo).unwrap(),
+            "word"
+        );
+        assert_eq!(std::env::var("SHRS_ENVSUBST_ASSIGN_EMPTY").unwrap(), "word");
+        assert_eq!(
+            envsubst(
+                "${SHRS_ENVSUBST_ASSIGN_UNSET=a}${SHRS_ENVSUBST_ASSIGN_UNSET=b}",
+                &options,
+                &mut echo
+            )
+            .unwrap(),
+            "aa"
+        );
+        assert!(matches!(
+            envsubst("${1:=word}", &options, &mut echo),
+            Err(PosixError::BadSubstitution(_))
+        ));
+    }
+
+    #[test]
+    fn parameter_error() {
+        std::env::set_var("SHRS_ENVSUBST_ERR_EMPTY", "");
+        std::env::remove_var("SHRS_ENVSUBST_ERR_UNSET");
+        let options = ShellOptions::default();
+
+        assert_eq!(
+            envsubst("${SHRS_ENVSUBST_ERR_EMPTY?oops}", &options, &mut echo).unwrap(),
+            ""
+        );
+        assert!(matches!(
+            envsubst("${SHRS_ENVSUBST_ERR_EMPTY:?oops}", &options, &mut echo),
+            Err(PosixError::ParameterUnset(name, message))
+                if name == "SHRS_ENVSUBST_ERR_EMPTY" && message == "oops"
+        ));
+        assert!(matches!(
+            envsubst("${SHRS_ENVSUBST_ERR_UNSET?}", &options, &mut echo),
+            Err(PosixError::ParameterUnset(_, message)) if message == "parameter null or not set"
+        ));
+        assert!(matches!(
+            envsubst("${SHRS_ENVSUBST_ERR_UNSET!x}", &options, &mut echo),
+            Err(PosixError::BadSubstitution(_))
+        ));
+    }
+
+    #[test]
+    fn parameter_trim() {
+        std::env::set_var("SHRS_ENVSUBST_PATH", "/usr/local/lib.tar.gz");
+        std::env::set_var("SHRS_ENVSUBST_UNICODE", "héllo");
+        std::env::remove_var("SHRS_ENVSUBST_TRIM_UNSET");
+        set_positional_args(["a", "b", "c"].map(String::from).to_vec());
+        let options = ShellOptions::default();
+
+        let cases = [
+            ("${#SHRS_ENVSUBST_PATH}", "21"),
+            ("${#SHRS_ENVSUBST_UNICODE}", "5"),
+            ("${#SHRS_ENVSUBST_TRIM_UNSET}", "0"),
+            ("${#}-${#1}-${#@}", "3-1-3"),
+            ("${SHRS_ENVSUBST_PATH#*/}", "usr/local/lib.tar.gz"),
+            ("${SHRS_ENVSUBST_PATH##*/}", "lib.tar.gz"),
+            ("${SHRS_ENVSUBST_PATH%.*}", "/usr/local/lib.tar"),
+            ("${SHRS_ENVSUBST_PATH%%.*}", "/usr/local/lib"),
+            ("${SHRS_ENVSUBST_PATH#/usr}", "/local/lib.tar.gz"),
+            ("${SHRS_ENVSUBST_PATH%.gz}", "/usr/local/lib.tar"),
+            ("${SHRS_ENVSUBST_PATH#local}", "/usr/local/lib.tar.gz"),
+            ("${SHRS_ENVSUBST_PATH%[.]tar*}", "/usr/local/lib"),
+            ("${SHRS_ENVSUBST_PATH##*}", ""),
+            ("${SHRS_ENVSUBST_PATH#}", "/usr/local/lib.tar.gz"),
+            ("${SHRS_ENVSUBST_UNICODE#h?}", "llo"),
+            ("${SHRS_ENVSUBST_UNICODE%$(echo l)*}", "hél"),
+            ("${SHRS_ENVSUBST_TRIM_UNSET%x}", ""),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(
+                envsubst(line, &options, &mut echo).unwrap(),
+                expected,
+                "{line}"
+            );
+        }
+
+        assert!(matches!(
+            envsubst("${SHRS_ENVSUBST_PATH:#x}", &options, &mut echo),
+            Err(PosixError::BadSubstitution(_))
+        ));
+        set_positional_args(vec![]);
+    }
+
+    #[test]
+    fn arithmetic() {
+        std::env::set_var("SHRS_ENVSUBST_NUM", "3");
+        assert_eq!(
+            envsubst("$(( 1 + 2 ))", &ShellOptions::default(), &mut echo).unwrap(),
+            "3"
+        );
+        assert_eq!(
+            envsubst(
+                "x$(( $SHRS_ENVSUBST_NUM * (4 - 2) ))",
+                &ShellOptions::default(),
+                &mut echo
+            )
+            .unwrap(),
+            "x6"
+        );
+        assert_eq!(
+            envsubst("$(( $(echo 5) % 3 ))", &ShellOptions::default(), &mut echo).unwrap(),
+            "2"
+        );
+        assert_eq!(
+            envsubst("$(( 1 + ))", &ShellOptions::default(), &mut echo).unwrap(),
+            "0"
+        );
+    }
+}
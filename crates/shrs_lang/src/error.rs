@@ -1,4 +1,3 @@
-
 use thiserror::Error;
 
 use crate::ParserError;
@@ -20,6 +19,27 @@ pub enum PosixError {
     /// Command not found
     #[error("Command not found: {0}")]
     CommandNotFound(String),
+    /// Variable expanded with `nounset` enabled was not set
+    #[error("{0}: unbound variable")]
+    UnsetVariable(String),
+    /// `${...}` expansion that couldn't be parsed
+    #[error("{0}: bad substitution")]
+    BadSubstitution(String),
+    /// Parameter expanded with `${VAR:?message}` was not set, with the message
+    #[error("{0}: {1}")]
+    ParameterUnset(String, String),
+    /// Command failed with `errexit` enabled, so the shell should exit with its status
+    #[error("Exiting after command failed with {0}")]
+    Errexit(std::process::ExitStatus),
+    /// `break` out of the given number of enclosing loops
+    #[error("break {0}")]
+    Break(usize),
+    /// `continue` with the next iteration of the loop the given number of loops out
+    #[error("continue {0}")]
+    Continue(usize),
+    /// `return` from the function being evaluated with the given status
+    #[error("return {0}")]
+    Return(std::process::ExitStatus),
     /// Job manager specific error
     #[error("Job manager error: {0}")]
     Job(anyhow::Error),
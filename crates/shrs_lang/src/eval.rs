@@ -1,42 +1,438 @@
 // Lot of code based off of https://github.com/nuta/nsh/blob/main/src/eval.rs
 
-use glob::glob;
-use shrs_job::{run_external_command, JobManager, Output, Process, ProcessGroup, Stdin};
+use std::{
+    cell::Cell,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, IsTerminal, Read, Seek, SeekFrom, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd},
+        unix::process::ExitStatusExt,
+    },
+    process::ExitStatus,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use crate::{ast, Lexer, Parser, PosixError};
+use glob::{glob_with, MatchOptions, Pattern};
+use nix::{
+    errno::Errno,
+    fcntl::{fcntl, FcntlArg},
+    sys::wait::{waitpid, WaitStatus},
+    unistd::{close, dup2, fork, ForkResult},
+};
+use shrs_job::{
+    run_external_command, BuiltinProcess, ForkedProcess, JobManager, Output, Process, ProcessGroup,
+    Stdin, Substitution, SubstitutionProcess,
+};
 
-pub fn eval(job_manager: &mut JobManager, parser: Parser, lexer: Lexer) -> Result<(), PosixError> {
+use crate::{
+    arith::eval_arith,
+    ast,
+    builtins::ShellBuiltins,
+    envsubst::envsubst,
+    fields::{ifs, split_fields},
+    functions::{define_function, function, in_function, positional_args, FunctionScope},
+    Lexer, Parser, PosixError, ShellOptions, Token,
+};
 
+/// Evaluate a command line, returning the exit status of the last command that ran in the
+/// foreground
+///
+/// Commands are looked up in `builtins` before they are run as external commands.
+pub fn eval(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    parser: Parser,
+    lexer: Lexer,
+) -> Result<ExitStatus, PosixError> {
     let parsed = match parser.parse(lexer) {
         Ok(parsed) => parsed,
         Err(e) => {
-            // TODO detailed parse errors
             eprintln!("parse error: {e}");
             return Err(PosixError::Parse(e));
         },
     };
 
-    let (procs, pgid) =
-        match eval_command(job_manager, &parsed, None, None) {
-            Ok((procs, pgid)) => (procs, pgid),
-            Err(PosixError::CommandNotFound(_)) => {
-                // let _ = cmd.run_hook(CommandNotFoundCtx {});
-                // TODO return error code 127
-                return Ok(());
-            },
-            _ => return Ok(()),
-        };
+    match run_command(job_manager, options, builtins, &parsed, false) {
+        Ok(status) => Ok(status),
+        Err(e @ PosixError::Errexit(_)) => Err(e),
+        Err(e) => {
+            eprintln!("shrs: {e}");
+            Err(e)
+        },
+    }
+}
+
+/// Split a line into words and expand them like the arguments of a command
+///
+/// Used for builtins, which the shell runs itself instead of evaluating the line. Operators are
+/// kept as they were written and comments are left out.
+pub fn expand_words(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    line: &str,
+) -> Result<Vec<String>, PosixError> {
+    let mut words = vec![];
+    for token in Lexer::new(line) {
+        let (start, token, end) = token.map_err(|e| PosixError::Eval(e.into()))?;
+        match token {
+            Token::WORD(word) | Token::ASSIGNMENT_WORD(word) | Token::NAME(word) => words.extend(
+                expand_arg(job_manager, options, builtins, &word.to_string())?,
+            ),
+            Token::NEWLINE | Token::COMMENT(_) => {},
+            _ => words.push(line[start..end].to_string()),
+        }
+    }
+    Ok(words)
+}
+
+/// Run a command to completion in the foreground and return its exit status
+///
+/// `condition` is set for commands whose status is tested, such as the condition of an `if` or
+/// the left side of `&&`, which don't make the shell exit when they fail with `set -e`.
+fn run_command(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    cmd: &ast::Command,
+    condition: bool,
+) -> Result<ExitStatus, PosixError> {
+    let status = match eval_command(
+        job_manager,
+        options,
+        builtins,
+        cmd,
+        condition,
+        None,
+        None,
+        None,
+    ) {
+        Ok((procs, pgid)) => run_job(job_manager, options, procs, pgid, true)?.unwrap_or_default(),
+        Err(PosixError::CommandNotFound(_)) => {
+            // let _ = cmd.run_hook(CommandNotFoundCtx {});
+            ExitStatus::from_raw(127 << 8)
+        },
+        Err(e) => return Err(e),
+    };
+    LAST_STATUS.with(|last| last.set(status));
+
+    if options.errexit && !condition && exits_on_failure(cmd) && !status.success() {
+        return Err(PosixError::Errexit(status));
+    }
+    Ok(status)
+}
+
+/// If the command makes the shell exit when it fails with `set -e`, which is the case for
+/// simple commands and pipelines, compound commands check the commands they are made of instead
+fn exits_on_failure(cmd: &ast::Command) -> bool {
+    match cmd {
+        ast::Command::Simple { .. } | ast::Command::Pipeline(..) | ast::Command::Subshell(_) => {
+            true
+        },
+        ast::Command::SeqList(cmd, None) | ast::Command::AsyncList(_, Some(cmd)) => {
+            exits_on_failure(cmd)
+        },
+        _ => false,
+    }
+}
+
+thread_local! {
+    /// Number of loops being evaluated, `break` and `continue` are only allowed inside of one
+    static LOOP_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts a loop as entered until it is dropped
+struct LoopDepth;
+
+impl LoopDepth {
+    fn enter() -> Self {
+        LOOP_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        LoopDepth
+    }
+}
+
+impl Drop for LoopDepth {
+    fn drop(&mut self) {
+        LOOP_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Evaluate `break [n]` or `continue [n]`, which leave the body of the `n`th enclosing loop
+///
+/// If there are fewer than `n` loops the outermost one is left. Using them outside of a loop is
+/// an error.
+fn loop_control(program: &str, args: &[String]) -> Result<ExitStatus, PosixError> {
+    let depth = LOOP_DEPTH.with(Cell::get);
+    let n = match args {
+        [] => Ok(1),
+        [n] => match n.parse::<usize>() {
+            Ok(0) | Err(_) => Err(format!("{program}: {n}: loop count out of range")),
+            Ok(n) => Ok(n),
+        },
+        _ => Err(format!("{program}: too many arguments")),
+    };
+    let n = match n {
+        Ok(_) if depth == 0 => Err(format!(
+            "{program}: only meaningful in a `for', `while', or `until' loop"
+        )),
+        n => n,
+    };
+
+    match n {
+        Ok(n) if program == "break" => Err(PosixError::Break(n.min(depth))),
+        Ok(n) => Err(PosixError::Continue(n.min(depth))),
+        Err(e) => {
+            eprintln!("shrs: {e}");
+            Ok(ExitStatus::from_raw(1 << 8))
+        },
+    }
+}
+
+thread_local! {
+    /// Status of the last command that ran, which `return` uses by default
+    static LAST_STATUS: Cell<ExitStatus> = Cell::new(ExitStatus::default());
+}
+
+/// Evaluate `return [n]`, which stops the function being evaluated with status `n`, or the status
+/// of the last command if it isn't given
+///
+/// Using it outside of a function is an error.
+fn return_status(args: &[String]) -> Result<ExitStatus, PosixError> {
+    let status = match args {
+        _ if !in_function() => Err("return: can only `return' from a function".to_string()),
+        [] => Ok(LAST_STATUS.with(Cell::get)),
+        // only the lowest 8 bits are kept, like the status of a process
+        [n] => n
+            .parse::<i32>()
+            .map(|n| ExitStatus::from_raw((n & 0xff) << 8))
+            .map_err(|_| format!("return: {n}: numeric argument required")),
+        _ => Err("return: too many arguments".to_string()),
+    };
+
+    match status {
+        Ok(status) => Err(PosixError::Return(status)),
+        Err(e) => {
+            eprintln!("shrs: {e}");
+            Ok(ExitStatus::from_raw(1 << 8))
+        },
+    }
+}
 
-    run_job(job_manager, procs, pgid, true)?;
-    Ok(())
+/// Evaluate one of the expressions of an arithmetic for loop, an empty expression does nothing
+fn loop_arith(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    expr: &str,
+) -> Result<i64, PosixError> {
+    if expr.is_empty() {
+        return Ok(0);
+    }
+    let expanded = expand_value(job_manager, options, builtins, expr)?;
+    eval_arith(&expanded).map_err(|e| PosixError::Eval(anyhow::anyhow!("(({expr})): {e}")))
+}
+
+/// Run the condition or body of a loop, returns `None` if the loop should stop because of
+/// `break`
+///
+/// `break` and `continue` for loops further out are passed on to the enclosing loop.
+fn run_loop_body(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    cmd: &ast::Command,
+    condition: bool,
+) -> Result<Option<ExitStatus>, PosixError> {
+    match run_command(job_manager, options, builtins, cmd, condition) {
+        Ok(status) => Ok(Some(status)),
+        Err(PosixError::Break(n)) if n > 1 => Err(PosixError::Break(n - 1)),
+        Err(PosixError::Continue(n)) if n > 1 => Err(PosixError::Continue(n - 1)),
+        Err(PosixError::Break(_)) => Ok(None),
+        Err(PosixError::Continue(_)) => Ok(Some(ExitStatus::default())),
+        Err(e) => Err(e),
+    }
 }
 
+/// Run a command in a forked copy of the shell and wait for it to exit
+///
+/// Variables, the working directory and shell options changed by the command only change in the
+/// copy, so they don't affect the shell.
+fn run_subshell(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    cmd: &ast::Command,
+    condition: bool,
+) -> Result<ExitStatus, PosixError> {
+    // anything still buffered would otherwise be written by both processes
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+
+    // SAFETY: the child only evaluates the command and exits without returning to the caller
+    match unsafe { fork() }.map_err(|e| PosixError::Eval(e.into()))? {
+        ForkResult::Child => {
+            let code = exit_code(run_command(job_manager, options, builtins, cmd, condition));
+            let _ = io::stdout().flush();
+            std::process::exit(code);
+        },
+        ForkResult::Parent { child } => loop {
+            match waitpid(child, None) {
+                Ok(WaitStatus::Exited(_, code)) => return Ok(ExitStatus::from_raw(code << 8)),
+                Ok(WaitStatus::Signaled(_, signal, _)) => {
+                    return Ok(ExitStatus::from_raw(signal as i32))
+                },
+                Ok(_) | Err(Errno::EINTR) => continue,
+                Err(e) => return Err(PosixError::Eval(e.into())),
+            }
+        },
+    }
+}
+
+/// Code that a copy of the shell exits with after running a command
+fn exit_code(result: Result<ExitStatus, PosixError>) -> i32 {
+    let status = match result {
+        Ok(status) | Err(PosixError::Errexit(status) | PosixError::Return(status)) => status,
+        // loops outside of the copy are not affected
+        Err(PosixError::Break(_) | PosixError::Continue(_)) => ExitStatus::default(),
+        Err(e) => {
+            eprintln!("shrs: {e}");
+            ExitStatus::from_raw(1 << 8)
+        },
+    };
+    status
+        .code()
+        .or(status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+/// Start the command of a process substitution, `<(cmd)` or `>(cmd)`, returns [None] if the
+/// argument isn't one
+///
+/// The argument is replaced by the path of a pipe that is connected to the command.
+fn process_substitution(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    arg: &str,
+) -> Result<Option<SubstitutionProcess>, PosixError> {
+    let (direction, cmd) = if let Some(cmd) = arg.strip_prefix("<(") {
+        (Substitution::Input, cmd)
+    } else if let Some(cmd) = arg.strip_prefix(">(") {
+        (Substitution::Output, cmd)
+    } else {
+        return Ok(None);
+    };
+    let Some(cmd) = cmd.strip_suffix(')') else {
+        return Ok(None);
+    };
+
+    let parsed = Parser::default()
+        .parse(Lexer::new(cmd))
+        .map_err(PosixError::Parse)?;
+    let proc = SubstitutionProcess::spawn(arg, direction, || {
+        exit_code(run_command(job_manager, options, builtins, &parsed, false))
+    })
+    .map_err(PosixError::Eval)?;
+    Ok(Some(proc))
+}
+
+/// Processes started for the process substitutions in the arguments of a command
+///
+/// Dropping them waits for them to exit, so they outlive a command that is run by the shell
+/// itself.
+struct Substitutions(Vec<SubstitutionProcess>);
+
+impl Substitutions {
+    /// Hand the processes over to the job of the command that was started, which waits for them
+    /// along with the command
+    fn into_procs(mut self) -> Vec<Box<dyn Process>> {
+        std::mem::take(&mut self.0)
+            .into_iter()
+            .map(|mut proc| {
+                proc.close();
+                Box::new(proc) as Box<dyn Process>
+            })
+            .collect()
+    }
+}
+
+impl Drop for Substitutions {
+    fn drop(&mut self) {
+        for proc in &mut self.0 {
+            // a `>(cmd)` reads until the shell's end of the pipe is closed
+            proc.close();
+            let _ = proc.wait();
+        }
+    }
+}
+
+/// Pass on what a builtin printed to where its stdout goes, returns the input of the next command
+/// if it is piped
+fn builtin_output(stdout: Output, output: &[u8]) -> Result<Option<Stdin>, PosixError> {
+    match stdout {
+        Output::Inherit => {
+            let mut stdout = io::stdout();
+            stdout
+                .write_all(output)
+                .and_then(|_| stdout.flush())
+                .map_err(|e| PosixError::Eval(e.into()))?;
+            Ok(None)
+        },
+        Output::CreatePipe => Ok(Some(Stdin::File(
+            input_file(output).map_err(|e| PosixError::Eval(e.into()))?,
+        ))),
+        Output::File(mut file) => {
+            file.write_all(output).map_err(PosixError::Redirect)?;
+            Ok(None)
+        },
+        Output::FileDescriptor(fd) => {
+            dup_fd(fd)?
+                .write_all(output)
+                .map_err(PosixError::Redirect)?;
+            Ok(None)
+        },
+    }
+}
+
+/// Print a simple command after it was expanded, for `set -x`
+fn trace(vars: &[(String, String)], words: &[String]) {
+    let line = vars
+        .iter()
+        .map(|(var, val)| format!("{var}={val}"))
+        .chain(words.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let _ = writeln!(io::stderr(), "+ {line}");
+}
+
+/// Exit status of `:`, `true` and `false`, which the shell evaluates itself
+fn status_builtin(program: &str) -> Option<ExitStatus> {
+    match program {
+        ":" | "true" => Some(ExitStatus::default()),
+        "false" => Some(ExitStatus::from_raw(1 << 8)),
+        _ => None,
+    }
+}
+
+/// Result of a command that was run by the shell and has already exited
+///
+/// Only the exit status is kept, in a process that doesn't exist, so compound commands and
+/// builtins don't start a process to report their status.
+fn exited(status: ExitStatus) -> (Vec<Box<dyn Process>>, Option<u32>) {
+    let proc = BuiltinProcess::new("", &[] as &[&str], status, None);
+    (vec![Box::new(proc)], None)
+}
+
+/// Returns the exit status of the job if it was run in the foreground
 fn run_job(
     job_manager: &mut JobManager,
+    options: &ShellOptions,
     procs: Vec<Box<dyn Process>>,
     pgid: Option<u32>,
     foreground: bool,
-) -> Result<(), PosixError> {
+) -> Result<Option<ExitStatus>, PosixError> {
     let proc_group = ProcessGroup {
         id: pgid,
         processes: procs,
@@ -45,19 +441,75 @@ fn run_job(
 
     let job_id = job_manager.create_job("", proc_group);
 
-    if foreground {
-        job_manager
-            .put_job_in_foreground(Some(job_id), false)
-            .map_err(|e| PosixError::Job(e))?;
-    } else {
+    if !foreground {
         job_manager
             .put_job_in_background(Some(job_id), false)
             .map_err(|e| PosixError::Job(e))?;
+        return Ok(None);
+    }
+
+    let status = job_manager
+        .put_job_in_foreground(Some(job_id), false)
+        .map_err(|e| PosixError::Job(e))?;
+    if !options.pipefail {
+        return Ok(status);
+    }
+    let statuses = job_manager
+        .get_jobs()
+        .into_iter()
+        .find(|job| job.id() == job_id)
+        .map(|job| {
+            // like in bash, a failed process substitution doesn't fail the pipeline
+            job.processes()
+                .iter()
+                .filter(|proc| !proc.is_substitution())
+                .filter_map(|proc| proc.status_code())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    Ok(pipeline_status(&statuses, true).or(status))
+}
+
+/// Exit status of a pipeline from the status of each of its commands in order
+///
+/// This is the status of the last command, or with `pipefail` the last command that failed.
+fn pipeline_status(statuses: &[ExitStatus], pipefail: bool) -> Option<ExitStatus> {
+    if pipefail {
+        if let Some(failed) = statuses.iter().rev().find(|status| !status.success()) {
+            return Some(*failed);
+        }
     }
-    Ok(())
+    statuses.last().copied()
 }
 
-fn expand_arg(arg: &String) -> Vec<String> {
+fn expand_arg(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    arg: &String,
+) -> Result<Vec<String>, PosixError> {
+    // each positional parameter is a separate word, instead of them being joined
+    if arg == "$@" || arg == "\"$@\"" {
+        return Ok(positional_args());
+    }
+
+    // single quotes escape all special characters
+    if let Some(quoted) = arg.strip_prefix('\'') {
+        return Ok(vec![quoted
+            .strip_suffix('\'')
+            .unwrap_or(quoted)
+            .to_string()]);
+    }
+
+    // double quotes allow substitutions but the result is never globbed
+    if let Some(quoted) = arg.strip_prefix('"') {
+        let quoted = quoted.strip_suffix('"').unwrap_or(quoted);
+        let a = envsubst(quoted, options, &mut |cmd| {
+            command_substitution(job_manager, options, builtins, cmd)
+        })?;
+        return Ok(vec![a]);
+    }
+
     let mut a = arg.clone();
 
     // expand ~
@@ -69,57 +521,627 @@ fn expand_arg(arg: &String) -> Vec<String> {
         );
     }
 
-    // quotes escape all special characters
-    let first = arg.chars().next().unwrap();
-    if first == '\'' || first == '\"' {
-        return a
-            .trim_matches(|c| c == '\'' || c == '\"')
-            .split_whitespace()
-            .map(ToString::to_string)
-            .collect();
-    }
-    // match globbed files only if the glob actually works
-    else if glob::Pattern::escape(a.as_str()) != a.as_str() {
-        if let Ok(files) = glob(a.as_str()) {
-            return files
-                .filter_map(|file| match file {
-                    Ok(s) => Some(s.to_string_lossy().to_string()),
-                    Err(s) => Some(s.to_string()),
-                })
-                .collect();
+    let a = envsubst(&a, options, &mut |cmd| {
+        command_substitution(job_manager, options, builtins, cmd)
+    })?;
+
+    Ok(expand_glob(&a))
+}
+
+/// Expand the value of an assignment, which is never split into fields or globbed
+fn expand_value(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    val: &str,
+) -> Result<String, PosixError> {
+    let val = match val.strip_prefix('~') {
+        Some(remaining) => format!(
+            "{}{}",
+            dirs::home_dir().unwrap().to_string_lossy(),
+            remaining
+        ),
+        None => val.to_string(),
+    };
+    envsubst(&val, options, &mut |cmd| {
+        command_substitution(job_manager, options, builtins, cmd)
+    })
+}
+
+/// Set a variable in the shell
+///
+/// Variables are stored in the environment of the shell process, but child processes only get
+/// [ShellBuiltins::exported_vars], so a new variable isn't passed to them unless it is exported.
+pub(crate) fn set_local(var: &str, val: &str) {
+    std::env::set_var(var, val);
+}
+
+/// Expand a pattern of a `case` arm, which is matched against the word like a glob
+///
+/// Quoted patterns and patterns that aren't valid globs only match the word itself.
+fn case_pattern(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    pattern: &str,
+) -> Result<Pattern, PosixError> {
+    let quoted = pattern.starts_with(['\'', '"']);
+    let pattern = if quoted {
+        expand_arg(job_manager, options, builtins, &pattern.to_string())?.concat()
+    } else {
+        expand_value(job_manager, options, builtins, pattern)?
+    };
+    match Pattern::new(&pattern) {
+        Ok(glob) if !quoted => Ok(glob),
+        _ => Ok(Pattern::new(&Pattern::escape(&pattern)).unwrap()),
+    }
+}
+
+/// Set a variable back to a value saved before, or unset it if it wasn't set
+pub(crate) fn restore_var(var: &str, val: Option<OsString>) {
+    match val {
+        Some(val) => std::env::set_var(var, val),
+        None => std::env::remove_var(var),
+    }
+}
+
+/// Value of a variable that is restored when this is dropped
+struct SavedVar {
+    var: String,
+    val: Option<OsString>,
+}
+
+impl SavedVar {
+    fn save(var: &str) -> Self {
+        SavedVar {
+            var: var.to_string(),
+            val: std::env::var_os(var),
+        }
+    }
+}
+
+impl Drop for SavedVar {
+    fn drop(&mut self) {
+        restore_var(&self.var, self.val.take());
+    }
+}
+
+/// Expand a word containing `*`, `?` or `[...]` to the matching paths, relative to the working
+/// directory
+///
+/// The paths are sorted and hidden files only match if the pattern starts with a `.`. A word
+/// that doesn't match anything, or isn't a valid pattern, is left as is.
+fn expand_glob(word: &str) -> Vec<String> {
+    if !word.contains(['*', '?', '[']) {
+        return vec![word.to_string()];
+    }
+
+    let Ok(pattern) = Pattern::new(word) else {
+        return vec![word.to_string()];
+    };
+    // glob never lists hidden files when a literal leading dot is required, so they are filtered
+    // out afterwards instead
+    let hidden = MatchOptions {
+        require_literal_leading_dot: true,
+        ..MatchOptions::new()
+    };
+    let mut files = glob_with(word, MatchOptions::new())
+        .into_iter()
+        .flatten()
+        .filter_map(|file| file.ok())
+        .filter(|file| pattern.matches_path_with(file, hidden))
+        .map(|file| file.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+
+    if files.is_empty() {
+        return vec![word.to_string()];
+    }
+    files.sort();
+    files
+}
+
+/// Evaluate a command and capture its stdout
+fn command_substitution(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    cmd: &str,
+) -> Result<String, PosixError> {
+    let parsed = Parser::default()
+        .parse(Lexer::new(cmd))
+        .map_err(PosixError::Parse)?;
+    // the command runs in a copy of the shell so builtins like `cd` and `exit` don't affect it,
+    // and a failing command substitution doesn't make the shell exit
+    let (mut proc, pgid) =
+        ForkedProcess::spawn(cmd, Stdin::Inherit, Output::CreatePipe, None, || {
+            exit_code(run_command(job_manager, options, builtins, &parsed, true))
+        })
+        .map_err(PosixError::Eval)?;
+
+    // read everything before waiting so the command can't block on a full pipe
+    let mut output = vec![];
+    if let Some(Stdin::File(mut stdout)) = proc.stdout() {
+        stdout
+            .read_to_end(&mut output)
+            .map_err(|e| PosixError::Eval(e.into()))?;
+    }
+    run_job(job_manager, options, vec![Box::new(proc)], pgid, true)?;
+
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Standard streams of a command after applying its redirections, [None] if not redirected
+#[derive(Default)]
+struct Redirected {
+    stdin: Option<Stdin>,
+    stdout: Option<Output>,
+    stderr: Option<Output>,
+}
+
+impl Redirected {
+    /// Redirect the output file descriptor `n`, which is stdout if it isn't given
+    fn set_output(&mut self, n: Option<usize>, output: Output) -> Result<(), PosixError> {
+        match n {
+            None | Some(1) => self.stdout = Some(output),
+            Some(2) => self.stderr = Some(output),
+            _ => return Err(unsupported_fd(n)),
         }
+        Ok(())
     }
+}
 
-    vec![a]
+fn unsupported_fd(n: Option<usize>) -> PosixError {
+    PosixError::Redirect(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{}: only stdin, stdout and stderr can be redirected",
+            n.unwrap_or_default()
+        ),
+    ))
+}
+
+/// Open the files of each redirection, later redirections override earlier ones
+fn apply_redirects(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    redirects: &[ast::Redirect],
+) -> Result<Redirected, PosixError> {
+    let mut redirected = Redirected::default();
+
+    for redirect in redirects {
+        let file = match redirect.mode {
+            ast::RedirectMode::Heredoc { .. } => String::new(),
+            _ => expand_arg(job_manager, options, builtins, &redirect.file)?
+                .into_iter()
+                .next()
+                .unwrap_or_default(),
+        };
+
+        match redirect.mode {
+            ast::RedirectMode::Read => {
+                if !matches!(redirect.n, None | Some(0)) {
+                    return Err(unsupported_fd(redirect.n));
+                }
+                let file = File::open(file).map_err(PosixError::Redirect)?;
+                redirected.stdin = Some(Stdin::File(file));
+            },
+            ast::RedirectMode::Heredoc { expand } => {
+                let body = match expand {
+                    true => envsubst(&redirect.file, options, &mut |cmd| {
+                        command_substitution(job_manager, options, builtins, cmd)
+                    })?,
+                    false => redirect.file.clone(),
+                };
+                let file = input_file(body.as_bytes()).map_err(PosixError::Redirect)?;
+                redirected.stdin = Some(Stdin::File(file));
+            },
+            ast::RedirectMode::Write | ast::RedirectMode::WriteAppend => {
+                let append = matches!(redirect.mode, ast::RedirectMode::WriteAppend);
+                let output = Output::File(open_output(&file, append, options.noclobber)?);
+                redirected.set_output(redirect.n, output)?;
+            },
+            ast::RedirectMode::Clobber => {
+                let output = Output::File(open_output(&file, false, false)?);
+                redirected.set_output(redirect.n, output)?;
+            },
+            ast::RedirectMode::ReadDup if matches!(redirect.n, None | Some(0)) => {
+                let fd = parse_fd(&file)?;
+                if fd != 0 {
+                    redirected.stdin = Some(Stdin::File(dup_fd(fd)?));
+                }
+            },
+            ast::RedirectMode::ReadDup | ast::RedirectMode::WriteDup => {
+                // `>&file` is the same as `&>file`
+                if redirect.n.is_none() && file.parse::<i32>().is_err() && file != "-" {
+                    let file = open_output(&file, false, options.noclobber)?;
+                    let stderr = file.try_clone().map_err(PosixError::Redirect)?;
+                    redirected.stdout = Some(Output::File(file));
+                    redirected.stderr = Some(Output::File(stderr));
+                    continue;
+                }
+                let output = dup_output(&redirected, parse_fd(&file)?)?;
+                redirected.set_output(redirect.n, output)?;
+            },
+            ast::RedirectMode::WriteBoth | ast::RedirectMode::WriteBothAppend => {
+                let append = matches!(redirect.mode, ast::RedirectMode::WriteBothAppend);
+                let file = open_output(&file, append, options.noclobber)?;
+                // both streams share the same file offset so output is interleaved correctly
+                let stderr = file.try_clone().map_err(PosixError::Redirect)?;
+                redirected.stdout = Some(Output::File(file));
+                redirected.stderr = Some(Output::File(stderr));
+            },
+            ast::RedirectMode::ReadWrite => {
+                // the file is created if it doesn't exist, but never truncated
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .open(file)
+                    .map_err(PosixError::Redirect)?;
+                match redirect.n {
+                    None | Some(0) => redirected.stdin = Some(Stdin::File(file)),
+                    n => redirected.set_output(n, Output::File(file))?,
+                }
+            },
+            ast::RedirectMode::ReadAppend => {
+                return Err(PosixError::Redirect(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "reading in append mode is not supported",
+                )));
+            },
+        }
+    }
+
+    Ok(redirected)
+}
+
+/// Parse the file descriptor given to `<&` or `>&`
+fn parse_fd(word: &str) -> Result<i32, PosixError> {
+    word.parse::<i32>().map_err(|_| {
+        PosixError::Redirect(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{word}: can only duplicate file descriptors"),
+        ))
+    })
+}
+
+/// Open a new handle to one of the shell's file descriptors
+fn dup_fd(fd: i32) -> Result<File, PosixError> {
+    let fd = nix::unistd::dup(fd).map_err(|e| PosixError::Redirect(e.into()))?;
+    // SAFETY: the file descriptor was just created and nothing else owns it
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Output that goes to the same place as the given file descriptor, taking into account the
+/// redirections applied so far
+fn dup_output(redirected: &Redirected, fd: i32) -> Result<Output, PosixError> {
+    let current = match fd {
+        1 => redirected.stdout.as_ref(),
+        2 => redirected.stderr.as_ref(),
+        _ => None,
+    };
+    match current {
+        Some(Output::File(file)) => Ok(Output::File(
+            file.try_clone().map_err(PosixError::Redirect)?,
+        )),
+        // stdout and stderr are duplicated in the command once its own streams are set up, so
+        // this also follows them into a pipe
+        _ if fd == 1 || fd == 2 => Ok(Output::FileDescriptor(fd)),
+        _ => Ok(Output::File(dup_fd(fd)?)),
+    }
+}
+
+/// Write the input of a command, such as the body of a here document, to an unnamed temporary
+/// file to read it from the start
+fn input_file(input: &[u8]) -> std::io::Result<File> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "shrs_heredoc_{}_{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    // the contents stay available until the file is closed
+    fs::remove_file(&path)?;
+    file.write_all(input)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+/// Open a file to redirect output to, either truncating or appending to it
+///
+/// With `noclobber` an existing file is not truncated and an error is returned instead, files
+/// that aren't regular files such as `/dev/null` can still be written to.
+fn open_output(file: &str, append: bool, noclobber: bool) -> Result<File, PosixError> {
+    if noclobber && !append {
+        match OpenOptions::new().write(true).create_new(true).open(file) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                if fs::metadata(file).map_or(true, |meta| meta.is_file()) {
+                    return Err(PosixError::Redirect(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{file}: cannot overwrite existing file"),
+                    )));
+                }
+            },
+            opened => return opened.map_err(PosixError::Redirect),
+        }
+    }
+
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(file)
+        .map_err(PosixError::Redirect)
+}
+
+/// Whether the command is evaluated by the shell instead of starting a process for it
+fn runs_in_shell(cmd: &ast::Command) -> bool {
+    matches!(
+        cmd,
+        ast::Command::And(..)
+            | ast::Command::Or(..)
+            | ast::Command::Not(_)
+            | ast::Command::SeqList(_, Some(_))
+            | ast::Command::Subshell(_)
+            | ast::Command::If { .. }
+            | ast::Command::While { .. }
+            | ast::Command::Until { .. }
+            | ast::Command::For { .. }
+            | ast::Command::ArithFor { .. }
+            | ast::Command::Case { .. }
+            | ast::Command::Redirected { .. }
+    )
+}
+
+/// Standard streams of the shell replaced by the redirections of a compound command, which are
+/// restored when it is dropped
+struct RedirectedFds(Vec<(i32, i32)>);
+
+impl RedirectedFds {
+    fn apply(redirected: &Redirected) -> Result<Self, PosixError> {
+        let mut saved = RedirectedFds(vec![]);
+        let stdin = redirected.stdin.as_ref().map(AsRawFd::as_raw_fd);
+        let outputs = [(&redirected.stdout, 1), (&redirected.stderr, 2)];
+        let outputs = outputs.iter().map(|(output, target)| {
+            let fd = match output {
+                Some(Output::File(file)) => Some(file.as_raw_fd()),
+                Some(Output::FileDescriptor(fd)) => Some(*fd),
+                _ => None,
+            };
+            (fd, *target)
+        });
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        for (fd, target) in std::iter::once((stdin, 0)).chain(outputs) {
+            let Some(fd) = fd.filter(|fd| *fd != target) else {
+                continue;
+            };
+            let copy = fcntl(target, FcntlArg::F_DUPFD_CLOEXEC(10))
+                .map_err(|e| PosixError::Redirect(e.into()))?;
+            saved.0.push((copy, target));
+            dup2(fd, target).map_err(|e| PosixError::Redirect(e.into()))?;
+        }
+        Ok(saved)
+    }
+}
+
+impl Drop for RedirectedFds {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        for (copy, target) in self.0.drain(..).rev() {
+            let _ = dup2(copy, target);
+            let _ = close(copy);
+        }
+    }
+}
+
+/// Call of a function found by [eval_command]
+#[derive(Clone, Copy)]
+struct Call<'a> {
+    body: &'a ast::Command,
+    args: &'a [String],
+    /// Variables assigned for the call (`FOO=bar f`)
+    vars: &'a [(String, String)],
+    redirected: &'a Redirected,
+}
+
+/// Run the body of a function in the shell with its redirections applied
+///
+/// The arguments are the positional parameters of the body, and the variables assigned for the
+/// call are set while it runs. The shell restores the variables declared `local` in it once it
+/// returns.
+fn call_function(
+    job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
+    call: Call,
+    condition: bool,
+) -> Result<ExitStatus, PosixError> {
+    let _fds = RedirectedFds::apply(call.redirected)?;
+    let _saved = call
+        .vars
+        .iter()
+        .map(|(var, _)| SavedVar::save(var))
+        .collect::<Vec<_>>();
+    for (var, val) in call.vars {
+        set_local(var, val);
+    }
+
+    let _scope = FunctionScope::enter(call.args);
+    builtins.push_scope();
+    let res = run_command(job_manager, options, builtins, call.body, condition);
+    builtins.pop_scope();
+    match res {
+        Ok(status) | Err(PosixError::Return(status)) => Ok(status),
+        Err(e) => Err(e),
+    }
 }
 
 /// Returns group of processes and also the pgid if it has one
+///
+/// Simple commands and pipelines are returned without waiting for them, while compound commands
+/// are run to completion and return a process that holds their exit status. Processes are put in
+/// the process group `pgid`, or a new one led by the first process if it isn't given.
+#[allow(clippy::too_many_arguments)]
 fn eval_command(
     job_manager: &mut JobManager,
+    options: &ShellOptions,
+    builtins: &mut dyn ShellBuiltins,
     cmd: &ast::Command,
+    condition: bool,
     stdin: Option<Stdin>,
     stdout: Option<Output>,
+    pgid: Option<u32>,
 ) -> Result<(Vec<Box<dyn Process>>, Option<u32>), PosixError> {
+    // compound commands are run by the shell itself, so to read from and write to a pipe they
+    // run in a copy of the shell with its stdin and stdout connected to it instead
+    if (stdin.is_some() || stdout.is_some()) && runs_in_shell(cmd) {
+        // a subshell is already a copy, so only its body is run in the fork
+        let body = if let ast::Command::Subshell(body) = cmd {
+            body
+        } else {
+            cmd
+        };
+        let (proc, pgid) = ForkedProcess::spawn(
+            "",
+            stdin.unwrap_or(Stdin::Inherit),
+            stdout.unwrap_or(Output::Inherit),
+            pgid,
+            || exit_code(run_command(job_manager, options, builtins, body, condition)),
+        )
+        .map_err(PosixError::Eval)?;
+        return Ok((vec![Box::new(proc)], pgid));
+    }
+
     match cmd {
         ast::Command::Simple {
-            assigns: _,
-            redirects: _,
+            assigns,
+            redirects,
             args,
         } => {
-            let mut args_it = args.iter();
-            let program = args_it.next().unwrap();
-            let args = args_it.flat_map(expand_arg).collect::<Vec<_>>();
+            let mut expanded: Vec<String> = vec![];
+            let mut substitutions = Substitutions(vec![]);
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0
+                    && expanded
+                        .first()
+                        .is_some_and(|program| builtins.expands_args(program))
+                {
+                    expanded.push(arg.clone());
+                    continue;
+                }
+                match process_substitution(job_manager, options, builtins, arg)? {
+                    Some(proc) => {
+                        expanded.push(proc.path().to_string_lossy().into_owned());
+                        substitutions.0.push(proc);
+                    },
+                    None => expanded.extend(expand_arg(job_manager, options, builtins, arg)?),
+                }
+            }
+            let mut vars = vec![];
+            for assign in assigns {
+                let val = expand_value(job_manager, options, builtins, &assign.val)?;
+                vars.push((assign.var.clone(), val));
+            }
+            if options.xtrace {
+                trace(&vars, &expanded);
+            }
+            // the command can expand to nothing, such as an unset variable, in which case the
+            // assignments are made in the shell
+            let Some((program, args)) = expanded.split_first() else {
+                for (var, val) in vars {
+                    set_local(&var, &val);
+                }
+                return Ok((vec![], None));
+            };
+
+            if program == "break" || program == "continue" {
+                return loop_control(program, args).map(exited);
+            }
+            if program == "return" {
+                return return_status(args).map(exited);
+            }
+
+            // redirections take priority over pipes
+            let redirected = apply_redirects(job_manager, options, builtins, redirects)?;
+
+            if let Some(body) = function(program) {
+                let call = Call {
+                    body: &body,
+                    args,
+                    vars: &vars,
+                    redirected: &redirected,
+                };
+                // like compound commands, a function in a pipeline runs in a copy of the shell
+                // connected to the pipe
+                if stdin.is_some() || stdout.is_some() {
+                    let (proc, pgid) = ForkedProcess::spawn(
+                        program,
+                        stdin.unwrap_or(Stdin::Inherit),
+                        stdout.unwrap_or(Output::Inherit),
+                        pgid,
+                        || {
+                            exit_code(call_function(
+                                job_manager,
+                                options,
+                                builtins,
+                                call,
+                                condition,
+                            ))
+                        },
+                    )
+                    .map_err(PosixError::Eval)?;
+                    return Ok((vec![Box::new(proc)], pgid));
+                }
+                let status = call_function(job_manager, options, builtins, call, condition)?;
+                return Ok(exited(status));
+            }
+
+            let proc_stdin = redirected.stdin.or(stdin).unwrap_or(Stdin::Inherit);
+            let proc_stdout = redirected.stdout.or(stdout).unwrap_or(Output::Inherit);
+            let proc_stderr = redirected.stderr.unwrap_or(Output::Inherit);
+
+            // these only exit with a status, so there is no need to start a process for them
+            if let Some(status) = status_builtin(program) {
+                // the next command of a pipeline reads nothing instead of the terminal
+                let stdout = match proc_stdout {
+                    Output::CreatePipe => Some(Stdin::File(
+                        File::open("/dev/null").map_err(|e| PosixError::Eval(e.into()))?,
+                    )),
+                    _ => None,
+                };
+                let proc = BuiltinProcess::new(program, args, status, stdout);
+                return Ok((vec![Box::new(proc)], None));
+            }
 
-            let proc_stdin = stdin.unwrap_or(Stdin::Inherit);
-            let proc_stdout = stdout.unwrap_or(Output::Inherit);
+            // builtins can't write to a pipe or file themselves, so what they print is passed on,
+            // which is also how it reaches the pipe of a copy of the shell without being styled
+            let capture = !matches!(proc_stdout, Output::Inherit) || !io::stdout().is_terminal();
+            if let Some((status, output)) = builtins.run(job_manager, &expanded, capture) {
+                let stdout = builtin_output(proc_stdout, &output)?;
+                let proc = BuiltinProcess::new(program, args, status, stdout);
+                return Ok((vec![Box::new(proc)], None));
+            }
 
+            let mut env = builtins.exported_vars();
+            env.extend(vars);
             let (proc, pgid) = match run_external_command(
                 program,
-                &args,
+                args,
+                &env,
                 proc_stdin,
                 proc_stdout,
-                Output::Inherit,
-                None,
+                proc_stderr,
+                pgid,
             ) {
                 Ok((proc, pgid)) => (proc, pgid),
                 Err(e) => match e.kind() {
@@ -129,32 +1151,1106 @@ fn eval_command(
                     _ => return Err(PosixError::Eval(e.into())),
                 },
             };
-            Ok((vec![proc], pgid))
+            let mut procs = substitutions.into_procs();
+            procs.push(proc);
+            Ok((procs, pgid))
         },
         ast::Command::Pipeline(a_cmd, b_cmd) => {
-            let (mut a_procs, _a_pgid) =
-                eval_command(job_manager, a_cmd, stdin, Some(Output::CreatePipe))?;
+            // every process of the pipeline joins the group of the first one, so the whole
+            // pipeline is one job that is stopped, resumed and interrupted together
+            let (mut a_procs, a_pgid) = eval_command(
+                job_manager,
+                options,
+                builtins,
+                a_cmd,
+                condition,
+                stdin,
+                Some(Output::CreatePipe),
+                pgid,
+            )?;
+            let pgid = a_pgid.or(pgid);
             let (b_procs, b_pgid) = eval_command(
                 job_manager,
+                options,
+                builtins,
                 b_cmd,
-                a_procs.last_mut().unwrap().stdout(),
+                condition,
+                a_procs.last_mut().and_then(|proc| proc.stdout()),
                 stdout,
+                pgid,
             )?;
             a_procs.extend(b_procs);
-            Ok((a_procs, b_pgid))
+            Ok((a_procs, pgid.or(b_pgid)))
+        },
+        ast::Command::And(a_cmd, b_cmd) | ast::Command::Or(a_cmd, b_cmd) => {
+            let mut status = run_command(job_manager, options, builtins, a_cmd, true)?;
+            // `&&` only runs the right side if the left succeeded and `||` if it failed
+            if status.success() == matches!(cmd, ast::Command::And(..)) {
+                status = run_command(job_manager, options, builtins, b_cmd, condition)?;
+            }
+            Ok(exited(status))
         },
         ast::Command::AsyncList(a_cmd, b_cmd) => {
             // TODO double check stdin and stdout
-            let (procs, pgid) = eval_command(job_manager, a_cmd, None, None)?;
-            run_job(job_manager, procs, pgid, false)?;
+            let (procs, pgid) = eval_command(
+                job_manager,
+                options,
+                builtins,
+                a_cmd,
+                condition,
+                None,
+                None,
+                None,
+            )?;
+            run_job(job_manager, options, procs, pgid, false)?;
 
             if let Some(b_cmd) = b_cmd {
-                eval_command(job_manager, b_cmd, None, None)
+                eval_command(
+                    job_manager,
+                    options,
+                    builtins,
+                    b_cmd,
+                    condition,
+                    None,
+                    None,
+                    None,
+                )
             } else {
                 Ok((vec![], None))
             }
         },
+        ast::Command::SeqList(a_cmd, None) => eval_command(
+            job_manager,
+            options,
+            builtins,
+            a_cmd,
+            condition,
+            stdin,
+            stdout,
+            pgid,
+        ),
+        ast::Command::SeqList(a_cmd, Some(b_cmd)) => {
+            run_command(job_manager, options, builtins, a_cmd, condition)?;
+            let status = run_command(job_manager, options, builtins, b_cmd, condition)?;
+            Ok(exited(status))
+        },
+        ast::Command::If { conds, else_part } => {
+            for ast::Condition { cond, body } in conds {
+                if run_command(job_manager, options, builtins, cond, true)?.success() {
+                    let status = run_command(job_manager, options, builtins, body, condition)?;
+                    return Ok(exited(status));
+                }
+            }
+            let status = match else_part {
+                Some(else_part) => {
+                    run_command(job_manager, options, builtins, else_part, condition)?
+                },
+                None => ExitStatus::default(),
+            };
+            Ok(exited(status))
+        },
+        ast::Command::While { cond, body } | ast::Command::Until { cond, body } => {
+            let until = matches!(cmd, ast::Command::Until { .. });
+            let _depth = LoopDepth::enter();
+            let mut status = ExitStatus::default();
+            loop {
+                match run_loop_body(job_manager, options, builtins, cond, true)? {
+                    Some(cond) if cond.success() != until => {},
+                    _ => break,
+                }
+                match run_loop_body(job_manager, options, builtins, body, condition)? {
+                    Some(body) => status = body,
+                    None => break,
+                }
+            }
+            Ok(exited(status))
+        },
+        ast::Command::For {
+            name,
+            wordlist,
+            body,
+        } => {
+            // the results of substitutions are split with IFS, but literal words are left alone
+            let ifs = ifs();
+            let mut words = vec![];
+            for word in wordlist {
+                let expanded = expand_arg(job_manager, options, builtins, word)?;
+                if word.starts_with(['\'', '"']) || !word.contains(['$', '`']) {
+                    words.extend(expanded);
+                } else {
+                    words.extend(expanded.iter().flat_map(|w| split_fields(w, &ifs)));
+                }
+            }
+            let _depth = LoopDepth::enter();
+            // the variable is put back however the loop is left
+            let _saved = SavedVar::save(name);
+            let mut status = ExitStatus::default();
+            for word in words {
+                set_local(name, &word);
+                match run_loop_body(job_manager, options, builtins, body, condition)? {
+                    Some(body) => status = body,
+                    None => break,
+                }
+            }
+            Ok(exited(status))
+        },
+        ast::Command::ArithFor {
+            init,
+            cond,
+            step,
+            body,
+        } => {
+            let _depth = LoopDepth::enter();
+            loop_arith(job_manager, options, builtins, init)?;
+            let mut status = ExitStatus::default();
+            while cond.is_empty() || loop_arith(job_manager, options, builtins, cond)? != 0 {
+                match run_loop_body(job_manager, options, builtins, body, condition)? {
+                    Some(body) => status = body,
+                    None => break,
+                }
+                loop_arith(job_manager, options, builtins, step)?;
+            }
+            Ok(exited(status))
+        },
+        ast::Command::Not(cmd) => {
+            // the status is tested, so a failure doesn't make the shell exit with `set -e`
+            let status = run_command(job_manager, options, builtins, cmd, true)?;
+            let code = if status.success() { 1 } else { 0 };
+            Ok(exited(ExitStatus::from_raw(code << 8)))
+        },
+        ast::Command::Subshell(cmd) => {
+            let status = run_subshell(job_manager, options, builtins, cmd, condition)?;
+            Ok(exited(status))
+        },
+        ast::Command::Redirected { cmd, redirects } => {
+            let redirected = apply_redirects(job_manager, options, builtins, redirects)?;
+            let _fds = RedirectedFds::apply(&redirected)?;
+            let status = run_command(job_manager, options, builtins, cmd, condition)?;
+            Ok(exited(status))
+        },
+        ast::Command::Fn {
+            fname,
+            body,
+            description,
+        } => {
+            define_function(fname, *body.clone(), description.clone());
+            Ok(exited(ExitStatus::default()))
+        },
+        ast::Command::Case { word, arms } => {
+            let word = expand_value(job_manager, options, builtins, word)?;
+            // only the first arm with a matching pattern is run
+            for arm in arms {
+                for pattern in &arm.pattern {
+                    if case_pattern(job_manager, options, builtins, pattern)?.matches(&word) {
+                        let status =
+                            run_command(job_manager, options, builtins, &arm.body, condition)?;
+                        return Ok(exited(status));
+                    }
+                }
+            }
+            Ok(exited(ExitStatus::default()))
+        },
         ast::Command::None => Ok((vec![], None)),
-        _ => todo!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        os::unix::process::ExitStatusExt,
+        process::ExitStatus,
+    };
+
+    use shrs_job::{JobManager, Output, ProcessId, Stdin};
+
+    use super::{
+        apply_redirects, eval, eval_command, expand_glob, expand_words, pipeline_status, run_job,
+        Redirected,
+    };
+    use crate::{
+        ast, function_description, set_positional_args, Lexer, NoBuiltins, Parser, PosixError,
+        ShellBuiltins, ShellOptions,
+    };
+
+    /// Evaluate a line and return its exit code
+    fn run(line: &str, options: &ShellOptions) -> Result<i32, PosixError> {
+        let lexer = Lexer::new(line);
+        eval(
+            &mut JobManager::default(),
+            options,
+            &mut NoBuiltins,
+            Parser::default(),
+            lexer,
+        )
+        .map(|status| status.code().unwrap())
+    }
+
+    /// Redirections of the simple command on the line
+    fn redirects(line: &str) -> Vec<ast::Redirect> {
+        let ast::Command::Simple { redirects, .. } =
+            Parser::default().parse(Lexer::new(line)).unwrap()
+        else {
+            panic!("expected simple command");
+        };
+        redirects
+    }
+
+    /// Apply the redirections of the simple command on the line
+    fn redirected(line: &str, options: &ShellOptions) -> Result<Redirected, PosixError> {
+        apply_redirects(
+            &mut JobManager::default(),
+            options,
+            &mut NoBuiltins,
+            &redirects(line),
+        )
+    }
+
+    #[test]
+    fn builtin_words() {
+        std::env::set_var("SHRS_EXPAND_WORDS", "val");
+        assert_eq!(
+            expand_words(
+                &mut JobManager::default(),
+                &ShellOptions::default(),
+                &mut NoBuiltins,
+                "echo \"a b\" $SHRS_EXPAND_WORDS 'c $d' # note"
+            )
+            .unwrap(),
+            ["echo", "a b", "val", "c $d"]
+        );
+    }
+
+    #[test]
+    fn heredoc_stdin() {
+        let input = "cat <<-EOF\n\thello\n\t  world\n\tEOF";
+        let Some(Stdin::File(mut stdin)) =
+            redirected(input, &ShellOptions::default()).unwrap().stdin
+        else {
+            panic!("expected stdin to be redirected to a file");
+        };
+        let mut contents = String::new();
+        stdin.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello\n  world\n");
+    }
+
+    #[test]
+    fn heredoc_expansion() {
+        let options = ShellOptions::default();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("heredoc");
+        std::env::set_var("SHRS_HEREDOC", "value");
+
+        let line = format!(
+            "cat <<EOF > {}\n$SHRS_HEREDOC '$SHRS_HEREDOC' $(echo sub)\nEOF",
+            path.display()
+        );
+        assert_eq!(run(&line, &options).unwrap(), 0);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "value 'value' sub\n"
+        );
+
+        // a quoted delimiter keeps the body as it is
+        let line = format!(
+            "cat <<'EOF' > {}\n$SHRS_HEREDOC $(echo sub)\nEOF",
+            path.display()
+        );
+        assert_eq!(run(&line, &options).unwrap(), 0);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "$SHRS_HEREDOC $(echo sub)\n"
+        );
+    }
+
+    #[test]
+    fn redirect_read_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("read_write");
+        std::fs::write(&path, "contents\n").unwrap();
+
+        let Some(Stdin::File(mut stdin)) = redirected(
+            &format!("cat <> {}", path.display()),
+            &ShellOptions::default(),
+        )
+        .unwrap()
+        .stdin
+        else {
+            panic!("expected stdin to be redirected to a file");
+        };
+        let mut contents = String::new();
+        stdin.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "contents\n");
+
+        // the file is opened for writing too, and created if it doesn't exist
+        let created = dir.path().join("created");
+        let Some(Output::File(mut stdout)) = redirected(
+            &format!("cmd 1<> {}", created.display()),
+            &ShellOptions::default(),
+        )
+        .unwrap()
+        .stdout
+        else {
+            panic!("expected stdout to be redirected to a file");
+        };
+        stdout.write_all(b"out\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&created).unwrap(), "out\n");
+    }
+
+    #[test]
+    fn redirect_both_streams() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirect_both");
+        let input = format!("cmd &> {}", path.display());
+
+        let redirected = redirected(&input, &ShellOptions::default()).unwrap();
+        let (Some(Output::File(mut stdout)), Some(Output::File(mut stderr))) =
+            (redirected.stdout, redirected.stderr)
+        else {
+            panic!("expected both streams to be redirected to a file");
+        };
+        stdout.write_all(b"out\n").unwrap();
+        stderr.write_all(b"err\n").unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, "out\nerr\n");
+    }
+
+    #[test]
+    fn redirect_stderr() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirect_err");
+        let input = format!("sh -c 'echo out; echo err >&2' 2> {}", path.display());
+
+        let redirected = redirected(&input, &ShellOptions::default()).unwrap();
+        assert!(redirected.stdout.is_none());
+        let Some(stderr) = redirected.stderr else {
+            panic!("expected stderr to be redirected");
+        };
+
+        // stdout is captured to check that it isn't sent to the file
+        let output = std::process::Command::new("sh")
+            .args(["-c", "echo out; echo err >&2"])
+            .stdout(std::process::Stdio::piped())
+            .stderr(stderr)
+            .output()
+            .unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "out\n");
+        assert_eq!(contents, "err\n");
+    }
+
+    #[test]
+    fn append_and_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirect_append");
+        let write = |op: &str, line: &str| {
+            let input = format!("echo {line} {op} {}", path.display());
+            let Some(Output::File(mut file)) =
+                redirected(&input, &ShellOptions::default()).unwrap().stdout
+            else {
+                panic!("expected stdout to be redirected to a file");
+            };
+            writeln!(file, "{line}").unwrap();
+        };
+
+        // appending creates the file and then adds to it
+        write(">>", "one");
+        write(">>", "two");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+
+        // writing replaces what was in the file
+        write(">", "three");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "three\n");
+    }
+
+    #[test]
+    fn noclobber() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("noclobber");
+        let options = ShellOptions {
+            noclobber: true,
+            ..Default::default()
+        };
+        let redirect = |op: &str| redirected(&format!("echo hi {op} {}", path.display()), &options);
+
+        // new files can be created but not overwritten
+        assert!(redirect(">").is_ok());
+        assert!(redirect(">").is_err());
+        assert!(redirect("&>").is_err());
+        assert!(redirect(">>").is_ok());
+        assert!(redirect(">|").is_ok());
+    }
+
+    #[test]
+    fn duplicate_stdout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("redirect_dup");
+        let options = ShellOptions::default();
+
+        let line = format!("cmd >{} 2>&1", path.display());
+        let Redirected { stdout, stderr, .. } = redirected(&line, &options).unwrap();
+        let (Some(Output::File(mut stdout)), Some(Output::File(mut stderr))) = (stdout, stderr)
+        else {
+            panic!("expected stderr to be redirected to the same file as stdout");
+        };
+        stdout.write_all(b"out\n").unwrap();
+        stderr.write_all(b"err\n").unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, "out\nerr\n");
+
+        // without a redirection, stderr follows stdout wherever the command sends it
+        let redirected = redirected("cmd 2>&1", &options).unwrap();
+        assert!(redirected.stdout.is_none());
+        assert!(matches!(redirected.stderr, Some(Output::FileDescriptor(1))));
+    }
+
+    #[test]
+    fn glob() {
+        let tmp = tempfile::tempdir().unwrap();
+        for file in ["b.rs", "a.rs", "c.txt", ".hidden.rs"] {
+            std::fs::File::create(tmp.path().join(file)).unwrap();
+        }
+        let dir = tmp.path().to_string_lossy();
+        let expand = |pattern: &str| {
+            expand_glob(&format!("{dir}/{pattern}"))
+                .into_iter()
+                .map(|file| file.trim_start_matches(&format!("{dir}/")).to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(expand("*.rs"), vec!["a.rs", "b.rs"]);
+        assert_eq!(expand("?.*"), vec!["a.rs", "b.rs", "c.txt"]);
+        assert_eq!(expand("[b-c].*"), vec!["b.rs", "c.txt"]);
+        assert_eq!(expand(".*.rs"), vec![".hidden.rs"]);
+        assert_eq!(expand("*.md"), vec!["*.md"]);
+    }
+
+    #[test]
+    fn pipeline_pgid() {
+        let mut job_manager = JobManager::default();
+        let options = ShellOptions::default();
+        let parsed = Parser::default()
+            .parse(Lexer::new("sleep 0 | sleep 0 | sleep 0"))
+            .unwrap();
+        let (procs, pgid) = eval_command(
+            &mut job_manager,
+            &options,
+            &mut NoBuiltins,
+            &parsed,
+            false,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        // the group is led by the first process and shared by the others
+        assert_eq!(procs.len(), 3);
+        let pgid = pgid.unwrap();
+        assert_eq!(procs[0].id(), Some(ProcessId::from(pgid)));
+        run_job(&mut job_manager, &options, procs, Some(pgid), true).unwrap();
+    }
+
+    #[test]
+    fn pipefail() {
+        let statuses = [1, 2, 0].map(|code| ExitStatus::from_raw(code << 8));
+        assert_eq!(pipeline_status(&statuses, false), Some(statuses[2]));
+        assert_eq!(pipeline_status(&statuses, true), Some(statuses[1]));
+        assert_eq!(pipeline_status(&statuses[2..], true), Some(statuses[2]));
+        assert_eq!(pipeline_status(&[], true), None);
+    }
+
+    #[test]
+    fn compound_commands() {
+        let options = ShellOptions::default();
+        assert_eq!(run("false; true", &options).unwrap(), 0);
+        assert_eq!(run("true && false", &options).unwrap(), 1);
+        assert_eq!(run("false || true", &options).unwrap(), 0);
+        assert_eq!(
+            run("if false; then true; else false; fi", &options).unwrap(),
+            1
+        );
+        assert_eq!(run("if false; then false; fi", &options).unwrap(), 0);
+        assert_eq!(run("while false; do true; done", &options).unwrap(), 0);
+        assert_eq!(run("for x in a b; do false; done", &options).unwrap(), 1);
+    }
+
+    #[test]
+    fn elif_chain() {
+        let chain = |first: &str, second: &str| {
+            format!(
+                "if {first}; then sh -c 'exit 2'
+                elif {second}; then sh -c 'exit 3'
+                elif true; then sh -c 'exit 4'
+                else sh -c 'exit 5'; fi"
+            )
+        };
+
+        let parsed = Parser::default()
+            .parse(Lexer::new(&chain("a", "b")))
+            .unwrap();
+        let ast::Command::If { conds, else_part } = parsed else {
+            panic!("expected an if statement, got {parsed:?}");
+        };
+        assert_eq!(conds.len(), 3);
+        assert!(else_part.is_some());
+
+        // the body of the first condition that succeeds runs, and no other
+        let options = ShellOptions::default();
+        assert_eq!(run(&chain("true", "true"), &options).unwrap(), 2);
+        assert_eq!(run(&chain("false", "true"), &options).unwrap(), 3);
+        assert_eq!(run(&chain("false", "false"), &options).unwrap(), 4);
+        let line = "if false; then true; elif false; then true; else sh -c 'exit 5'; fi";
+        assert_eq!(run(line, &options).unwrap(), 5);
+    }
+
+    /// Stand-in for the builtins of the shell that only exports `PATH`
+    struct ExportPath;
+
+    impl ShellBuiltins for ExportPath {
+        fn run(
+            &mut self,
+            _job_manager: &mut JobManager,
+            _args: &[String],
+            _capture: bool,
+        ) -> Option<(ExitStatus, Vec<u8>)> {
+            None
+        }
+
+        fn exported_vars(&self) -> Vec<(String, String)> {
+            std::env::vars().filter(|(var, _)| var == "PATH").collect()
+        }
+    }
+
+    #[test]
+    fn unexported_vars() {
+        let run = |line: &str| {
+            eval(
+                &mut JobManager::default(),
+                &ShellOptions::default(),
+                &mut ExportPath,
+                Parser::default(),
+                Lexer::new(line),
+            )
+            .map(|status| status.code().unwrap())
+        };
+        std::env::set_var("SHRS_EVAL_UNEXPORTED", "val");
+        let check = "sh -c 'test \"$SHRS_EVAL_UNEXPORTED\" = val'";
+        assert_eq!(run(check).unwrap(), 1);
+
+        // it is passed on if it is assigned for the command
+        assert_eq!(
+            run(&format!("SHRS_EVAL_UNEXPORTED=val {check}")).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn assignments() {
+        let options = ShellOptions::default();
+        std::env::remove_var("SHRS_EVAL_LOCAL");
+        std::env::set_var("SHRS_EVAL_VAL", "val");
+        assert_eq!(run("SHRS_EVAL_LOCAL=$SHRS_EVAL_VAL", &options).unwrap(), 0);
+        assert_eq!(std::env::var("SHRS_EVAL_LOCAL").unwrap(), "val");
+
+        std::env::remove_var("SHRS_EVAL_CMD");
+        let check = "SHRS_EVAL_CMD=a=b sh -c 'test \"$SHRS_EVAL_CMD\" = a=b'";
+        assert_eq!(run(check, &options).unwrap(), 0);
+        assert!(std::env::var("SHRS_EVAL_CMD").is_err());
+    }
+
+    #[test]
+    fn comments() {
+        let options = ShellOptions::default();
+        assert_eq!(run("# only a comment", &options).unwrap(), 0);
+        assert_eq!(run("false # ; true", &options).unwrap(), 1);
+        assert_eq!(run("true;#x\nfalse", &options).unwrap(), 1);
+    }
+
+    #[test]
+    fn loop_control() {
+        let options = ShellOptions::default();
+        let var = |name| std::env::var(name).unwrap_or_default();
+
+        let line = "for i in a b; do SHRS_LOOP_BREAK=$i; break; false; done";
+        assert_eq!(run(line, &options).unwrap(), 0);
+        assert_eq!(var("SHRS_LOOP_BREAK"), "a");
+
+        let line = "for i in a b; do SHRS_LOOP_CONTINUE=$i; continue; SHRS_LOOP_CONTINUE=x; done";
+        assert_eq!(run(line, &options).unwrap(), 0);
+        assert_eq!(var("SHRS_LOOP_CONTINUE"), "b");
+
+        // leaving enclosing loops
+        let line = "for i in a b; do for j in c d; do SHRS_LOOP_BREAK2=$i$j; break 2; done; done";
+        assert_eq!(run(line, &options).unwrap(), 0);
+        assert_eq!(var("SHRS_LOOP_BREAK2"), "ac");
+        let line =
+            "for i in a b; do for j in c d; do SHRS_LOOP_CONTINUE2=$i$j; continue 2; done; done";
+        assert_eq!(run(line, &options).unwrap(), 0);
+        assert_eq!(var("SHRS_LOOP_CONTINUE2"), "bc");
+        assert_eq!(run("while true; do break 5; done", &options).unwrap(), 0);
+
+        assert_eq!(run("break", &options).unwrap(), 1);
+        assert_eq!(run("for i in a; do break 0; done", &options).unwrap(), 1);
+    }
+
+    #[test]
+    fn function_return() {
+        let options = ShellOptions::default();
+        let line = "shrs_eval_early() { true; return 3; false; }; shrs_eval_early";
+        assert_eq!(run(line, &options).unwrap(), 3);
+        let line = "shrs_eval_last() { false; return; }; shrs_eval_last";
+        assert_eq!(run(line, &options).unwrap(), 1);
+        let line = "shrs_eval_loop() { for i in a b; do return 4; done; }; shrs_eval_loop";
+        assert_eq!(run(line, &options).unwrap(), 4);
+        assert_eq!(run("shrs_eval_early || true", &options).unwrap(), 0);
+        assert_eq!(
+            run("shrs_eval_end() { false; }; shrs_eval_end", &options).unwrap(),
+            1
+        );
+
+        assert_eq!(run("return 2", &options).unwrap(), 1);
+    }
+
+    #[test]
+    fn xtrace() {
+        let options = ShellOptions {
+            xtrace: true,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace");
+
+        // every command of the loop is printed once it was expanded
+        let line = format!(
+            "{{ for i in 1 2; do SHRS_EVAL_TRACE=$i echo $i > /dev/null; done; }} 2> {}",
+            path.display()
+        );
+        assert_eq!(run(&line, &options).unwrap(), 0);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "+ SHRS_EVAL_TRACE=1 echo 1\n+ SHRS_EVAL_TRACE=2 echo 2\n"
+        );
+    }
+
+    #[test]
+    fn function_redirects() {
+        let options = ShellOptions::default();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("function");
+        let read = |path| std::fs::read_to_string(path).unwrap();
+        run("shrs_eval_print() { echo $1; }", &options).unwrap();
+
+        let line = format!("shrs_eval_print a > {}", path.display());
+        assert_eq!(run(&line, &options).unwrap(), 0);
+        assert_eq!(read(&path), "a\n");
+
+        let line = format!("shrs_eval_print b | cat > {}", path.display());
+        assert_eq!(run(&line, &options).unwrap(), 0);
+        assert_eq!(read(&path), "b\n");
+
+        // the body reads from the redirected stdin and the pipe
+        let copy = dir.path().join("copy");
+        let line = format!(
+            "shrs_eval_cat() {{ cat; }}; shrs_eval_cat < {} > {}",
+            path.display(),
+            copy.display()
+        );
+        assert_eq!(run(&line, &options).unwrap(), 0);
+        assert_eq!(read(&copy), "b\n");
+        let line = format!("echo c | shrs_eval_cat > {}", copy.display());
+        assert_eq!(run(&line, &options).unwrap(), 0);
+        assert_eq!(read(&copy), "c\n");
+
+        // variables assigned for the call are only set while it runs
+        std::env::remove_var("SHRS_EVAL_FN_VAR");
+        let line = format!(
+            "shrs_eval_var() {{ echo $SHRS_EVAL_FN_VAR; }}; SHRS_EVAL_FN_VAR=x shrs_eval_var > {}",
+            path.display()
+        );
+        assert_eq!(run(&line, &options).unwrap(), 0);
+        assert_eq!(read(&path), "x\n");
+        assert!(std::env::var("SHRS_EVAL_FN_VAR").is_err());
+    }
+
+    #[test]
+    fn positional_args() {
+        let options = ShellOptions::default();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("positional");
+        let line = format!(
+            "shrs_eval_show() {{ echo $1 > {0}; echo $@ >> {0}; \
+             for arg in \"$@\"; do echo \"[$arg]\" >> {0}; done; }}",
+            path.display()
+        );
+        run(&line, &options).unwrap();
+
+        set_positional_args(vec!["outer".to_string()]);
+        assert_eq!(run("shrs_eval_show 'a b' c", &options).unwrap(), 0);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "a b\na b c\n[a b]\n[c]\n"
+        );
+        // the arguments of the call are gone once it returns
+        assert_eq!(crate::positional_args(), ["outer"]);
+        set_positional_args(vec![]);
+    }
+
+    #[test]
+    fn function_doc_comment() {
+        let options = ShellOptions::default();
+        let line = "# greet someone\n#   by name\nshrs_eval_doc_greet() { echo hello $1; }\n\
+                    shrs_eval_doc_ll() { # long listing\n ls -l; }\n\
+                    # not about the next function\n\nshrs_eval_doc_none() { true; }";
+        assert_eq!(run(line, &options).unwrap(), 0);
+        assert_eq!(
+            function_description("shrs_eval_doc_greet").as_deref(),
+            Some("greet someone by name")
+        );
+        assert_eq!(
+            function_description("shrs_eval_doc_ll").as_deref(),
+            Some("long listing")
+        );
+        assert_eq!(function_description("shrs_eval_doc_none"), None);
+    }
+
+    #[test]
+    fn parameter_expansion() {
+        let options = ShellOptions::default();
+        std::env::remove_var("SHRS_EVAL_DEFAULT");
+        assert_eq!(
+            run(": ${SHRS_EVAL_DEFAULT:=${HOME:+a b}}", &options).unwrap(),
+            0
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("parameter");
+        let line = format!("echo \"$SHRS_EVAL_DEFAULT\" > {}", path.display());
+        assert_eq!(run(&line, &options).unwrap(), 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a b\n");
+        assert!(matches!(
+            run("true ${SHRS_EVAL_UNSET:?missing}", &options),
+            Err(PosixError::ParameterUnset(name, _)) if name == "SHRS_EVAL_UNSET"
+        ));
+    }
+
+    #[test]
+    fn status_builtins() {
+        let options = ShellOptions::default();
+        assert_eq!(run(":", &options).unwrap(), 0);
+        assert_eq!(run("true ignored", &options).unwrap(), 0);
+        assert_eq!(run("false", &options).unwrap(), 1);
+        assert_eq!(run("false || : && true", &options).unwrap(), 0);
+        // the next command of a pipeline reads nothing
+        assert_eq!(
+            run("true | sh -c 'test -z \"$(cat)\"'", &options).unwrap(),
+            0
+        );
+
+        // redirections are still applied
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("status");
+        assert_eq!(
+            run(&format!(": > {}", path.display()), &options).unwrap(),
+            0
+        );
+        assert!(path.exists());
+    }
+
+    /// Stand-in for the builtins of the shell that only knows `history`, `echo` and `raw`, which
+    /// prints its arguments as typed like `echo`
+    struct History;
+
+    impl ShellBuiltins for History {
+        fn run(
+            &mut self,
+            _job_manager: &mut JobManager,
+            args: &[String],
+            capture: bool,
+        ) -> Option<(ExitStatus, Vec<u8>)> {
+            let output = match args[0].as_str() {
+                "history" => b"ls\ncd src\nls -a\n".to_vec(),
+                "echo" | "raw" => format!("{}\n", args[1..].join(" ")).into_bytes(),
+                _ => return None,
+            };
+            if !capture {
+                std::io::stdout().write_all(&output).unwrap();
+            }
+            Some((
+                ExitStatus::from_raw(0),
+                capture.then_some(output).unwrap_or_default(),
+            ))
+        }
+
+        fn expands_args(&self, name: &str) -> bool {
+            name == "raw"
+        }
+    }
+
+    #[test]
+    fn shell_builtins() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("builtins");
+        let run = |line: &str| {
+            eval(
+                &mut JobManager::default(),
+                &ShellOptions::default(),
+                &mut History,
+                Parser::default(),
+                Lexer::new(&format!("{line} > {}", path.display())),
+            )
+            .map(|status| status.code().unwrap())
+        };
+
+        // the output of the builtin is passed on to the next command of the pipeline
+        assert_eq!(run("history | grep ls").unwrap(), 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "ls\nls -a\n");
+
+        assert_eq!(run("history").unwrap(), 0);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "ls\ncd src\nls -a\n"
+        );
+
+        assert_eq!(run("history | grep ls | wc -l").unwrap(), 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().trim(), "2");
+
+        // a redirection applies to the builtin instead of being passed as arguments
+        assert_eq!(run("echo hi").unwrap(), 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hi\n");
+
+        // only the name of a builtin that expands its arguments itself is expanded
+        assert_eq!(run("$(echo raw) ~ '$HOME' \"a b\"").unwrap(), 0);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "~ '$HOME' \"a b\"\n"
+        );
+    }
+
+    #[test]
+    fn negate() {
+        let options = ShellOptions::default();
+        assert_eq!(run("! false", &options).unwrap(), 0);
+        assert_eq!(run("! true", &options).unwrap(), 1);
+        assert_eq!(run("! sh -c 'exit 3'", &options).unwrap(), 0);
+        assert_eq!(run("! true | false", &options).unwrap(), 0);
+        assert_eq!(
+            run("if ! false; then true; else false; fi", &options).unwrap(),
+            0
+        );
+        assert_eq!(
+            run("if ! true; then true; else false; fi", &options).unwrap(),
+            1
+        );
+
+        let errexit = ShellOptions {
+            errexit: true,
+            ..Default::default()
+        };
+        assert_eq!(run("! true; true", &errexit).unwrap(), 0);
+    }
+
+    #[test]
+    fn subshell() {
+        let options = ShellOptions::default();
+        std::env::set_var("SHRS_EVAL_SUBSHELL", "outer");
+        let line = "(SHRS_EVAL_SUBSHELL=inner; sh -c 'test $0 = inner' $SHRS_EVAL_SUBSHELL)";
+        assert_eq!(run(line, &options).unwrap(), 0);
+        assert_eq!(std::env::var("SHRS_EVAL_SUBSHELL").unwrap(), "outer");
+
+        assert_eq!(run("(true; sh -c 'exit 3')", &options).unwrap(), 3);
+        assert_eq!(run("(false) || true", &options).unwrap(), 0);
+    }
+
+    #[test]
+    fn process_substitution() {
+        let options = ShellOptions::default();
+        assert_eq!(
+            run(
+                "cmp -s <(printf 'b\\na\\n' | sort) <(echo a; echo b)",
+                &options
+            )
+            .unwrap(),
+            0
+        );
+        assert_eq!(run("cmp -s <(echo a) <(echo b)", &options).unwrap(), 1);
+
+        // the substitution has exited by the time the command has
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("proc_sub");
+        let line = format!("echo hi | tee >(cat > {}) > /dev/null", path.display());
+        assert_eq!(run(&line, &options).unwrap(), 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hi\n");
+
+        // a failed substitution doesn't fail the pipeline, even with pipefail
+        let pipefail = ShellOptions {
+            pipefail: true,
+            ..Default::default()
+        };
+        assert_eq!(run("cat <(false) | true", &pipefail).unwrap(), 0);
+        assert_eq!(run("false | true", &pipefail).unwrap(), 1);
+    }
+
+    #[test]
+    fn for_ifs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("for_ifs");
+        std::env::set_var("SHRS_FOR_IFS_LIST", "a:b::c");
+        let ifs = std::env::var_os("IFS");
+        // IFS is only set in the subshell, other tests expanding words in parallel use the default
+        run(
+            &format!(
+                "(IFS=:; for x in $SHRS_FOR_IFS_LIST d:e; do echo $x >> {}; done)",
+                path.display()
+            ),
+            &ShellOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(std::env::var_os("IFS"), ifs);
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb\nc\nd:e\n");
+    }
+
+    #[test]
+    fn for_restores_var() {
+        let options = ShellOptions::default();
+        std::env::set_var("SHRS_FOR_VAR", "before");
+        let line = "for SHRS_FOR_VAR in a b; do SHRS_FOR_SEEN=$SHRS_FOR_VAR; done";
+        assert_eq!(run(line, &options).unwrap(), 0);
+        assert_eq!(std::env::var("SHRS_FOR_SEEN").unwrap(), "b");
+        assert_eq!(std::env::var("SHRS_FOR_VAR").unwrap(), "before");
+
+        // also when the loop is left early
+        let line = "for SHRS_FOR_VAR in a b; do break; done";
+        assert_eq!(run(line, &options).unwrap(), 0);
+        assert_eq!(std::env::var("SHRS_FOR_VAR").unwrap(), "before");
+
+        std::env::remove_var("SHRS_FOR_VAR");
+        let line = "shrs_for_return() { for SHRS_FOR_VAR in a; do return 2; done; }; \
+                    shrs_for_return";
+        assert_eq!(run(line, &options).unwrap(), 2);
+        assert!(std::env::var("SHRS_FOR_VAR").is_err());
+    }
+
+    #[test]
+    fn case() {
+        let options = ShellOptions::default();
+        let case = |word: &str| {
+            std::env::set_var("SHRS_CASE_WORD", word);
+            let line = "case $SHRS_CASE_WORD in
+                a.txt | *.md) sh -c 'exit 2';;
+                [0-9]?) sh -c 'exit 3';;
+                '*') sh -c 'exit 4';;
+                b*) sh -c 'exit 5';;
+                *) sh -c 'exit 6';;
+            esac";
+            run(line, &options).unwrap()
+        };
+        assert_eq!(case("a.txt"), 2);
+        assert_eq!(case("notes.md"), 2);
+        assert_eq!(case("1x"), 3);
+        assert_eq!(case("*"), 4);
+        // only the first matching arm runs
+        assert_eq!(case("b.md"), 2);
+        assert_eq!(case("bc"), 5);
+        assert_eq!(case("other"), 6);
+
+        assert_eq!(run("case x in y) false;; esac", &options).unwrap(), 0);
+    }
+
+    #[test]
+    fn arith_for() {
+        let options = ShellOptions::default();
+        let line = "SHRS_FOR_SUM=0; for ((SHRS_FOR_I = 0; SHRS_FOR_I < 5; SHRS_FOR_I++)); do \
+                    SHRS_FOR_SUM=$((SHRS_FOR_SUM + SHRS_FOR_I)); done";
+        assert_eq!(run(line, &options).unwrap(), 0);
+        assert_eq!(std::env::var("SHRS_FOR_SUM").unwrap(), "10");
+        assert_eq!(std::env::var("SHRS_FOR_I").unwrap(), "5");
+
+        // an empty condition loops until `break`
+        let line = "for ((SHRS_FOR_I = 0; ; SHRS_FOR_I += 2))
+                    do
+                        if test $SHRS_FOR_I -ge 6; then break; fi
+                    done";
+        assert_eq!(run(line, &options).unwrap(), 0);
+        assert_eq!(std::env::var("SHRS_FOR_I").unwrap(), "6");
+
+        assert!(run("for ((; 1 +; )); do :; done", &options).is_err());
+    }
+
+    #[test]
+    fn errexit() {
+        let options = ShellOptions {
+            errexit: true,
+            ..Default::default()
+        };
+        let exits = |line: &str| matches!(run(line, &options), Err(PosixError::Errexit(_)));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("errexit");
+        assert!(exits(&format!("false; touch {}", path.display())));
+        assert!(!path.exists());
+
+        assert!(exits("true && false; true"));
+        assert!(exits("false || false; true"));
+        assert!(exits("if true; then false; fi; true"));
+        assert!(exits("for x in a; do false; done; true"));
+        assert!(exits("nonexistent_command_for_errexit; true"));
+
+        // failures that are tested don't count
+        assert!(!exits("false && true; true"));
+        assert!(!exits("false || true"));
+        assert!(!exits("if false; then true; fi; true"));
+        assert!(!exits("while false; do true; done; true"));
+        assert!(!exits("until true; do false; done"));
+
+        // without errexit the status is only returned
+        assert_eq!(run("false; true", &ShellOptions::default()).unwrap(), 0);
+    }
+
+    #[test]
+    fn compound_pipeline() {
+        let options = ShellOptions::default();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pipeline");
+        let piped = |line: &str| {
+            let line = format!("{line} > {}", path.display());
+            assert_eq!(run(&line, &options).unwrap(), 0);
+            std::fs::read_to_string(&path).unwrap()
+        };
+
+        assert_eq!(piped("for x in b a; do echo $x; done | sort"), "a\nb\n");
+        assert_eq!(piped("if true; then echo hi; fi | cat"), "hi\n");
+        assert_eq!(
+            piped("printf 'b\\na\\n' | while true; do sort; break; done"),
+            "a\nb\n"
+        );
+        assert_eq!(piped("true && echo a | tr a b"), "b\n");
+        assert_eq!(piped("(echo a; echo b) | grep b"), "b\n");
+        assert_eq!(piped("printf 'b\\na\\n' | (sort)"), "a\nb\n");
+        // the status of the pipeline is the one of the compound command when it comes last
+        assert_eq!(run("true | if true; then false; fi", &options).unwrap(), 1);
+    }
+
+    #[test]
+    fn compound_redirects() {
+        let options = ShellOptions::default();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("compound");
+
+        let line = format!("if true; then echo hi; fi > {}", path.display());
+        assert_eq!(run(&line, &options).unwrap(), 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hi\n");
+
+        let line = format!(
+            "for x in a b; do echo $x; done >> {0}; if true; then sort -r; fi < {0} > {0}.out",
+            path.display()
+        );
+        assert_eq!(run(&line, &options).unwrap(), 0);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("compound.out")).unwrap(),
+            "hi\nb\na\n"
+        );
     }
 }
@@ -0,0 +1,41 @@
+//! Splitting of expanded words into fields
+
+/// Separators used when `IFS` is unset
+pub const DEFAULT_IFS: &str = " \t\n";
+
+/// Split a string into fields on any of the characters in `ifs`
+///
+/// Empty fields are dropped and an empty `ifs` disables splitting.
+pub fn split_fields(s: &str, ifs: &str) -> Vec<String> {
+    if s.is_empty() {
+        return vec![];
+    }
+    if ifs.is_empty() {
+        return vec![s.to_string()];
+    }
+    s.split(|c| ifs.contains(c))
+        .filter(|field| !field.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Value of `IFS` in the environment, falling back to [DEFAULT_IFS] if it is unset
+pub(crate) fn ifs() -> String {
+    std::env::var("IFS").unwrap_or_else(|_| DEFAULT_IFS.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_fields, DEFAULT_IFS};
+
+    #[test]
+    fn split() {
+        assert_eq!(split_fields(" a\tb\n\nc ", DEFAULT_IFS), ["a", "b", "c"]);
+        assert_eq!(
+            split_fields("/bin:/usr/bin::/sbin", ":"),
+            ["/bin", "/usr/bin", "/sbin"]
+        );
+        assert_eq!(split_fields("a b:c", ""), ["a b:c"]);
+        assert!(split_fields("", DEFAULT_IFS).is_empty());
+    }
+}
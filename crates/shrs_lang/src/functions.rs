@@ -0,0 +1,105 @@
+//! Shell functions defined with `name() { ... }`, and the arguments of each call
+
+use std::{cell::RefCell, collections::HashMap, sync::Mutex};
+
+use crate::ast;
+
+/// Function that has been defined
+struct Function {
+    body: ast::Command,
+    /// Comment documenting the function
+    description: Option<String>,
+}
+
+/// Functions that have been defined, by name
+static FUNCTIONS: Mutex<Option<HashMap<String, Function>>> = Mutex::new(None);
+
+/// Define a function, replacing any function of the same name
+pub(crate) fn define_function(name: &str, body: ast::Command, description: Option<String>) {
+    FUNCTIONS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(name.to_string(), Function { body, description });
+}
+
+/// Body of the function with the given name
+pub(crate) fn function(name: &str) -> Option<ast::Command> {
+    let functions = FUNCTIONS.lock().unwrap();
+    functions.as_ref()?.get(name).map(|f| f.body.clone())
+}
+
+/// Description of the function with the given name, taken from the comment documenting it
+pub fn function_description(name: &str) -> Option<String> {
+    let functions = FUNCTIONS.lock().unwrap();
+    functions.as_ref()?.get(name)?.description.clone()
+}
+
+/// Remove a function, returns false if it wasn't defined
+pub fn unset_function(name: &str) -> bool {
+    FUNCTIONS
+        .lock()
+        .unwrap()
+        .as_mut()
+        .is_some_and(|functions| functions.remove(name).is_some())
+}
+
+/// Names of all the functions that are defined, sorted
+pub fn function_names() -> Vec<String> {
+    let mut names = FUNCTIONS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|functions| functions.keys().cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+thread_local! {
+    /// Arguments of each function call being evaluated
+    static SCOPES: RefCell<Vec<Vec<String>>> = const { RefCell::new(Vec::new()) };
+    /// Positional parameters outside of any function
+    static SHELL_ARGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Scope of a function call, which is entered until it is dropped
+///
+/// The positional parameters are the arguments of the call while it is entered.
+pub(crate) struct FunctionScope;
+
+impl FunctionScope {
+    pub(crate) fn enter(args: &[String]) -> Self {
+        SCOPES.with(|scopes| scopes.borrow_mut().push(args.to_vec()));
+        FunctionScope
+    }
+}
+
+impl Drop for FunctionScope {
+    fn drop(&mut self) {
+        SCOPES.with(|scopes| scopes.borrow_mut().pop());
+    }
+}
+
+/// Positional parameters (`$1`, `$2`, ...), which are the arguments of the function call being
+/// evaluated or otherwise those set with [set_positional_args]
+pub fn positional_args() -> Vec<String> {
+    SCOPES.with(|scopes| match scopes.borrow().last() {
+        Some(args) => args.clone(),
+        None => SHELL_ARGS.with(|args| args.borrow().clone()),
+    })
+}
+
+/// Replace the positional parameters, only for the rest of the call if a function call is being
+/// evaluated
+pub fn set_positional_args(args: Vec<String>) {
+    SCOPES.with(|scopes| match scopes.borrow_mut().last_mut() {
+        Some(scope_args) => *scope_args = args,
+        None => SHELL_ARGS.with(|shell_args| *shell_args.borrow_mut() = args),
+    })
+}
+
+/// If a function call is being evaluated
+pub(crate) fn in_function() -> bool {
+    SCOPES.with(|scopes| !scopes.borrow().is_empty())
+}
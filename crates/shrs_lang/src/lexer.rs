@@ -48,6 +48,8 @@ pub enum Token<'input> {
     LESSGREAT,
     DLESSDASH,
     CLOBBER,
+    ANDGREAT,
+    ANDDGREAT,
 
     IF,
     THEN,
@@ -69,12 +71,26 @@ pub enum Token<'input> {
     FNAME(&'input str),
     NAME(&'input str),
     IO_NUMBER(&'input str),
+    /// Body of a here document, follows the `<<` or `<<-` operator
+    HEREDOC(&'input str),
+    /// Body of a here document whose delimiter was quoted, which isn't expanded
+    QUOTED_HEREDOC(&'input str),
+    /// Comment starting with `#` up until the end of the line, ignored by the parser
+    COMMENT(&'input str),
+    /// Expressions of an arithmetic `for ((init; cond; step))` loop, without the parentheses
+    ARITH(&'input str),
 }
 
 #[derive(Debug, PartialEq, Eq, Error)]
 pub enum Error {
     #[error("unrecognized character {1} in range {0}:{2}")]
     UnrecognizedChar(usize, char, usize),
+    #[error("missing here document delimiter")]
+    MissingHeredocDelimiter(usize),
+    #[error("here document is not terminated by {1}")]
+    UnterminatedHeredoc(usize, String),
+    #[error("arithmetic for loop needs to be of the form ((init; cond; step))")]
+    InvalidArithFor(usize),
 }
 
 // TODO could technically make EOF a token so we don't need to do Result<Option> shinengans
@@ -83,6 +99,13 @@ pub struct Lexer<'input> {
     input: &'input str,
     chars: CharIndices<'input>,
     lookahead: Option<(usize, char, usize)>,
+    /// Token to emit before lexing any further, used for the body of here documents
+    queued: Option<(usize, Token<'input>, usize)>,
+    /// End of the bodies of the here documents started on the current line, which are skipped
+    /// at the end of the line
+    heredoc_end: Option<usize>,
+    /// If the last token was `for`, after which `((` starts an arithmetic loop
+    after_for: bool,
 }
 
 impl<'input> Lexer<'input> {
@@ -94,6 +117,9 @@ impl<'input> Lexer<'input> {
             input,
             chars,
             lookahead,
+            queued: None,
+            heredoc_end: None,
+            after_for: false,
         }
     }
 
@@ -118,7 +144,7 @@ impl<'input> Lexer<'input> {
         start: usize,
         end: usize,
     ) -> Result<(usize, Token<'input>, usize), Error> {
-        let (word, end) = self.take_until(start, end, |ch| !is_word_continue(ch));
+        let (word, end) = self.word(start, end);
         let token = match word {
             "if" => Token::IF,
             "then" => Token::THEN,
@@ -133,30 +159,200 @@ impl<'input> Lexer<'input> {
             "until" => Token::UNTIL,
             "for" => Token::FOR,
             "in" => Token::IN,
+            // a number right before a redirection is the file descriptor being redirected
+            word if word.chars().all(|ch| ch.is_ascii_digit())
+                && matches!(self.lookahead, Some((_, '<' | '>', _))) =>
+            {
+                Token::IO_NUMBER(word)
+            },
             word => Token::WORD(word),
         };
         Ok((start, token, end))
     }
 
+    /// Read a word, including any command substitutions (`$(...)` or backticks) and parameter
+    /// expansions (`${...}`) inside of it even if they contain characters that would otherwise end
+    /// the word
+    ///
+    /// A word can also start with a process substitution, `<(...)` or `>(...)`.
+    fn word(&mut self, start: usize, mut end: usize) -> (&'input str, usize) {
+        let mut prev = self.input[start..end].chars().next();
+        match prev {
+            Some('`') => end = self.take_substitution(end, '`'),
+            Some('<' | '>') => {
+                if let Some((_, _, paren_end)) = self.advance() {
+                    end = self.take_substitution(paren_end, ')');
+                }
+            },
+            _ => {},
+        }
+        while let Some((_, ch, ch_end)) = self.lookahead {
+            if prev == Some('$') && matches!(ch, '(' | '{') {
+                self.advance();
+                end = self.take_substitution(ch_end, if ch == '(' { ')' } else { '}' });
+            } else if ch == '`' {
+                self.advance();
+                end = self.take_substitution(ch_end, '`');
+            } else if is_word_continue(ch) {
+                self.advance();
+                end = ch_end;
+            } else {
+                break;
+            }
+            prev = Some(ch);
+        }
+        (&self.input[start..end], end)
+    }
+
+    /// Consume the rest of a command substitution up to and including the closing character,
+    /// returns the end of the substitution
+    fn take_substitution(&mut self, mut end: usize, close: char) -> usize {
+        let open = match close {
+            ')' => Some('('),
+            '}' => Some('{'),
+            _ => None,
+        };
+        let mut depth = 0;
+        while let Some((_, ch, ch_end)) = self.advance() {
+            end = ch_end;
+            if Some(ch) == open {
+                depth += 1;
+            } else if ch == close {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+            }
+        }
+        end
+    }
+
     // TODO escape characters
+    /// The quotes are kept so the evaluator knows not to expand the word
     fn single_quote(
         &mut self,
         start: usize,
         end: usize,
     ) -> Result<(usize, Token<'input>, usize), Error> {
         let (_, end) = self.take_until(start + 1, end, |ch| ch == '\'');
-        self.advance();
-        Ok((start, Token::WORD(&self.input[start + 1..end]), end))
+        // include the closing quote if the quote is terminated
+        let end = match self.advance() {
+            Some((_, _, quote_end)) => quote_end,
+            None => end,
+        };
+        Ok((start, Token::WORD(&self.input[start..end]), end))
     }
 
+    /// The quotes are kept so the evaluator knows not to glob the word
     fn double_quote(
         &mut self,
         start: usize,
         end: usize,
     ) -> Result<(usize, Token<'input>, usize), Error> {
         let (_, end) = self.take_until(start + 1, end, |ch| ch == '"');
+        let end = match self.advance() {
+            Some((_, _, quote_end)) => quote_end,
+            None => end,
+        };
+        Ok((start, Token::WORD(&self.input[start..end]), end))
+    }
+
+    /// Comments are only started by a `#` at the beginning of a word, a `#` inside of a word is
+    /// consumed as part of the word
+    fn comment(&mut self, start: usize, end: usize) -> (usize, Token<'input>, usize) {
+        let (comment, end) = self.take_until(start, end, |ch| ch == '\n');
+        (start, Token::COMMENT(comment), end)
+    }
+
+    /// Read the delimiter of a here document and find its body in the lines following the current
+    /// one, the body is queued to be emitted after the operator
+    fn heredoc(&mut self, start: usize, strip_tabs: bool) -> Result<(), Error> {
+        while let Some((_, ' ' | '\t', _)) = self.lookahead {
+            self.advance();
+        }
+
+        // quotes in the delimiter are removed, and the body is only expanded if there were none
+        let mut delimiter = String::new();
+        let mut quoted = false;
+        while let Some((_, ch, _)) = self.lookahead {
+            match ch {
+                '\'' | '"' => {
+                    quoted = true;
+                    self.advance();
+                    while let Some((_, quoted, _)) = self.advance() {
+                        if quoted == ch {
+                            break;
+                        }
+                        delimiter.push(quoted);
+                    }
+                },
+                ch if is_word_continue(ch) => {
+                    self.advance();
+                    delimiter.push(ch);
+                },
+                _ => break,
+            }
+        }
+        if delimiter.is_empty() {
+            return Err(Error::MissingHeredocDelimiter(start));
+        }
+
+        // the body starts on the line after the command, or after the previous here document if
+        // there are multiple on the same line
+        let position = self.lookahead.map_or(self.input.len(), |(pos, _, _)| pos);
+        let body_start = match self.heredoc_end {
+            Some(end) => end,
+            None => match self.input[position..].find('\n') {
+                Some(newline) => position + newline + 1,
+                None => return Err(Error::UnterminatedHeredoc(start, delimiter)),
+            },
+        };
+
+        let mut line_start = body_start;
+        for line in self.input[body_start..].split_inclusive('\n') {
+            let content = line.strip_suffix('\n').unwrap_or(line);
+            let content = if strip_tabs {
+                content.trim_start_matches('\t')
+            } else {
+                content
+            };
+            if content == delimiter {
+                let body = &self.input[body_start..line_start];
+                let token = if quoted {
+                    Token::QUOTED_HEREDOC(body)
+                } else {
+                    Token::HEREDOC(body)
+                };
+                self.queued = Some((body_start, token, line_start));
+                self.heredoc_end = Some(line_start + line.len());
+                return Ok(());
+            }
+            line_start += line.len();
+        }
+        Err(Error::UnterminatedHeredoc(start, delimiter))
+    }
+
+    /// Read the `((init; cond; step))` of an arithmetic for loop, after the first `(`
+    fn arith_for(&mut self, start: usize) -> Result<(usize, Token<'input>, usize), Error> {
         self.advance();
-        Ok((start, Token::WORD(&self.input[start + 1..end]), end))
+        let inner_start = start + 2;
+        let mut depth = 0;
+        let mut separators = 0;
+        while let Some((pos, ch, _)) = self.advance() {
+            match ch {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ')' => match self.advance() {
+                    Some((_, ')', end)) if separators == 2 => {
+                        return Ok((start, Token::ARITH(&self.input[inner_start..pos]), end));
+                    },
+                    _ => break,
+                },
+                ';' if depth == 0 => separators += 1,
+                _ => {},
+            }
+        }
+        Err(Error::InvalidArithFor(start))
     }
 
     // utils for reading until condition is met
@@ -203,10 +399,22 @@ impl<'input> Iterator for Lexer<'input> {
 
     // TODO create proc macro to generate all this?
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(token) = self.queued.take() {
+            return Some(Ok(token));
+        }
+
         while let Some((start, ch, end)) = self.advance() {
             // TODO see if this could be generated with macro
             let token = match ch {
-                '\n' => Some(Ok((start, Token::NEWLINE, end))),
+                '\n' => {
+                    // skip over the bodies of here documents, they were already emitted
+                    if let Some(heredoc_end) = self.heredoc_end.take() {
+                        while matches!(self.lookahead, Some((pos, _, _)) if pos < heredoc_end) {
+                            self.advance();
+                        }
+                    }
+                    Some(Ok((start, Token::NEWLINE, end)))
+                },
                 ';' => match self.lookahead {
                     Some((_, ';', new_end)) => {
                         self.advance();
@@ -219,6 +427,16 @@ impl<'input> Iterator for Lexer<'input> {
                         self.advance();
                         Some(Ok((start, Token::AND_IF, new_end)))
                     },
+                    Some((_, '>', new_end)) => {
+                        self.advance();
+                        match self.lookahead {
+                            Some((_, '>', new_end)) => {
+                                self.advance();
+                                Some(Ok((start, Token::ANDDGREAT, new_end)))
+                            },
+                            _ => Some(Ok((start, Token::ANDGREAT, new_end))),
+                        }
+                    },
                     _ => Some(Ok((start, Token::AMP, end))),
                 },
                 '|' => match self.lookahead {
@@ -228,14 +446,23 @@ impl<'input> Iterator for Lexer<'input> {
                     },
                     _ => Some(Ok((start, Token::PIPE, end))),
                 },
-                '`' => Some(Ok((start, Token::BACKTICK, end))),
+                '`' => Some(self.keyword(start, end)),
                 '=' => Some(Ok((start, Token::EQUAL, end))),
                 '\\' => Some(Ok((start, Token::BACKSLASH, end))),
                 '<' => match self.lookahead {
-                    // TODO current doesn't support <<-
                     Some((_, '<', new_end)) => {
                         self.advance();
-                        Some(Ok((start, Token::DLESS, new_end)))
+                        let (token, strip_tabs, new_end) = match self.lookahead {
+                            Some((_, '-', new_end)) => {
+                                self.advance();
+                                (Token::DLESSDASH, true, new_end)
+                            },
+                            _ => (Token::DLESS, false, new_end),
+                        };
+                        match self.heredoc(start, strip_tabs) {
+                            Ok(()) => Some(Ok((start, token, new_end))),
+                            Err(e) => Some(Err(e)),
+                        }
                     },
                     Some((_, '&', new_end)) => {
                         self.advance();
@@ -245,6 +472,7 @@ impl<'input> Iterator for Lexer<'input> {
                         self.advance();
                         Some(Ok((start, Token::LESSGREAT, new_end)))
                     },
+                    Some((_, '(', _)) => Some(self.keyword(start, end)),
                     _ => Some(Ok((start, Token::LESS, end))),
                 },
                 '>' => match self.lookahead {
@@ -260,9 +488,13 @@ impl<'input> Iterator for Lexer<'input> {
                         self.advance();
                         Some(Ok((start, Token::CLOBBER, new_end)))
                     },
+                    Some((_, '(', _)) => Some(self.keyword(start, end)),
                     _ => Some(Ok((start, Token::GREAT, end))),
                 },
 
+                '(' if self.after_for && matches!(self.lookahead, Some((_, '(', _))) => {
+                    Some(self.arith_for(start))
+                },
                 '(' => Some(Ok((start, Token::LPAREN, end))),
                 ')' => Some(Ok((start, Token::RPAREN, end))),
                 '{' => Some(Ok((start, Token::LBRACE, end))),
@@ -270,10 +502,12 @@ impl<'input> Iterator for Lexer<'input> {
                 '!' => Some(Ok((start, Token::BANG, end))),
                 '\'' => Some(self.single_quote(start, end)),
                 '"' => Some(self.double_quote(start, end)),
+                '#' => Some(Ok(self.comment(start, end))),
                 ch if is_word_start(ch) => Some(self.keyword(start, end)),
                 ch if ch.is_whitespace() => continue,
                 ch => return Some(Err(Error::UnrecognizedChar(start, ch, end))),
             };
+            self.after_for = matches!(token, Some(Ok((_, Token::FOR, _))));
             return token;
         }
         None
@@ -298,7 +532,7 @@ fn is_word_continue(ch: char) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use super::{Lexer, Token};
+    use super::{Error, Lexer, Token};
 
     #[test]
     fn single_quote() {
@@ -309,9 +543,245 @@ mod tests {
         );
     }
 
+    #[test]
+    fn redirect_both() {
+        let tokens = Lexer::new("a &> b &>> c")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::WORD("a"),
+                Token::ANDGREAT,
+                Token::WORD("b"),
+                Token::ANDDGREAT,
+                Token::WORD("c")
+            ]
+        );
+    }
+
+    #[test]
+    fn comment() {
+        let tokens = Lexer::new("echo a#b # note\nls")
+            .map(|t| t.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                (0, Token::WORD("echo"), 4),
+                (5, Token::WORD("a#b"), 8),
+                (9, Token::COMMENT("# note"), 15),
+                (15, Token::NEWLINE, 16),
+                (16, Token::WORD("ls"), 18)
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_boundaries() {
+        let tokens = |line| Lexer::new(line).map(|t| t.unwrap().1).collect::<Vec<_>>();
+        assert_eq!(
+            tokens("# only a comment"),
+            vec![Token::COMMENT("# only a comment")]
+        );
+        assert_eq!(
+            tokens("true;# note"),
+            vec![Token::WORD("true"), Token::SEMI, Token::COMMENT("# note")]
+        );
+        assert_eq!(
+            tokens("echo \"a # b\" '#' #"),
+            vec![
+                Token::WORD("echo"),
+                Token::WORD("\"a # b\""),
+                Token::WORD("'#'"),
+                Token::COMMENT("#")
+            ]
+        );
+        assert_eq!(
+            tokens("ls # list | wc\npwd"),
+            vec![
+                Token::WORD("ls"),
+                Token::COMMENT("# list | wc"),
+                Token::NEWLINE,
+                Token::WORD("pwd")
+            ]
+        );
+    }
+
+    #[test]
+    fn command_substitution() {
+        let tokens = Lexer::new("echo a$(ls -a | wc $(pwd))b `date +%s`;")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::WORD("echo"),
+                Token::WORD("a$(ls -a | wc $(pwd))b"),
+                Token::WORD("`date +%s`"),
+                Token::SEMI
+            ]
+        );
+    }
+
+    #[test]
+    fn process_substitution() {
+        let tokens = Lexer::new("diff <(sort a | uniq) >(wc -l)x < (b)")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::WORD("diff"),
+                Token::WORD("<(sort a | uniq)"),
+                Token::WORD(">(wc -l)x"),
+                Token::LESS,
+                Token::LPAREN,
+                Token::WORD("b"),
+                Token::RPAREN
+            ]
+        );
+    }
+
+    #[test]
+    fn parameter_expansion() {
+        let tokens = Lexer::new("echo ${A:-b c}x ${A:-${B}} { ${A}; }")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::WORD("echo"),
+                Token::WORD("${A:-b c}x"),
+                Token::WORD("${A:-${B}}"),
+                Token::LBRACE,
+                Token::WORD("${A}"),
+                Token::SEMI,
+                Token::RBRACE
+            ]
+        );
+    }
+
+    #[test]
+    fn arithmetic_expansion() {
+        let tokens = Lexer::new("echo $(( (1 + 2) * $i ))")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![Token::WORD("echo"), Token::WORD("$(( (1 + 2) * $i ))")]
+        );
+    }
+
+    #[test]
+    fn quotes_kept() {
+        let tokens = Lexer::new("ls '*.rs' \"*.rs\"")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::WORD("ls"),
+                Token::WORD("'*.rs'"),
+                Token::WORD("\"*.rs\"")
+            ]
+        );
+    }
+
+    #[test]
+    fn io_number() {
+        let tokens = Lexer::new("cmd 2>&1 2 >out 3<file")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::WORD("cmd"),
+                Token::IO_NUMBER("2"),
+                Token::GREATAND,
+                Token::WORD("1"),
+                Token::WORD("2"),
+                Token::GREAT,
+                Token::WORD("out"),
+                Token::IO_NUMBER("3"),
+                Token::LESS,
+                Token::WORD("file"),
+            ]
+        );
+    }
+
     #[test]
     fn keywords() {
         let mut lexer = Lexer::new("case");
         assert_eq!(lexer.next(), Some(Ok((0, Token::CASE, 4))));
     }
+
+    #[test]
+    fn heredoc() {
+        let tokens = Lexer::new("cat <<EOF | wc\nhello\n  world\nEOF\nls")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::WORD("cat"),
+                Token::DLESS,
+                Token::HEREDOC("hello\n  world\n"),
+                Token::PIPE,
+                Token::WORD("wc"),
+                Token::NEWLINE,
+                Token::WORD("ls")
+            ]
+        );
+    }
+
+    #[test]
+    fn multiple_heredocs() {
+        let tokens = Lexer::new("cmd <<'A' <<-\"B\"\na\nA\n\tb\n\tB")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::WORD("cmd"),
+                Token::DLESS,
+                Token::QUOTED_HEREDOC("a\n"),
+                Token::DLESSDASH,
+                Token::QUOTED_HEREDOC("\tb\n"),
+                Token::NEWLINE,
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_heredoc() {
+        let mut lexer = Lexer::new("cat <<EOF\nhello");
+        assert_eq!(lexer.next(), Some(Ok((0, Token::WORD("cat"), 3))));
+        assert_eq!(
+            lexer.next(),
+            Some(Err(Error::UnterminatedHeredoc(4, "EOF".to_string())))
+        );
+    }
+
+    #[test]
+    fn arith_for() {
+        let tokens = Lexer::new("for ((i = (1); i < 3; i++)); do :; done")
+            .map(|t| t.unwrap().1)
+            .take(3)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![Token::FOR, Token::ARITH("i = (1); i < 3; i++"), Token::SEMI]
+        );
+
+        // only after `for`
+        let tokens = Lexer::new("((a))")
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(tokens[..2], [Token::LPAREN, Token::LPAREN]);
+
+        let mut lexer = Lexer::new("for ((i = 0)); do :; done");
+        lexer.next();
+        assert_eq!(lexer.next(), Some(Err(Error::InvalidArithFor(4))));
+    }
 }
@@ -8,16 +8,31 @@ extern crate lalrpop_util;
 lalrpop_mod!(pub grammar);
 
 mod parser;
-pub use parser::{Parser, ParserError};
+pub use parser::{Parser, ParserError, Span, SyntaxError};
 
 mod lexer;
-pub use lexer::{Lexer, Token, RESERVED_WORDS};
+pub use lexer::{Error as LexerError, Lexer, Token, RESERVED_WORDS};
 
 pub mod ast;
 
+mod arith;
+mod envsubst;
 mod eval;
-pub use eval::eval;
+pub use eval::{eval, expand_words};
 
+mod builtins;
+pub use builtins::{NoBuiltins, ShellBuiltins};
+
+mod functions;
+pub use functions::{
+    function_description, function_names, positional_args, set_positional_args, unset_function,
+};
+
+mod fields;
+pub use fields::{split_fields, DEFAULT_IFS};
 
 mod error;
 pub use error::PosixError;
+
+mod options;
+pub use options::ShellOptions;
@@ -0,0 +1,45 @@
+//! Options that change how commands are evaluated
+
+/// Shell options, which are toggled with the `set` builtin
+#[derive(Debug, Clone, Default)]
+pub struct ShellOptions {
+    /// Exit when a command fails (`set -e`)
+    pub errexit: bool,
+    /// Refuse to overwrite existing files with `>` (`set -C`), `>|` overwrites them regardless
+    pub noclobber: bool,
+    /// Treat expanding a variable that isn't set as an error (`set -u`)
+    pub nounset: bool,
+    /// Print each command before running it (`set -x`)
+    pub xtrace: bool,
+    /// The status of a pipeline is that of the last command to fail instead of the last command
+    /// (`set -o pipefail`)
+    pub pipefail: bool,
+}
+
+impl ShellOptions {
+    /// Name of every option along with whether it is enabled
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, bool)> {
+        [
+            ("errexit", self.errexit),
+            ("noclobber", self.noclobber),
+            ("nounset", self.nounset),
+            ("pipefail", self.pipefail),
+            ("xtrace", self.xtrace),
+        ]
+        .into_iter()
+    }
+
+    /// Enable or disable an option by name, returns false if there is no such option
+    pub fn set(&mut self, name: &str, enabled: bool) -> bool {
+        let option = match name {
+            "errexit" => &mut self.errexit,
+            "noclobber" => &mut self.noclobber,
+            "nounset" => &mut self.nounset,
+            "pipefail" => &mut self.pipefail,
+            "xtrace" => &mut self.xtrace,
+            _ => return false,
+        };
+        *option = enabled;
+        true
+    }
+}
@@ -1,14 +1,106 @@
 //! Generated parser
 
+use std::fmt;
+
+use lalrpop_util::ParseError;
 use thiserror::Error;
 
-use crate::{ast, grammar, lexer::Lexer};
+use crate::{
+    ast, grammar,
+    lexer::{Error as LexerError, Lexer, Token},
+};
 
-// TODO better errors for unsuccessful parses
 #[derive(Error, Debug)]
 pub enum ParserError {
-    #[error("unsuccessful parse")]
-    UnsuccessfulParse,
+    /// The input doesn't follow the grammar, see [SyntaxError]
+    #[error("{0}")]
+    Syntax(SyntaxError),
+    /// The input ended in the middle of a command, which more lines could complete
+    #[error("unexpected end of file")]
+    UnexpectedEof,
+}
+
+impl ParserError {
+    /// Where in the input the error is, if it isn't at the end of it
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ParserError::Syntax(e) => Some(e.span),
+            ParserError::UnexpectedEof => None,
+        }
+    }
+}
+
+/// Range of byte offsets into the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Token or character that the parser didn't expect, along with where it is
+///
+/// Displayed as the message followed by the line of the input with a caret under the span.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    /// What went wrong, such as ``unexpected `fi` ``
+    pub message: String,
+    pub span: Span,
+    /// Line the span starts on, counted from 1
+    pub line: usize,
+    /// Column the span starts at in characters, counted from 1
+    pub column: usize,
+    /// Text of the line the span starts on
+    pub source_line: String,
+}
+
+impl SyntaxError {
+    fn new(input: &str, message: String, start: usize, end: usize) -> Self {
+        let start = start.min(input.len());
+        let end = end.clamp(start, input.len());
+        let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[start..].find('\n').map_or(input.len(), |i| start + i);
+        Self {
+            message,
+            span: Span { start, end },
+            line: input[..start].matches('\n').count() + 1,
+            column: input[line_start..start].chars().count() + 1,
+            source_line: input[line_start..line_end].to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // tabs are kept so the caret lines up with the line, which it only underlines the part of
+        // the span on
+        let indent: String = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let mut len = 0;
+        let width = self
+            .source_line
+            .chars()
+            .skip(self.column - 1)
+            .take_while(|c| {
+                let in_span = len < self.span.end - self.span.start;
+                len += c.len_utf8();
+                in_span
+            })
+            .count()
+            .max(1);
+        write!(
+            f,
+            "{} at line {}, column {}\n{}\n{indent}{}",
+            self.message,
+            self.line,
+            self.column,
+            self.source_line,
+            "^".repeat(width)
+        )
+    }
 }
 
 #[derive(Default)]
@@ -16,9 +108,46 @@ pub struct Parser {}
 
 impl Parser {
     pub fn parse(&self, lexer: Lexer) -> Result<ast::Command, ParserError> {
+        let input = lexer.input();
+        let tokens = lexer.filter(|token| !matches!(token, Ok((_, Token::COMMENT(_), _))));
         grammar::ProgramParser::new()
-            .parse(lexer.input(), lexer)
-            .map_err(|_e| ParserError::UnsuccessfulParse)
+            .parse(input, tokens)
+            .map_err(|e| match e {
+                ParseError::UnrecognizedEOF { .. }
+                | ParseError::User {
+                    error: LexerError::UnterminatedHeredoc(..),
+                } => ParserError::UnexpectedEof,
+                ParseError::InvalidToken { location } => {
+                    let end = input[location..]
+                        .chars()
+                        .next()
+                        .map_or(location, |c| location + c.len_utf8());
+                    let message = "invalid token".to_string();
+                    ParserError::Syntax(SyntaxError::new(input, message, location, end))
+                },
+                ParseError::UnrecognizedToken {
+                    token: (start, _, end),
+                    ..
+                }
+                | ParseError::ExtraToken {
+                    token: (start, _, end),
+                } => {
+                    let message = match &input[start..end] {
+                        "\n" => "unexpected newline".to_string(),
+                        token => format!("unexpected `{token}`"),
+                    };
+                    ParserError::Syntax(SyntaxError::new(input, message, start, end))
+                },
+                ParseError::User { error } => {
+                    let (start, end) = match error {
+                        LexerError::UnrecognizedChar(start, _, end) => (start, end),
+                        LexerError::MissingHeredocDelimiter(start)
+                        | LexerError::UnterminatedHeredoc(start, _)
+                        | LexerError::InvalidArithFor(start) => (start, start + 1),
+                    };
+                    ParserError::Syntax(SyntaxError::new(input, error.to_string(), start, end))
+                },
+            })
     }
 }
 
@@ -41,3 +170,72 @@ mod tests {
     }
 }
 */
+
+#[cfg(test)]
+mod parser_error_tests {
+    use super::{Parser, ParserError, Span, SyntaxError};
+    use crate::Lexer;
+
+    fn parse(input: &str) -> Result<(), ParserError> {
+        Parser::default().parse(Lexer::new(input)).map(|_| ())
+    }
+
+    #[test]
+    fn unexpected_eof() {
+        assert!(parse("if true; then echo a; fi").is_ok());
+        assert!(matches!(
+            parse("if true; then\necho a\n"),
+            Err(ParserError::UnexpectedEof)
+        ));
+        assert!(matches!(
+            parse("cat <<EOF\nhi\n"),
+            Err(ParserError::UnexpectedEof)
+        ));
+        assert!(matches!(parse("echo a; fi"), Err(ParserError::Syntax(_))));
+    }
+
+    fn syntax_error(input: &str) -> SyntaxError {
+        match parse(input) {
+            Err(ParserError::Syntax(e)) => e,
+            res => panic!("expected a syntax error, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn syntax_error_spans() {
+        let e = syntax_error("echo a; fi");
+        assert_eq!(e.message, "unexpected `fi`");
+        assert_eq!(e.span, Span { start: 8, end: 10 });
+        assert_eq!((e.line, e.column), (1, 9));
+        assert_eq!(
+            e.to_string(),
+            "unexpected `fi` at line 1, column 9\necho a; fi\n        ^^"
+        );
+
+        // the line and column are found in multi-line input
+        let e = syntax_error("echo a\nif true; then\n\techo b; done\nfi");
+        assert_eq!(e.message, "unexpected `done`");
+        assert_eq!((e.line, e.column), (3, 10));
+        assert_eq!(e.source_line, "\techo b; done");
+        assert_eq!(e.to_string().lines().last(), Some("\t        ^^^^"));
+
+        let e = syntax_error("ls |\n| wc");
+        assert_eq!(e.message, "unexpected `|`");
+        assert_eq!((e.line, e.column), (2, 1));
+
+        let e = syntax_error("echo a )");
+        assert_eq!(e.message, "unexpected `)`");
+        assert_eq!(e.span, Span { start: 7, end: 8 });
+    }
+
+    #[test]
+    fn lexer_error_spans() {
+        let e = syntax_error("for ((i = 0)); do echo; done");
+        assert_eq!(e.span.start, 4);
+        assert_eq!((e.line, e.column), (1, 5));
+
+        let e = syntax_error("cat <<");
+        assert_eq!(e.message, "missing here document delimiter");
+        assert!(e.to_string().ends_with("cat <<\n    ^"));
+    }
+}
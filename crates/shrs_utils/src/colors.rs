@@ -0,0 +1,38 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crossterm::style::ContentStyle;
+
+/// Whether ANSI styling should be emitted when rendering
+static COLORS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Check if styling is currently enabled
+pub fn colors_enabled() -> bool {
+    COLORS_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Globally enable or disable styling
+///
+/// When disabled, [`crate::StyledBuf`] and any style passed through [`effective_style`] render as
+/// plain text.
+pub fn set_colors_enabled(enabled: bool) {
+    COLORS_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Determine if styling should be enabled from the environment
+///
+/// Respects the [NO_COLOR](https://no-color.org) convention, where any non-empty value disables
+/// color, as well as `TERM=dumb`.
+pub fn colors_enabled_from_env() -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|val| !val.is_empty());
+    let dumb_term = std::env::var_os("TERM").is_some_and(|term| term == "dumb");
+    !no_color && !dumb_term
+}
+
+/// The style that should actually be rendered, which is no style at all if colors are disabled
+pub fn effective_style(style: ContentStyle) -> ContentStyle {
+    if colors_enabled() {
+        style
+    } else {
+        ContentStyle::new()
+    }
+}
@@ -1,9 +1,11 @@
 mod algo;
+mod colors;
 mod cursor_buffer;
 mod macros;
 mod styled_buf;
 
 pub use algo::*;
+pub use colors::*;
 pub use cursor_buffer::*;
 pub use macros::*;
 pub use styled_buf::*;
@@ -3,8 +3,12 @@ use std::{fmt::Display, ops::Range};
 use crossterm::style::{Attribute, Color, ContentStyle, StyledContent, Stylize};
 use unicode_width::UnicodeWidthStr;
 
+use crate::effective_style;
+
 /// Text to be rendered by painter
 /// styles has a style for each character in content
+///
+/// Styles are dropped when rendering if colors are disabled, see [`crate::set_colors_enabled`]
 #[derive(Clone, Debug)]
 pub struct StyledBuf {
     pub content: String,
@@ -59,7 +63,10 @@ impl StyledBuf {
             let mut x: Vec<StyledContent<String>> = vec![];
 
             for c in line.chars() {
-                x.push(StyledContent::new(self.styles[i], c.to_string()));
+                x.push(StyledContent::new(
+                    effective_style(self.styles[i]),
+                    c.to_string(),
+                ));
                 i += 1;
             }
             i += 1;
@@ -70,7 +77,10 @@ impl StyledBuf {
     pub fn spans(&self) -> Vec<StyledContent<String>> {
         let mut x: Vec<StyledContent<String>> = vec![];
         for (i, c) in self.content.chars().enumerate() {
-            x.push(StyledContent::new(self.styles[i], c.to_string()));
+            x.push(StyledContent::new(
+                effective_style(self.styles[i]),
+                c.to_string(),
+            ));
         }
         x
     }
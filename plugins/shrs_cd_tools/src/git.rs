@@ -76,6 +76,68 @@ pub fn latest_commit_summary() -> anyhow::Result<String> {
     Ok(str::from_utf8(&res.stdout).unwrap().trim().to_string())
 }
 
+/// Run a git command and split its output into lines
+fn lines_of(args: &[&str]) -> anyhow::Result<Vec<String>> {
+    let res = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| Error::GitError(e.to_string()))?;
+
+    if !res.status.success() {
+        return Err(anyhow::anyhow!(Error::NotGitRepo));
+    }
+
+    Ok(str::from_utf8(&res.stdout)?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// Get names of all local branches
+pub fn local_branches() -> anyhow::Result<Vec<String>> {
+    lines_of(&["for-each-ref", "--format=%(refname:strip=2)", "refs/heads"])
+}
+
+/// Get names of all remote tracking branches, in the form `remote/branch`
+pub fn remote_branches() -> anyhow::Result<Vec<String>> {
+    let branches = lines_of(&[
+        "for-each-ref",
+        "--format=%(refname:strip=2)",
+        "refs/remotes",
+    ])?;
+    // skip the symbolic ref pointing at the default branch of each remote
+    Ok(branches
+        .into_iter()
+        .filter(|branch| !branch.ends_with("/HEAD"))
+        .collect())
+}
+
+/// Get names of all tags
+pub fn tags() -> anyhow::Result<Vec<String>> {
+    lines_of(&["for-each-ref", "--format=%(refname:strip=2)", "refs/tags"])
+}
+
+/// Get names of all configured remotes
+pub fn remotes() -> anyhow::Result<Vec<String>> {
+    lines_of(&["remote"])
+}
+
+/// Get tracked files with unstaged changes, relative to the current directory
+pub fn modified_files() -> anyhow::Result<Vec<String>> {
+    lines_of(&["ls-files", "--modified"])
+}
+
+/// Get files that are not tracked or ignored, relative to the current directory
+pub fn untracked_files() -> anyhow::Result<Vec<String>> {
+    lines_of(&["ls-files", "--others", "--exclude-standard"])
+}
+
+/// Get files with staged changes, relative to the current directory
+pub fn staged_files() -> anyhow::Result<Vec<String>> {
+    lines_of(&["diff", "--name-only", "--cached", "--relative"])
+}
+
 fn metadata_fn(query_res: &mut QueryResult) -> anyhow::Result<()> {
     query_res.add_metadata(Git { branch: branch()? });
 
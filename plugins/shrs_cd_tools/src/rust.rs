@@ -1,6 +1,10 @@
 //! Utilities for rust based projects
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
 use serde::Deserialize;
 use shrs::anyhow;
@@ -20,6 +24,155 @@ pub struct Package {
     pub description: String,
 }
 
+/// Parts of a `Cargo.toml` needed to find its targets, which unlike [CargoToml] also accepts
+/// virtual workspace manifests and fields inherited from the workspace
+#[derive(Deserialize)]
+struct Manifest {
+    package: Option<ManifestPackage>,
+    workspace: Option<Workspace>,
+    #[serde(default)]
+    bin: Vec<Target>,
+    #[serde(default)]
+    example: Vec<Target>,
+    #[serde(default)]
+    test: Vec<Target>,
+    #[serde(default)]
+    bench: Vec<Target>,
+}
+
+#[derive(Deserialize)]
+struct ManifestPackage {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Workspace {
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+/// A `[[bin]]`, `[[example]]`, `[[test]]` or `[[bench]]` target
+#[derive(Deserialize)]
+struct Target {
+    name: Option<String>,
+}
+
+/// Names of the targets of every package in a workspace
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CargoTargets {
+    pub packages: Vec<String>,
+    pub bins: Vec<String>,
+    pub examples: Vec<String>,
+    pub tests: Vec<String>,
+    pub benches: Vec<String>,
+}
+
+impl CargoTargets {
+    /// Find the targets of the workspace (or package) that `dir` is part of
+    ///
+    /// Targets are read from the manifests as well as discovered from the conventional locations
+    /// such as `src/bin` and `examples`.
+    pub fn find(dir: &Path) -> anyhow::Result<Self> {
+        let Some(root) = workspace_root(dir) else {
+            return Err(anyhow::anyhow!("not in a cargo project"));
+        };
+        let manifest = read_manifest(&root)?;
+
+        let mut package_dirs = vec![];
+        if manifest.package.is_some() {
+            package_dirs.push(root.clone());
+        }
+        if let Some(workspace) = &manifest.workspace {
+            for member in &workspace.members {
+                let pattern = root.join(member);
+                let paths = glob::glob(&pattern.to_string_lossy())?;
+                package_dirs.extend(paths.flatten().filter(|p| p.join("Cargo.toml").is_file()));
+            }
+        }
+
+        let mut targets = CargoTargets::default();
+        for package_dir in package_dirs {
+            // skip members that fail to parse instead of failing the whole workspace
+            if let Ok(manifest) = read_manifest(&package_dir) {
+                targets.add_package(&package_dir, &manifest);
+            }
+        }
+        for names in [
+            &mut targets.packages,
+            &mut targets.bins,
+            &mut targets.examples,
+            &mut targets.tests,
+            &mut targets.benches,
+        ] {
+            names.sort();
+            names.dedup();
+        }
+
+        Ok(targets)
+    }
+
+    fn add_package(&mut self, dir: &Path, manifest: &Manifest) {
+        let Some(package) = &manifest.package else {
+            return;
+        };
+        self.packages.push(package.name.clone());
+
+        if dir.join("src/main.rs").is_file() {
+            self.bins.push(package.name.clone());
+        }
+        for (names, explicit, auto_dir) in [
+            (&mut self.bins, &manifest.bin, "src/bin"),
+            (&mut self.examples, &manifest.example, "examples"),
+            (&mut self.tests, &manifest.test, "tests"),
+            (&mut self.benches, &manifest.bench, "benches"),
+        ] {
+            names.extend(explicit.iter().filter_map(|target| target.name.clone()));
+            names.append(&mut discover_targets(&dir.join(auto_dir)));
+        }
+    }
+}
+
+/// Find the outermost directory with a `Cargo.toml` that declares a workspace, or the nearest
+/// directory with a `Cargo.toml` if there is no workspace
+fn workspace_root(dir: &Path) -> Option<PathBuf> {
+    let mut nearest = None;
+    for ancestor in dir.ancestors() {
+        let Ok(manifest) = read_manifest(ancestor) else {
+            continue;
+        };
+        if manifest.workspace.is_some() {
+            return Some(ancestor.to_path_buf());
+        }
+        nearest.get_or_insert(ancestor.to_path_buf());
+    }
+    nearest
+}
+
+fn read_manifest(dir: &Path) -> anyhow::Result<Manifest> {
+    let contents = fs::read_to_string(dir.join("Cargo.toml"))?;
+    Ok(toml::from_str(&contents)?)
+}
+
+/// Targets cargo discovers automatically, either `name.rs` or `name/main.rs`
+fn discover_targets(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let is_target = match path.is_dir() {
+                true => path.join("main.rs").is_file(),
+                false => path.extension().is_some_and(|ext| ext == "rs"),
+            };
+            let name = path.file_stem()?.to_str()?.to_string();
+            is_target.then_some(name)
+        })
+        .collect()
+}
+
 fn cargo_toml_parser(query_res: &mut QueryResult, content: &String) -> anyhow::Result<()> {
     let parsed: CargoToml = toml::from_str(content)?;
     query_res.add_metadata(parsed);
@@ -43,7 +196,7 @@ mod tests {
 
     use shrs::anyhow;
 
-    use super::module;
+    use super::{module, CargoTargets};
     use crate::rust::CargoToml;
 
     #[test]
@@ -57,4 +210,17 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn find_workspace_targets() -> anyhow::Result<()> {
+        // this crate is a member of the shrs workspace
+        let path = std::env::current_dir()?;
+        let targets = CargoTargets::find(&path)?;
+
+        assert!(targets.packages.contains(&String::from("shrs_cd_tools")));
+        assert!(targets.packages.contains(&String::from("shrs_core")));
+        assert!(targets.examples.contains(&String::from("completer")));
+
+        Ok(())
+    }
 }
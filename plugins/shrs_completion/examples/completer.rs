@@ -4,8 +4,19 @@ use shrs_completion::completions::*;
 fn main() {
     let mut mycompleter = DefaultCompleter::default();
     mycompleter.register(ssh_rule());
+    mycompleter.register(make_rule());
+    mycompleter.register(cargo_rule());
 
-    let myshell = ShellBuilder::default().build().unwrap();
+    let myshell = ShellBuilder::default()
+        .with_completer(mycompleter)
+        .with_plugin(GitCompletionPlugin::new())
+        .with_plugin(
+            HelpCompletionPlugin::new()
+                .with_command("docker")
+                .with_command("kubectl"),
+        )
+        .build()
+        .unwrap();
 
     myshell.run().unwrap();
 }
@@ -0,0 +1,92 @@
+use shrs::prelude::{
+    cmdname_eq_pred, default_format, flag_pred, Completion, CompletionCtx, Pred, Rule,
+};
+use shrs_cd_tools::rust::CargoTargets;
+
+/// Kind of cargo target that an argument names
+#[derive(Debug, PartialEq, Eq)]
+enum TargetKind {
+    Package,
+    Bin,
+    Example,
+    Test,
+    Bench,
+}
+
+/// Complete the targets of the cargo workspace in the working directory
+///
+/// Target names follow flags like `--bin` and `--example`, while `cargo test <tab>` and
+/// `cargo bench <tab>` complete test and bench targets.
+pub fn cargo_rule() -> Rule {
+    Rule::new(Pred::new(cargo_pred), cargo_targets_action)
+}
+
+fn cargo_pred(ctx: &CompletionCtx) -> bool {
+    cmdname_eq_pred("cargo".into())(ctx) && ctx.arg_num() >= 2 && !flag_pred(ctx)
+}
+
+fn cargo_targets_action(ctx: &CompletionCtx) -> Vec<Completion> {
+    let Some(kind) = target_kind(&ctx.line) else {
+        return vec![];
+    };
+    let Ok(cwd) = std::env::current_dir() else {
+        return vec![];
+    };
+    let targets = CargoTargets::find(&cwd).unwrap_or_default();
+
+    let names = match kind {
+        TargetKind::Package => targets.packages,
+        TargetKind::Bin => targets.bins,
+        TargetKind::Example => targets.examples,
+        TargetKind::Test => targets.tests,
+        TargetKind::Bench => targets.benches,
+    };
+    default_format(names)
+}
+
+/// Decide which targets to complete based on the subcommand and the previous word
+fn target_kind(line: &[String]) -> Option<TargetKind> {
+    let subcommand = line.get(1)?;
+    let prev = line.get(line.len().checked_sub(2)?)?;
+
+    match prev.as_str() {
+        "-p" | "--package" => Some(TargetKind::Package),
+        "--bin" => Some(TargetKind::Bin),
+        "--example" => Some(TargetKind::Example),
+        "--test" => Some(TargetKind::Test),
+        "--bench" => Some(TargetKind::Bench),
+        _ if line.len() == 3 => match subcommand.as_str() {
+            "test" | "t" => Some(TargetKind::Test),
+            "bench" => Some(TargetKind::Bench),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{target_kind, TargetKind};
+
+    fn line(line: &[&str]) -> Vec<String> {
+        line.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn cargo_target_kind() {
+        assert_eq!(
+            target_kind(&line(&["cargo", "run", "--bin", ""])),
+            Some(TargetKind::Bin)
+        );
+        assert_eq!(
+            target_kind(&line(&["cargo", "build", "-p", "sh"])),
+            Some(TargetKind::Package)
+        );
+        assert_eq!(
+            target_kind(&line(&["cargo", "test", ""])),
+            Some(TargetKind::Test)
+        );
+        assert_eq!(target_kind(&line(&["cargo", "run", ""])), None);
+        assert_eq!(target_kind(&line(&["cargo", ""])), None);
+    }
+}
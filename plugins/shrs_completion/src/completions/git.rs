@@ -0,0 +1,332 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use shrs::{anyhow, prelude::*};
+use shrs_cd_tools::git;
+
+/// Completes the arguments of a single git subcommand
+pub type GitHandler = Rc<dyn Fn(&GitCtx) -> Vec<Completion>>;
+
+/// `git remote` operations that take the name of an existing remote
+const REMOTE_OPERATIONS: [&str; 8] = [
+    "rename",
+    "remove",
+    "rm",
+    "set-head",
+    "set-branches",
+    "get-url",
+    "set-url",
+    "show",
+];
+
+/// Branches and remotes of the repository in the working directory
+#[derive(Default)]
+struct GitRefs {
+    local_branches: Vec<String>,
+    remote_branches: Vec<String>,
+    tags: Vec<String>,
+    remotes: Vec<String>,
+}
+
+impl GitRefs {
+    /// Query git for the refs of the current repository
+    ///
+    /// Outside of a git repository (or if git is not installed) everything is left empty.
+    fn load() -> Self {
+        Self {
+            local_branches: git::local_branches().unwrap_or_default(),
+            remote_branches: git::remote_branches().unwrap_or_default(),
+            tags: git::tags().unwrap_or_default(),
+            remotes: git::remotes().unwrap_or_default(),
+        }
+    }
+}
+
+/// Cache of [GitRefs] shared between the completion rules and the hooks that invalidate it
+type GitRefsCache = Rc<RefCell<Option<GitRefs>>>;
+
+/// Context passed to a [GitHandler]
+///
+/// Refs are cached between completions, while file lists are queried each time since they change
+/// whenever the user edits a file.
+pub struct GitCtx<'a> {
+    /// The completion context of the whole line
+    pub ctx: &'a CompletionCtx,
+    cache: &'a GitRefsCache,
+}
+
+impl GitCtx<'_> {
+    /// Name of the git subcommand being completed
+    pub fn subcommand(&self) -> &str {
+        self.ctx.line.get(1).map(String::as_str).unwrap_or_default()
+    }
+
+    /// Arguments after the subcommand, not including the word currently being typed
+    pub fn args(&self) -> &[String] {
+        let end = self.ctx.line.len().saturating_sub(1);
+        self.ctx.line.get(2..end).unwrap_or_default()
+    }
+
+    /// Arguments after the subcommand that are not flags
+    pub fn positional_args(&self) -> Vec<&String> {
+        self.args()
+            .iter()
+            .take_while(|arg| *arg != "--")
+            .filter(|arg| !arg.starts_with('-'))
+            .collect()
+    }
+
+    /// Check if a `--` separating revisions from paths was already typed
+    pub fn after_double_dash(&self) -> bool {
+        self.args().iter().any(|arg| arg == "--")
+    }
+
+    /// Check if a flag was passed to the subcommand
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.args()
+            .iter()
+            .take_while(|arg| *arg != "--")
+            .any(|arg| arg == flag)
+    }
+
+    /// Local and remote tracking branches
+    pub fn branches(&self) -> Vec<String> {
+        self.with_refs(|refs| {
+            let mut branches = refs.local_branches.clone();
+            branches.extend(refs.remote_branches.iter().cloned());
+            branches
+        })
+    }
+
+    /// Branches, tags and `HEAD`
+    pub fn refs(&self) -> Vec<String> {
+        let mut refs = vec![String::from("HEAD")];
+        refs.append(&mut self.branches());
+        refs.append(&mut self.with_refs(|refs| refs.tags.clone()));
+        refs
+    }
+
+    /// Names of configured remotes
+    pub fn remotes(&self) -> Vec<String> {
+        self.with_refs(|refs| refs.remotes.clone())
+    }
+
+    /// Run a function on the cached refs, loading them if the cache was invalidated
+    fn with_refs(&self, f: impl Fn(&GitRefs) -> Vec<String>) -> Vec<String> {
+        let mut cache = self.cache.borrow_mut();
+        f(cache.get_or_insert_with(GitRefs::load))
+    }
+}
+
+/// Context sensitive completions for git subcommand arguments
+///
+/// The subcommand (second word) picks a [GitHandler] that decides what to complete, for example
+/// `git add` completes modified and untracked files while `git checkout` completes branches.
+/// Handlers for additional subcommands can be added with [GitCompletionPlugin::with_handler].
+///
+/// The refs are queried once and cached until the working directory changes or another git
+/// command is ran.
+pub struct GitCompletionPlugin {
+    cache: GitRefsCache,
+    handlers: HashMap<String, GitHandler>,
+}
+
+impl GitCompletionPlugin {
+    pub fn new() -> Self {
+        let plugin = Self {
+            cache: Rc::new(RefCell::new(None)),
+            handlers: HashMap::new(),
+        };
+
+        plugin
+            .with_handler("add", add_handler)
+            .with_handler("branch", branch_handler)
+            .with_handler("checkout", checkout_handler)
+            .with_handler("cherry-pick", ref_handler)
+            .with_handler("diff", ref_or_path_handler)
+            .with_handler("fetch", remote_handler)
+            .with_handler("log", ref_or_path_handler)
+            .with_handler("merge", branch_handler)
+            .with_handler("pull", remote_handler)
+            .with_handler("push", remote_handler)
+            .with_handler("rebase", branch_handler)
+            .with_handler("remote", remote_operation_handler)
+            .with_handler("reset", ref_or_path_handler)
+            .with_handler("restore", restore_handler)
+            .with_handler("show", ref_handler)
+            .with_handler("switch", branch_handler)
+    }
+
+    /// Complete the arguments of a subcommand with a custom handler
+    ///
+    /// Replaces the existing handler if the subcommand already has one.
+    pub fn with_handler(
+        mut self,
+        subcommand: &str,
+        handler: impl Fn(&GitCtx) -> Vec<Completion> + 'static,
+    ) -> Self {
+        self.handlers
+            .insert(subcommand.to_string(), Rc::new(handler));
+        self
+    }
+}
+
+impl Default for GitCompletionPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin for GitCompletionPlugin {
+    fn init(&self, shell: &mut ShellConfig) -> anyhow::Result<()> {
+        let cache = self.cache.clone();
+        shell
+            .hooks
+            .insert(move |_ctx: &ChangeDirCtx| -> anyhow::Result<()> {
+                cache.replace(None);
+                Ok(())
+            });
+
+        // git commands may have created or deleted branches
+        let cache = self.cache.clone();
+        shell
+            .hooks
+            .insert(move |ctx: &AfterCommandCtx| -> anyhow::Result<()> {
+                if ctx.command.split_whitespace().next() == Some("git") {
+                    cache.replace(None);
+                }
+                Ok(())
+            });
+
+        Ok(())
+    }
+
+    fn post_init(&self, _sh: &mut Shell, states: &mut States) -> anyhow::Result<()> {
+        let Ok(mut completer) = states.try_get_mut::<Box<dyn Completer>>() else {
+            return Err(anyhow::anyhow!("no completer registered"));
+        };
+
+        let handlers = self.handlers.clone();
+        let pred_handlers = self.handlers.clone();
+        let cache = self.cache.clone();
+        completer.register(Rule::new(
+            Pred::new(move |ctx: &CompletionCtx| {
+                git_subcommand_arg_pred(ctx)
+                    && ctx
+                        .line
+                        .get(1)
+                        .is_some_and(|subcommand| pred_handlers.contains_key(subcommand))
+            }),
+            move |ctx: &CompletionCtx| {
+                let git_ctx = GitCtx { ctx, cache: &cache };
+                match handlers.get(git_ctx.subcommand()) {
+                    Some(handler) => handler(&git_ctx),
+                    None => vec![],
+                }
+            },
+        ));
+
+        Ok(())
+    }
+
+    fn meta(&self) -> PluginMeta {
+        PluginMeta::new(
+            "Git Completion",
+            "Completes git subcommand arguments such as branches, remotes and changed files",
+            None,
+        )
+    }
+}
+
+/// Check if we are completing a non-flag argument of a git subcommand
+fn git_subcommand_arg_pred(ctx: &CompletionCtx) -> bool {
+    ctx.cmd_name().map(String::as_str) == Some("git") && ctx.arg_num() >= 2 && !flag_pred(ctx)
+}
+
+/// Files that can be staged
+fn add_handler(_ctx: &GitCtx) -> Vec<Completion> {
+    let mut files = git::modified_files().unwrap_or_default();
+    files.append(&mut git::untracked_files().unwrap_or_default());
+    default_format(files)
+}
+
+fn branch_handler(ctx: &GitCtx) -> Vec<Completion> {
+    default_format(ctx.branches())
+}
+
+/// Branches, or changed files to discard after `--`
+fn checkout_handler(ctx: &GitCtx) -> Vec<Completion> {
+    if ctx.after_double_dash() {
+        return default_format(git::modified_files().unwrap_or_default());
+    }
+    default_format(ctx.branches())
+}
+
+fn ref_handler(ctx: &GitCtx) -> Vec<Completion> {
+    default_format(ctx.refs())
+}
+
+/// Refs, or changed files after `--`
+fn ref_or_path_handler(ctx: &GitCtx) -> Vec<Completion> {
+    if ctx.after_double_dash() {
+        return default_format(git::modified_files().unwrap_or_default());
+    }
+    default_format(ctx.refs())
+}
+
+/// Remote as the first argument, followed by branches
+fn remote_handler(ctx: &GitCtx) -> Vec<Completion> {
+    if ctx.positional_args().is_empty() {
+        return default_format(ctx.remotes());
+    }
+    default_format(ctx.branches())
+}
+
+/// Remote name for `git remote` operations that act on an existing remote
+fn remote_operation_handler(ctx: &GitCtx) -> Vec<Completion> {
+    match ctx.positional_args().as_slice() {
+        [op] if REMOTE_OPERATIONS.contains(&op.as_str()) => default_format(ctx.remotes()),
+        _ => vec![],
+    }
+}
+
+/// Staged files with `--staged`, otherwise files with unstaged changes
+fn restore_handler(ctx: &GitCtx) -> Vec<Completion> {
+    let files = if ctx.has_flag("--staged") || ctx.has_flag("-S") {
+        git::staged_files()
+    } else {
+        git::modified_files()
+    };
+    default_format(files.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use shrs::prelude::CompletionCtx;
+
+    use super::{git_subcommand_arg_pred, GitCtx};
+
+    fn ctx(line: &[&str]) -> CompletionCtx {
+        CompletionCtx::new(line.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn subcommand_args() {
+        assert!(git_subcommand_arg_pred(&ctx(&["git", "checkout", "ma"])));
+        assert!(!git_subcommand_arg_pred(&ctx(&["git", "checkout", "-"])));
+        assert!(!git_subcommand_arg_pred(&ctx(&["git", "che"])));
+        assert!(!git_subcommand_arg_pred(&ctx(&["make", "checkout", "ma"])));
+
+        let cache = Rc::new(RefCell::new(None));
+        let line = ctx(&["git", "push", "-f", "origin", "--", "ma"]);
+        let git_ctx = GitCtx {
+            ctx: &line,
+            cache: &cache,
+        };
+        assert_eq!(git_ctx.subcommand(), "push");
+        assert_eq!(git_ctx.positional_args(), vec!["origin"]);
+        assert!(git_ctx.has_flag("-f"));
+        assert!(git_ctx.after_double_dash());
+    }
+}
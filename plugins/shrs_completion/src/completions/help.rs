@@ -0,0 +1,312 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    process::{Command, Stdio},
+    rc::Rc,
+    time::SystemTime,
+};
+
+use shrs::{anyhow, prelude::*};
+
+/// Subcommands and flags listed in the help output of a command
+#[derive(Default, Debug, PartialEq)]
+struct HelpPage {
+    subcommands: Vec<(String, String)>,
+    flags: Vec<(String, String)>,
+}
+
+/// Parsed help pages of every (sub)command that was completed so far
+#[derive(Default)]
+struct HelpCache {
+    /// Version of each tool, refreshed when its executable is modified
+    versions: HashMap<String, (Option<SystemTime>, String)>,
+    /// Help pages keyed by tool version and the path of subcommands
+    pages: HashMap<(String, Vec<String>), Rc<HelpPage>>,
+}
+
+impl HelpCache {
+    fn version(&mut self, cmd: &str) -> String {
+        let mtime = executable_path(cmd)
+            .and_then(|path| path.metadata().ok())
+            .and_then(|m| m.modified().ok());
+        match self.versions.get(cmd) {
+            Some((cached_mtime, version)) if *cached_mtime == mtime => version.clone(),
+            _ => {
+                let version = run_help(cmd, &["--version"])
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                self.versions
+                    .insert(cmd.to_string(), (mtime, version.clone()));
+                version
+            },
+        }
+    }
+
+    /// Help page of a subcommand, `path` starts with the name of the tool
+    fn page(&mut self, path: &[String]) -> Rc<HelpPage> {
+        let key = (self.version(&path[0]), path.to_vec());
+        self.pages
+            .entry(key)
+            .or_insert_with(|| Rc::new(load_help_page(path)))
+            .clone()
+    }
+}
+
+/// Completions for tools with many nested subcommands, learned by parsing their help output
+///
+/// Completing `docker container <tab>` runs `docker container --help` (or `docker help container`
+/// if that doesn't list anything) and offers the subcommands and flags it lists. Help pages are
+/// parsed lazily and cached per tool version.
+///
+/// The completions are only used as a fallback when no other rule matches, and only for the
+/// commands registered with [HelpCompletionPlugin::with_command].
+#[derive(Default)]
+pub struct HelpCompletionPlugin {
+    commands: HashSet<String>,
+}
+
+impl HelpCompletionPlugin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Complete a command by parsing its help output
+    pub fn with_command(mut self, cmd: &str) -> Self {
+        self.commands.insert(cmd.to_string());
+        self
+    }
+}
+
+impl Plugin for HelpCompletionPlugin {
+    fn init(&self, _shell: &mut ShellConfig) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn post_init(&self, _sh: &mut Shell, states: &mut States) -> anyhow::Result<()> {
+        let Ok(mut completer) = states.try_get_mut::<Box<dyn Completer>>() else {
+            return Err(anyhow::anyhow!("no completer registered"));
+        };
+
+        let commands = self.commands.clone();
+        let cache = RefCell::new(HelpCache::default());
+        completer.register(Rule::fallback(
+            Pred::new(move |ctx: &CompletionCtx| {
+                ctx.arg_num() >= 1 && ctx.cmd_name().is_some_and(|cmd| commands.contains(cmd))
+            }),
+            move |ctx: &CompletionCtx| help_action(&mut cache.borrow_mut(), ctx),
+        ));
+
+        Ok(())
+    }
+
+    fn meta(&self) -> PluginMeta {
+        PluginMeta::new(
+            "Help Completion",
+            "Completes subcommands and flags by parsing the help output of commands",
+            None,
+        )
+    }
+}
+
+fn help_action(cache: &mut HelpCache, ctx: &CompletionCtx) -> Vec<Completion> {
+    // walk down the subcommands typed so far, not including the word currently being typed
+    let mut path = vec![ctx.line[0].clone()];
+    let mut page = cache.page(&path);
+    for word in &ctx.line[1..ctx.line.len() - 1] {
+        if word.starts_with('-') {
+            continue;
+        }
+        if !page.subcommands.iter().any(|(name, _)| name == word) {
+            break;
+        }
+        path.push(word.clone());
+        page = cache.page(&path);
+    }
+
+    match flag_pred(ctx) {
+        true => default_format_with_comment(page.flags.clone()),
+        false => default_format_with_comment(page.subcommands.clone()),
+    }
+}
+
+/// Find the executable that would be ran for a command
+fn executable_path(cmd: &str) -> Option<PathBuf> {
+    let path = std::env::var("PATH").ok()?;
+    path.split(':')
+        .map(|dir| PathBuf::from(dir).join(cmd))
+        .find(|path| path.is_file())
+}
+
+/// Run a command and collect both stdout and stderr, since some tools print help to stderr
+fn run_help(cmd: &str, args: &[&str]) -> String {
+    let Ok(output) = Command::new(cmd).args(args).stdin(Stdio::null()).output() else {
+        return String::new();
+    };
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    text
+}
+
+fn load_help_page(path: &[String]) -> HelpPage {
+    let (cmd, subcommands) = (&path[0], &path[1..]);
+
+    let mut args = subcommands.iter().map(String::as_str).collect::<Vec<_>>();
+    args.push("--help");
+    let page = parse_help(&run_help(cmd, &args));
+    if page != HelpPage::default() {
+        return page;
+    }
+
+    let mut args = vec!["help"];
+    args.extend(subcommands.iter().map(String::as_str));
+    parse_help(&run_help(cmd, &args))
+}
+
+/// Extract subcommands and flags from help output
+///
+/// Subcommands are the entries of sections with a header containing `command`, such as
+/// `Commands:` or `Management Commands:`. Flags are any indented lines starting with `-`.
+fn parse_help(text: &str) -> HelpPage {
+    let mut page = HelpPage::default();
+    let mut in_commands = false;
+    // indentation of entries in the current section, deeper lines are wrapped descriptions
+    let mut entry_indent = None;
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        if indent == 0 {
+            let header = line.trim_end();
+            in_commands = header.ends_with(':') && header.to_lowercase().contains("command");
+            entry_indent = None;
+            continue;
+        }
+
+        let (spec, desc) = split_entry(line.trim());
+        if spec.starts_with('-') {
+            page.flags.extend(
+                spec.split([',', ' '])
+                    .filter_map(flag_name)
+                    .map(|flag| (flag, desc.clone())),
+            );
+            continue;
+        }
+
+        if !in_commands || *entry_indent.get_or_insert(indent) != indent {
+            continue;
+        }
+        page.subcommands.extend(
+            spec.split(',')
+                .filter_map(|name| {
+                    let name = name.split_whitespace().next()?.trim_end_matches('*');
+                    let valid = name.starts_with(|c: char| c.is_ascii_alphanumeric())
+                        && name
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+                    valid.then(|| name.to_string())
+                })
+                .map(|name| (name, desc.clone())),
+        );
+    }
+
+    page
+}
+
+/// Split an entry into its name and description, which are separated by at least two spaces
+fn split_entry(entry: &str) -> (&str, String) {
+    let end = [entry.find("  "), entry.find('\t')]
+        .into_iter()
+        .flatten()
+        .min();
+    match end {
+        Some(end) => (&entry[..end], entry[end..].trim().to_string()),
+        None => (entry, String::new()),
+    }
+}
+
+/// Flag name without the value placeholder, for example `--file=FILE` is `--file`
+fn flag_name(token: &str) -> Option<String> {
+    let name = token.split(['=', ':', '[', '<']).next().unwrap_or_default();
+    (name.starts_with('-') && !name.trim_start_matches('-').is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_help;
+
+    fn names(entries: &[(String, String)]) -> Vec<&str> {
+        entries.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn docker_help() {
+        let help = r#"
+Usage:  docker [OPTIONS] COMMAND
+
+A self-sufficient runtime for containers
+
+Common Commands:
+  run         Create and run a new container from an image
+  exec        Execute a command in a running container
+
+Management Commands:
+  buildx*     Docker Buildx
+  container   Manage containers
+              that wrap onto another line
+
+Global Options:
+      --config string      Location of client config files
+  -D, --debug              Enable debug mode
+"#;
+        let page = parse_help(help);
+        assert_eq!(
+            names(&page.subcommands),
+            vec!["run", "exec", "buildx", "container"]
+        );
+        assert_eq!(page.subcommands[3].1, "Manage containers");
+        assert_eq!(names(&page.flags), vec!["--config", "-D", "--debug"]);
+        assert_eq!(page.flags[0].1, "Location of client config files");
+    }
+
+    #[test]
+    fn cargo_help() {
+        let help = r#"Rust's package manager
+
+Usage: cargo [+toolchain] [OPTIONS] [COMMAND]
+
+Options:
+  -V, --version                  Print version info and exit
+      --color <WHEN>             Coloring: auto, always, never
+
+Commands:
+    build, b    Compile the current package
+    test, t     Run the tests
+    ...         See all commands with --list
+"#;
+        let page = parse_help(help);
+        assert_eq!(names(&page.subcommands), vec!["build", "b", "test", "t"]);
+        assert_eq!(names(&page.flags), vec!["-V", "--version", "--color"]);
+    }
+
+    #[test]
+    fn kubectl_help() {
+        let help = r#"kubectl controls the Kubernetes cluster manager.
+
+Basic Commands (Beginner):
+  create          Create a resource from a file or from stdin
+  expose          Take a replication controller, service, deployment or pod and expose it
+
+Options:
+    -A, --all-namespaces=false:
+	If present, list the requested object(s) across all namespaces.
+"#;
+        let page = parse_help(help);
+        assert_eq!(names(&page.subcommands), vec!["create", "expose"]);
+        assert_eq!(names(&page.flags), vec!["-A", "--all-namespaces"]);
+    }
+}
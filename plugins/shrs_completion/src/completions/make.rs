@@ -0,0 +1,193 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use shrs::prelude::{cmdname_eq_pred, default_format, Completion, CompletionCtx, Pred, Rule};
+
+/// Makefile names that make looks for, in the order it looks for them
+const MAKEFILE_NAMES: [&str; 3] = ["GNUmakefile", "makefile", "Makefile"];
+
+/// Targets parsed from a makefile, along with every file that was read to produce them
+struct CachedTargets {
+    sources: Vec<(PathBuf, Option<SystemTime>)>,
+    targets: Vec<String>,
+}
+
+impl CachedTargets {
+    /// Cache is stale once any of the makefiles it was built from was modified
+    fn is_valid(&self) -> bool {
+        self.sources
+            .iter()
+            .all(|(path, mtime)| modified_time(path) == *mtime)
+    }
+}
+
+/// Complete target names of the makefile in the working directory
+///
+/// Parsed targets are cached per makefile and reparsed when the makefile (or any makefile it
+/// includes) is modified.
+pub fn make_rule() -> Rule {
+    let cache: RefCell<HashMap<PathBuf, CachedTargets>> = RefCell::new(HashMap::new());
+    Rule::new(Pred::new(make_pred), move |ctx: &CompletionCtx| {
+        make_targets_action(&cache, ctx)
+    })
+}
+
+fn make_pred(ctx: &CompletionCtx) -> bool {
+    cmdname_eq_pred("make".into())(ctx)
+}
+
+fn make_targets_action(
+    cache: &RefCell<HashMap<PathBuf, CachedTargets>>,
+    ctx: &CompletionCtx,
+) -> Vec<Completion> {
+    let Some(makefile) = find_makefile(ctx) else {
+        return vec![];
+    };
+
+    let mut cache = cache.borrow_mut();
+    let cached = cache
+        .entry(makefile.clone())
+        .or_insert_with(|| parse_makefile(&makefile));
+    if !cached.is_valid() {
+        *cached = parse_makefile(&makefile);
+    }
+
+    default_format(cached.targets.clone())
+}
+
+/// Find the makefile to complete from, respecting `-f` if it was passed
+fn find_makefile(ctx: &CompletionCtx) -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+
+    // don't consider the word currently being typed
+    let args = &ctx.line[..ctx.line.len().saturating_sub(1)];
+    let explicit = args
+        .windows(2)
+        .rev()
+        .find(|w| w[0] == "-f" || w[0] == "--file" || w[0] == "--makefile")
+        .map(|w| w[1].clone());
+
+    match explicit {
+        Some(file) => Some(cwd.join(file)),
+        None => MAKEFILE_NAMES
+            .iter()
+            .map(|name| cwd.join(name))
+            .find(|path| path.is_file()),
+    }
+}
+
+/// Read a makefile and any makefiles it includes
+fn parse_makefile(makefile: &Path) -> CachedTargets {
+    let mut sources = vec![];
+    let mut targets = vec![];
+    let mut visited = HashSet::new();
+    let mut pending = vec![makefile.to_path_buf()];
+
+    while let Some(path) = pending.pop() {
+        if !visited.insert(path.clone()) {
+            continue;
+        }
+        sources.push((path.clone(), modified_time(&path)));
+
+        // included makefiles are best effort, they may not exist or be generated by the build
+        let Ok(contents) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let (mut file_targets, includes) = parse_targets(&contents);
+        targets.append(&mut file_targets);
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        pending.extend(includes.iter().map(|include| dir.join(include)));
+    }
+
+    let mut seen = HashSet::new();
+    targets.retain(|t| seen.insert(t.clone()));
+    targets.sort();
+
+    CachedTargets { sources, targets }
+}
+
+/// Extract the target names and included files from the contents of a makefile
+fn parse_targets(contents: &str) -> (Vec<String>, Vec<String>) {
+    let mut targets = vec![];
+    let mut includes = vec![];
+
+    for line in contents.lines() {
+        // recipes and indented lines never define targets
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let line = line.split('#').next().unwrap_or_default();
+
+        if let Some(files) = ["include ", "-include ", "sinclude "]
+            .iter()
+            .find_map(|directive| line.strip_prefix(directive))
+        {
+            includes.extend(
+                files
+                    .split_whitespace()
+                    .filter(|f| !f.contains('$'))
+                    .map(|f| f.to_string()),
+            );
+            continue;
+        }
+
+        let Some((names, rest)) = line.split_once(':') else {
+            continue;
+        };
+        // `:=` and `::=` are assignments, not rules
+        if rest.starts_with('=') || rest.starts_with(":=") || names.contains('=') {
+            continue;
+        }
+
+        targets.extend(
+            names
+                .split_whitespace()
+                // skip special targets (.PHONY), pattern rules and computed names
+                .filter(|t| !t.starts_with('.') && !t.contains('%') && !t.contains('$'))
+                .map(|t| t.to_string()),
+        );
+    }
+
+    (targets, includes)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_targets;
+
+    #[test]
+    fn parse_makefile_targets() {
+        let contents = r#"
+CC := gcc
+CFLAGS = -Wall
+.PHONY: all clean
+include config.mk
+-include $(DEPS) local.mk
+
+all: main # build everything
+	$(CC) $(CFLAGS) -o main main.c
+
+%.o: %.c
+	$(CC) -c $<
+
+install uninstall:
+	echo done
+
+clean::
+	rm -f main
+"#;
+        let (targets, includes) = parse_targets(contents);
+        assert_eq!(targets, vec!["all", "install", "uninstall", "clean"]);
+        assert_eq!(includes, vec!["config.mk", "local.mk"]);
+    }
+}
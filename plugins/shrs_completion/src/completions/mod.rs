@@ -1,5 +1,13 @@
 //! Completion scripts organized by the executable
 
+mod cargo;
+mod git;
+mod help;
+mod make;
 mod ssh;
 
+pub use cargo::cargo_rule;
+pub use git::{GitCompletionPlugin, GitCtx, GitHandler};
+pub use help::HelpCompletionPlugin;
+pub use make::make_rule;
 pub use ssh::ssh_rule;
@@ -29,6 +29,7 @@ fn known_hosts_action(_ctx: &CompletionCtx) -> Vec<Completion> {
                     completion: pat.pattern.clone(),
                     replace_method: ReplaceMethod::Append,
                     comment: None,
+                    description: None,
                 })
                 .collect::<Vec<_>>()
         })
@@ -1,32 +1,53 @@
 use std::{
     collections::HashSet,
     fs::File,
-    io::{BufRead, BufReader, BufWriter, Write},
-    path::PathBuf,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
 };
 
 use shrs::{
     anyhow::Result,
     plugin::Plugin,
-    prelude::{History, States},
+    prelude::{ExitCtx, History, StateMut, States},
     shell::{Shell, ShellConfig},
 };
 use thiserror::Error;
 
+/// When new history entries are written to the history file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlushMode {
+    /// Append each command to the history file right after it is entered
+    ///
+    /// History survives crashes and is shared live between shells that use the same file.
+    #[default]
+    EachCommand,
+    /// Append the commands entered in this session when the shell exits, causing fewer writes
+    OnExit,
+}
+
 /// Store the history persistently in a file on disk
 ///
-/// History file is a very simple file consistaning of each history item on it's own line
+/// History file is a very simple file consisting of each history item on it's own line, from
+/// oldest to newest. New entries are only ever appended to the file, so multiple shells can share
+/// the same history file without overwriting each other's history. Before history is read, lines
+/// appended by other shells since the last read are merged in with [FileBackedHistoryState::sync].
+///
+/// Older versions wrote the file from newest to oldest without a newline after the last entry,
+/// such files are converted when they are opened.
 // TODO potential options
 // - history len
-// - remove duplicates
 // - only use valid commands
 // - resolve alias
 pub struct FileBackedHistoryState {
+    /// History entries, most recent first
     hist: Vec<String>,
     hist_file: PathBuf,
+    flush_mode: FlushMode,
+    /// Entries that have not been written to the history file yet
+    pending: Vec<String>,
+    /// Number of bytes of the history file that were already merged into `hist`
+    offset: u64,
     // config options
-    // /// Don't keep duplicate history values
-    // dedup: bool,
     // /// Max length of history to keep
     // max_length: usize,
 }
@@ -41,94 +62,334 @@ pub enum FileBackedHistoryError {
 
 impl FileBackedHistoryState {
     pub fn new(hist_file: PathBuf) -> Result<Self, FileBackedHistoryError> {
-        let hist = parse_history_file(hist_file.clone())?;
-        Ok(FileBackedHistoryState { hist, hist_file })
+        Self::with_flush_mode(hist_file, FlushMode::default())
+    }
+
+    pub fn with_flush_mode(
+        hist_file: PathBuf,
+        flush_mode: FlushMode,
+    ) -> Result<Self, FileBackedHistoryError> {
+        File::options()
+            .append(true)
+            .create(true)
+            .open(&hist_file)
+            .map_err(FileBackedHistoryError::OpeningHistFile)?;
+        convert_newest_first(&hist_file)?;
+
+        let mut state = FileBackedHistoryState {
+            hist: vec![],
+            hist_file,
+            flush_mode,
+            pending: vec![],
+            offset: 0,
+        };
+        state.sync()?;
+        Ok(state)
+    }
+
+    /// Merge in the entries appended to the history file since it was last read
+    ///
+    /// New entries are ordered the way they appear in the file, which is the order the shells
+    /// wrote them in. Entries of this shell that were not flushed yet stay the most recent.
+    pub fn sync(&mut self) -> Result<(), FileBackedHistoryError> {
+        let mut handle =
+            File::open(&self.hist_file).map_err(FileBackedHistoryError::OpeningHistFile)?;
+        let len = handle
+            .metadata()
+            .map_err(FileBackedHistoryError::OpeningHistFile)?
+            .len();
+
+        // file shrunk, another shell cleared the history
+        if len < self.offset {
+            self.hist.clear();
+            self.offset = 0;
+        }
+        if len == self.offset {
+            return Ok(());
+        }
+
+        let mut contents = vec![];
+        handle
+            .seek(SeekFrom::Start(self.offset))
+            .and_then(|_| handle.read_to_end(&mut contents))
+            .map_err(FileBackedHistoryError::OpeningHistFile)?;
+
+        // leave a partially written last line for the next sync
+        let Some(end) = contents.iter().rposition(|b| *b == b'\n') else {
+            return Ok(());
+        };
+        self.offset += end as u64 + 1;
+
+        // rebuild newest first, keeping only the most recent copy of each command
+        let new_entries = String::from_utf8_lossy(&contents[..end]);
+        let old_hist = std::mem::take(&mut self.hist);
+        let mut seen = HashSet::new();
+        self.hist = self
+            .pending
+            .iter()
+            .rev()
+            .map(String::as_str)
+            .chain(new_entries.lines().rev())
+            .filter(|cmd| !cmd.is_empty())
+            .map(str::to_string)
+            .chain(old_hist)
+            .filter(|cmd| seen.insert(cmd.clone()))
+            .collect();
+        Ok(())
     }
 
-    fn flush(&mut self) -> Result<(), FileBackedHistoryError> {
-        // TODO not efficient to dedup every step
-        self.dedup();
+    /// Insert a new entry, writing it to the history file if using [FlushMode::EachCommand]
+    pub fn add(&mut self, cmd: String) -> Result<(), FileBackedHistoryError> {
+        self.hist.retain(|entry| *entry != cmd);
+        self.hist.insert(0, cmd.clone());
+        self.pending.push(cmd);
+
+        match self.flush_mode {
+            FlushMode::EachCommand => self.flush(),
+            FlushMode::OnExit => Ok(()),
+        }
+    }
+
+    /// Remove every entry, including the ones already written to the history file
+    pub fn clear(&mut self) -> Result<(), FileBackedHistoryError> {
+        self.hist.clear();
+        self.pending.clear();
+        self.offset = 0;
+        File::create(&self.hist_file).map_err(FileBackedHistoryError::OpeningHistFile)?;
+        Ok(())
+    }
+
+    /// Append the entries that were not written yet to the end of the history file
+    pub fn flush(&mut self) -> Result<(), FileBackedHistoryError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
         // TODO consider keeping handle to history file open the entire time
-        let handle = File::options()
-            .write(true)
+        let mut handle = File::options()
+            .append(true)
+            .create(true)
             .open(&self.hist_file)
             .map_err(FileBackedHistoryError::OpeningHistFile)?;
-        let mut writer = BufWriter::new(handle);
-        writer
-            .write_all(self.hist.join("\n").as_bytes())
+
+        // write everything at once, appends of a single write don't interleave with writes from
+        // other shells
+        let mut contents = String::new();
+        for cmd in self.pending.drain(..) {
+            contents.push_str(&cmd);
+            contents.push('\n');
+        }
+        let start = handle
+            .metadata()
+            .map_err(FileBackedHistoryError::Flush)?
+            .len();
+        handle
+            .write_all(contents.as_bytes())
             .map_err(FileBackedHistoryError::Flush)?;
-        writer.flush().map_err(FileBackedHistoryError::Flush)?;
+
+        // skip reading back our own entries, unless other shells wrote before them in which case
+        // the next sync merges everything in file order
+        if start == self.offset {
+            self.offset += contents.len() as u64;
+        }
         Ok(())
     }
+}
 
-    /// Remove duplicate entries
-    fn dedup(&mut self) {
-        let mut uniques = HashSet::new();
-        self.hist.retain(|x| uniques.insert(x.clone()));
+/// Rewrite a history file written from newest to oldest entry by older versions from oldest to
+/// newest
+///
+/// Entries are always appended with a newline, so a file that doesn't end with one is in the old
+/// format.
+fn convert_newest_first(hist_file: &Path) -> Result<(), FileBackedHistoryError> {
+    let contents =
+        std::fs::read_to_string(hist_file).map_err(FileBackedHistoryError::OpeningHistFile)?;
+    if contents.is_empty() || contents.ends_with('\n') {
+        return Ok(());
     }
+
+    let mut converted = String::new();
+    for cmd in contents.lines().rev().filter(|cmd| !cmd.is_empty()) {
+        converted.push_str(cmd);
+        converted.push('\n');
+    }
+    std::fs::write(hist_file, converted).map_err(FileBackedHistoryError::Flush)
+}
+
+pub struct FileBackedHistoryPlugin {
+    flush_mode: FlushMode,
 }
 
-pub struct FileBackedHistoryPlugin {}
 impl FileBackedHistoryPlugin {
     pub fn new() -> Self {
-        FileBackedHistoryPlugin {}
+        FileBackedHistoryPlugin {
+            flush_mode: FlushMode::default(),
+        }
+    }
+
+    /// Choose when history is written to the history file
+    pub fn with_flush_mode(mut self, flush_mode: FlushMode) -> Self {
+        self.flush_mode = flush_mode;
+        self
+    }
+}
+
+impl Default for FileBackedHistoryPlugin {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Plugin for FileBackedHistoryPlugin {
     fn init(&self, shell: &mut ShellConfig) -> Result<()> {
-        shell.states.insert(FileBackedHistoryState::new(
+        shell.states.insert(FileBackedHistoryState::with_flush_mode(
             shell.config_dir.join("history"),
+            self.flush_mode,
         )?);
+        shell.hooks.insert(flush_on_exit_hook);
 
         shell.history = Box::new(FileBackedHistory {});
         Ok(())
     }
 }
 
+fn flush_on_exit_hook(
+    mut state: StateMut<FileBackedHistoryState>,
+    _sh: &Shell,
+    _ctx: &ExitCtx,
+) -> Result<()> {
+    state.flush()?;
+    Ok(())
+}
+
 pub struct FileBackedHistory;
 
 impl History for FileBackedHistory {
     fn add(&self, _sh: &Shell, states: &States, cmd: String) {
         let mut state = states.get_mut::<FileBackedHistoryState>();
         if !cmd.starts_with("history run") {
-            state.hist.insert(0, cmd);
-            // TODO consider how often we want to flush
-            state.flush().unwrap();
+            state.add(cmd).unwrap();
         }
     }
 
     fn clear(&self, _sh: &Shell, states: &States) {
         let mut state = states.get_mut::<FileBackedHistoryState>();
-
-        state.hist.clear();
-        state.flush().unwrap();
+        state.clear().unwrap();
     }
 
     fn len(&self, _sh: &Shell, states: &States) -> usize {
-        let state = states.get_mut::<FileBackedHistoryState>();
+        let mut state = states.get_mut::<FileBackedHistoryState>();
+        // failing to read new entries from other shells shouldn't prevent using our own history
+        let _ = state.sync();
 
         state.hist.len()
     }
 
+    // doesn't sync so indices stay the same as when len was called
     fn get(&self, _sh: &Shell, states: &States, i: usize) -> Option<String> {
         let state = states.get_mut::<FileBackedHistoryState>();
         state.hist.get(i).cloned()
     }
     fn items(&self, _sh: &Shell, states: &States) -> Vec<String> {
-        states.get_mut::<FileBackedHistoryState>().hist.clone()
+        let mut state = states.get_mut::<FileBackedHistoryState>();
+        let _ = state.sync();
+        state.hist.clone()
     }
 }
 
-fn parse_history_file(hist_file: PathBuf) -> Result<Vec<String>, FileBackedHistoryError> {
-    let handle = File::options()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(hist_file)
-        .map_err(FileBackedHistoryError::OpeningHistFile)?;
-    let reader = BufReader::new(handle);
-    // TODO should error/terminate when a line cannot be read?
-    let hist = reader.lines().map_while(Result::ok).collect::<Vec<_>>();
-    Ok(hist)
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::{FileBackedHistoryState, FlushMode};
+
+    #[test]
+    fn flush_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        let hist_file = dir.path().join("history");
+        std::fs::write(&hist_file, "ls\ncd\n").unwrap();
+
+        let mut each = FileBackedHistoryState::new(hist_file.clone()).unwrap();
+        assert_eq!(each.hist, vec!["cd", "ls"]);
+        let mut on_exit =
+            FileBackedHistoryState::with_flush_mode(hist_file.clone(), FlushMode::OnExit).unwrap();
+
+        // both shells append without overwriting each other
+        each.add("echo a".into()).unwrap();
+        on_exit.add("echo b".into()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&hist_file).unwrap(),
+            "ls\ncd\necho a\n"
+        );
+        on_exit.flush().unwrap();
+        each.add("ls".into()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&hist_file).unwrap(),
+            "ls\ncd\necho a\necho b\nls\n"
+        );
+
+        let reloaded = FileBackedHistoryState::new(hist_file.clone()).unwrap();
+        assert_eq!(reloaded.hist, vec!["ls", "echo b", "echo a", "cd"]);
+    }
+
+    #[test]
+    fn sync_between_shells() {
+        let dir = tempfile::tempdir().unwrap();
+        let hist_file = dir.path().join("history");
+        std::fs::write(&hist_file, "ls\n").unwrap();
+
+        let mut a = FileBackedHistoryState::new(hist_file.clone()).unwrap();
+        let mut b = FileBackedHistoryState::new(hist_file.clone()).unwrap();
+
+        a.add("echo a".into()).unwrap();
+        b.add("echo b".into()).unwrap();
+        a.add("ls".into()).unwrap();
+
+        // entries are interleaved in the order they were written, without duplicates
+        a.sync().unwrap();
+        b.sync().unwrap();
+        assert_eq!(a.hist, vec!["ls", "echo b", "echo a"]);
+        assert_eq!(b.hist, a.hist);
+
+        // partially written lines are picked up once they are complete
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&hist_file)
+            .and_then(|mut f| f.write_all(b"make"))
+            .unwrap();
+        b.sync().unwrap();
+        assert_eq!(b.hist[0], "ls");
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&hist_file)
+            .and_then(|mut f| f.write_all(b" test\n"))
+            .unwrap();
+        b.sync().unwrap();
+        assert_eq!(b.hist[0], "make test");
+
+        // clearing in one shell clears the other
+        a.clear().unwrap();
+        b.sync().unwrap();
+        assert!(b.hist.is_empty());
+    }
+
+    #[test]
+    fn newest_first_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let hist_file = dir.path().join("history");
+        // written by older versions
+        std::fs::write(&hist_file, "ls\ncd\necho a").unwrap();
+
+        let mut state = FileBackedHistoryState::new(hist_file.clone()).unwrap();
+        assert_eq!(state.hist, vec!["ls", "cd", "echo a"]);
+        state.add("make".into()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&hist_file).unwrap(),
+            "echo a\ncd\nls\nmake\n"
+        );
+
+        // files in the current format are left alone
+        let reloaded = FileBackedHistoryState::new(hist_file.clone()).unwrap();
+        assert_eq!(reloaded.hist, vec!["make", "ls", "cd", "echo a"]);
+    }
 }
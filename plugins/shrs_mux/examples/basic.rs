@@ -1,7 +1,7 @@
 use std::path::Path;
 
 use shrs::prelude::*;
-use shrs_mux::{python::*, BashLang, MuxPlugin, NuLang, SqliteLang};
+use shrs_mux::{python::*, BashLang, ExternalLang, MuxPlugin, NuLang, SqliteLang};
 
 fn main() {
     // the remote supplied here currently does not support interactive passwords, use an ssh key
@@ -12,6 +12,7 @@ fn main() {
         .register_lang("bash", BashLang::new())
         .register_lang("python", PythonLang::new())
         .register_lang("nu", NuLang::new())
+        .register_lang("node", ExternalLang::new("node", ["node", "-e", "{}"]))
         // .register_lang("ssh", SshLang::new(ssh_remote))
         .register_lang("sqlite", SqliteLang::new(Path::new("/tmp/test.sqlite")));
 
@@ -0,0 +1,119 @@
+use std::process::{Command, Stdio};
+
+use shrs::prelude::*;
+
+/// Placeholder in the command template that is replaced with the command to evaluate
+const PLACEHOLDER: &str = "{}";
+
+/// Evaluates commands with an external interpreter
+///
+/// The interpreter is ran once per command using a template like `["python3", "-c", "{}"]`, where
+/// `{}` is replaced with the command. If no argument contains `{}`, the command is written to the
+/// interpreter's stdin instead. The interpreter's exit status becomes the status of the command.
+pub struct ExternalLang {
+    name: String,
+    template: Vec<String>,
+}
+
+impl ExternalLang {
+    pub fn new<S: ToString>(name: &str, template: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            name: name.to_string(),
+            template: template.into_iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// Build the command line for the interpreter, returns if the command should be piped to stdin
+    fn interpreter_args(&self, cmd: &str) -> (Vec<String>, bool) {
+        let use_stdin = !self.template.iter().any(|arg| arg.contains(PLACEHOLDER));
+        let args = self
+            .template
+            .iter()
+            .map(|arg| arg.replace(PLACEHOLDER, cmd))
+            .collect();
+        (args, use_stdin)
+    }
+}
+
+impl Lang for ExternalLang {
+    fn eval(&self, _sh: &Shell, states: &States, cmd: String) -> shrs::anyhow::Result<CmdOutput> {
+        let (args, use_stdin) = self.interpreter_args(&cmd);
+        let Some((program, args)) = args.split_first() else {
+            return Err(shrs::anyhow::anyhow!("empty interpreter command"));
+        };
+
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdin(if use_stdin {
+                Stdio::piped()
+            } else {
+                Stdio::inherit()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Ok(rt) = states.try_get::<Runtime>() {
+            command.current_dir(&rt.working_dir).envs(rt.env.iter());
+        }
+
+        let mut child = command.spawn()?;
+        if use_stdin {
+            use std::io::Write;
+
+            // dropping stdin closes it so the interpreter knows the input ended
+            let mut stdin = child.stdin.take().expect("Failed to open stdin");
+            stdin.write_all(cmd.as_bytes())?;
+            stdin.write_all(b"\n")?;
+        }
+        let output = child.wait_with_output()?;
+
+        // printing through the output writer also captures it into the command output
+        if let Ok(mut out) = states.try_get_mut::<OutputWriter>() {
+            out.print_bytes(&output.stdout)?;
+            out.eprint_bytes(&output.stderr)?;
+        }
+
+        Ok(CmdOutput::from_status(exit_code(output.status)))
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn needs_line_check(&self, _shell: &Shell, _ctx: &States) -> bool {
+        false
+    }
+}
+
+/// Exit code of the interpreter, following the shell convention of 128 + signal if it was killed
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExternalLang;
+
+    #[test]
+    fn template_args() {
+        let lang = ExternalLang::new("python", ["python3", "-c", "{}"]);
+        assert_eq!(
+            lang.interpreter_args("print(1)"),
+            (
+                vec!["python3".into(), "-c".into(), "print(1)".into()],
+                false
+            )
+        );
+
+        let lang = ExternalLang::new("node", ["node"]);
+        assert_eq!(
+            lang.interpreter_args("1 + 1"),
+            (vec!["node".to_string()], true)
+        );
+    }
+}
@@ -1,6 +1,7 @@
 mod bash;
+mod external;
 mod nu;
 mod sqlite;
 mod ssh;
 
-pub use self::{bash::*, nu::*, sqlite::*, ssh::*};
+pub use self::{bash::*, external::*, nu::*, sqlite::*, ssh::*};
@@ -9,7 +9,7 @@ use std::{cell::RefCell, collections::HashMap, rc::Rc};
 use anyhow::anyhow;
 use builtin::mux_builtin;
 pub use highlighter::MuxHighlighter;
-pub use lang::{BashLang, NuLang, SqliteLang, SshLang};
+pub use lang::{BashLang, ExternalLang, NuLang, SqliteLang, SshLang};
 use shrs::{prelude::*, readline::highlight::ShrsTheme};
 
 pub struct MuxState {
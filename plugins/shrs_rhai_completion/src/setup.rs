@@ -9,6 +9,7 @@ fn default_completion(completion: String) -> Completion {
         completion,
         replace_method: ReplaceMethod::Replace,
         comment: None,
+        description: None,
     }
 }
 
@@ -19,6 +20,7 @@ fn default_completion_with_comment(completion: String, comment: String) -> Compl
         completion,
         replace_method: ReplaceMethod::Replace,
         comment: Some(comment),
+        description: None,
     }
 }
 
@@ -43,6 +45,7 @@ fn new_completion(
         } else {
             Some(comment.into_string().unwrap())
         },
+        description: None,
     }
 }
 
@@ -91,6 +94,7 @@ fn with_format(arr: Array, add_space: bool, replace_method: ReplaceMethod) -> Ar
                     completion: s.clone().cast(),
                     replace_method,
                     comment: None,
+                    description: None,
                 }
             } else if s.is::<Completion>() {
                 s.clone_cast::<Completion>()
@@ -102,6 +106,7 @@ fn with_format(arr: Array, add_space: bool, replace_method: ReplaceMethod) -> Ar
                     completion: c.first().unwrap().into(),
                     replace_method,
                     comment: Some(c.last().unwrap().into()),
+                    description: None,
                 }
             } else {
                 panic!("Incorrect type {}", s.type_name());
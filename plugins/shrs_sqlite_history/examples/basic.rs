@@ -0,0 +1,11 @@
+use shrs::shell::ShellBuilder;
+use shrs_sqlite_history::SqliteHistoryPlugin;
+
+fn main() {
+    let myshell = ShellBuilder::default()
+        .with_plugin(SqliteHistoryPlugin::new())
+        .build()
+        .unwrap();
+
+    myshell.run().expect("Error when running shell");
+}
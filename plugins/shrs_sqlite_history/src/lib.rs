@@ -0,0 +1,439 @@
+//! SQLite backed history with command metadata
+//!
+//! Each history entry stores the directory the command was ran in, its exit status, how long it
+//! took, the hostname and the time it was ran. Entries can be queried with
+//! [SqliteHistoryState::search].
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use rusqlite::{params, params_from_iter, types::Value, Connection};
+use shrs::{
+    anyhow,
+    prelude::{AfterCommandCtx, History, Plugin, PluginMeta, Shell, ShellConfig, StateMut, States},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SqliteHistoryError {
+    #[error("error when opening history database {0}")]
+    Open(rusqlite::Error),
+    #[error("error when querying history database {0}")]
+    Query(#[from] rusqlite::Error),
+}
+
+/// A single command stored in history
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub id: i64,
+    /// Command as it was typed
+    pub command: String,
+    /// Working directory the command was ran in
+    pub cwd: PathBuf,
+    pub hostname: String,
+    /// Identifies the shell instance that ran the command
+    pub session: String,
+    /// When the command was entered
+    pub timestamp: SystemTime,
+    /// Exit status of the command, [None] if the command did not finish
+    pub exit_status: Option<i32>,
+    /// How long the command took, [None] if the command did not finish
+    pub duration: Option<Duration>,
+}
+
+/// Filter on exit status used by [HistoryFilter]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StatusFilter {
+    Succeeded,
+    Failed,
+}
+
+/// Criteria for searching history, see [SqliteHistoryState::search]
+///
+/// All criteria are optional and are combined together. Results are ordered from most recent to
+/// least recent.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    prefix: Option<String>,
+    contains: Option<String>,
+    cwd: Option<PathBuf>,
+    hostname: Option<String>,
+    session: Option<String>,
+    status: Option<StatusFilter>,
+    limit: Option<usize>,
+}
+
+impl HistoryFilter {
+    /// Only commands starting with the given text
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+    /// Only commands containing the given text
+    pub fn contains(mut self, text: impl Into<String>) -> Self {
+        self.contains = Some(text.into());
+        self
+    }
+    /// Only commands ran in the given directory
+    pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
+    /// Only commands ran on the given host
+    pub fn hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = Some(hostname.into());
+        self
+    }
+    /// Only commands ran in the given session
+    pub fn session(mut self, session: impl Into<String>) -> Self {
+        self.session = Some(session.into());
+        self
+    }
+    /// Only commands that exited successfully
+    pub fn succeeded(mut self) -> Self {
+        self.status = Some(StatusFilter::Succeeded);
+        self
+    }
+    /// Only commands that exited with a non-zero status
+    pub fn failed(mut self) -> Self {
+        self.status = Some(StatusFilter::Failed);
+        self
+    }
+    /// Return at most the given number of entries
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Build the WHERE clause and parameters for the filter
+    fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut clauses = vec![];
+        let mut values = vec![];
+
+        if let Some(prefix) = &self.prefix {
+            clauses.push("substr(command, 1, length(?)) = ?");
+            values.push(Value::Text(prefix.clone()));
+            values.push(Value::Text(prefix.clone()));
+        }
+        if let Some(text) = &self.contains {
+            clauses.push("instr(command, ?) > 0");
+            values.push(Value::Text(text.clone()));
+        }
+        if let Some(cwd) = &self.cwd {
+            clauses.push("cwd = ?");
+            values.push(Value::Text(cwd.to_string_lossy().to_string()));
+        }
+        if let Some(hostname) = &self.hostname {
+            clauses.push("hostname = ?");
+            values.push(Value::Text(hostname.clone()));
+        }
+        if let Some(session) = &self.session {
+            clauses.push("session = ?");
+            values.push(Value::Text(session.clone()));
+        }
+        match self.status {
+            Some(StatusFilter::Succeeded) => clauses.push("exit_status = 0"),
+            Some(StatusFilter::Failed) => clauses.push("exit_status != 0"),
+            None => {},
+        }
+
+        let mut sql = String::new();
+        if !clauses.is_empty() {
+            sql += " WHERE ";
+            sql += &clauses.join(" AND ");
+        }
+        sql += " ORDER BY id DESC";
+        if let Some(limit) = self.limit {
+            sql += " LIMIT ?";
+            values.push(Value::Integer(limit as i64));
+        }
+        (sql, values)
+    }
+}
+
+/// Connection to the history database along with the command currently being ran
+pub struct SqliteHistoryState {
+    conn: Connection,
+    hostname: String,
+    session: String,
+    /// Entry of the command currently running, metadata is filled in once it completes
+    running: Option<(i64, Instant)>,
+}
+
+impl SqliteHistoryState {
+    /// Open (and create if needed) the history database at the given path
+    pub fn new(db_file: &Path) -> Result<Self, SqliteHistoryError> {
+        let conn = Connection::open(db_file).map_err(SqliteHistoryError::Open)?;
+        Self::from_connection(conn)
+    }
+
+    /// Use an in memory database, history will not persist across instances of the shell
+    pub fn in_memory() -> Result<Self, SqliteHistoryError> {
+        let conn = Connection::open_in_memory().map_err(SqliteHistoryError::Open)?;
+        Self::from_connection(conn)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, SqliteHistoryError> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                command TEXT NOT NULL,
+                cwd TEXT NOT NULL,
+                hostname TEXT NOT NULL,
+                session TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                exit_status INTEGER,
+                duration_ms INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS history_command ON history(command);
+            CREATE INDEX IF NOT EXISTS history_cwd ON history(cwd);",
+        )?;
+
+        let hostname = gethostname::gethostname().to_string_lossy().to_string();
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let session = format!("{}-{}", std::process::id(), started);
+
+        Ok(Self {
+            conn,
+            hostname,
+            session,
+            running: None,
+        })
+    }
+
+    /// Identifier of the current shell instance
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// Record a command that is about to run
+    pub fn insert(&mut self, command: &str) -> Result<(), SqliteHistoryError> {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+
+        self.conn.execute(
+            "INSERT INTO history (command, cwd, hostname, session, timestamp)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                command,
+                cwd.to_string_lossy(),
+                self.hostname,
+                self.session,
+                timestamp
+            ],
+        )?;
+        self.running = Some((self.conn.last_insert_rowid(), Instant::now()));
+        Ok(())
+    }
+
+    /// Fill in the exit status and duration of the command that was last inserted
+    ///
+    /// No-op if there is no command currently running
+    pub fn finish(&mut self, exit_status: i32) -> Result<(), SqliteHistoryError> {
+        let Some((id, start)) = self.running.take() else {
+            return Ok(());
+        };
+        let duration_ms = start.elapsed().as_millis() as i64;
+
+        self.conn.execute(
+            "UPDATE history SET exit_status = ?1, duration_ms = ?2 WHERE id = ?3",
+            params![exit_status, duration_ms, id],
+        )?;
+        Ok(())
+    }
+
+    /// Search history with the given filter
+    pub fn search(&self, filter: &HistoryFilter) -> Result<Vec<HistoryEntry>, SqliteHistoryError> {
+        let (clause, values) = filter.to_sql();
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, command, cwd, hostname, session, timestamp, exit_status, duration_ms
+            FROM history{clause}"
+        ))?;
+
+        let entries = stmt
+            .query_map(params_from_iter(values), |row| {
+                Ok(HistoryEntry {
+                    id: row.get(0)?,
+                    command: row.get(1)?,
+                    cwd: PathBuf::from(row.get::<_, String>(2)?),
+                    hostname: row.get(3)?,
+                    session: row.get(4)?,
+                    timestamp: UNIX_EPOCH + Duration::from_secs(row.get::<_, i64>(5)? as u64),
+                    exit_status: row.get(6)?,
+                    duration: row
+                        .get::<_, Option<i64>>(7)?
+                        .map(|ms| Duration::from_millis(ms as u64)),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    fn clear(&mut self) -> Result<(), SqliteHistoryError> {
+        self.conn.execute("DELETE FROM history", [])?;
+        self.running = None;
+        Ok(())
+    }
+
+    fn len(&self) -> Result<usize, SqliteHistoryError> {
+        let len: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM history", [], |row| row.get(0))?;
+        Ok(len as usize)
+    }
+
+    /// Get command by index, where index zero is the most recent command
+    fn get(&self, i: usize) -> Result<Option<String>, SqliteHistoryError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT command FROM history ORDER BY id DESC LIMIT 1 OFFSET ?1")?;
+        let mut rows = stmt.query(params![i as i64])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn commands(&self) -> Result<Vec<String>, SqliteHistoryError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT command FROM history ORDER BY id DESC")?;
+        let commands = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(commands)
+    }
+}
+
+/// Store history in an SQLite database on disk
+pub struct SqliteHistoryPlugin {
+    db_file: Option<PathBuf>,
+}
+
+impl SqliteHistoryPlugin {
+    /// Store history in `history.db` in the config directory
+    pub fn new() -> Self {
+        Self { db_file: None }
+    }
+
+    /// Store history in a database at a custom location
+    pub fn with_file(db_file: PathBuf) -> Self {
+        Self {
+            db_file: Some(db_file),
+        }
+    }
+}
+
+impl Default for SqliteHistoryPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin for SqliteHistoryPlugin {
+    fn init(&self, shell: &mut ShellConfig) -> anyhow::Result<()> {
+        let db_file = self
+            .db_file
+            .clone()
+            .unwrap_or_else(|| shell.config_dir.join("history.db"));
+
+        shell.states.insert(SqliteHistoryState::new(&db_file)?);
+        shell.hooks.insert(after_command_hook);
+        shell.history = Box::new(SqliteHistory);
+        Ok(())
+    }
+
+    fn meta(&self) -> PluginMeta {
+        PluginMeta::new(
+            "SQLite History",
+            "Stores history along with command metadata in an SQLite database",
+            None,
+        )
+    }
+}
+
+fn after_command_hook(
+    mut state: StateMut<SqliteHistoryState>,
+    ctx: &AfterCommandCtx,
+) -> anyhow::Result<()> {
+    state.finish(ctx.cmd_output.status.code().unwrap_or(-1))?;
+    Ok(())
+}
+
+/// [History] implementation that reads and writes to [SqliteHistoryState]
+pub struct SqliteHistory;
+
+impl History for SqliteHistory {
+    fn add(&self, _sh: &Shell, states: &States, cmd: String) {
+        if cmd.starts_with("history run") {
+            return;
+        }
+        if let Err(e) = states.get_mut::<SqliteHistoryState>().insert(&cmd) {
+            warn!("failed to add history entry {e}");
+        }
+    }
+
+    fn clear(&self, _sh: &Shell, states: &States) {
+        if let Err(e) = states.get_mut::<SqliteHistoryState>().clear() {
+            warn!("failed to clear history {e}");
+        }
+    }
+
+    fn len(&self, _sh: &Shell, states: &States) -> usize {
+        states.get::<SqliteHistoryState>().len().unwrap_or_default()
+    }
+
+    fn get(&self, _sh: &Shell, states: &States, i: usize) -> Option<String> {
+        states.get::<SqliteHistoryState>().get(i).ok().flatten()
+    }
+
+    fn items(&self, _sh: &Shell, states: &States) -> Vec<String> {
+        states
+            .get::<SqliteHistoryState>()
+            .commands()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HistoryFilter, SqliteHistoryState};
+
+    #[test]
+    fn search_history() {
+        let mut state = SqliteHistoryState::in_memory().unwrap();
+        state.insert("cargo build").unwrap();
+        state.finish(0).unwrap();
+        state.insert("cargo test").unwrap();
+        state.finish(101).unwrap();
+        state.insert("ls").unwrap();
+
+        assert_eq!(state.len().unwrap(), 3);
+        assert_eq!(state.get(0).unwrap().as_deref(), Some("ls"));
+        assert_eq!(state.get(3).unwrap(), None);
+
+        let cargo = state
+            .search(&HistoryFilter::default().prefix("cargo"))
+            .unwrap();
+        let commands = cargo.iter().map(|e| e.command.as_str()).collect::<Vec<_>>();
+        assert_eq!(commands, vec!["cargo test", "cargo build"]);
+
+        let failed = state.search(&HistoryFilter::default().failed()).unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].exit_status, Some(101));
+
+        // unfinished commands have no metadata
+        let latest = state.search(&HistoryFilter::default().limit(1)).unwrap();
+        assert_eq!(latest[0].exit_status, None);
+        assert_eq!(latest[0].duration, None);
+    }
+}
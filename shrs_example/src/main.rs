@@ -79,11 +79,10 @@ fn main() {
 
     // =-=-= Completion =-=-=
     // Get list of binaries in path and initialize the completer to autocomplete command names
-    let path_string = env.get("PATH").unwrap().to_string();
     let mut completer = DefaultCompleter::default();
     completer.register(Rule::new(
         Pred::new(cmdname_pred),
-        Box::new(cmdname_action(path_string)),
+        Box::new(cmdname_action(PathExecCache::new())),
     ));
     completer.register(Rule::new(
         Pred::new(cmdname_pred),
@@ -110,6 +109,9 @@ fn main() {
     bindings
         .insert("C-n", "Move down one in the command history", cd_stack_up)
         .unwrap();
+    bindings
+        .insert("C-r", "Search history", HistoryPickerKeybinding::default())
+        .unwrap();
 
     // =-=-= Readline =-=-=
     // Initialize readline with all of our components
@@ -136,25 +138,18 @@ fn main() {
         ("la", "ls -a --color=auto"),
     ]);
 
-    // =-=-= Hooks =-=-=
-    // Create a hook that prints a welcome message on startup
-    let startup_msg =
-        |mut out: StateMut<OutputWriter>, _startup: &StartupCtx| -> anyhow::Result<()> {
-            let welcome_str = format!(
-                r#"
+    // =-=-= Startup message =-=-=
+    // Print a welcome banner on startup, instead of the default of showing `motd.txt` from the
+    // config directory
+    let motd = Motd::Text(format!(
+        r#"
         __
    ___ / /  _______
   (_-</ _ \/ __(_-<
  /___/_//_/_/ /___/
 a rusty POSIX shell | build {}"#,
-                env!("SHRS_VERSION")
-            );
-
-            out.println(welcome_str)?;
-            Ok(())
-        };
-    let mut hooks = Hooks::new();
-    hooks.insert(startup_msg);
+        env!("SHRS_VERSION")
+    ));
 
     // =-=-= Plugins =-=-=
     let mux_plugin = MuxPlugin::new()
@@ -167,7 +162,7 @@ a rusty POSIX shell | build {}"#,
     // Construct the final shell
     let myshell = ShellBuilder::default()
         .with_completer(completer)
-        .with_hooks(hooks)
+        .with_motd(motd)
         .with_env(env)
         .with_alias(alias)
         .with_keybindings(bindings)
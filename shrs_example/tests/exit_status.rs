@@ -0,0 +1,99 @@
+//! Run the example shell in a pseudoterminal and check the status it exits with
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    os::{fd::FromRawFd, unix::process::CommandExt},
+    process::{Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use nix::{
+    libc,
+    pty::{openpty, Winsize},
+    unistd::setsid,
+};
+
+/// Cursor position request, sent each time the shell starts reading a line
+const CURSOR_POSITION: &[u8] = b"\x1b[6n";
+
+/// Type each line into the shell once it asks for input, then wait for it to exit
+fn run_shell(lines: &[&str]) -> ExitStatus {
+    let winsize = Winsize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let pty = openpty(&winsize, None).unwrap();
+    let mut master = unsafe { File::from_raw_fd(pty.master) };
+    let slave = unsafe { File::from_raw_fd(pty.slave) };
+
+    let home = tempfile::tempdir().unwrap();
+    let mut child = unsafe {
+        Command::new(env!("CARGO_BIN_EXE_shrs_example"))
+            .env("HOME", home.path())
+            .stdin(Stdio::from(slave.try_clone().unwrap()))
+            .stdout(Stdio::from(slave.try_clone().unwrap()))
+            .stderr(Stdio::from(slave))
+            // make the pseudoterminal the controlling terminal, which the shell reads keys from
+            .pre_exec(|| {
+                setsid()?;
+                if libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            })
+            .spawn()
+            .unwrap()
+    };
+
+    let mut input = master.try_clone().unwrap();
+    let typed = lines
+        .iter()
+        .map(|line| format!("{line}\r"))
+        .collect::<Vec<_>>();
+    thread::spawn(move || {
+        let mut typed = typed.into_iter();
+        let mut output = vec![];
+        let mut buf = [0; 1024];
+        // reading fails once the shell exits and closes the pseudoterminal
+        while let Ok(n @ 1..) = master.read(&mut buf) {
+            output.extend_from_slice(&buf[..n]);
+            while let Some(start) = output
+                .windows(CURSOR_POSITION.len())
+                .position(|window| window == CURSOR_POSITION)
+            {
+                output.drain(..start + CURSOR_POSITION.len());
+                input.write_all(b"\x1b[1;1R").unwrap();
+                if let Some(line) = typed.next() {
+                    input.write_all(line.as_bytes()).unwrap();
+                }
+            }
+        }
+    });
+
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status;
+        }
+        if start.elapsed() > Duration::from_secs(30) {
+            child.kill().unwrap();
+            panic!("shell didn't exit after typing {lines:?}");
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn exit_code() {
+    assert_eq!(run_shell(&["exit 3"]).code(), Some(3));
+}
+
+#[test]
+fn exit_with_last_status() {
+    assert_eq!(run_shell(&["false", "exit"]).code(), Some(1));
+    assert_eq!(run_shell(&["false", "true", "exit"]).code(), Some(0));
+}